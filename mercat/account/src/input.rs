@@ -1,5 +1,8 @@
 use log::info;
-use mercat_common::{gen_seed, save_config};
+use mercat_common::{
+    account_transfer::TransferParties, encoding::Encoding, gen_seed, save_config, tx_ids::TxIdArg,
+    ChainDirs,
+};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -23,6 +26,24 @@ pub struct CreateAccountInfo {
     )]
     pub db_dir: Option<PathBuf>,
 
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
     /// An asset ticker name which is a string of at most 12 characters.
     /// In these test CLIs, the unique account id is created from the pair of username and ticker.
     #[structopt(
@@ -49,12 +70,52 @@ pub struct CreateAccountInfo {
     pub save_config: Option<PathBuf>,
 
     /// Instructs the CLI to act as a cheater.
-    #[structopt(long, help = "Instructs the CLI to act as a cheater.")]
+    #[structopt(
+        long,
+        help = "Instructs the CLI to act as a cheater. Requires the `cheating` feature."
+    )]
     pub cheat: bool,
 
-    /// Transaction id.
-    #[structopt(long, help = "Transaction id.")]
-    pub tx_id: u32,
+    /// Instructs the CLI to keep a registry of the hashes of the account blinding factors and
+    /// to fail if a blinding factor repeats, e.g., due to a broken rng.
+    #[structopt(
+        long,
+        help = "Fail if the encryption randomness of the new account was already used in this database."
+    )]
+    pub detect_reuse: bool,
+
+    /// Encrypts the secret account file with a passphrase. The passphrase is read from the
+    /// `MERCAT_PASSPHRASE` environment variable, or from the terminal if it is not set. The
+    /// other commands ask for the passphrase when they load an encrypted file.
+    #[structopt(
+        long,
+        help = "Encrypt the secret account file with a passphrase from MERCAT_PASSPHRASE or the terminal."
+    )]
+    pub encrypt_secrets: bool,
+
+    /// Creates the account transaction and validates it against the asset registry, without
+    /// writing any file. Prints whether the validation would pass, and exits with 1 if not.
+    #[structopt(
+        long,
+        help = "Check that the account creation would validate, without writing anything."
+    )]
+    pub check_only: bool,
+
+    /// Derives the keys from the seed itself, instead of from the subseed of the ticker. This
+    /// recovers the accounts that were created before the subseeds. Also applies to
+    /// `--check-only`.
+    #[structopt(
+        long,
+        help = "Derive the keys from the seed itself, as for the accounts created before the per-ticker subseeds."
+    )]
+    pub legacy_seed: bool,
+
+    /// Transaction id. `auto` allocates the next free id. An explicit id must not be used yet.
+    #[structopt(
+        long,
+        help = "The transaction ID, or auto to allocate the next free one."
+    )]
+    pub tx_id: TxIdArg,
 
     /// Instructs the CLI to print the transaction data in stdout.
     #[structopt(
@@ -62,6 +123,14 @@ pub struct CreateAccountInfo {
         help = "Instructs the CLI to print the transaction data in stdout."
     )]
     pub stdout: bool,
+
+    /// The text encoding of the binary data that is read from or printed to the command line.
+    #[structopt(
+        long,
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
+    )]
+    pub encoding: Encoding,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
@@ -82,6 +151,24 @@ pub struct DecryptAccountInfo {
     )]
     pub db_dir: Option<PathBuf>,
 
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
     /// An asset ticker name which is a string of at most 12 characters.
     /// In these test CLIs, the unique account id is created from the pair of username and ticker.
     #[structopt(
@@ -90,6 +177,210 @@ pub struct DecryptAccountInfo {
         help = "The asset ticker name. String of at most 12 characters."
     )]
     pub ticker: String,
+
+    /// Reconstructs the balance right after this transaction from the validated instructions
+    /// in the history of the account, instead of decrypting the current balance.
+    #[structopt(
+        long,
+        help = "Print the balance as of this transaction ID instead of the current balance."
+    )]
+    pub as_of: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
+pub struct ListPendingInfo {
+    /// The name of the user. The name can be any valid string that can be used as a file name.
+    #[structopt(short, long, help = "The name of the user. This name must be unique.")]
+    pub user: String,
+
+    /// The directory that will serve as the database of the on/off-chain data and will be used
+    /// to save and load the data that in a real execution would be written to the on/off the
+    /// blockchain. Defaults to the current directory. This directory will have two main
+    /// sub-directories: `on-chain` and `off-chain`.
+    #[structopt(
+        parse(from_os_str),
+        help = "The directory to load and save the input and output files. Defaults to current directory.",
+        short,
+        long
+    )]
+    pub db_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
+    /// An asset ticker name which is a string of at most 12 characters.
+    /// In these test CLIs, the unique account id is created from the pair of username and ticker.
+    #[structopt(
+        short,
+        long,
+        help = "The asset ticker name. String of at most 12 characters."
+    )]
+    pub ticker: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
+pub struct ResetAccountInfo {
+    /// The name of the user. The name can be any valid string that can be used as a file name.
+    #[structopt(short, long, help = "The name of the user. This name must be unique.")]
+    pub user: String,
+
+    /// The directory that will serve as the database of the on/off-chain data and will be used
+    /// to save and load the data that in a real execution would be written to the on/off the
+    /// blockchain. Defaults to the current directory. This directory will have two main
+    /// sub-directories: `on-chain` and `off-chain`.
+    #[structopt(
+        parse(from_os_str),
+        help = "The directory to load and save the input and output files. Defaults to current directory.",
+        short,
+        long
+    )]
+    pub db_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
+    /// An asset ticker name which is a string of at most 12 characters.
+    /// In these test CLIs, the unique account id is created from the pair of username and ticker.
+    #[structopt(
+        short,
+        long,
+        help = "The asset ticker name. String of at most 12 characters."
+    )]
+    pub ticker: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
+pub struct CloseAccountInfo {
+    /// The name of the user. The name can be any valid string that can be used as a file name.
+    #[structopt(short, long, help = "The name of the user. This name must be unique.")]
+    pub user: String,
+
+    /// The directory that will serve as the database of the on/off-chain data and will be used
+    /// to save and load the data that in a real execution would be written to the on/off the
+    /// blockchain. Defaults to the current directory. This directory will have two main
+    /// sub-directories: `on-chain` and `off-chain`.
+    #[structopt(
+        parse(from_os_str),
+        help = "The directory to load and save the input and output files. Defaults to current directory.",
+        short,
+        long
+    )]
+    pub db_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
+    /// An asset ticker name which is a string of at most 12 characters.
+    /// In these test CLIs, the unique account id is created from the pair of username and ticker.
+    #[structopt(
+        short,
+        long,
+        help = "The asset ticker name. String of at most 12 characters."
+    )]
+    pub ticker: String,
+
+    /// An optional seed, to feed to the RNG, that can be passed to reproduce a previous run of this CLI.
+    /// The seed can be found inside the logs.
+    #[structopt(
+        long,
+        help = "Base64 encoding of an initial seed for the RNG. If not provided, the seed will be chosen at random."
+    )]
+    pub seed: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
+pub struct QuerySentInfo {
+    /// The name of the sender.
+    #[structopt(short, long, help = "The name of the sender.")]
+    pub user: String,
+
+    /// The directory that will serve as the database of the on/off-chain data and will be used
+    /// to save and load the data that in a real execution would be written to the on/off the
+    /// blockchain. Defaults to the current directory. This directory will have two main
+    /// sub-directories: `on-chain` and `off-chain`.
+    #[structopt(
+        parse(from_os_str),
+        help = "The directory to load and save the input and output files. Defaults to current directory.",
+        short,
+        long
+    )]
+    pub db_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
+    /// An asset ticker name which is a string of at most 12 characters.
+    /// In these test CLIs, the unique account id is created from the pair of username and ticker.
+    #[structopt(
+        short,
+        long,
+        help = "The asset ticker name. String of at most 12 characters."
+    )]
+    pub ticker: String,
+
+    /// The id of the transfer.
+    #[structopt(long, help = "The transaction ID of the transfer.")]
+    pub tx_id: u32,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
@@ -101,24 +392,368 @@ pub struct IssueAssetInfo {
     )]
     pub account_id_from_ticker: String,
 
-    /// A transaction ID for the asset issuance transaction.
+    /// A transaction ID for the asset issuance transaction. `auto` allocates the next free id.
+    /// An explicit id must not be used by any instruction yet.
+    #[structopt(
+        long,
+        help = "The transaction ID, or auto to allocate the next free one."
+    )]
+    pub tx_id: TxIdArg,
+
+    /// An optional seed, to feed to the RNG, that can be passed to reproduce a previous run of this CLI.
+    /// The seed can be found inside the logs.
+    #[structopt(
+        long,
+        help = "Base64 encoding of an initial seed for the RNG. If not provided, the seed will be chosen at random."
+    )]
+    pub seed: Option<String>,
+
+    /// Amount to issue.
+    #[structopt(short, long, help = "The amount of assets to issue.")]
+    pub amount: u32,
+
+    /// The directory that will serve as the database of the on/off-chain data and will be used
+    /// to save and load the data that in a real execution would be written to the on/off the
+    /// blockchain. Defaults to the current directory. This directory will have two main
+    /// sub-directories: `on-chain` and `off-chain`.
+    #[structopt(
+        parse(from_os_str),
+        help = "The directory to load and save the input and output files. Defaults to current directory.",
+        short,
+        long
+    )]
+    pub db_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
+    /// The issuer's name. An account must have already been created for this user.
+    #[structopt(short, long, help = "The name of the issuer.")]
+    pub issuer: String,
+
+    /// An optional path to save the config used for this experiment.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "Path to save the input command line arguments as a config file."
+    )]
+    pub save_config: Option<PathBuf>,
+
+    /// Instructs the CLI to print the transaction data in stdout.
+    #[structopt(
+        long,
+        help = "Instructs the CLI to print the transaction data in stdout."
+    )]
+    pub stdout: bool,
+
+    /// Instructs the CLI to act as a cheater.
+    #[structopt(
+        long,
+        help = "Instructs the CLI to act as a cheater. Requires the `cheating` feature."
+    )]
+    pub cheat: bool,
+
+    /// Also requires the creation of the issuer's account to be at or before the last validated
+    /// transaction. The issuer's account must always be validated.
+    #[structopt(
+        long,
+        help = "Require the issuer's account creation to be at or before the last validated transaction."
+    )]
+    pub strict_account_check: bool,
+
+    /// The text encoding of the binary data that is read from or printed to the command line.
+    #[structopt(
+        long,
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
+    )]
+    pub encoding: Encoding,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
+pub struct CreateTransactionInfo {
+    /// Account ID of the issuer will be generated from the username and ticker name pair.
+    #[structopt(
+        long,
+        required_unless = "sender-account-id",
+        help = "The ticker name that will be used to generate the unique account id of the user."
+    )]
+    pub account_id_from_ticker: Option<String>,
+
+    /// A transaction ID for the transaction. `auto` allocates the next free id.
+    /// An explicit id must not be used by any instruction yet.
+    #[structopt(
+        long,
+        help = "The transaction ID, or auto to allocate the next free one."
+    )]
+    pub tx_id: TxIdArg,
+
+    /// An optional seed, to feed to the RNG, that can be passed to reproduce a previous run of this CLI.
+    /// The seed can be found inside the logs.
+    #[structopt(
+        long,
+        help = "Base64 encoding of an initial seed for the RNG. If not provided, the seed will be chosen at random."
+    )]
+    pub seed: Option<String>,
+
+    /// Amount to transfer.
+    #[structopt(short, long, help = "The amount of assets to transfer.")]
+    pub amount: u32,
+
+    /// The directory that will serve as the database of the on/off-chain data and will be used
+    /// to save and load the data that in a real execution would be written to the on/off the
+    /// blockchain. Defaults to the current directory. This directory will have two main
+    /// sub-directories: `on-chain` and `off-chain`.
+    #[structopt(
+        parse(from_os_str),
+        help = "The directory to load and save the input and output files. Defaults to current directory.",
+        short,
+        long
+    )]
+    pub db_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
+    /// The sender's name. An account must have already been created for this user.
+    #[structopt(
+        long,
+        required_unless = "sender-account-id",
+        help = "The sender's name."
+    )]
+    pub sender: Option<String>,
+
+    /// The receiver's name. An account must have already been created for this user.
+    #[structopt(
+        short,
+        long,
+        required_unless = "receiver-account-id",
+        help = "The sender's name."
+    )]
+    pub receiver: Option<String>,
+
+    /// An alternative to the sender's name and ticker. The sender's user name and ticker are
+    /// looked up in the account map.
+    #[structopt(
+        long,
+        conflicts_with_all = &["sender", "account-id-from-ticker"],
+        requires = "receiver-account-id",
+        help = "The sender's account id in the --encoding, as printed by create --stdout."
+    )]
+    pub sender_account_id: Option<String>,
+
+    /// An alternative to the receiver's name. The receiver's account must belong to the same
+    /// ticker as the sender's account.
+    #[structopt(
+        long,
+        conflicts_with = "receiver",
+        requires = "sender-account-id",
+        help = "The receiver's account id in the --encoding, as printed by create --stdout."
+    )]
+    pub receiver_account_id: Option<String>,
+
+    /// Incoming transfers, issuances and mediator credits become spendable only after this many
+    /// further transactions have been validated. Defaults to 0, i.e., they are spendable as soon
+    /// as they are validated.
+    #[structopt(
+        long,
+        default_value = "0",
+        help = "The number of tx_ids that must pass before a validated incoming transfer, issuance or credit can be spent."
+    )]
+    pub min_confirmations: u32,
+
+    /// The transaction mediator's name. Used to retrieve mediator's public keys.
+    /// Use `mercat-mediator` CLI to create the credentials needed for this role.
+    #[structopt(short, long, help = "The mediator's name.")]
+    pub mediator: String,
+
+    /// An optional path to save the config used for this experiment.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "Path to save the input command line arguments as a config file."
+    )]
+    pub save_config: Option<PathBuf>,
+
+    /// Instructs the CLI to print the transaction data in stdout.
+    #[structopt(
+        long,
+        help = "Instructs the CLI to print the transaction data in stdout."
+    )]
+    pub stdout: bool,
+
+    /// Instructs the CLI to act as a cheater.
+    #[structopt(
+        long,
+        help = "Instructs the CLI to act as a cheater. Requires the `cheating` feature."
+    )]
+    pub cheat: bool,
+
+    /// The text encoding of the binary data that is read from or printed to the command line.
+    #[structopt(
+        long,
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
+    )]
+    pub encoding: Encoding,
+}
+
+impl CreateTransactionInfo {
+    /// Returns the selector of the sender and the receiver accounts. The account ids take
+    /// precedence over the user names, if both are provided in a config file.
+    pub fn parties(&self) -> TransferParties {
+        match (&self.sender_account_id, &self.receiver_account_id) {
+            (Some(sender_account_id), Some(receiver_account_id)) => TransferParties::ByAccountId {
+                sender_account_id: sender_account_id.clone(),
+                receiver_account_id: receiver_account_id.clone(),
+            },
+            _ => TransferParties::ByName {
+                sender: self.sender.clone().unwrap_or_default(),
+                receiver: self.receiver.clone().unwrap_or_default(),
+                ticker: self.account_id_from_ticker.clone().unwrap_or_default(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
+pub struct FinalizeTransactionInfo {
+    /// Account ID of the receiver will be generated from the username and ticker name pair.
+    #[structopt(
+        long,
+        help = "The ticker name that will be used to generate the unique account id of the user."
+    )]
+    pub account_id_from_ticker: String,
+
+    /// The transaction ID for the transaction.
     /// The CLI will not throw any errors if a duplicate id is passed.
     /// It will silently overwrite the transaction.
     #[structopt(long, help = "The transaction ID.")]
     pub tx_id: u32,
 
-    /// An optional seed, to feed to the RNG, that can be passed to reproduce a previous run of this CLI.
-    /// The seed can be found inside the logs.
+    /// An optional seed, to feed to the RNG, that can be passed to reproduce a previous run of this CLI.
+    /// The seed can be found inside the logs.
+    #[structopt(
+        long,
+        help = "Base64 encoding of an initial seed for the RNG. If not provided, the seed will be chosen at random."
+    )]
+    pub seed: Option<String>,
+
+    /// The expected amount to receive.
+    #[structopt(short, long, help = "The expected amount to receive.")]
+    pub amount: u32,
+
+    /// The directory that will serve as the database of the on/off-chain data and will be used
+    /// to save and load the data that in a real execution would be written to the on/off the
+    /// blockchain. Defaults to the current directory. This directory will have two main
+    /// sub-directories: `on-chain` and `off-chain`.
+    #[structopt(
+        parse(from_os_str),
+        help = "The directory to load and save the input and output files. Defaults to current directory.",
+        short,
+        long
+    )]
+    pub db_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
+    // TODO(CRYP-110)
+    // Depending on how we decide to name transaction files, we may or may not need the sender's name.
+    /// The sender's name. An account must have already been created for this user.
+    #[structopt(long, help = "The sender's name.")]
+    pub sender: String,
+
+    /// The receiver's name. An account must have already been created for this user.
+    #[structopt(short, long, help = "The sender's name.")]
+    pub receiver: String,
+
+    /// An optional path to save the config used for this experiment.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "Path to save the input command line arguments as a config file."
+    )]
+    pub save_config: Option<PathBuf>,
+
+    /// Instructs the CLI to print the transaction data in stdout.
+    #[structopt(
+        long,
+        help = "Instructs the CLI to print the transaction data in stdout."
+    )]
+    pub stdout: bool,
+
+    /// Instructs the CLI to act as a cheater.
+    #[structopt(
+        long,
+        help = "Instructs the CLI to act as a cheater. Requires the `cheating` feature."
+    )]
+    pub cheat: bool,
+
+    /// Replaces an existing finalization of the transaction, e.g., one that was only partially
+    /// written because the previous run was interrupted.
     #[structopt(
         long,
-        help = "Base64 encoding of an initial seed for the RNG. If not provided, the seed will be chosen at random."
+        help = "Replace an existing, possibly incomplete, finalization of the transaction."
     )]
-    pub seed: Option<String>,
+    pub force_refinalize: bool,
 
-    /// Amount to issue.
-    #[structopt(short, long, help = "The amount of assets to issue.")]
-    pub amount: u32,
+    /// The text encoding of the binary data that is read from or printed to the command line.
+    #[structopt(
+        long,
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
+    )]
+    pub encoding: Encoding,
+}
 
+#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
+pub struct CreatePaymentRequestInfo {
     /// The directory that will serve as the database of the on/off-chain data and will be used
     /// to save and load the data that in a real execution would be written to the on/off the
     /// blockchain. Defaults to the current directory. This directory will have two main
@@ -131,57 +766,58 @@ pub struct IssueAssetInfo {
     )]
     pub db_dir: Option<PathBuf>,
 
-    /// The issuer's name. An account must have already been created for this user.
-    #[structopt(short, long, help = "The name of the issuer.")]
-    pub issuer: String,
-
-    /// An optional path to save the config used for this experiment.
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
     #[structopt(
         parse(from_os_str),
         long,
-        help = "Path to save the input command line arguments as a config file."
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
     )]
-    pub save_config: Option<PathBuf>,
+    pub on_chain_dir: Option<PathBuf>,
 
-    /// Instructs the CLI to print the transaction data in stdout.
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
     #[structopt(
+        parse(from_os_str),
         long,
-        help = "Instructs the CLI to print the transaction data in stdout."
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
     )]
-    pub stdout: bool,
+    pub off_chain_dir: Option<PathBuf>,
 
-    /// Instructs the CLI to act as a cheater.
-    #[structopt(long, help = "Instructs the CLI to act as a cheater.")]
-    pub cheat: bool,
-}
+    /// The receiver's name. An account must have already been created for this user.
+    #[structopt(short, long, help = "The receiver's name.")]
+    pub receiver: String,
 
-#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
-pub struct CreateTransactionInfo {
-    /// Account ID of the issuer will be generated from the username and ticker name pair.
+    /// The name of the user who is asked to pay.
+    #[structopt(long, help = "The sender's name.")]
+    pub sender: String,
+
+    /// The ticker of the receiver's account.
     #[structopt(
         long,
         help = "The ticker name that will be used to generate the unique account id of the user."
     )]
     pub account_id_from_ticker: String,
 
-    /// A transaction ID for the transaction.
-    /// The CLI will not throw any errors if a duplicate id is passed.
-    /// It will silently overwrite the transaction.
+    /// The requested amount.
+    #[structopt(short, long, help = "The requested amount.")]
+    pub amount: u32,
+
+    /// The transaction ID that the approving transfer will use.
     #[structopt(long, help = "The transaction ID.")]
     pub tx_id: u32,
 
-    /// An optional seed, to feed to the RNG, that can be passed to reproduce a previous run of this CLI.
-    /// The seed can be found inside the logs.
+    /// An optional path to save the config used for this experiment.
     #[structopt(
+        parse(from_os_str),
         long,
-        help = "Base64 encoding of an initial seed for the RNG. If not provided, the seed will be chosen at random."
+        help = "Path to save the input command line arguments as a config file."
     )]
-    pub seed: Option<String>,
-
-    /// Amount to transfer.
-    #[structopt(short, long, help = "The amount of assets to transfer.")]
-    pub amount: u32,
+    pub save_config: Option<PathBuf>,
+}
 
+#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
+pub struct ApprovePaymentRequestInfo {
     /// The directory that will serve as the database of the on/off-chain data and will be used
     /// to save and load the data that in a real execution would be written to the on/off the
     /// blockchain. Defaults to the current directory. This directory will have two main
@@ -194,19 +830,54 @@ pub struct CreateTransactionInfo {
     )]
     pub db_dir: Option<PathBuf>,
 
-    /// The sender's name. An account must have already been created for this user.
-    #[structopt(long, help = "The sender's name.")]
-    pub sender: String,
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
 
-    /// The receiver's name. An account must have already been created for this user.
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
+    /// The sender's name. The request must be addressed to this user.
     #[structopt(short, long, help = "The sender's name.")]
-    pub receiver: String,
+    pub sender: String,
 
-    /// The transaction mediator's name. Used to retrieve mediator's public keys.
-    /// Use `mercat-mediator` CLI to create the credentials needed for this role.
-    #[structopt(short, long, help = "The mediator's name.")]
+    /// The transaction's mediator.
+    #[structopt(long, help = "The transaction's mediator name.")]
     pub mediator: String,
 
+    /// The transaction ID of the payment request.
+    #[structopt(long, help = "The transaction ID of the payment request.")]
+    pub tx_id: u32,
+
+    /// Incoming transfers, issuances and mediator credits become spendable only after this many
+    /// further transactions have been validated. Defaults to 0, i.e., they are spendable as soon
+    /// as they are validated.
+    #[structopt(
+        long,
+        default_value = "0",
+        help = "The number of tx_ids that must pass before a validated incoming transfer, issuance or credit can be spent."
+    )]
+    pub min_confirmations: u32,
+
+    /// An optional seed, to feed to the RNG, that can be passed to reproduce a previous run of this CLI.
+    /// The seed can be found inside the logs.
+    #[structopt(
+        long,
+        help = "Base64 encoding of an initial seed for the RNG. If not provided, the seed will be chosen at random."
+    )]
+    pub seed: Option<String>,
+
     /// An optional path to save the config used for this experiment.
     #[structopt(
         parse(from_os_str),
@@ -223,36 +894,26 @@ pub struct CreateTransactionInfo {
     pub stdout: bool,
 
     /// Instructs the CLI to act as a cheater.
-    #[structopt(long, help = "Instructs the CLI to act as a cheater.")]
-    pub cheat: bool,
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
-pub struct FinalizeTransactionInfo {
-    /// Account ID of the receiver will be generated from the username and ticker name pair.
     #[structopt(
         long,
-        help = "The ticker name that will be used to generate the unique account id of the user."
+        help = "Instructs the CLI to act as a cheater. Requires the `cheating` feature."
     )]
-    pub account_id_from_ticker: String,
-
-    /// The transaction ID for the transaction.
-    /// The CLI will not throw any errors if a duplicate id is passed.
-    /// It will silently overwrite the transaction.
-    #[structopt(long, help = "The transaction ID.")]
-    pub tx_id: u32,
+    pub cheat: bool,
 
-    /// An optional seed, to feed to the RNG, that can be passed to reproduce a previous run of this CLI.
-    /// The seed can be found inside the logs.
+    /// The text encoding of the binary data that is read from or printed to the command line.
     #[structopt(
         long,
-        help = "Base64 encoding of an initial seed for the RNG. If not provided, the seed will be chosen at random."
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
     )]
-    pub seed: Option<String>,
+    pub encoding: Encoding,
+}
 
-    /// The expected amount to receive.
-    #[structopt(short, long, help = "The expected amount to receive.")]
-    pub amount: u32,
+#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
+pub struct BatchInfo {
+    /// A JSON array of operations, each with an `op` of `create-account` or `issue`.
+    #[structopt(parse(from_os_str), long, help = "The JSON file with the operations.")]
+    pub batch: PathBuf,
 
     /// The directory that will serve as the database of the on/off-chain data and will be used
     /// to save and load the data that in a real execution would be written to the on/off the
@@ -266,34 +927,30 @@ pub struct FinalizeTransactionInfo {
     )]
     pub db_dir: Option<PathBuf>,
 
-    // TODO(CRYP-110)
-    // Depending on how we decide to name transaction files, we may or may not need the sender's name.
-    /// The sender's name. An account must have already been created for this user.
-    #[structopt(long, help = "The sender's name.")]
-    pub sender: String,
-
-    /// The receiver's name. An account must have already been created for this user.
-    #[structopt(short, long, help = "The sender's name.")]
-    pub receiver: String,
-
-    /// An optional path to save the config used for this experiment.
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
     #[structopt(
         parse(from_os_str),
         long,
-        help = "Path to save the input command line arguments as a config file."
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
     )]
-    pub save_config: Option<PathBuf>,
+    pub on_chain_dir: Option<PathBuf>,
 
-    /// Instructs the CLI to print the transaction data in stdout.
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
     #[structopt(
+        parse(from_os_str),
         long,
-        help = "Instructs the CLI to print the transaction data in stdout."
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
     )]
-    pub stdout: bool,
+    pub off_chain_dir: Option<PathBuf>,
 
-    /// Instructs the CLI to act as a cheater.
-    #[structopt(long, help = "Instructs the CLI to act as a cheater.")]
-    pub cheat: bool,
+    /// Runs the remaining operations after a failure, instead of stopping at the first one.
+    #[structopt(
+        long,
+        help = "Run the remaining operations after a failure, and report all failures at the end."
+    )]
+    pub continue_on_error: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
@@ -316,8 +973,55 @@ pub enum CLI {
     /// Finalize a MERCAT transaction.
     FinalizeTransaction(FinalizeTransactionInfo),
 
+    /// Request a payment from another user.
+    CreatePaymentRequest(CreatePaymentRequestInfo),
+
+    /// Approve a payment request by creating the requested MERCAT transaction.
+    ApprovePaymentRequest(ApprovePaymentRequestInfo),
+
     /// Decrypt the account balance.
     Decrypt(DecryptAccountInfo),
+
+    /// List the un-validated transfers of an account, in the order of its pending counter.
+    Pending(ListPendingInfo),
+
+    /// Remove the un-validated transfers sent from an account, e.g., when they are stuck.
+    ResetAccount(ResetAccountInfo),
+
+    /// Close an account with a zero balance. Run it again after the validation to remove the
+    /// secret account.
+    Close(CloseAccountInfo),
+
+    /// Decrypt the amount of a transfer from the sender's own record of it.
+    QuerySent(QuerySentInfo),
+
+    /// Create accounts and issue assets from a batch file.
+    Batch(BatchInfo),
+}
+
+impl CLI {
+    /// Returns the chain directories of the command, with its `--on-chain-dir` and
+    /// `--off-chain-dir` overrides applied. `None` if the command has no database directory.
+    pub fn chain_dirs(&self) -> Option<ChainDirs> {
+        let (db_dir, on_chain_dir, off_chain_dir) = match self {
+            CLI::Create(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::CreateFrom { .. } => return None,
+            CLI::Issue(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::CreateTransaction(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::FinalizeTransaction(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::CreatePaymentRequest(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::ApprovePaymentRequest(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::Decrypt(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::Pending(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::ResetAccount(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::Close(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::QuerySent(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::Batch(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+        };
+        db_dir.clone().map(|db_dir| {
+            ChainDirs::with_overrides(db_dir, on_chain_dir.clone(), off_chain_dir.clone())
+        })
+    }
 }
 
 pub fn parse_input() -> CLI {
@@ -336,10 +1040,17 @@ pub fn parse_input() -> CLI {
                 seed,
                 ticker: cfg.ticker,
                 db_dir,
+                on_chain_dir: cfg.on_chain_dir,
+                off_chain_dir: cfg.off_chain_dir,
                 user: cfg.user.clone(),
                 cheat: cfg.cheat,
+                detect_reuse: cfg.detect_reuse,
+                encrypt_secrets: cfg.encrypt_secrets,
+                check_only: cfg.check_only,
+                legacy_seed: cfg.legacy_seed,
                 tx_id: cfg.tx_id,
                 stdout: cfg.stdout,
+                encoding: cfg.encoding,
             };
 
             info!(
@@ -373,7 +1084,10 @@ pub fn parse_input() -> CLI {
             let cfg = DecryptAccountInfo {
                 ticker: cfg.ticker,
                 db_dir,
+                on_chain_dir: cfg.on_chain_dir,
+                off_chain_dir: cfg.off_chain_dir,
                 user: cfg.user.clone(),
+                as_of: cfg.as_of,
             };
 
             info!(
@@ -384,6 +1098,99 @@ pub fn parse_input() -> CLI {
             return CLI::Decrypt(cfg);
         }
 
+        CLI::Pending(cfg) => {
+            let db_dir = cfg.db_dir.clone().or_else(|| std::env::current_dir().ok());
+
+            let cfg = ListPendingInfo {
+                ticker: cfg.ticker,
+                db_dir,
+                on_chain_dir: cfg.on_chain_dir,
+                off_chain_dir: cfg.off_chain_dir,
+                user: cfg.user.clone(),
+            };
+
+            info!(
+                "Parsed the following config from the command line:\n{:#?}",
+                cfg.clone()
+            );
+
+            return CLI::Pending(cfg);
+        }
+
+        CLI::ResetAccount(cfg) => {
+            let db_dir = cfg.db_dir.clone().or_else(|| std::env::current_dir().ok());
+
+            let cfg = ResetAccountInfo {
+                ticker: cfg.ticker,
+                db_dir,
+                on_chain_dir: cfg.on_chain_dir,
+                off_chain_dir: cfg.off_chain_dir,
+                user: cfg.user.clone(),
+            };
+
+            info!(
+                "Parsed the following config from the command line:\n{:#?}",
+                cfg.clone()
+            );
+
+            return CLI::ResetAccount(cfg);
+        }
+
+        CLI::Close(cfg) => {
+            let db_dir = cfg.db_dir.clone().or_else(|| std::env::current_dir().ok());
+
+            let seed: Option<String> = cfg.seed.clone().or_else(|| Some(gen_seed()));
+            info!("Seed: {:?}", seed.clone().unwrap()); // unwrap won't panic
+
+            let cfg = CloseAccountInfo {
+                ticker: cfg.ticker,
+                db_dir,
+                on_chain_dir: cfg.on_chain_dir,
+                off_chain_dir: cfg.off_chain_dir,
+                user: cfg.user.clone(),
+                seed,
+            };
+
+            info!(
+                "Parsed the following config from the command line:\n{:#?}",
+                cfg.clone()
+            );
+
+            return CLI::Close(cfg);
+        }
+
+        CLI::QuerySent(cfg) => {
+            let db_dir = cfg.db_dir.clone().or_else(|| std::env::current_dir().ok());
+
+            let cfg = QuerySentInfo {
+                ticker: cfg.ticker,
+                db_dir,
+                on_chain_dir: cfg.on_chain_dir,
+                off_chain_dir: cfg.off_chain_dir,
+                user: cfg.user.clone(),
+                tx_id: cfg.tx_id,
+            };
+
+            info!(
+                "Parsed the following config from the command line:\n{:#?}",
+                cfg.clone()
+            );
+
+            return CLI::QuerySent(cfg);
+        }
+
+        CLI::Batch(cfg) => {
+            let db_dir = cfg.db_dir.clone().or_else(|| std::env::current_dir().ok());
+            let cfg = BatchInfo { db_dir, ..cfg };
+
+            info!(
+                "Parsed the following config from the command line:\n{:#?}",
+                cfg.clone()
+            );
+
+            return CLI::Batch(cfg);
+        }
+
         CLI::Issue(cfg) => {
             let db_dir = cfg.db_dir.clone().or_else(|| std::env::current_dir().ok());
 
@@ -396,10 +1203,14 @@ pub fn parse_input() -> CLI {
                 seed,
                 amount: cfg.amount,
                 db_dir,
+                on_chain_dir: cfg.on_chain_dir,
+                off_chain_dir: cfg.off_chain_dir,
                 issuer: cfg.issuer,
                 save_config: cfg.save_config.clone(),
                 stdout: cfg.stdout,
                 cheat: cfg.cheat,
+                strict_account_check: cfg.strict_account_check,
+                encoding: cfg.encoding,
             };
 
             info!(
@@ -425,12 +1236,18 @@ pub fn parse_input() -> CLI {
                 seed,
                 amount: cfg.amount,
                 db_dir,
+                on_chain_dir: cfg.on_chain_dir,
+                off_chain_dir: cfg.off_chain_dir,
                 sender: cfg.sender,
                 receiver: cfg.receiver,
+                sender_account_id: cfg.sender_account_id,
+                receiver_account_id: cfg.receiver_account_id,
                 mediator: cfg.mediator,
+                min_confirmations: cfg.min_confirmations,
                 save_config: cfg.save_config.clone(),
                 stdout: cfg.stdout,
                 cheat: cfg.cheat,
+                encoding: cfg.encoding,
             };
 
             info!(
@@ -456,11 +1273,15 @@ pub fn parse_input() -> CLI {
                 seed,
                 amount: cfg.amount,
                 db_dir,
+                on_chain_dir: cfg.on_chain_dir,
+                off_chain_dir: cfg.off_chain_dir,
                 sender: cfg.sender,
                 receiver: cfg.receiver,
                 save_config: cfg.save_config.clone(),
                 stdout: cfg.stdout,
                 cheat: cfg.cheat,
+                force_refinalize: cfg.force_refinalize,
+                encoding: cfg.encoding,
             };
 
             info!(
@@ -473,5 +1294,63 @@ pub fn parse_input() -> CLI {
 
             return CLI::FinalizeTransaction(cfg);
         }
+
+        CLI::CreatePaymentRequest(cfg) => {
+            let db_dir = cfg.db_dir.clone().or_else(|| std::env::current_dir().ok());
+
+            let cfg = CreatePaymentRequestInfo {
+                db_dir,
+                on_chain_dir: cfg.on_chain_dir,
+                off_chain_dir: cfg.off_chain_dir,
+                receiver: cfg.receiver,
+                sender: cfg.sender,
+                account_id_from_ticker: cfg.account_id_from_ticker,
+                amount: cfg.amount,
+                tx_id: cfg.tx_id,
+                save_config: cfg.save_config.clone(),
+            };
+
+            info!(
+                "Parsed the following config from the command line:\n{:#?}",
+                cfg.clone()
+            );
+
+            // Save the config if the argument is passed.
+            save_config(cfg.save_config.clone(), &cfg);
+
+            return CLI::CreatePaymentRequest(cfg);
+        }
+
+        CLI::ApprovePaymentRequest(cfg) => {
+            let db_dir = cfg.db_dir.clone().or_else(|| std::env::current_dir().ok());
+
+            let seed: Option<String> = cfg.seed.clone().or_else(|| Some(gen_seed()));
+            info!("Seed: {:?}", seed.clone().unwrap());
+
+            let cfg = ApprovePaymentRequestInfo {
+                db_dir,
+                on_chain_dir: cfg.on_chain_dir,
+                off_chain_dir: cfg.off_chain_dir,
+                sender: cfg.sender,
+                mediator: cfg.mediator,
+                tx_id: cfg.tx_id,
+                min_confirmations: cfg.min_confirmations,
+                seed,
+                save_config: cfg.save_config.clone(),
+                stdout: cfg.stdout,
+                cheat: cfg.cheat,
+                encoding: cfg.encoding,
+            };
+
+            info!(
+                "Parsed the following config from the command line:\n{:#?}",
+                cfg.clone()
+            );
+
+            // Save the config if the argument is passed.
+            save_config(cfg.save_config.clone(), &cfg);
+
+            return CLI::ApprovePaymentRequest(cfg);
+        }
     }
 }
@@ -7,79 +7,248 @@ use env_logger;
 use input::{parse_input, CLI};
 use log::info;
 use mercat_common::{
-    account_create::process_create_account,
-    account_issue::process_issue_asset,
-    account_transfer::{process_create_tx, process_finalize_tx},
-    debug_decrypt_account_balance,
+    account_close::process_close_account,
+    account_create::{process_check_account_creation, process_create_account_with},
+    account_issue::process_issue_asset_with,
+    account_reset::process_reset_account,
+    account_transfer::{process_create_tx, process_finalize_tx_with, process_query_sent},
+    batch::{load_batch, process_batch},
+    debug_decrypt_account_balance_with_progress,
     errors::Error,
-    init_print_logger,
+    history::process_balance_as_of,
+    init_profiling_logger, install_panic_hook,
+    payment_request::{process_approve_payment_request, process_create_payment_request},
+    pending_for_account,
+    tx_ids::resolve_tx_id,
 };
 use metrics::timing;
-use std::time::Instant;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Instant,
+};
+
+/// Set by the SIGINT handler to cancel a running decryption.
+static CANCEL_DECRYPTION: AtomicBool = AtomicBool::new(false);
+
+/// The exit code of a command that was cancelled with SIGINT.
+const CANCELLED_EXIT_CODE: i32 = 130;
+
+/// Logs the decrypted `balance`. A decryption that was cancelled with SIGINT is not a failure
+/// of the program, so it is reported without a panic, and the process exits with a non-zero code.
+fn print_balance(label: &str, balance: Result<u32, Error>) {
+    match balance {
+        Err(Error::DecryptCancelled) => {
+            println!("cancelled");
+            std::process::exit(CANCELLED_EXIT_CODE);
+        }
+        balance => info!("{}: {}", label, balance.unwrap()),
+    }
+}
 
 fn main() {
     env_logger::init();
     info!("Starting the program.");
-    init_print_logger();
+    let profile = init_profiling_logger();
+    install_panic_hook();
 
     let parse_arg_timer = Instant::now();
     let args = parse_input();
+    let chain_dirs = args.chain_dirs();
     timing!("account.argument_parse", parse_arg_timer, Instant::now());
 
     match args {
+        CLI::Create(cfg) if cfg.check_only => {
+            let db_dir = chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap();
+            match process_check_account_creation(cfg.seed, db_dir, cfg.ticker, cfg.legacy_seed) {
+                Ok(()) => info!("The account creation would pass the validation."),
+                Err(error) => {
+                    info!("The account creation would fail the validation: {}", error);
+                    std::process::exit(1);
+                }
+            }
+        }
         CLI::Create(cfg) => {
-            let db_dir = cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap();
-            process_create_account(
-                cfg.seed, db_dir, cfg.ticker, cfg.user, cfg.stdout, cfg.tx_id, cfg.cheat,
+            let db_dir = chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap();
+            let tx_id = resolve_tx_id(db_dir.clone(), cfg.tx_id).unwrap();
+            process_create_account_with(
+                cfg.seed,
+                db_dir,
+                cfg.ticker,
+                cfg.user,
+                cfg.stdout,
+                cfg.encoding,
+                tx_id,
+                cfg.cheat,
+                cfg.detect_reuse,
+                cfg.encrypt_secrets,
+                cfg.legacy_seed,
             )
             .unwrap()
         }
         CLI::CreateFrom { config: _ } => panic!("This should not be called directly!"),
-        CLI::Decrypt(cfg) => info!(
-            "Account balance: {}",
-            debug_decrypt_account_balance(
+        CLI::Decrypt(cfg) if cfg.as_of.is_some() => {
+            let as_of = cfg.as_of.unwrap(); // unwrap won't panic
+            let balance = process_balance_as_of(
+                chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
+                &cfg.user,
+                &cfg.ticker,
+                as_of,
+            );
+            print_balance(&format!("Account balance as of tx-{}", as_of), balance)
+        }
+        CLI::Decrypt(cfg) => {
+            // Stop the search on SIGINT, instead of killing the process.
+            ctrlc::set_handler(|| CANCEL_DECRYPTION.store(true, Ordering::Relaxed))
+                .expect("Failed to set the SIGINT handler.");
+            let max = u64::from(u32::max_value());
+            let balance = debug_decrypt_account_balance_with_progress(
+                cfg.user,
+                cfg.ticker,
+                chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
+                |done| eprint!("\rDecrypting: {:.2}%", done as f64 * 100.0 / max as f64),
+                &CANCEL_DECRYPTION,
+            );
+            eprintln!();
+            print_balance("Account balance", balance)
+        }
+        CLI::Pending(cfg) => {
+            let pending = pending_for_account(
+                chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
+                &cfg.user,
+                &cfg.ticker,
+            )
+            .unwrap();
+            for (tx_id, state) in pending {
+                println!("tx-{}: {}", tx_id, state);
+            }
+        }
+        CLI::ResetAccount(cfg) => {
+            let removed = process_reset_account(
+                chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
+                cfg.user,
+                cfg.ticker,
+            )
+            .unwrap();
+            for tx_file in removed.iter() {
+                println!("Removed {}", tx_file);
+            }
+            info!("Removed {} pending instructions.", removed.len());
+        }
+        CLI::Close(cfg) => {
+            let closed = process_close_account(
+                cfg.seed.unwrap(),
+                chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
+                cfg.user.clone(),
+                cfg.ticker.clone(),
+            )
+            .unwrap();
+            if closed {
+                println!("The account {}-{} is closed.", cfg.user, cfg.ticker);
+            } else {
+                println!(
+                    "Requested the closure of {}-{}. Run the command again after the validation.",
+                    cfg.user, cfg.ticker
+                );
+            }
+        }
+        CLI::QuerySent(cfg) => {
+            let (receiver, amount) = process_query_sent(
+                chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
                 cfg.user,
                 cfg.ticker,
-                cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap()
+                cfg.tx_id,
+            )
+            .unwrap();
+            println!("tx-{}: sent {} to {}", cfg.tx_id, amount, receiver);
+        }
+        CLI::Batch(cfg) => {
+            let ops = load_batch(&cfg.batch).unwrap();
+            let report = process_batch(
+                chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
+                &ops,
+                cfg.continue_on_error,
+            );
+            println!("{}", report);
+            if !report.is_success() {
+                std::process::exit(1);
+            }
+        }
+        CLI::Issue(cfg) => {
+            let db_dir = chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap();
+            let tx_id = resolve_tx_id(db_dir.clone(), cfg.tx_id).unwrap();
+            process_issue_asset_with(
+                cfg.seed.ok_or(Error::EmptySeed).unwrap(),
+                db_dir,
+                cfg.issuer,
+                cfg.account_id_from_ticker,
+                cfg.amount,
+                cfg.stdout,
+                cfg.encoding,
+                tx_id,
+                cfg.cheat,
+                cfg.strict_account_check,
+            )
+            .unwrap()
+        }
+        CLI::CreateTransaction(cfg) => {
+            let db_dir = chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap();
+            let (sender, receiver, ticker) =
+                cfg.parties().resolve(db_dir.clone(), cfg.encoding).unwrap();
+            let tx_id = resolve_tx_id(db_dir.clone(), cfg.tx_id).unwrap();
+            process_create_tx(
+                cfg.seed.ok_or(Error::EmptySeed).unwrap(),
+                db_dir,
+                sender,
+                receiver,
+                cfg.mediator,
+                ticker,
+                cfg.amount,
+                cfg.stdout,
+                cfg.encoding,
+                tx_id,
+                cfg.min_confirmations,
+                cfg.cheat,
             )
             .unwrap()
-        ),
-        CLI::Issue(cfg) => process_issue_asset(
+        }
+        CLI::FinalizeTransaction(cfg) => process_finalize_tx_with(
             cfg.seed.ok_or(Error::EmptySeed).unwrap(),
-            cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap(),
-            cfg.issuer,
+            chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
+            cfg.sender,
+            cfg.receiver,
             cfg.account_id_from_ticker,
             cfg.amount,
             cfg.stdout,
+            cfg.encoding,
             cfg.tx_id,
             cfg.cheat,
+            cfg.force_refinalize,
         )
         .unwrap(),
-        CLI::CreateTransaction(cfg) => process_create_tx(
-            cfg.seed.ok_or(Error::EmptySeed).unwrap(),
-            cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap(),
-            cfg.sender,
+        CLI::CreatePaymentRequest(cfg) => process_create_payment_request(
+            chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
             cfg.receiver,
-            cfg.mediator,
+            cfg.sender,
             cfg.account_id_from_ticker,
             cfg.amount,
-            cfg.stdout,
             cfg.tx_id,
-            cfg.cheat,
         )
         .unwrap(),
-        CLI::FinalizeTransaction(cfg) => process_finalize_tx(
+        CLI::ApprovePaymentRequest(cfg) => process_approve_payment_request(
             cfg.seed.ok_or(Error::EmptySeed).unwrap(),
-            cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap(),
+            chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
             cfg.sender,
-            cfg.receiver,
-            cfg.account_id_from_ticker,
-            cfg.amount,
+            cfg.mediator,
             cfg.stdout,
+            cfg.encoding,
             cfg.tx_id,
+            cfg.min_confirmations,
             cfg.cheat,
         )
         .unwrap(),
     };
+    if let Some(report) = profile.report_if_enabled() {
+        println!("{}", report);
+    }
     info!("The program finished successfully.");
 }
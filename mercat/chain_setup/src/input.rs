@@ -10,6 +10,24 @@ pub struct CLI {
     #[structopt(short, long, help = "Space separated list of a ticker names.")]
     pub ticker_names: Vec<String>,
 
+    /// Space separated list of `<TICKER>=<MEDIATOR>` pairs. Each of these mediators may credit
+    /// the accounts of the ticker with deposits from outside of MERCAT.
+    #[structopt(
+        long,
+        parse(try_from_str = parse_credit_mediator),
+        help = "Space separated list of <TICKER>=<MEDIATOR> pairs of the mediators that may credit the ticker."
+    )]
+    pub credit_mediators: Vec<(String, String)>,
+
+    /// Space separated list of `<TICKER>=<BITS>` pairs. The amounts of each of these tickers
+    /// are restricted to the given number of bits. Defaults to the full width of the amounts.
+    #[structopt(
+        long,
+        parse(try_from_str = parse_max_bits),
+        help = "Space separated list of <TICKER>=<BITS> pairs of the maximum bit-length of the ticker's amounts."
+    )]
+    pub max_bits: Vec<(String, u32)>,
+
     /// The directory that will serve as the database of the on/off-chain data and will be used
     /// to save and load the data that in a real execution would be written to the on/off the
     /// blockchain. Defaults to the current directory. This directory will have two main
@@ -21,6 +39,59 @@ pub struct CLI {
         long
     )]
     pub db_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
+    /// An optional subcommand. Sets up the chain from the arguments above if omitted.
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
+pub enum Command {
+    /// Create the asset registry, if it does not exist yet. An existing registry is left as it
+    /// is.
+    InitRegistry {
+        /// Space separated list of the ticker names of the new registry. Defaults to none.
+        #[structopt(short, long, help = "Space separated list of a ticker names.")]
+        ticker_names: Vec<String>,
+    },
+}
+
+fn parse_credit_mediator(pair: &str) -> Result<(String, String), String> {
+    let mut parts = pair.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(ticker), Some(mediator)) if !ticker.is_empty() && !mediator.is_empty() => {
+            Ok((ticker.to_string(), mediator.to_string()))
+        }
+        _ => Err(format!("Expected <TICKER>=<MEDIATOR>, got {}", pair)),
+    }
+}
+
+fn parse_max_bits(pair: &str) -> Result<(String, u32), String> {
+    let mut parts = pair.splitn(2, '=');
+    match (parts.next(), parts.next().map(str::parse::<u32>)) {
+        (Some(ticker), Some(Ok(max_bits))) if !ticker.is_empty() => {
+            Ok((ticker.to_string(), max_bits))
+        }
+        _ => Err(format!("Expected <TICKER>=<BITS>, got {}", pair)),
+    }
 }
 
 pub fn parse_input() -> Result<CLI, confy::ConfyError> {
@@ -31,6 +102,10 @@ pub fn parse_input() -> Result<CLI, confy::ConfyError> {
 
     Ok(CLI {
         ticker_names: args.ticker_names,
+        credit_mediators: args.credit_mediators,
+        max_bits: args.max_bits,
         db_dir,
+        on_chain_dir: args.on_chain_dir,
+        off_chain_dir: args.off_chain_dir,
     })
 }
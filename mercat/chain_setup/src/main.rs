@@ -1,22 +1,53 @@
 mod input;
 
 use env_logger;
-use input::parse_input;
+use input::{parse_input, Command};
 use log::info;
-use mercat_common::{chain_setup::process_asset_id_creation, errors::Error, init_print_logger};
+use mercat_common::{
+    chain_setup::{process_asset_id_creation, process_init_registry, set_ticker_max_bits},
+    errors::Error,
+    init_profiling_logger, install_panic_hook,
+    mediator_credit::authorize_credit_mediator,
+    ChainDirs,
+};
 use metrics::timing;
 use std::time::Instant;
 
 fn main() {
     env_logger::init();
     info!("Starting the program.");
-    init_print_logger();
+    let profile = init_profiling_logger();
+    install_panic_hook();
 
     let start = Instant::now();
     let args = parse_input().unwrap();
     timing!("chain_setup.argument_parse", start, Instant::now());
 
-    let db_dir = args.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap();
-    process_asset_id_creation(db_dir, args.ticker_names).unwrap();
+    let db_dir = ChainDirs::with_overrides(
+        args.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap(),
+        args.on_chain_dir,
+        args.off_chain_dir,
+    );
+    if let Some(Command::InitRegistry { ticker_names }) = args.cmd {
+        if process_init_registry(db_dir, ticker_names).unwrap() {
+            info!("Initialized the asset registry.");
+        } else {
+            info!("The asset registry is already initialized.");
+        }
+        if let Some(report) = profile.report_if_enabled() {
+            println!("{}", report);
+        }
+        return;
+    }
+    process_asset_id_creation(db_dir.clone(), args.ticker_names).unwrap();
+    for (ticker, mediator) in args.credit_mediators {
+        authorize_credit_mediator(db_dir.clone(), ticker, mediator).unwrap();
+    }
+    for (ticker, max_bits) in args.max_bits {
+        set_ticker_max_bits(db_dir.clone(), ticker, max_bits).unwrap();
+    }
+    if let Some(report) = profile.report_if_enabled() {
+        println!("{}", report);
+    }
     info!("The program finished successfully.");
 }
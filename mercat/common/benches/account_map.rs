@@ -0,0 +1,91 @@
+//! Measures the cost of creating an account as the number of existing accounts grows. Only the
+//! shard of the new account is read and rewritten, so the cost stays flat.
+
+use codec::Encode;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use cryptography::asset_proofs::CipherText;
+use curve25519_dalek::{constants::RISTRETTO_BASEPOINT_POINT, scalar::Scalar};
+use mercat_common::{
+    account_create::process_create_account, account_map_shard_file,
+    chain_setup::process_asset_id_creation, encoding::Encoding, gen_seed, save_to_file, ChainDirs,
+    PrintableAccountId, COMMON_OBJECTS_DIR, OFF_CHAIN_DIR,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+const TICKER: &str = "ACME";
+
+/// Writes the shards of an account map with `count` made-up accounts.
+fn populate_account_map(db_dir: &ChainDirs, count: u64) {
+    let mut shards: BTreeMap<String, HashMap<String, (String, String, u32)>> = BTreeMap::new();
+    for seed in 0..count {
+        let account_id = CipherText {
+            x: Scalar::from(seed + 1) * RISTRETTO_BASEPOINT_POINT,
+            y: Scalar::from(seed + 2) * RISTRETTO_BASEPOINT_POINT,
+        }
+        .encode();
+        shards
+            .entry(account_map_shard_file(&account_id))
+            .or_default()
+            .insert(
+                PrintableAccountId(account_id).to_string(),
+                (format!("user{}", seed), TICKER.to_string(), seed as u32),
+            );
+    }
+    for (shard_file, mapping) in shards {
+        save_to_file(
+            db_dir.clone(),
+            OFF_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &shard_file,
+            &mapping,
+        )
+        .unwrap();
+    }
+}
+
+fn bench_create_account(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create_account");
+    group.sample_size(20);
+    for count in &[0u64, 10_000, 100_000] {
+        let mut db_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        db_dir.push(format!("chain_dir/bench/account_map_{}", count));
+        let db_dir = ChainDirs::new(db_dir);
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+        process_asset_id_creation(db_dir.clone(), vec![TICKER.to_string()]).unwrap();
+        populate_account_map(&db_dir, *count);
+
+        let mut next_user = 0u32;
+        group.bench_with_input(BenchmarkId::from_parameter(count), count, |b, _| {
+            b.iter_batched(
+                || {
+                    next_user += 1;
+                    (gen_seed(), format!("new_user{}", next_user), next_user)
+                },
+                |(seed, user, tx_id)| {
+                    process_create_account(
+                        Some(seed),
+                        db_dir.clone(),
+                        TICKER.to_string(),
+                        user,
+                        false,
+                        Encoding::default(),
+                        tx_id,
+                        false,
+                        false,
+                        false,
+                    )
+                    .unwrap()
+                },
+                BatchSize::SmallInput,
+            )
+        });
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_create_account);
+criterion_main!(benches);
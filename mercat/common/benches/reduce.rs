@@ -0,0 +1,97 @@
+//! Measures the bookkeeping of the validation reduce on a batch of 10k transfers. The results
+//! are pre-sized and the accounts to update are collected without copying the names of every
+//! result, which the allocation counts printed before the timings show.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use mercat_common::{validate::accounts_to_update, Direction, ValidationResult};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::BTreeSet,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const TRANSFERS: u32 = 10_000;
+const USERS: u32 = 100;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let value = f();
+    (value, ALLOCATIONS.load(Ordering::Relaxed) - before)
+}
+
+/// The sender and the receiver update of every transfer, as pushed by `validate_all_pending`.
+fn push_results(mut results: Vec<ValidationResult>) -> Vec<ValidationResult> {
+    for tx_id in 0..TRANSFERS {
+        let sender = format!("user{}", tx_id % USERS);
+        let receiver = format!("user{}", (tx_id + 1) % USERS);
+        results.push(ValidationResult::new(
+            &sender,
+            "ACME",
+            Direction::Outgoing,
+            None,
+            tx_id,
+        ));
+        results.push(ValidationResult::new(
+            &receiver,
+            "ACME",
+            Direction::Incoming,
+            None,
+            tx_id,
+        ));
+    }
+    results
+}
+
+/// How the accounts to update were collected before, for comparison.
+fn accounts_to_update_by_set(results: &[ValidationResult]) -> BTreeSet<(String, String)> {
+    results
+        .iter()
+        .map(|result| (result.user().to_string(), result.ticker().to_string()))
+        .collect()
+}
+
+fn bench_reduce(c: &mut Criterion) {
+    let (_, unsized_allocations) = count_allocations(|| push_results(vec![]));
+    let (results, sized_allocations) =
+        count_allocations(|| push_results(Vec::with_capacity(2 * TRANSFERS as usize)));
+    let (_, set_allocations) = count_allocations(|| accounts_to_update_by_set(&results));
+    let (_, vec_allocations) = count_allocations(|| accounts_to_update(&results));
+    println!(
+        "results: {} allocations without capacity, {} with capacity",
+        unsized_allocations, sized_allocations
+    );
+    println!(
+        "accounts to update: {} allocations with a set, {} with a sorted vector",
+        set_allocations, vec_allocations
+    );
+
+    let mut group = c.benchmark_group("reduce");
+    group.bench_function("accounts_to_update_by_set", |b| {
+        b.iter(|| accounts_to_update_by_set(&results))
+    });
+    group.bench_function("accounts_to_update", |b| {
+        b.iter(|| accounts_to_update(&results))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_reduce);
+criterion_main!(benches);
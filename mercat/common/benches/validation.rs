@@ -0,0 +1,207 @@
+//! Compares the cost of validating a well-formed transfer with the cost of rejecting a transfer
+//! whose instruction is structurally invalid. The latter fails before any proof is verified.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use cryptography::mercat::{JustifiedTransferTx, TransferTxState, TxSubstate};
+use mercat_common::{
+    account_create::process_create_account,
+    account_issue::process_issue_asset,
+    account_transfer::{process_create_tx, process_finalize_tx},
+    chain_setup::process_asset_id_creation,
+    confidential_transaction_file, construct_path,
+    encoding::Encoding,
+    gen_seed,
+    justify::{justify_asset_transfer_transaction, process_create_mediator},
+    load_object_from, load_tx_file, save_object,
+    validate::{sender_pending_balance, validate_all_pending, validate_transaction},
+    verification_cache::VerificationCache,
+    ChainDirs, CoreTransaction, TransferInstruction, COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
+};
+use std::path::PathBuf;
+
+const TICKER: &str = "ACME";
+const SENDER: &str = "alice";
+const RECEIVER: &str = "bob";
+const MEDIATOR: &str = "mike";
+const TX_ID: u32 = 4;
+
+/// Creates the accounts, funds the sender, and justifies a transfer of 4 tokens.
+fn justified_transfer(db_dir: &ChainDirs) -> (JustifiedTransferTx, PathBuf) {
+    let _ = std::fs::remove_dir_all(&db_dir.root);
+    process_asset_id_creation(db_dir.clone(), vec![TICKER.to_string()]).unwrap();
+    process_create_mediator(
+        gen_seed(),
+        db_dir.clone(),
+        MEDIATOR.to_string(),
+        Encoding::default(),
+        false,
+    )
+    .unwrap();
+    for (tx_id, user) in &[(1, SENDER), (2, RECEIVER)] {
+        process_create_account(
+            Some(gen_seed()),
+            db_dir.clone(),
+            TICKER.to_string(),
+            user.to_string(),
+            false,
+            Encoding::default(),
+            *tx_id,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+    }
+    validate_all_pending(db_dir.clone()).unwrap();
+    process_issue_asset(
+        gen_seed(),
+        db_dir.clone(),
+        SENDER.to_string(),
+        TICKER.to_string(),
+        10,
+        false,
+        Encoding::default(),
+        3,
+        false,
+    )
+    .unwrap();
+    validate_all_pending(db_dir.clone()).unwrap();
+    process_create_tx(
+        gen_seed(),
+        db_dir.clone(),
+        SENDER.to_string(),
+        RECEIVER.to_string(),
+        MEDIATOR.to_string(),
+        TICKER.to_string(),
+        4,
+        false,
+        Encoding::default(),
+        TX_ID,
+        0,
+        false,
+    )
+    .unwrap();
+    process_finalize_tx(
+        gen_seed(),
+        db_dir.clone(),
+        SENDER.to_string(),
+        RECEIVER.to_string(),
+        TICKER.to_string(),
+        4,
+        false,
+        Encoding::default(),
+        TX_ID,
+        false,
+    )
+    .unwrap();
+    justify_asset_transfer_transaction(
+        db_dir.clone(),
+        SENDER.to_string(),
+        RECEIVER.to_string(),
+        MEDIATOR.to_string(),
+        TICKER.to_string(),
+        gen_seed(),
+        false,
+        Encoding::default(),
+        TX_ID,
+        None,
+        false,
+        false,
+    )
+    .unwrap();
+
+    let state = TransferTxState::Justification(TxSubstate::Started);
+    let file_name =
+        confidential_transaction_file(TX_ID, &MEDIATOR.to_string(), &TICKER.to_string(), state);
+    let path =
+        construct_path(db_dir.clone(), ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &file_name).unwrap();
+    let tx = match load_tx_file(
+        TX_ID,
+        MEDIATOR.to_string(),
+        state.to_string(),
+        path.to_str().unwrap().to_string(),
+    )
+    .unwrap()
+    {
+        CoreTransaction::TransferJustify { tx, .. } => tx,
+        other => panic!("Expected TransferJustify, got {:?}", other),
+    };
+    (tx, path)
+}
+
+/// Removes the validated instruction, so that every iteration validates the transfer again.
+fn remove_validated_instruction(db_dir: &ChainDirs) {
+    let state = TransferTxState::Justification(TxSubstate::Validated);
+    let file_name =
+        confidential_transaction_file(TX_ID, &SENDER.to_string(), &TICKER.to_string(), state);
+    let path =
+        construct_path(db_dir.clone(), ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &file_name).unwrap();
+    let _ = std::fs::remove_file(path);
+}
+
+fn bench_validate_transaction(c: &mut Criterion) {
+    let mut db_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    db_dir.push("chain_dir/bench/validation");
+    let db_dir = ChainDirs::new(db_dir);
+    let (tx, path) = justified_transfer(&db_dir);
+    let pending_balance = sender_pending_balance(
+        db_dir.clone(),
+        &SENDER.to_string(),
+        &TICKER.to_string(),
+        TX_ID,
+    )
+    .unwrap();
+
+    let mut group = c.benchmark_group("validate_transaction");
+    group.sample_size(10);
+    // An empty cache in every iteration, so that the proofs are always verified.
+    group.bench_function("valid", |b| {
+        b.iter_batched(
+            || remove_validated_instruction(&db_dir),
+            |_| {
+                validate_transaction(
+                    db_dir.clone(),
+                    tx.clone(),
+                    MEDIATOR.to_string(),
+                    pending_balance.clone(),
+                    TX_ID,
+                    &mut VerificationCache::default(),
+                    None,
+                )
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    // An instruction in the wrong state is rejected by the structural checks.
+    let mut instruction: TransferInstruction = load_object_from(path).unwrap();
+    instruction.state = TransferTxState::Finalization(TxSubstate::Started);
+    let state = TransferTxState::Justification(TxSubstate::Started);
+    save_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &confidential_transaction_file(TX_ID, &MEDIATOR.to_string(), &TICKER.to_string(), state),
+        &instruction,
+    )
+    .unwrap();
+    group.bench_function("wrong_state", |b| {
+        b.iter(|| {
+            validate_transaction(
+                db_dir.clone(),
+                tx.clone(),
+                MEDIATOR.to_string(),
+                pending_balance.clone(),
+                TX_ID,
+                &mut VerificationCache::default(),
+                None,
+            )
+        })
+    });
+    group.finish();
+
+    let _ = std::fs::remove_dir_all(&db_dir.root);
+}
+
+criterion_group!(benches, bench_validate_transaction);
+criterion_main!(benches);
@@ -0,0 +1,497 @@
+//! Closing an emptied account. The owner proves that the balance of the account encrypts zero,
+//! and the validator marks the account as closed once the proof verifies against the on-chain
+//! balance. No transfers are made from or to a closed account. The owner's secret account is
+//! only removed after the validator has accepted the closure.
+
+use crate::{
+    all_unverified_tx_files, construct_path, create_rng_from_seed,
+    discrete_log::is_zero,
+    errors::Error,
+    load_account_map, load_object, load_tx_file,
+    mediator_credit::{challenge, decompress},
+    parse_tx_name, remove_file, save_object,
+    secret_store::load_secret_object,
+    tx_balance_deltas, user_public_account_balance_file, user_public_account_file,
+    user_secret_account_file, ChainDirs, OrderedPubAccount, OFF_CHAIN_DIR, ON_CHAIN_DIR,
+};
+use codec::{Decode, Encode};
+use cryptography::mercat::{EncryptedAmount, SecAccount};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT, ristretto::RistrettoPoint, scalar::Scalar,
+};
+use log::{error, info};
+use rand::{CryptoRng, RngCore};
+
+const ZERO_BALANCE_LABEL: &[u8] = b"mercat-account-close-zero-balance";
+
+/// The owner's request to close the account, in the owner's on-chain directory.
+#[inline]
+pub fn account_closure_file(ticker: &str) -> String {
+    format!("{}_account_closure", ticker)
+}
+
+/// The closure that the validator has accepted, in the owner's on-chain directory.
+#[inline]
+pub fn account_closed_file(ticker: &str) -> String {
+    format!("{}_account_closed", ticker)
+}
+
+/// A proof that a balance `(x, y)` encrypts zero under `pub_key = s * g`. Since
+/// `y - s^-1 * x = amount * g`, the balance encrypts zero if and only if `x = s * y`. It proves
+/// the knowledge of the secret key `s`, with the same `s` in `pub_key = s * g` and `x = s * y`.
+/// Unlike a correctness proof, it does not need the randomness of the balance, which is the sum
+/// of the randomness of all the transactions of the account.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct ZeroBalanceProof {
+    pub base_commitment: [u8; 32],
+    pub balance_commitment: [u8; 32],
+    pub response: [u8; 32],
+}
+
+/// The request to close an account, with the balance that it was made for.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct AccountClosure {
+    pub enc_balance: EncryptedAmount,
+    pub proof: ZeroBalanceProof,
+}
+
+fn zero_balance_challenge(
+    pub_key: &RistrettoPoint,
+    enc_balance: &EncryptedAmount,
+    base_commitment: &[u8; 32],
+    balance_commitment: &[u8; 32],
+) -> Scalar {
+    challenge(
+        ZERO_BALANCE_LABEL,
+        &[
+            pub_key.compress().as_bytes(),
+            &enc_balance.encode(),
+            base_commitment,
+            balance_commitment,
+        ],
+    )
+}
+
+fn prove_zero_balance<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    secret: &Scalar,
+    pub_key: &RistrettoPoint,
+    enc_balance: &EncryptedAmount,
+) -> ZeroBalanceProof {
+    let nonce = Scalar::random(rng);
+    let base_commitment = (nonce * RISTRETTO_BASEPOINT_POINT).compress().to_bytes();
+    let balance_commitment = (nonce * enc_balance.y).compress().to_bytes();
+    let c = zero_balance_challenge(pub_key, enc_balance, &base_commitment, &balance_commitment);
+    ZeroBalanceProof {
+        base_commitment,
+        balance_commitment,
+        response: (nonce + c * secret).to_bytes(),
+    }
+}
+
+fn verify_zero_balance(pub_key: &RistrettoPoint, closure: &AccountClosure) -> bool {
+    let proof = &closure.proof;
+    let (base_commitment, balance_commitment) = match (
+        decompress(&proof.base_commitment),
+        decompress(&proof.balance_commitment),
+    ) {
+        (Some(base_commitment), Some(balance_commitment)) => (base_commitment, balance_commitment),
+        _ => return false,
+    };
+    let response = match Scalar::from_canonical_bytes(proof.response) {
+        Some(response) => response,
+        None => return false,
+    };
+    let enc_balance = &closure.enc_balance;
+    let c = zero_balance_challenge(
+        pub_key,
+        enc_balance,
+        &proof.base_commitment,
+        &proof.balance_commitment,
+    );
+    response * RISTRETTO_BASEPOINT_POINT == base_commitment + c * pub_key
+        && response * enc_balance.y == balance_commitment + c * enc_balance.x
+}
+
+fn is_closed(db_dir: ChainDirs, user: &str, ticker: &str) -> Result<bool, Error> {
+    Ok(construct_path(db_dir, ON_CHAIN_DIR, user, &account_closed_file(ticker))?.exists())
+}
+
+/// Fails with `AccountClosed` if the validator has accepted the closure of the account.
+pub fn ensure_not_closed(db_dir: ChainDirs, user: &str, ticker: &str) -> Result<(), Error> {
+    if is_closed(db_dir, user, ticker)? {
+        return Err(Error::AccountClosed {
+            user: user.to_string(),
+            ticker: ticker.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Requests the closure of the `user`'s `ticker` account, with a proof that its balance is zero.
+/// Fails with `AccountNotEmpty` otherwise. Once the validator has accepted the closure, calling
+/// it again removes the secret account. Returns true if the secret account was removed.
+pub fn process_close_account(
+    seed: String,
+    db_dir: ChainDirs,
+    user: String,
+    ticker: String,
+) -> Result<bool, Error> {
+    if is_closed(db_dir.clone(), &user, &ticker)? {
+        let secret_file = user_secret_account_file(&ticker);
+        if construct_path(db_dir.clone(), OFF_CHAIN_DIR, &user, &secret_file)?.exists() {
+            remove_file(db_dir, OFF_CHAIN_DIR, &user, &secret_file)?;
+        }
+        info!("CLI log: The account {}-{} is closed.", user, ticker);
+        return Ok(true);
+    }
+
+    let ordered_pub_account: OrderedPubAccount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        &user,
+        &user_public_account_file(&ticker),
+    )?;
+    let enc_balance: EncryptedAmount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        &user,
+        &user_public_account_balance_file(&ticker),
+    )?;
+    let secret: SecAccount =
+        load_secret_object(db_dir.clone(), &user, &user_secret_account_file(&ticker))?;
+    if !is_zero(&enc_balance, &secret.enc_keys.secret) {
+        return Err(Error::AccountNotEmpty { user, ticker });
+    }
+
+    let mut rng = create_rng_from_seed(Some(seed))?;
+    let proof = prove_zero_balance(
+        &mut rng,
+        &secret.enc_keys.secret.secret,
+        &ordered_pub_account.pub_account.owner_enc_pub_key.pub_key,
+        &enc_balance,
+    );
+    save_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        &user,
+        &account_closure_file(&ticker),
+        &AccountClosure { enc_balance, proof },
+    )?;
+    info!(
+        "CLI log: Requested the closure of {}-{}. Run the command again after the validation \
+         to remove the secret account.",
+        user, ticker
+    );
+    Ok(false)
+}
+
+/// Accepts the closure request of the `user`'s `ticker` account if its proof verifies against
+/// the on-chain balance and no pending transaction refers to the account. The request is
+/// removed in either case.
+pub fn validate_account_closure(
+    db_dir: ChainDirs,
+    user: &str,
+    ticker: &String,
+) -> Result<(), Error> {
+    let closure: AccountClosure = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        user,
+        &account_closure_file(ticker),
+    )?;
+    remove_file(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        user,
+        &account_closure_file(ticker),
+    )?;
+    let rejected = |reason: &str| Error::AccountClosureRejected {
+        user: user.to_string(),
+        ticker: ticker.clone(),
+        reason: reason.to_string(),
+    };
+
+    let ordered_pub_account: OrderedPubAccount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        user,
+        &user_public_account_file(ticker),
+    )?;
+    let enc_balance: EncryptedAmount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        user,
+        &user_public_account_balance_file(ticker),
+    )?;
+    if closure.enc_balance.encode() != enc_balance.encode() {
+        return Err(rejected("the balance changed after the request"));
+    }
+    if !verify_zero_balance(
+        &ordered_pub_account.pub_account.owner_enc_pub_key.pub_key,
+        &closure,
+    ) {
+        return Err(rejected("the zero balance proof does not verify"));
+    }
+
+    // An incoming transfer that is not validated yet would be stranded.
+    let account_id = ordered_pub_account.pub_account.enc_asset_id;
+    for tx_file in all_unverified_tx_files(db_dir.clone())? {
+        let tx = match parse_tx_name(tx_file)
+            .and_then(|(tx_id, user, state, path)| load_tx_file(tx_id, user, state, path))
+        {
+            Ok(tx) => tx,
+            Err(_) => continue,
+        };
+        if tx_balance_deltas(&tx)
+            .iter()
+            .any(|(id, _, _)| *id == account_id)
+        {
+            return Err(rejected("a pending transaction refers to the account"));
+        }
+    }
+
+    save_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        user,
+        &account_closed_file(ticker),
+        &closure,
+    )
+}
+
+/// Validates the closure requests of all the accounts. A rejected request is logged and
+/// removed, the owner may request the closure again.
+pub fn validate_pending_closures(db_dir: ChainDirs) -> Result<(), Error> {
+    for (_, (user, ticker, _)) in load_account_map(db_dir.clone()) {
+        if !construct_path(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &user,
+            &account_closure_file(&ticker),
+        )?
+        .exists()
+        {
+            continue;
+        }
+        match validate_account_closure(db_dir.clone(), &user, &ticker) {
+            Ok(()) => info!("Closed the account {}-{}.", user, ticker),
+            Err(error) => error!(
+                "Error in the validation of the closure of {}-{}: {:#?}",
+                user, ticker, error
+            ),
+        }
+    }
+    Ok(())
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account,
+        account_issue::process_issue_asset,
+        account_transfer::process_create_tx,
+        chain_setup::process_asset_id_creation,
+        debug_decrypt_account_balance,
+        encoding::Encoding,
+        gen_seed,
+        justify::process_create_mediator,
+        mediator_credit::{authorize_credit_mediator, process_mediator_credit},
+        test_utils::test_db_dir,
+        validate::validate_all_pending,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_close_account() {
+        let db_dir = test_db_dir("close_account");
+
+        let acme = String::from("ACME");
+        let (alice, bob, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        // The owner refuses to close a non-empty account.
+        match process_close_account(gen_seed(), db_dir.clone(), alice.clone(), acme.clone()) {
+            Err(Error::AccountNotEmpty { user, .. }) => assert_eq!(user, alice),
+            other => panic!("Expected AccountNotEmpty, got {:?}", other),
+        }
+
+        // A crafted request for a non-empty account does not verify.
+        let account: OrderedPubAccount = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_file(&acme),
+        )
+        .unwrap();
+        let enc_balance: EncryptedAmount = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_balance_file(&acme),
+        )
+        .unwrap();
+        let secret: SecAccount =
+            load_secret_object(db_dir.clone(), &alice, &user_secret_account_file(&acme)).unwrap();
+        let proof = prove_zero_balance(
+            &mut StdRng::from_seed([3u8; 32]),
+            &secret.enc_keys.secret.secret,
+            &account.pub_account.owner_enc_pub_key.pub_key,
+            &enc_balance,
+        );
+        save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &account_closure_file(&acme),
+            &AccountClosure { enc_balance, proof },
+        )
+        .unwrap();
+        match validate_account_closure(db_dir.clone(), &alice, &acme) {
+            Err(Error::AccountClosureRejected { user, .. }) => assert_eq!(user, alice),
+            other => panic!("Expected AccountClosureRejected, got {:?}", other),
+        }
+        ensure_not_closed(db_dir.clone(), &alice, &acme).unwrap();
+
+        // The empty account is closed once the validator accepts the request, and only then
+        // the secret account is removed.
+        assert!(
+            !process_close_account(gen_seed(), db_dir.clone(), bob.clone(), acme.clone()).unwrap()
+        );
+        ensure_not_closed(db_dir.clone(), &bob, &acme).unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        // No issuances or mediator credits to the closed account.
+        authorize_credit_mediator(db_dir.clone(), acme.clone(), mike.clone()).unwrap();
+        let issue_to_bob = |tx_id| {
+            process_issue_asset(
+                gen_seed(),
+                db_dir.clone(),
+                bob.clone(),
+                acme.clone(),
+                10,
+                false,
+                Encoding::default(),
+                tx_id,
+                false,
+            )
+        };
+        let credit_bob = |tx_id| {
+            process_mediator_credit(
+                gen_seed(),
+                db_dir.clone(),
+                mike.clone(),
+                bob.clone(),
+                acme.clone(),
+                5,
+                tx_id,
+            )
+        };
+        match issue_to_bob(4) {
+            Err(Error::AccountClosed { user, .. }) => assert_eq!(user, bob),
+            other => panic!("Expected AccountClosed, got {:?}", other),
+        }
+        match credit_bob(5) {
+            Err(Error::AccountClosed { user, .. }) => assert_eq!(user, bob),
+            other => panic!("Expected AccountClosed, got {:?}", other),
+        }
+        // The validator rejects them too, e.g., if they were submitted before the closure.
+        let closed_path = construct_path(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &bob,
+            &account_closed_file(&acme),
+        )
+        .unwrap();
+        let moved_path = closed_path.with_extension("moved");
+        std::fs::rename(&closed_path, &moved_path).unwrap();
+        issue_to_bob(4).unwrap();
+        credit_bob(5).unwrap();
+        std::fs::rename(&moved_path, &closed_path).unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(
+            debug_decrypt_account_balance(bob.clone(), acme.clone(), db_dir.clone()).unwrap(),
+            0
+        );
+
+        let secret_path = construct_path(
+            db_dir.clone(),
+            OFF_CHAIN_DIR,
+            &bob,
+            &user_secret_account_file(&acme),
+        )
+        .unwrap();
+        assert!(secret_path.exists());
+        assert!(
+            process_close_account(gen_seed(), db_dir.clone(), bob.clone(), acme.clone()).unwrap()
+        );
+        assert!(!secret_path.exists());
+
+        // No transfers to the closed account.
+        match process_create_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            6,
+            0,
+            false,
+        ) {
+            Err(Error::AccountClosed { user, .. }) => assert_eq!(user, bob),
+            other => panic!("Expected AccountClosed, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
@@ -1,83 +1,124 @@
+#[cfg(any(test, feature = "cheating"))]
+use crate::cheating;
 use crate::{
-    account_create_transaction_file, create_rng_from_seed, errors::Error, get_asset_ids,
-    non_empty_account_id, save_object, update_account_map, user_secret_account_file,
-    OrderedPubAccountTx, OrderingState, PrintableAccountId, COMMON_OBJECTS_DIR, OFF_CHAIN_DIR,
-    ON_CHAIN_DIR,
+    account_create_transaction_file, create_rng_from_seed, create_rng_from_subseed,
+    encoding::Encoding,
+    ensure_cheating_supported,
+    errors::Error,
+    get_asset_ids, load_from_file, save_object, save_to_file,
+    secret_store::{passphrase, save_secret_object},
+    update_account_map, user_secret_account_file, ChainDirs, OrderedPubAccountTx, OrderingState,
+    PrintableAccountId, BLINDING_REGISTRY_FILE, COMMON_OBJECTS_DIR, OFF_CHAIN_DIR, ON_CHAIN_DIR,
 };
-use base64;
 use codec::Encode;
 use cryptography::{
     asset_id_from_ticker,
     asset_proofs::{CommitmentWitness, ElgamalSecretKey},
     mercat::{
-        account::AccountCreator, AccountCreatorInitializer, EncryptedAssetId, EncryptionKeys,
-        SecAccount,
+        account::{AccountCreator, AccountValidator},
+        AccountCreatorInitializer, AccountCreatorVerifier, EncryptionKeys, SecAccount,
     },
 };
 use curve25519_dalek::scalar::Scalar;
-use log::{error, info};
+use log::info;
 use metrics::timing;
-use rand::{CryptoRng, Rng, RngCore};
-use std::{path::PathBuf, time::Instant};
+use rand::{rngs::StdRng, CryptoRng, RngCore};
+use sha3::{Digest, Sha3_256};
+use std::{collections::HashSet, time::Instant};
 
 pub fn process_create_account(
     seed: Option<String>,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     ticker: String,
     user: String,
     stdout: bool,
+    encoding: Encoding,
     tx_id: u32,
     cheat: bool,
+    detect_reuse: bool,
+    encrypt_secrets: bool,
 ) -> Result<(), Error> {
+    process_create_account_with(
+        seed,
+        db_dir,
+        ticker,
+        user,
+        stdout,
+        encoding,
+        tx_id,
+        cheat,
+        detect_reuse,
+        encrypt_secrets,
+        false,
+    )
+}
+
+/// Returns the rng of the account of `ticker`, from the subseed of the ticker. With
+/// `legacy_seed`, the seed is used as it is, as it was before the subseeds, to recover the
+/// accounts that were created that way.
+fn account_rng(seed: Option<String>, ticker: &str, legacy_seed: bool) -> Result<StdRng, Error> {
+    if legacy_seed {
+        create_rng_from_seed(seed)
+    } else {
+        create_rng_from_subseed(seed, ticker)
+    }
+}
+
+/// Creates the account like `process_create_account`. The keys are derived from the subseed of
+/// the ticker, or with `legacy_seed`, from the seed itself.
+pub fn process_create_account_with(
+    seed: Option<String>,
+    db_dir: ChainDirs,
+    ticker: String,
+    user: String,
+    stdout: bool,
+    encoding: Encoding,
+    tx_id: u32,
+    cheat: bool,
+    detect_reuse: bool,
+    encrypt_secrets: bool,
+    legacy_seed: bool,
+) -> Result<(), Error> {
+    ensure_cheating_supported(cheat)?;
+    let passphrase = if encrypt_secrets {
+        Some(passphrase()?)
+    } else {
+        None
+    };
+
     // Setup the rng.
-    let mut rng = create_rng_from_seed(seed)?;
+    let mut rng = account_rng(seed, &ticker, legacy_seed)?;
 
     // Create the account.
     let secret_account = create_secret_account(&mut rng, ticker.clone())?;
     let valid_asset_ids = get_asset_ids(db_dir.clone())?;
+    let blinding_registry = if detect_reuse {
+        Some(register_blinding(db_dir.clone(), &secret_account, tx_id)?)
+    } else {
+        None
+    };
 
     let create_account_timer = Instant::now();
     let account_creator = AccountCreator;
-    let mut account_tx = account_creator
+    let account_tx = account_creator
         .create(&secret_account, &valid_asset_ids, &mut rng)
         .map_err(|error| Error::LibraryError { error })?;
     timing!("account.call_library", create_account_timer, Instant::now(), "tx_id" => tx_id.to_string());
-    if cheat {
-        // To simplify the cheating selection process, we randomly choose a cheating strategy,
-        // instead of requiring the caller to know of all the different cheating strategies.
-        let n: u32 = rng.gen_range(0, 2);
-        match n {
-            0 => {
-                info!("CLI log: tx-{}: Cheating by overwriting the asset id of the account. Correct ticker: {} and asset id: {:?}",
-                      tx_id, ticker, secret_account.asset_id_witness.value());
-                let cheat_asset_id =
-                    asset_id_from_ticker("CHEAT").map_err(|error| Error::LibraryError { error })?;
-                let cheat_asset_id_witness =
-                    CommitmentWitness::new(cheat_asset_id.clone().into(), Scalar::random(&mut rng));
-                let cheat_enc_asset_id = secret_account
-                    .clone()
-                    .enc_keys
-                    .public
-                    .encrypt(&cheat_asset_id_witness);
-                account_tx.pub_account.enc_asset_id = EncryptedAssetId::from(cheat_enc_asset_id);
-            }
-            1 => {
-                info!("CLI log: tx-{}: Cheating by overwriting the account id. Correct account id: {}",
-                      tx_id, PrintableAccountId(account_tx.pub_account.enc_asset_id.encode()));
-                account_tx.pub_account.enc_asset_id += non_empty_account_id();
-            }
-            _ => error!("CLI log: tx-{}: This should never happen!", tx_id),
-        }
-    }
+    #[cfg(any(test, feature = "cheating"))]
+    let account_tx = if cheat {
+        cheating::cheat_on_account_creation(&mut rng, tx_id, &ticker, &secret_account, account_tx)?
+    } else {
+        account_tx
+    };
 
     // Save the artifacts to file.
     let save_to_file_timer = Instant::now();
-    save_object(
+    save_secret_object(
         db_dir.clone(),
-        OFF_CHAIN_DIR,
         &user,
         &user_secret_account_file(&ticker),
         &secret_account,
+        passphrase.as_deref(),
     )?;
 
     let account_id = account_tx.pub_account.enc_asset_id.clone();
@@ -96,20 +137,51 @@ pub fn process_create_account(
 
     if stdout {
         info!(
-            "CLI log: tx-{}: Transaction as base64:\n{}\n",
+            "CLI log: tx-{}: Transaction as {}:\n{}\n",
             tx_id,
-            base64::encode(instruction.account_tx.encode())
+            encoding,
+            encoding.encode(instruction.account_tx.encode())
+        );
+        info!(
+            "CLI log: tx-{}: Account id as {}:\n{}\n",
+            tx_id,
+            encoding,
+            PrintableAccountId(account_id.encode()).encode_with(encoding)
         );
     }
 
-    update_account_map(db_dir, user, ticker, account_id, tx_id)?;
+    update_account_map(db_dir.clone(), user, ticker, account_id, tx_id)?;
+    if let Some(registry) = blinding_registry {
+        save_blinding_registry(db_dir, &registry)?;
+    }
 
     timing!("account.save_output", save_to_file_timer, Instant::now(), "tx_id" => tx_id.to_string());
 
     Ok(())
 }
 
-fn create_secret_account<R: RngCore + CryptoRng>(
+/// Creates an account transaction and verifies it against the current asset registry, without
+/// writing anything to the `db_dir`. Returns the error that the creation or the validation
+/// would fail with, e.g., for a ticker that is not in the registry. The keys are derived as by
+/// `process_create_account_with` for the same `legacy_seed`.
+pub fn process_check_account_creation(
+    seed: Option<String>,
+    db_dir: ChainDirs,
+    ticker: String,
+    legacy_seed: bool,
+) -> Result<(), Error> {
+    let mut rng = account_rng(seed, &ticker, legacy_seed)?;
+    let secret_account = create_secret_account(&mut rng, ticker)?;
+    let valid_asset_ids = get_asset_ids(db_dir)?;
+    let account_tx = AccountCreator
+        .create(&secret_account, &valid_asset_ids, &mut rng)
+        .map_err(|error| Error::LibraryError { error })?;
+    AccountValidator {}
+        .verify(&account_tx, &valid_asset_ids)
+        .map_err(|error| Error::LibraryError { error })
+}
+
+pub(crate) fn create_secret_account<R: RngCore + CryptoRng>(
     rng: &mut R,
     ticker_id: String,
 ) -> Result<SecAccount, Error> {
@@ -129,3 +201,461 @@ fn create_secret_account<R: RngCore + CryptoRng>(
         asset_id_witness,
     })
 }
+
+/// Adds the hash of the asset id blinding factor of the new account to the registry, and fails
+/// if the same blinding factor has already been used by another account in `db_dir`. The
+/// returned registry is saved by `save_blinding_registry` once the account itself is saved.
+/// Only the hashes are stored, so the registry does not leak the blinding factors.
+fn register_blinding(
+    db_dir: ChainDirs,
+    secret_account: &SecAccount,
+    tx_id: u32,
+) -> Result<HashSet<String>, Error> {
+    let mut hasher = Sha3_256::new();
+    hasher.input(secret_account.asset_id_witness.blinding().as_bytes());
+    let blinding_hash = hex::encode(hasher.result());
+
+    // A missing registry is empty, but a registry that cannot be read is not replaced.
+    let mut registry: HashSet<String> = match load_from_file(
+        db_dir,
+        OFF_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        BLINDING_REGISTRY_FILE,
+    ) {
+        Err(Error::FileReadError { ref error, .. })
+            if error.kind() == std::io::ErrorKind::NotFound =>
+        {
+            HashSet::new()
+        }
+        result => result?,
+    };
+    if !registry.insert(blinding_hash) {
+        return Err(Error::RandomnessReuseDetected { tx_id });
+    }
+    Ok(registry)
+}
+
+fn save_blinding_registry(db_dir: ChainDirs, registry: &HashSet<String>) -> Result<(), Error> {
+    save_to_file(
+        db_dir,
+        OFF_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        BLINDING_REGISTRY_FILE,
+        registry,
+    )
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_issue::process_issue_asset,
+        account_map_shard_file, chain_dir,
+        chain_setup::process_asset_id_creation,
+        construct_path, debug_decrypt_account_balance, derive_subseed, gen_seed,
+        get_user_ticker_from, last_verified_tx_id, load_account_map, migrate_validation_state,
+        secret_store::load_secret_object,
+        test_utils::{test_db_dir, unittest_dir},
+        user_public_account_file,
+        validate::validate_all_pending,
+        verification_cache::VERIFICATION_CACHE_FILE,
+        ASSET_ID_LIST_FILE, LAST_VALIDATED_TX_ID_FILE, USER_ACCOUNT_MAP, VALIDATOR_DIR,
+    };
+    use cryptography::{asset_proofs::CipherText, mercat::EncryptedAssetId};
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    /// A broken rng that always produces the same bytes.
+    struct RepeatingRng;
+
+    impl RngCore for RepeatingRng {
+        fn next_u32(&mut self) -> u32 {
+            7
+        }
+        fn next_u64(&mut self) -> u64 {
+            7
+        }
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            for byte in dest.iter_mut() {
+                *byte = 7;
+            }
+        }
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for RepeatingRng {}
+
+    fn register(db_dir: ChainDirs, secret_account: &SecAccount, tx_id: u32) -> Result<(), Error> {
+        let registry = register_blinding(db_dir.clone(), secret_account, tx_id)?;
+        save_blinding_registry(db_dir, &registry)
+    }
+
+    #[test]
+    fn test_randomness_reuse_detection() {
+        let db_dir = test_db_dir("randomness_reuse");
+
+        // Accounts created with a healthy rng do not collide.
+        let mut rng = StdRng::from_seed([42u8; 32]);
+        let first = create_secret_account(&mut rng, "ACME".to_string()).unwrap();
+        let second = create_secret_account(&mut rng, "ACME".to_string()).unwrap();
+        register(db_dir.clone(), &first, 0).unwrap();
+        register(db_dir.clone(), &second, 1).unwrap();
+
+        // A broken rng repeats the blinding factor, which is caught on the second account.
+        let repeated = create_secret_account(&mut RepeatingRng, "ACME".to_string()).unwrap();
+        register(db_dir.clone(), &repeated, 2).unwrap();
+        let repeated = create_secret_account(&mut RepeatingRng, "TSLA".to_string()).unwrap();
+        match register(db_dir.clone(), &repeated, 3) {
+            Err(Error::RandomnessReuseDetected { tx_id }) => assert_eq!(tx_id, 3),
+            other => panic!("Expected RandomnessReuseDetected, got {:?}", other),
+        }
+
+        // A corrupt registry is reported instead of being replaced by an empty one.
+        let registry_path = construct_path(
+            db_dir.clone(),
+            OFF_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            BLINDING_REGISTRY_FILE,
+        )
+        .unwrap();
+        std::fs::write(&registry_path, "not json").unwrap();
+        match register(db_dir.clone(), &second, 4) {
+            Err(Error::ObjectDeserializationError { .. }) => {}
+            other => panic!("Expected ObjectDeserializationError, got {:?}", other),
+        }
+        assert_eq!(std::fs::read_to_string(&registry_path).unwrap(), "not json");
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_separate_chain_dirs() {
+        let base = unittest_dir("separate_chain_dirs");
+        let (on_chain_dir, off_chain_dir) = (base.join("shared/chain"), base.join("local/secrets"));
+        let db_dir = ChainDirs::with_overrides(
+            base.join("db"),
+            Some(on_chain_dir.clone()),
+            Some(off_chain_dir.clone()),
+        );
+
+        let (alice, acme) = (String::from("alice"), String::from("ACME"));
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_account(
+            Some(gen_seed()),
+            db_dir.clone(),
+            acme.clone(),
+            alice.clone(),
+            false,
+            Encoding::default(),
+            1,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            2,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(
+            debug_decrypt_account_balance(alice.clone(), acme.clone(), db_dir.clone()).unwrap(),
+            10
+        );
+
+        assert_eq!(chain_dir(db_dir.clone(), ON_CHAIN_DIR), on_chain_dir);
+        assert!(on_chain_dir
+            .join(&alice)
+            .join(user_public_account_file(&acme))
+            .exists());
+        assert!(off_chain_dir
+            .join(&alice)
+            .join(user_secret_account_file(&acme))
+            .exists());
+        // Nothing is written to the default sub-directories.
+        assert!(!db_dir.root.join(ON_CHAIN_DIR).exists());
+        assert!(!db_dir.root.join(OFF_CHAIN_DIR).exists());
+
+        let default_dirs = ChainDirs::new(db_dir.root.clone());
+        assert_eq!(
+            chain_dir(default_dirs, ON_CHAIN_DIR),
+            db_dir.root.join(ON_CHAIN_DIR)
+        );
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_account_map_migration() {
+        let db_dir = test_db_dir("account_map_migration");
+
+        let account_ids: Vec<EncryptedAssetId> = (1..=20u64)
+            .map(|seed| CipherText {
+                x: Scalar::from(seed) * RISTRETTO_BASEPOINT_POINT,
+                y: Scalar::from(seed + 1) * RISTRETTO_BASEPOINT_POINT,
+            })
+            .collect();
+        let legacy: HashMap<String, (String, String, u32)> = account_ids
+            .iter()
+            .enumerate()
+            .map(|(index, account_id)| {
+                (
+                    PrintableAccountId(account_id.encode()).to_string(),
+                    (format!("user{}", index), "ACME".to_string(), index as u32),
+                )
+            })
+            .collect();
+        save_to_file(
+            db_dir.clone(),
+            OFF_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            USER_ACCOUNT_MAP,
+            &legacy,
+        )
+        .unwrap();
+
+        // The first lookup moves the records into the shards, and removes the single file.
+        assert_eq!(
+            get_user_ticker_from(account_ids[3], db_dir.clone()).unwrap(),
+            ("user3".to_string(), "ACME".to_string(), 3)
+        );
+        let file_exists = |file_name: &str| {
+            construct_path(db_dir.clone(), OFF_CHAIN_DIR, COMMON_OBJECTS_DIR, file_name)
+                .unwrap()
+                .exists()
+        };
+        assert!(!file_exists(USER_ACCOUNT_MAP));
+        let shards: HashSet<String> = account_ids
+            .iter()
+            .map(|account_id| account_map_shard_file(&account_id.encode()))
+            .collect();
+        assert!(shards.len() > 1);
+        assert!(shards.iter().all(|shard| file_exists(shard)));
+        assert_eq!(load_account_map(db_dir.clone()), legacy);
+
+        // New records only go to the shard of their account.
+        update_account_map(
+            db_dir.clone(),
+            "user3".to_string(),
+            "TSLA".to_string(),
+            account_ids[3],
+            30,
+        )
+        .unwrap();
+        assert_eq!(
+            get_user_ticker_from(account_ids[3], db_dir.clone()).unwrap(),
+            ("user3".to_string(), "TSLA".to_string(), 30)
+        );
+        assert_eq!(
+            get_user_ticker_from(account_ids[4], db_dir.clone()).unwrap(),
+            ("user4".to_string(), "ACME".to_string(), 4)
+        );
+        assert_eq!(load_account_map(db_dir.clone()).len(), legacy.len());
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_validation_state_migration() {
+        let db_dir = test_db_dir("validation_state_migration");
+
+        // The earlier versions kept the account map on-chain, and the bookkeeping of the
+        // validator either on-chain or off-chain.
+        let shard = account_map_shard_file(&[7u8]);
+        for (on_off_chain, file_name) in &[
+            (ON_CHAIN_DIR, shard.as_str()),
+            (ON_CHAIN_DIR, VERIFICATION_CACHE_FILE),
+            (OFF_CHAIN_DIR, LAST_VALIDATED_TX_ID_FILE),
+            (ON_CHAIN_DIR, ASSET_ID_LIST_FILE),
+        ] {
+            save_to_file(
+                db_dir.clone(),
+                on_off_chain,
+                COMMON_OBJECTS_DIR,
+                file_name,
+                &3i32,
+            )
+            .unwrap();
+        }
+        let file_exists = |on_off_chain: &str, file_name: &str| {
+            construct_path(db_dir.clone(), on_off_chain, COMMON_OBJECTS_DIR, file_name)
+                .unwrap()
+                .exists()
+        };
+
+        // Reading the state does not move it.
+        assert_eq!(last_verified_tx_id(db_dir.clone()), -1);
+        assert!(file_exists(OFF_CHAIN_DIR, LAST_VALIDATED_TX_ID_FILE));
+
+        assert_eq!(migrate_validation_state(db_dir.clone()).unwrap(), 3);
+        assert!(file_exists(OFF_CHAIN_DIR, &shard) && !file_exists(ON_CHAIN_DIR, &shard));
+        assert!(file_exists(VALIDATOR_DIR, VERIFICATION_CACHE_FILE));
+        assert!(!file_exists(ON_CHAIN_DIR, VERIFICATION_CACHE_FILE));
+        assert!(file_exists(VALIDATOR_DIR, LAST_VALIDATED_TX_ID_FILE));
+        assert!(!file_exists(OFF_CHAIN_DIR, LAST_VALIDATED_TX_ID_FILE));
+        assert!(file_exists(ON_CHAIN_DIR, ASSET_ID_LIST_FILE));
+        assert_eq!(last_verified_tx_id(db_dir.clone()), 3);
+
+        // There is nothing left to move.
+        assert_eq!(migrate_validation_state(db_dir.clone()).unwrap(), 0);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_unsafe_name_components_are_refused() {
+        let base = unittest_dir("unsafe_names");
+        let db_dir = ChainDirs::new(base.join("db"));
+
+        let acme = String::from("ACME");
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        for user in &["../../etc", "alice/../../etc", "..", "alice\\bob"] {
+            match process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                1,
+                false,
+                false,
+                false,
+            ) {
+                Err(Error::UnsafeNameComponent { component }) => assert_eq!(&component, user),
+                other => panic!("Expected UnsafeNameComponent, got {:?}", other),
+            }
+        }
+        // Nothing escaped the on-chain and off-chain directories.
+        assert!(!base.join("etc").exists());
+        assert!(!db_dir.root.join("etc").exists());
+
+        match save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            "alice",
+            "../../escaped",
+            &1u32,
+        ) {
+            Err(Error::UnsafeNameComponent { component }) => assert_eq!(component, "../../escaped"),
+            other => panic!("Expected UnsafeNameComponent, got {:?}", other),
+        }
+        assert!(!base.join("escaped").exists());
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+    fn files_in(dir: &PathBuf) -> Vec<PathBuf> {
+        let mut files = vec![];
+        for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+            if entry.path().is_dir() {
+                files.extend(files_in(&entry.path()));
+            } else {
+                files.push(entry.path());
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_check_only_account_creation() {
+        let db_dir = test_db_dir("check_only_account_creation");
+
+        process_asset_id_creation(db_dir.clone(), vec![String::from("ACME")]).unwrap();
+        let files = files_in(&db_dir.root);
+
+        for legacy_seed in &[false, true] {
+            process_check_account_creation(
+                Some(gen_seed()),
+                db_dir.clone(),
+                String::from("ACME"),
+                *legacy_seed,
+            )
+            .unwrap();
+            // A ticker that is not in the asset registry.
+            assert!(process_check_account_creation(
+                Some(gen_seed()),
+                db_dir.clone(),
+                String::from("BETA"),
+                *legacy_seed,
+            )
+            .is_err());
+        }
+        assert_eq!(files_in(&db_dir.root), files);
+
+        std::fs::remove_dir_all(&db_dir.root).unwrap();
+    }
+
+    #[test]
+    fn test_subseeds_per_ticker() {
+        let db_dir = test_db_dir("subseeds_per_ticker");
+
+        let seed = gen_seed();
+        assert_eq!(derive_subseed(&seed, "ACME"), derive_subseed(&seed, "ACME"));
+        assert_ne!(derive_subseed(&seed, "ACME"), derive_subseed(&seed, "TSLA"));
+
+        let (alice, bob) = (String::from("alice"), String::from("bob"));
+        let tickers = vec![String::from("ACME"), String::from("TSLA")];
+        process_asset_id_creation(db_dir.clone(), tickers.clone()).unwrap();
+        let mut tx_id = 0;
+        for ticker in &tickers {
+            for (user, legacy_seed) in &[(&alice, false), (&bob, true)] {
+                tx_id += 1;
+                process_create_account_with(
+                    Some(seed.clone()),
+                    db_dir.clone(),
+                    ticker.clone(),
+                    user.to_string(),
+                    false,
+                    Encoding::default(),
+                    tx_id,
+                    false,
+                    false,
+                    false,
+                    *legacy_seed,
+                )
+                .unwrap();
+            }
+        }
+        let secret_key = |user: &str, ticker: &str| {
+            let secret: SecAccount =
+                load_secret_object(db_dir.clone(), user, &user_secret_account_file(ticker))
+                    .unwrap();
+            secret.enc_keys.secret.secret
+        };
+
+        // One master seed gives independent keys to the tickers, which are recovered from the
+        // subseed of each ticker.
+        assert_ne!(secret_key(&alice, "ACME"), secret_key(&alice, "TSLA"));
+        for ticker in &tickers {
+            let mut rng = StdRng::from_seed(derive_subseed(&seed, ticker));
+            let recovered = create_secret_account(&mut rng, ticker.clone()).unwrap();
+            assert_eq!(recovered.enc_keys.secret.secret, secret_key(&alice, ticker));
+        }
+
+        // The legacy scheme derives the keys from the seed itself, the same for each ticker.
+        let mut rng = create_rng_from_seed(Some(seed.clone())).unwrap();
+        let recovered = create_secret_account(&mut rng, String::from("ACME")).unwrap();
+        assert_eq!(recovered.enc_keys.secret.secret, secret_key(&bob, "ACME"));
+        assert_eq!(secret_key(&bob, "ACME"), secret_key(&bob, "TSLA"));
+
+        std::fs::remove_dir_all(&db_dir.root).unwrap();
+    }
+}
@@ -1,48 +1,100 @@
+#[cfg(any(test, feature = "cheating"))]
+use crate::cheating;
 use crate::{
-    asset_transaction_file, create_rng_from_seed, errors::Error, last_ordering_state, load_object,
-    save_object, user_public_account_file, user_secret_account_file, OrderedAssetInstruction,
-    OrderedPubAccount, OrderingState, COMMON_OBJECTS_DIR, OFF_CHAIN_DIR, ON_CHAIN_DIR,
+    account_close::ensure_not_closed, audit::record_state_transition,
+    chain_setup::ensure_amount_in_ticker_range, create_rng_from_seed, encoding::Encoding,
+    ensure_cheating_supported, errors::Error, last_ordering_state, last_verified_tx_id,
+    load_account_map, load_object, secret_store::load_secret_object, tx_state::save_instruction,
+    user_public_account_file, user_secret_account_file, ChainDirs, OrderedAssetInstruction,
+    OrderedPubAccount, OrderingState, COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
 };
-use base64;
 use codec::Encode;
-use cryptography::{
-    asset_id_from_ticker,
-    asset_proofs::CommitmentWitness,
-    mercat::{asset::AssetIssuer, Account, AssetTransactionIssuer, AssetTxState, TxSubstate},
+use cryptography::mercat::{
+    asset::AssetIssuer, Account, AssetTransactionIssuer, AssetTxState, TxSubstate,
 };
-use curve25519_dalek::scalar::Scalar;
 use log::info;
 use metrics::timing;
-use rand::Rng;
-use std::{path::PathBuf, time::Instant};
+use std::time::Instant;
 
 pub fn process_issue_asset(
     seed: String,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     issuer: String,
     ticker: String,
     amount: u32,
     stdout: bool,
+    encoding: Encoding,
     tx_id: u32,
     cheat: bool,
 ) -> Result<(), Error> {
-    let mut rng = create_rng_from_seed(Some(seed))?;
+    process_issue_asset_with(
+        seed, db_dir, issuer, ticker, amount, stdout, encoding, tx_id, cheat, false,
+    )
+}
 
-    let load_from_file_timer = Instant::now();
-    let issuer_ordered_pub_account: OrderedPubAccount = load_object(
+/// Returns the validated public account of the `issuer`, or `IssuanceToUnvalidatedAccount` if
+/// the creation of the account has not been validated. With `strict`, the creation must also be
+/// at or before the last validated tx_id.
+fn load_validated_issuer_account(
+    db_dir: ChainDirs,
+    issuer: &String,
+    ticker: &String,
+    tx_id: u32,
+    strict: bool,
+) -> Result<OrderedPubAccount, Error> {
+    let unvalidated = || Error::IssuanceToUnvalidatedAccount {
+        tx_id,
+        issuer: issuer.clone(),
+        ticker: ticker.clone(),
+    };
+    let creation_tx_id = load_account_map(db_dir.clone())
+        .into_iter()
+        .find(|(_, (user, account_ticker, _))| user == issuer && account_ticker == ticker)
+        .map(|(_, (_, _, creation_tx_id))| creation_tx_id)
+        .ok_or_else(unvalidated)?;
+    let account: OrderedPubAccount = load_object(
         db_dir.clone(),
         ON_CHAIN_DIR,
-        &issuer,
-        &user_public_account_file(&ticker),
-    )?;
+        issuer,
+        &user_public_account_file(ticker),
+    )
+    .map_err(|_| unvalidated())?;
+    if account.last_processed_tx_counter.is_none()
+        || (strict && creation_tx_id as i32 > last_verified_tx_id(db_dir))
+    {
+        return Err(unvalidated());
+    }
+    Ok(account)
+}
+
+/// Issues like `process_issue_asset`. With `strict`, the issuer's account creation must be at
+/// or before the last validated transaction, and not only have a validated public account.
+pub fn process_issue_asset_with(
+    seed: String,
+    db_dir: ChainDirs,
+    issuer: String,
+    ticker: String,
+    amount: u32,
+    stdout: bool,
+    encoding: Encoding,
+    tx_id: u32,
+    cheat: bool,
+    strict: bool,
+) -> Result<(), Error> {
+    ensure_cheating_supported(cheat)?;
+    if amount == 0 {
+        return Err(Error::ZeroIssuanceAmount { tx_id });
+    }
+    ensure_amount_in_ticker_range(db_dir.clone(), &ticker, amount)?;
+    ensure_not_closed(db_dir.clone(), &issuer, &ticker)?;
+    let mut rng = create_rng_from_seed(Some(seed))?;
+
+    let load_from_file_timer = Instant::now();
+    let issuer_ordered_pub_account =
+        load_validated_issuer_account(db_dir.clone(), &issuer, &ticker, tx_id, strict)?;
     let issuer_account = Account {
         public: issuer_ordered_pub_account.pub_account,
-        secret: load_object(
-            db_dir.clone(),
-            OFF_CHAIN_DIR,
-            &issuer,
-            &user_secret_account_file(&ticker),
-        )?,
+        secret: load_secret_object(db_dir.clone(), &issuer, &user_secret_account_file(&ticker))?,
     };
 
     timing!(
@@ -60,7 +112,7 @@ pub fn process_issue_asset(
         tx_id,
         db_dir.clone(),
     )?;
-    let next_pending_tx_counter = ordering_state.last_pending_tx_counter + 1;
+    let next_pending_tx_counter = ordering_state.next_pending_tx_counter(tx_id)?;
 
     timing!(
         "account.finalize_tx.calc_pending_state",
@@ -69,29 +121,14 @@ pub fn process_issue_asset(
         "tx_id" => tx_id.to_string()
     );
 
-    let mut amount = amount;
-    // To simplify the cheating selection process, we randomly choose a cheating strategy,
-    // instead of requiring the caller to know of all the different cheating strategies.
-    let cheating_strategy: u32 = rng.gen_range(1, 2); // TODO: CRYP-111: see below
-
-    // The first cheating strategies make changes to the input, while the 2nd one
-    // changes the output.
-    if cheat && cheating_strategy == 0 {
-        // TODO: CRYP-111: At the moment, this cheating is not detected since the mediator's
-        //       off-chain knowledge is not reflected in the MERACT calls. As a result, the
-        //       the mediator has no way of knowing whether the provided amount is what it
-        //       should be or not.
-        info!(
-            "CLI log: tx-{}: Cheating by changing the agreed upon amount. Correct amount: {}",
-            tx_id, amount
-        );
-        amount += 1
-    }
+    #[cfg(any(test, feature = "cheating"))]
+    let cheating_strategy =
+        cheating::random_strategy(&mut rng, cheating::CheatOperation::IssueAsset);
 
     // Initialize the asset issuance process.
     let issuance_init_timer = Instant::now();
     let ctx_issuer = AssetIssuer;
-    let mut asset_tx = ctx_issuer
+    let asset_tx = ctx_issuer
         .initialize_asset_transaction(&issuer_account, &[], amount, &mut rng)
         .map_err(|error| Error::LibraryError { error })?;
 
@@ -101,22 +138,12 @@ pub fn process_issue_asset(
         tx_id,
     };
 
-    if cheat && cheating_strategy == 1 {
-        info!("CLI log: tx-{}: Cheating by overwriting the encrypted issued amount. Correct ticker: {} and encrypted amount: {:?}",
-                      tx_id, ticker, &asset_tx.memo.enc_issued_amount);
-        let cheat_asset_id =
-            asset_id_from_ticker("CHEAT").map_err(|error| Error::LibraryError { error })?;
-        let cheat_asset_id_witness =
-            CommitmentWitness::new(cheat_asset_id.clone().into(), Scalar::random(&mut rng));
-        let cheat_enc_asset_id = issuer_account
-            .secret
-            .clone()
-            .enc_keys
-            .public
-            .encrypt(&cheat_asset_id_witness);
-
-        asset_tx.memo.enc_issued_amount = cheat_enc_asset_id;
-    }
+    #[cfg(any(test, feature = "cheating"))]
+    let asset_tx = if cheat && cheating_strategy == cheating::CheatStrategy::OverwriteIssuedAmount {
+        cheating::cheat_on_issued_amount(&mut rng, tx_id, &ticker, &issuer_account, asset_tx)?
+    } else {
+        asset_tx
+    };
     timing!(
         "account.issue_asset.init",
         issuance_init_timer,
@@ -133,19 +160,22 @@ pub fn process_issue_asset(
         amount,
     };
 
-    save_object(
-        db_dir,
-        ON_CHAIN_DIR,
+    save_instruction(
+        db_dir.clone(),
         COMMON_OBJECTS_DIR,
-        &asset_transaction_file(tx_id, &issuer, state),
+        tx_id,
+        &ticker,
+        &issuer,
         &instruction,
     )?;
+    record_state_transition(db_dir, tx_id, &issuer, None, state)?;
 
     if stdout {
         info!(
-            "CLI log: tx-{}: Transaction as base64:\n{}\n",
+            "CLI log: tx-{}: Transaction as {}:\n{}\n",
             tx_id,
-            base64::encode(asset_tx.encode())
+            encoding,
+            encoding.encode(asset_tx.encode())
         );
     }
 
@@ -158,3 +188,68 @@ pub fn process_issue_asset(
 
     Ok(())
 }
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account, chain_setup::process_asset_id_creation, gen_seed,
+        test_utils::test_db_dir, validate::validate_all_pending,
+    };
+
+    #[test]
+    fn test_issuance_to_unvalidated_account() {
+        let db_dir = test_db_dir("issuance_to_unvalidated_account");
+
+        let acme = String::from("ACME");
+        let alice = String::from("alice");
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_account(
+            Some(gen_seed()),
+            db_dir.clone(),
+            acme.clone(),
+            alice.clone(),
+            false,
+            Encoding::default(),
+            1,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+        let issue = |tx_id: u32, strict: bool| {
+            process_issue_asset_with(
+                gen_seed(),
+                db_dir.clone(),
+                alice.clone(),
+                acme.clone(),
+                10,
+                false,
+                Encoding::default(),
+                tx_id,
+                false,
+                strict,
+            )
+        };
+
+        for strict in &[false, true] {
+            match issue(2, *strict) {
+                Err(Error::IssuanceToUnvalidatedAccount {
+                    tx_id,
+                    issuer,
+                    ticker,
+                }) => assert_eq!((tx_id, issuer, ticker), (2, alice.clone(), acme.clone())),
+                other => panic!("Expected IssuanceToUnvalidatedAccount, got {:?}", other),
+            }
+        }
+
+        validate_all_pending(db_dir.clone()).unwrap();
+        issue(2, true).unwrap();
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
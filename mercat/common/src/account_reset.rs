@@ -0,0 +1,255 @@
+//! Recovery of an account whose pending transfers are stuck, e.g., because the mediator never
+//! justifies them. The account is realigned with the validated on-chain state.
+
+use crate::{
+    all_unverified_tx_files, errors::Error, last_verified_tx_id, load_object, load_tx_file,
+    parse_tx_name, remove_file, save_object, user_public_account_file, ChainDirs, CoreTransaction,
+    OrderedPubAccount, COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
+};
+use cryptography::mercat::{TransferTxState, TxSubstate};
+use log::info;
+use std::{collections::BTreeSet, path::Path};
+
+/// Returns true if the instruction in the `state` belongs to a transfer that is not finished.
+fn is_pending_state(state: &str) -> bool {
+    state == TransferTxState::Initialization(TxSubstate::Started).to_string()
+        || state == TransferTxState::Finalization(TxSubstate::Started).to_string()
+        || state == TransferTxState::Justification(TxSubstate::Started).to_string()
+}
+
+/// Removes the un-validated transfers sent from the `user`'s `ticker` account, together with
+/// the receiver's and the mediator's instructions of those transfers. The validated and the
+/// rejected instructions are never removed. Without the pending transfers, the ordering state
+/// of the next transaction is taken from the validated account, whose counter is moved up to
+/// the last validated transaction. Returns the paths of the removed files, ordered by their
+/// transaction id.
+pub fn process_reset_account(
+    db_dir: ChainDirs,
+    user: String,
+    ticker: String,
+) -> Result<Vec<String>, Error> {
+    let mut ordered_pub_account: OrderedPubAccount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        &user,
+        &user_public_account_file(&ticker),
+    )?;
+    let account_id = ordered_pub_account.pub_account.enc_asset_id;
+
+    let mut pending = vec![];
+    for tx_file in all_unverified_tx_files(db_dir.clone())? {
+        let (tx_id, tx_user, state, tx_file_path) = parse_tx_name(tx_file)?;
+        if is_pending_state(&state) {
+            pending.push((tx_id, tx_user, state, tx_file_path));
+        }
+    }
+
+    let mut stuck = BTreeSet::new();
+    for (tx_id, tx_user, state, tx_file_path) in pending.iter().cloned() {
+        let sender_account_id = match load_tx_file(tx_id, tx_user, state, tx_file_path)? {
+            CoreTransaction::TransferInit { tx, .. } => tx.memo.sender_account_id,
+            CoreTransaction::TransferFinalize { tx, .. } => tx.init_data.memo.sender_account_id,
+            CoreTransaction::TransferJustify { tx, .. } => {
+                tx.finalized_data.init_data.memo.sender_account_id
+            }
+            _ => continue,
+        };
+        if sender_account_id == account_id {
+            stuck.insert(tx_id);
+        }
+    }
+
+    let mut removed = vec![];
+    for (tx_id, _, _, tx_file_path) in pending {
+        if !stuck.contains(&tx_id) {
+            continue;
+        }
+        let file_name = Path::new(&tx_file_path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or(Error::PathBufConversionError)?;
+        remove_file(db_dir.clone(), ON_CHAIN_DIR, COMMON_OBJECTS_DIR, file_name)?;
+        info!("tx-{}: Removed {}", tx_id, tx_file_path);
+        removed.push((tx_id, tx_file_path));
+    }
+    removed.sort();
+
+    let last_validated = last_verified_tx_id(db_dir.clone());
+    if last_validated >= 0 {
+        let last_validated = Some(last_validated as u32);
+        if ordered_pub_account.last_processed_tx_counter < last_validated {
+            ordered_pub_account.last_processed_tx_counter = last_validated;
+            save_object(
+                db_dir,
+                ON_CHAIN_DIR,
+                &user,
+                &user_public_account_file(&ticker),
+                &ordered_pub_account,
+            )?;
+        }
+    }
+
+    Ok(removed
+        .into_iter()
+        .map(|(_, tx_file_path)| tx_file_path)
+        .collect())
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account,
+        account_issue::process_issue_asset,
+        account_transfer::{process_create_tx, process_finalize_tx},
+        chain_setup::process_asset_id_creation,
+        debug_decrypt_account_balance,
+        encoding::Encoding,
+        gen_seed,
+        justify::{justify_asset_transfer_transaction, process_create_mediator},
+        pending_for_account,
+        test_utils::test_db_dir,
+        validate::validate_all_pending,
+    };
+
+    #[test]
+    fn test_reset_account() {
+        let db_dir = test_db_dir("reset_account");
+
+        let acme = String::from("ACME");
+        let alice = String::from("alice");
+        let bob = String::from("bob");
+        let mike = String::from("mike");
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        let create_tx = |amount: u32, tx_id: u32| {
+            process_create_tx(
+                gen_seed(),
+                db_dir.clone(),
+                alice.clone(),
+                bob.clone(),
+                mike.clone(),
+                acme.clone(),
+                amount,
+                false,
+                Encoding::default(),
+                tx_id,
+                0,
+                false,
+            )
+        };
+
+        // The transfer is finalized by bob, but never justified by the mediator.
+        create_tx(4, 4).unwrap();
+        process_finalize_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            4,
+            false,
+        )
+        .unwrap();
+        // Its amount is pending, so the rest of the balance cannot cover the full amount.
+        assert!(create_tx(10, 5).is_err());
+
+        let removed = process_reset_account(db_dir.clone(), alice.clone(), acme.clone()).unwrap();
+        assert_eq!(removed.len(), 2);
+        for tx_file in removed {
+            assert_eq!(parse_tx_name(tx_file).unwrap().0, 4);
+        }
+        assert!(pending_for_account(db_dir.clone(), &alice, &acme)
+            .unwrap()
+            .is_empty());
+        // Bob has nothing pending, so there is nothing to reset.
+        assert!(
+            process_reset_account(db_dir.clone(), bob.clone(), acme.clone())
+                .unwrap()
+                .is_empty()
+        );
+
+        // The confirmed balance can be spent in full again.
+        create_tx(10, 5).unwrap();
+        process_finalize_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            5,
+            false,
+        )
+        .unwrap();
+        justify_asset_transfer_transaction(
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            gen_seed(),
+            false,
+            Encoding::default(),
+            5,
+            None,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        let balance = |user: &String| {
+            debug_decrypt_account_balance(user.clone(), acme.clone(), db_dir.clone()).unwrap()
+        };
+        assert_eq!(balance(&alice), 0);
+        assert_eq!(balance(&bob), 10);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
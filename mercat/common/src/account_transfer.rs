@@ -1,35 +1,150 @@
+#[cfg(any(test, feature = "cheating"))]
+use crate::cheating;
 use crate::{
-    compute_enc_pending_balance, confidential_transaction_file, construct_path,
-    create_rng_from_seed, debug_decrypt, errors::Error, last_ordering_state, load_object,
-    non_empty_account_id, save_object, user_public_account_balance_file, user_public_account_file,
-    user_secret_account_file, OrderedPubAccount, OrderedTransferInstruction, OrderingState,
-    PrintableAccountId, COMMON_OBJECTS_DIR, MEDIATOR_PUBLIC_ACCOUNT_FILE, OFF_CHAIN_DIR,
-    ON_CHAIN_DIR,
+    account_close::ensure_not_closed,
+    audit::record_state_transition,
+    balances::{compute_enc_pending_balance, ConfirmedBalance},
+    chain_setup::ensure_amount_in_ticker_range,
+    confidential_transaction_file, construct_path, create_rng_from_seed, debug_decrypt,
+    discrete_log::is_zero,
+    encoding::Encoding,
+    ensure_cheating_supported,
+    errors::Error,
+    get_user_ticker_from, last_ordering_state, load_object, load_object_from, remove_file,
+    save_object,
+    secret_store::load_secret_object,
+    sent_record_file,
+    tx_state::{current_tx_state, save_instruction, TxState},
+    user_public_account_balance_file, user_public_account_file, user_secret_account_file,
+    ChainDirs, OrderedPubAccount, OrderedTransferInstruction, OrderingState, COMMON_OBJECTS_DIR,
+    MEDIATOR_PUBLIC_ACCOUNT_FILE, OFF_CHAIN_DIR, ON_CHAIN_DIR,
 };
-use base64;
 use codec::{Decode, Encode};
 use cryptography::mercat::{
     transaction::{CtxReceiver, CtxSender},
-    Account, EncryptedAmount, EncryptionPubKey, InitializedTransferTx, PubAccount,
-    TransferTransactionReceiver, TransferTransactionSender, TransferTxState, TxSubstate,
+    Account, EncryptedAmount, EncryptedAssetId, EncryptionPubKey, FinalizedTransferTx,
+    InitializedTransferTx, PubAccount, SecAccount, TransferTransactionReceiver,
+    TransferTransactionSender, TransferTxState, TxSubstate,
 };
 use log::{debug, info};
 use metrics::timing;
-use rand::Rng;
-use std::{path::PathBuf, time::Instant};
+use std::time::Instant;
+
+/// The sender's own copy of a transfer. The amount in the memo of the transfer is encrypted
+/// both under the sender's and the receiver's key, and the transfer carries the proof that the
+/// two encrypt the same amount. Keeping the whole transfer lets the sender show the amount it
+/// sent, even after the on-chain instructions are compacted.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct SentRecord {
+    pub receiver: String,
+    pub tx: InitializedTransferTx,
+}
+
+/// Checks that the loaded init data is a transfer from `sender` to `receiver`, so that the
+/// receiver does not bind itself to another transfer that was stored under the same name.
+fn ensure_init_matches(
+    tx_id: u32,
+    tx: &InitializedTransferTx,
+    sender: &PubAccount,
+    receiver: &PubAccount,
+) -> Result<(), Error> {
+    if tx.memo.sender_account_id != sender.enc_asset_id {
+        return Err(Error::FinalizeInitMismatch {
+            tx_id,
+            party: String::from("sender"),
+        });
+    }
+    if tx.memo.receiver_account_id != receiver.enc_asset_id {
+        return Err(Error::FinalizeInitMismatch {
+            tx_id,
+            party: String::from("receiver"),
+        });
+    }
+    Ok(())
+}
+
+/// Selects the sender and the receiver of a transfer, either by their user names and the
+/// ticker, or by their account ids, in the encoding of the CLI.
+#[derive(Clone, Debug)]
+pub enum TransferParties {
+    ByName {
+        sender: String,
+        receiver: String,
+        ticker: String,
+    },
+    ByAccountId {
+        sender_account_id: String,
+        receiver_account_id: String,
+    },
+}
+
+impl TransferParties {
+    /// Returns the (sender, receiver, ticker) of the transfer. When selected by account ids,
+    /// which are decoded with `encoding`, both accounts must belong to the same ticker.
+    pub fn resolve(
+        self,
+        db_dir: ChainDirs,
+        encoding: Encoding,
+    ) -> Result<(String, String, String), Error> {
+        match self {
+            TransferParties::ByName {
+                sender,
+                receiver,
+                ticker,
+            } => Ok((sender, receiver, ticker)),
+            TransferParties::ByAccountId {
+                sender_account_id,
+                receiver_account_id,
+            } => {
+                let (sender, sender_ticker) =
+                    user_ticker_from_printable_id(&sender_account_id, encoding, db_dir.clone())?;
+                let (receiver, receiver_ticker) =
+                    user_ticker_from_printable_id(&receiver_account_id, encoding, db_dir)?;
+                if sender_ticker != receiver_ticker {
+                    return Err(Error::TickerMismatch {
+                        sender_ticker,
+                        receiver_ticker,
+                    });
+                }
+                Ok((sender, receiver, sender_ticker))
+            }
+        }
+    }
+}
+
+fn user_ticker_from_printable_id(
+    account_id: &str,
+    encoding: Encoding,
+    db_dir: ChainDirs,
+) -> Result<(String, String), Error> {
+    let not_found = || Error::AccountNotFound {
+        account_id: account_id.to_string(),
+    };
+    let bytes = encoding.decode(account_id).map_err(|_| not_found())?;
+    let enc_asset_id = EncryptedAssetId::decode(&mut &bytes[..]).map_err(|_| not_found())?;
+    let (user, ticker, _tx_id) =
+        get_user_ticker_from(enc_asset_id, db_dir).map_err(|_| not_found())?;
+    Ok((user, ticker))
+}
 
 pub fn process_create_tx(
     seed: String,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     sender: String,
     receiver: String,
     mediator: String,
     ticker: String,
     amount: u32,
     stdout: bool,
+    encoding: Encoding,
     tx_id: u32,
+    min_confirmations: u32,
     cheat: bool,
 ) -> Result<(), Error> {
+    ensure_cheating_supported(cheat)?;
+    ensure_amount_in_ticker_range(db_dir.clone(), &ticker, amount)?;
+    ensure_not_closed(db_dir.clone(), &sender, &ticker)?;
+    ensure_not_closed(db_dir.clone(), &receiver, &ticker)?;
     let mut rng = create_rng_from_seed(Some(seed))?;
     let load_from_file_timer = Instant::now();
 
@@ -46,12 +161,7 @@ pub fn process_create_tx(
         &user_public_account_balance_file(&ticker),
     )?;
     let sender_account = Account {
-        secret: load_object(
-            db_dir.clone(),
-            OFF_CHAIN_DIR,
-            &sender,
-            &user_secret_account_file(&ticker),
-        )?,
+        secret: load_secret_object(db_dir.clone(), &sender, &user_secret_account_file(&ticker))?,
         public: sender_ordered_pub_account.pub_account,
     };
 
@@ -79,7 +189,7 @@ pub fn process_create_tx(
     // Calculate the pending
     let calc_pending_state_timer = Instant::now();
     let last_processed_tx_counter = sender_ordered_pub_account.last_processed_tx_counter;
-    let last_processed_account_balance = sender_account_balance;
+    let last_processed_account_balance = ConfirmedBalance(sender_account_balance);
     let ordering_state = last_ordering_state(
         sender.clone(),
         last_processed_tx_counter,
@@ -89,21 +199,60 @@ pub fn process_create_tx(
 
     let pending_balance = compute_enc_pending_balance(
         &sender,
+        &ticker,
         ordering_state.clone(),
         last_processed_tx_counter,
-        last_processed_account_balance,
+        last_processed_account_balance.clone(),
+        0,
         db_dir.clone(),
     )?;
+    // Without a balance, the range proof of the transfer is bound to fail.
+    if is_zero(
+        pending_balance.enc_amount(),
+        &sender_account.secret.enc_keys.secret,
+    ) {
+        return Err(Error::NoBalanceForTicker {
+            user: sender,
+            ticker,
+        });
+    }
+    // The proofs are made against the full pending balance, which is what the mediator and the
+    // validator recompute. The confirmation window only limits how much of it can be spent.
+    if min_confirmations > 0 {
+        let spendable = compute_enc_pending_balance(
+            &sender,
+            &ticker,
+            ordering_state.clone(),
+            last_processed_tx_counter,
+            last_processed_account_balance,
+            min_confirmations,
+            db_dir.clone(),
+        )?;
+        let spendable = sender_account
+            .secret
+            .enc_keys
+            .secret
+            .decrypt(spendable.enc_amount())
+            .map_err(|error| Error::LibraryError { error })?;
+        if amount > spendable {
+            return Err(Error::UnconfirmedFunds {
+                tx_id,
+                amount,
+                spendable,
+                min_confirmations,
+            });
+        }
+    }
     debug!(
         "------------> initiating transfer tx: {}, pending_balance: {}",
         tx_id,
         debug_decrypt(
             sender_account.public.enc_asset_id,
-            pending_balance.clone(),
+            pending_balance.enc_amount().clone(),
             db_dir.clone()
         )?
     );
-    let next_pending_tx_counter = ordering_state.last_pending_tx_counter + 1;
+    let next_pending_tx_counter = ordering_state.next_pending_tx_counter(tx_id)?;
 
     timing!(
         "account.create_tx.calc_pending_state",
@@ -112,20 +261,18 @@ pub fn process_create_tx(
         "tx_id" => tx_id.to_string()
     );
 
-    let mut amount = amount;
-    // To simplify the cheating selection process, we randomly choose a cheating strategy,
-    // instead of requiring the caller to know of all the different cheating strategies.
-    let cheating_strategy: u32 = rng.gen_range(0, 2);
+    #[cfg(any(test, feature = "cheating"))]
+    let cheating_strategy =
+        cheating::random_strategy(&mut rng, cheating::CheatOperation::CreateTransaction);
 
     // The first cheating strategies make changes to the input, while the subsequent ones
     // changes the output.
-    if cheat && cheating_strategy == 0 {
-        info!(
-            "CLI log: tx-{}: Cheating by changing the agreed upon amount. Correct amount: {}",
-            tx_id, amount
-        );
-        amount += 1
-    }
+    #[cfg(any(test, feature = "cheating"))]
+    let amount = if cheat && cheating_strategy == cheating::CheatStrategy::ChangeAmount {
+        cheating::cheat_on_amount(tx_id, amount)
+    } else {
+        amount
+    };
 
     // Initialize the transaction.
     let create_tx_timer = Instant::now();
@@ -137,10 +284,10 @@ pub fn process_create_tx(
             owner_enc_pub_key: sender_account.public.owner_enc_pub_key,
         },
     };
-    let mut asset_tx = ctx_sender
+    let asset_tx = ctx_sender
         .create_transaction(
             &pending_account,
-            &pending_balance,
+            pending_balance.enc_amount(),
             &receiver_account.pub_account,
             &mediator_account,
             &[],
@@ -156,14 +303,12 @@ pub fn process_create_tx(
     };
     timing!("account.create_tx.create", create_tx_timer, Instant::now());
 
-    if cheat && cheating_strategy == 1 {
-        info!(
-            "CLI log: tx-{}: Cheating by changing the sender's account id. Correct account id: {}",
-            tx_id,
-            PrintableAccountId(pending_account.public.enc_asset_id.encode())
-        );
-        asset_tx.memo.sender_account_id += non_empty_account_id();
-    }
+    #[cfg(any(test, feature = "cheating"))]
+    let asset_tx = if cheat && cheating_strategy == cheating::CheatStrategy::ChangeSenderAccountId {
+        cheating::cheat_on_sender_account_id(tx_id, &pending_account.public, asset_tx)
+    } else {
+        asset_tx
+    };
 
     // Save the artifacts to file.
     let new_state = TransferTxState::Initialization(TxSubstate::Started);
@@ -174,22 +319,36 @@ pub fn process_create_tx(
         data: asset_tx.encode().to_vec(),
     };
 
-    save_object(
-        db_dir,
-        ON_CHAIN_DIR,
+    save_instruction(
+        db_dir.clone(),
         COMMON_OBJECTS_DIR,
-        &confidential_transaction_file(tx_id, &sender, new_state),
+        tx_id,
+        &ticker,
+        &sender,
         &instruction,
     )?;
+    record_state_transition(db_dir.clone(), tx_id, &sender, None, new_state)?;
 
     if stdout {
         info!(
-            "CLI log: tx-{}: Transaction as base64:\n{}\n",
+            "CLI log: tx-{}: Transaction as {}:\n{}\n",
             tx_id,
-            base64::encode(asset_tx.encode())
+            encoding,
+            encoding.encode(asset_tx.encode())
         );
     }
 
+    save_object(
+        db_dir,
+        OFF_CHAIN_DIR,
+        &sender,
+        &sent_record_file(tx_id, &ticker),
+        &SentRecord {
+            receiver,
+            tx: asset_tx,
+        },
+    )?;
+
     timing!(
         "account.create_tx.save_to_file",
         save_to_file_timer,
@@ -199,17 +358,110 @@ pub fn process_create_tx(
     Ok(())
 }
 
+/// Decrypts the amount that the `sender` sent in the transfer `tx_id` from its own record of
+/// the transfer. Returns the receiver and the amount.
+pub fn process_query_sent(
+    db_dir: ChainDirs,
+    sender: String,
+    ticker: String,
+    tx_id: u32,
+) -> Result<(String, u32), Error> {
+    let record: SentRecord = load_object(
+        db_dir.clone(),
+        OFF_CHAIN_DIR,
+        &sender,
+        &sent_record_file(tx_id, &ticker),
+    )?;
+    let secret_account: SecAccount =
+        load_secret_object(db_dir, &sender, &user_secret_account_file(&ticker))?;
+    let amount = secret_account
+        .enc_keys
+        .secret
+        .decrypt(&record.tx.memo.enc_amount_using_sender)
+        .map_err(|error| Error::LibraryError { error })?;
+    Ok((record.receiver, amount))
+}
+
+/// Checks for a finalization of the transfer `tx_id` that is already on disk. Returns true if
+/// there is a complete one, so that there is nothing left to do. An incomplete one, e.g., from
+/// an interrupted write, fails with `Error::PartialFinalizeDetected`. With `force_refinalize`,
+/// the existing finalization is removed, whether it is complete or not.
+fn check_existing_finalization(
+    db_dir: ChainDirs,
+    tx_id: u32,
+    sender: &String,
+    ticker: &String,
+    force_refinalize: bool,
+) -> Result<bool, Error> {
+    let state = TransferTxState::Finalization(TxSubstate::Started);
+    match current_tx_state(db_dir.clone(), tx_id, ticker)? {
+        Some(TxState::Transfer(current)) if current == state => {}
+        // Either there is nothing to resume, or the transfer has moved on and saving the
+        // finalization fails with `Error::IllegalStateTransition`.
+        _ => return Ok(false),
+    }
+    let file_name = confidential_transaction_file(tx_id, sender, ticker, state);
+    let path = construct_path(db_dir.clone(), ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &file_name)?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    let is_complete = load_object_from::<OrderedTransferInstruction>(path.clone())
+        .map(|instruction| {
+            instruction.state == state
+                && FinalizedTransferTx::decode(&mut &instruction.data[..]).is_ok()
+        })
+        .unwrap_or(false);
+    if force_refinalize {
+        info!("tx-{}: Removing the finalization {:?}.", tx_id, path);
+        remove_file(db_dir, ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &file_name)?;
+        Ok(false)
+    } else if is_complete {
+        info!("tx-{}: The transfer is already finalized.", tx_id);
+        Ok(true)
+    } else {
+        Err(Error::PartialFinalizeDetected { tx_id, path })
+    }
+}
+
 pub fn process_finalize_tx(
     seed: String,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     sender: String,
     receiver: String,
     ticker: String,
     amount: u32,
     stdout: bool,
+    encoding: Encoding,
     tx_id: u32,
     cheat: bool,
 ) -> Result<(), Error> {
+    process_finalize_tx_with(
+        seed, db_dir, sender, receiver, ticker, amount, stdout, encoding, tx_id, cheat, false,
+    )
+}
+
+/// Finalizes the transfer like `process_finalize_tx`. An interrupted finalization of the same
+/// transfer is detected, and is only replaced with `force_refinalize`.
+pub fn process_finalize_tx_with(
+    seed: String,
+    db_dir: ChainDirs,
+    sender: String,
+    receiver: String,
+    ticker: String,
+    amount: u32,
+    stdout: bool,
+    encoding: Encoding,
+    tx_id: u32,
+    cheat: bool,
+    force_refinalize: bool,
+) -> Result<(), Error> {
+    ensure_cheating_supported(cheat)?;
+    if check_existing_finalization(db_dir.clone(), tx_id, &sender, &ticker, force_refinalize)? {
+        return Ok(());
+    }
+    ensure_amount_in_ticker_range(db_dir.clone(), &ticker, amount)?;
+    ensure_not_closed(db_dir.clone(), &sender, &ticker)?;
+    ensure_not_closed(db_dir.clone(), &receiver, &ticker)?;
     let mut rng = create_rng_from_seed(Some(seed))?;
     let load_from_file_timer = Instant::now();
     let state = TransferTxState::Initialization(TxSubstate::Started);
@@ -221,33 +473,46 @@ pub fn process_finalize_tx(
         &user_public_account_file(&ticker),
     )?;
     let receiver_account = Account {
-        secret: load_object(
+        secret: load_secret_object(
             db_dir.clone(),
-            OFF_CHAIN_DIR,
             &receiver,
             &user_secret_account_file(&ticker),
         )?,
         public: receiver_ordered_pub_account.pub_account,
     };
 
+    let sender_ordered_pub_account: OrderedPubAccount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        &sender,
+        &user_public_account_file(&ticker),
+    )?;
+
     let instruction: OrderedTransferInstruction = load_object(
         db_dir.clone(),
         ON_CHAIN_DIR,
         COMMON_OBJECTS_DIR,
-        &confidential_transaction_file(tx_id.clone(), &sender, state),
+        &confidential_transaction_file(tx_id.clone(), &sender, &ticker, state),
     )?;
 
+    let instruction_path = construct_path(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        &sender.clone(),
+        &confidential_transaction_file(tx_id.clone(), &sender, &ticker, state),
+    )?;
     let tx = InitializedTransferTx::decode(&mut &instruction.data[..]).map_err(|error| {
         Error::ObjectLoadError {
             error,
-            path: construct_path(
-                db_dir.clone(),
-                ON_CHAIN_DIR,
-                &sender.clone(),
-                &confidential_transaction_file(tx_id.clone(), &sender, state),
-            ),
+            path: instruction_path,
         }
     })?;
+    ensure_init_matches(
+        tx_id,
+        &tx,
+        &sender_ordered_pub_account.pub_account,
+        &receiver_account.public,
+    )?;
 
     timing!(
         "account.finalize_tx.load_from_file",
@@ -264,7 +529,7 @@ pub fn process_finalize_tx(
         tx_id,
         db_dir.clone(),
     )?;
-    let next_pending_tx_counter = ordering_state.last_pending_tx_counter + 1;
+    let next_pending_tx_counter = ordering_state.next_pending_tx_counter(tx_id)?;
 
     timing!(
         "account.finalize_tx.calc_pending_state",
@@ -273,25 +538,23 @@ pub fn process_finalize_tx(
         "tx_id" => tx_id.to_string()
     );
 
-    let mut amount = amount;
-    // To simplify the cheating selection process, we randomly choose a cheating strategy,
-    // instead of requiring the caller to know of all the different cheating strategies.
-    let cheating_strategy: u32 = rng.gen_range(0, 2);
+    #[cfg(any(test, feature = "cheating"))]
+    let cheating_strategy =
+        cheating::random_strategy(&mut rng, cheating::CheatOperation::FinalizeTransaction);
 
-    // The first cheating strategies make changes to the input, while the 2nd one
+    // The first cheating strategies make changes to the input, while the subsequent ones
     // changes the output.
-    if cheat && cheating_strategy == 0 {
-        info!(
-            "CLI log: tx-{}: Cheating by changing the agreed upon amount. Correct amount: {}",
-            tx_id, amount
-        );
-        amount += 1
-    }
+    #[cfg(any(test, feature = "cheating"))]
+    let amount = if cheat && cheating_strategy == cheating::CheatStrategy::ChangeAmount {
+        cheating::cheat_on_amount(tx_id, amount)
+    } else {
+        amount
+    };
 
     // Finalize the transaction.
     let finalize_by_receiver_timer = Instant::now();
     let receiver = CtxReceiver {};
-    let mut asset_tx = receiver
+    let asset_tx = receiver
         .finalize_transaction(tx, receiver_account.clone(), amount, &mut rng)
         .map_err(|error| Error::LibraryError { error })?;
 
@@ -301,13 +564,13 @@ pub fn process_finalize_tx(
         tx_id,
     };
 
-    if cheat && cheating_strategy == 1 {
-        info!(
-            "CLI log: tx-{}: Cheating by changing the receiver's account id. Correct account id: {}",
-            tx_id, PrintableAccountId(receiver_account.public.enc_asset_id.encode())
-        );
-        asset_tx.init_data.memo.receiver_account_id += non_empty_account_id();
-    }
+    #[cfg(any(test, feature = "cheating"))]
+    let asset_tx = if cheat && cheating_strategy == cheating::CheatStrategy::ChangeReceiverAccountId
+    {
+        cheating::cheat_on_receiver_account_id(tx_id, &receiver_account.public, asset_tx)
+    } else {
+        asset_tx
+    };
 
     timing!(
         "account.finalize_tx.finalize_by_receiver",
@@ -325,19 +588,28 @@ pub fn process_finalize_tx(
         data: asset_tx.encode().to_vec(),
     };
 
-    save_object(
-        db_dir,
-        ON_CHAIN_DIR,
+    save_instruction(
+        db_dir.clone(),
         COMMON_OBJECTS_DIR,
-        &confidential_transaction_file(tx_id, &sender, state),
+        tx_id,
+        &ticker,
+        &sender,
         &instruction,
     )?;
+    record_state_transition(
+        db_dir,
+        tx_id,
+        &receiver,
+        Some(TransferTxState::Initialization(TxSubstate::Started)),
+        state,
+    )?;
 
     if stdout {
         info!(
-            "CLI log: tx-{}: Transaction as base64:\n{}\n",
+            "CLI log: tx-{}: Transaction as {}:\n{}\n",
             tx_id,
-            base64::encode(asset_tx.encode())
+            encoding,
+            encoding.encode(asset_tx.encode())
         );
     }
 
@@ -350,3 +622,1011 @@ pub fn process_finalize_tx(
 
     Ok(())
 }
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account,
+        account_issue::process_issue_asset,
+        chain_setup::process_asset_id_creation,
+        debug_decrypt_account_balance, gen_seed,
+        justify::{justify_asset_transfer_transaction, process_create_mediator},
+        pending_for_account,
+        test_utils::test_db_dir,
+        update_account_map,
+        validate::validate_all_pending,
+        PrintableAccountId,
+    };
+    use cryptography::asset_proofs::CipherText;
+    use curve25519_dalek::{constants::RISTRETTO_BASEPOINT_POINT, scalar::Scalar};
+
+    fn account_id(seed: u64) -> EncryptedAssetId {
+        CipherText {
+            x: Scalar::from(seed) * RISTRETTO_BASEPOINT_POINT,
+            y: Scalar::from(seed + 1) * RISTRETTO_BASEPOINT_POINT,
+        }
+    }
+
+    fn printable(account_id: &EncryptedAssetId) -> String {
+        PrintableAccountId(account_id.encode()).encode_with(Encoding::Hex)
+    }
+
+    #[test]
+    fn test_resolve_transfer_parties() {
+        let db_dir = test_db_dir("transfer_parties");
+
+        let (alice_acme, bob_acme, bob_tsla) = (account_id(1), account_id(3), account_id(5));
+        for (user, ticker, id, tx_id) in [
+            ("alice", "ACME", &alice_acme, 0),
+            ("bob", "ACME", &bob_acme, 1),
+            ("bob", "TSLA", &bob_tsla, 2),
+        ]
+        .iter()
+        {
+            update_account_map(
+                db_dir.clone(),
+                user.to_string(),
+                ticker.to_string(),
+                (*id).clone(),
+                *tx_id,
+            )
+            .unwrap();
+        }
+
+        // Selecting by names does not touch the account map.
+        let by_name = TransferParties::ByName {
+            sender: "alice".to_string(),
+            receiver: "bob".to_string(),
+            ticker: "ACME".to_string(),
+        };
+        let want = ("alice".to_string(), "bob".to_string(), "ACME".to_string());
+        assert_eq!(
+            by_name.resolve(db_dir.clone(), Encoding::Hex).unwrap(),
+            want
+        );
+
+        // Selecting by account ids resolves to the same parties.
+        let by_id = TransferParties::ByAccountId {
+            sender_account_id: printable(&alice_acme),
+            receiver_account_id: printable(&bob_acme),
+        };
+        assert_eq!(by_id.resolve(db_dir.clone(), Encoding::Hex).unwrap(), want);
+
+        // The account ids follow the encoding, instead of the base64 of the account map.
+        let by_base64_id = TransferParties::ByAccountId {
+            sender_account_id: PrintableAccountId(alice_acme.encode())
+                .encode_with(Encoding::Base64),
+            receiver_account_id: PrintableAccountId(bob_acme.encode())
+                .encode_with(Encoding::Base64),
+        };
+        assert_eq!(
+            by_base64_id
+                .clone()
+                .resolve(db_dir.clone(), Encoding::Base64)
+                .unwrap(),
+            want
+        );
+        match by_base64_id.resolve(db_dir.clone(), Encoding::Hex) {
+            Err(Error::AccountNotFound { .. }) => {}
+            other => panic!("Expected AccountNotFound, got {:?}", other),
+        }
+
+        // The tickers of the two accounts must match.
+        let mismatch = TransferParties::ByAccountId {
+            sender_account_id: printable(&alice_acme),
+            receiver_account_id: printable(&bob_tsla),
+        };
+        match mismatch.resolve(db_dir.clone(), Encoding::Hex) {
+            Err(Error::TickerMismatch { .. }) => {}
+            other => panic!("Expected TickerMismatch, got {:?}", other),
+        }
+
+        // Unknown account ids are reported.
+        let unknown = printable(&account_id(7));
+        let missing = TransferParties::ByAccountId {
+            sender_account_id: unknown.clone(),
+            receiver_account_id: printable(&bob_acme),
+        };
+        match missing.resolve(db_dir.clone(), Encoding::Hex) {
+            Err(Error::AccountNotFound { account_id }) => assert_eq!(account_id, unknown),
+            other => panic!("Expected AccountNotFound, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_same_tx_id_on_different_tickers() {
+        let db_dir = test_db_dir("same_tx_id_on_different_tickers");
+
+        let mike = String::from("mike");
+        let transfers = [("alice", "bob", "ACME"), ("carol", "dave", "BETA")];
+        process_asset_id_creation(db_dir.clone(), vec!["ACME".to_string(), "BETA".to_string()])
+            .unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        let mut tx_id = 0;
+        for (sender, receiver, ticker) in transfers.iter() {
+            for user in &[sender, receiver] {
+                tx_id += 1;
+                process_create_account(
+                    Some(gen_seed()),
+                    db_dir.clone(),
+                    ticker.to_string(),
+                    user.to_string(),
+                    false,
+                    Encoding::default(),
+                    tx_id,
+                    false,
+                    false,
+                    false,
+                )
+                .unwrap();
+            }
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        for (sender, _, ticker) in transfers.iter() {
+            tx_id += 1;
+            process_issue_asset(
+                gen_seed(),
+                db_dir.clone(),
+                sender.to_string(),
+                ticker.to_string(),
+                10,
+                false,
+                Encoding::default(),
+                tx_id,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        // Both transfers use the same tx_id.
+        tx_id += 1;
+        for (sender, receiver, ticker) in transfers.iter() {
+            let (sender, receiver, ticker) =
+                (sender.to_string(), receiver.to_string(), ticker.to_string());
+            process_create_tx(
+                gen_seed(),
+                db_dir.clone(),
+                sender.clone(),
+                receiver.clone(),
+                mike.clone(),
+                ticker.clone(),
+                4,
+                false,
+                Encoding::default(),
+                tx_id,
+                0,
+                false,
+            )
+            .unwrap();
+            process_finalize_tx(
+                gen_seed(),
+                db_dir.clone(),
+                sender.clone(),
+                receiver.clone(),
+                ticker.clone(),
+                4,
+                false,
+                Encoding::default(),
+                tx_id,
+                false,
+            )
+            .unwrap();
+            justify_asset_transfer_transaction(
+                db_dir.clone(),
+                sender,
+                receiver,
+                mike.clone(),
+                ticker,
+                gen_seed(),
+                false,
+                Encoding::default(),
+                tx_id,
+                None,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        // Each justification is saved in its own file.
+        let state = TransferTxState::Justification(TxSubstate::Started);
+        for (_, _, ticker) in transfers.iter() {
+            let path = construct_path(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                COMMON_OBJECTS_DIR,
+                &confidential_transaction_file(tx_id, &mike, &ticker.to_string(), state),
+            )
+            .unwrap();
+            assert!(path.exists());
+        }
+
+        validate_all_pending(db_dir.clone()).unwrap();
+        for (sender, receiver, ticker) in transfers.iter() {
+            for (user, balance) in &[(sender, 6), (receiver, 4)] {
+                let got = debug_decrypt_account_balance(
+                    user.to_string(),
+                    ticker.to_string(),
+                    db_dir.clone(),
+                )
+                .unwrap();
+                assert_eq!(got, *balance);
+            }
+        }
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_min_confirmations() {
+        let db_dir = test_db_dir("min_confirmations");
+
+        let acme = String::from("ACME");
+        let (alice, bob, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        let transfer = |sender: &String, receiver: &String, tx_id: u32, min_confirmations: u32| {
+            process_create_tx(
+                gen_seed(),
+                db_dir.clone(),
+                sender.clone(),
+                receiver.clone(),
+                mike.clone(),
+                acme.clone(),
+                4,
+                false,
+                Encoding::default(),
+                tx_id,
+                min_confirmations,
+                false,
+            )?;
+            process_finalize_tx(
+                gen_seed(),
+                db_dir.clone(),
+                sender.clone(),
+                receiver.clone(),
+                acme.clone(),
+                4,
+                false,
+                Encoding::default(),
+                tx_id,
+                false,
+            )?;
+            justify_asset_transfer_transaction(
+                db_dir.clone(),
+                sender.clone(),
+                receiver.clone(),
+                mike.clone(),
+                acme.clone(),
+                gen_seed(),
+                false,
+                Encoding::default(),
+                tx_id,
+                None,
+                false,
+                false,
+            )?;
+            validate_all_pending(db_dir.clone())
+        };
+        // A stray file does not break the search for the unconfirmed credits.
+        std::fs::write(
+            construct_path(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                COMMON_OBJECTS_DIR,
+                "tx_stray.json",
+            )
+            .unwrap(),
+            "{}",
+        )
+        .unwrap();
+
+        // Alice was issued the funds in tx-3, so with 3 confirmations they are locked until tx-6.
+        match transfer(&alice, &bob, 4, 3) {
+            Err(Error::UnconfirmedFunds {
+                tx_id,
+                amount,
+                spendable,
+                min_confirmations,
+            }) => assert_eq!((tx_id, amount, spendable, min_confirmations), (4, 4, 0, 3)),
+            other => panic!("Expected UnconfirmedFunds, got {:?}", other),
+        }
+        transfer(&alice, &bob, 6, 3).unwrap();
+
+        // Bob received the funds in tx-6, so they are locked until tx-9.
+        match transfer(&bob, &alice, 8, 3) {
+            Err(Error::UnconfirmedFunds {
+                tx_id,
+                amount,
+                spendable,
+                min_confirmations,
+            }) => assert_eq!((tx_id, amount, spendable, min_confirmations), (8, 4, 0, 3)),
+            other => panic!("Expected UnconfirmedFunds, got {:?}", other),
+        }
+        transfer(&bob, &alice, 9, 3).unwrap();
+
+        let balance = |user: &String| {
+            debug_decrypt_account_balance(user.clone(), acme.clone(), db_dir.clone()).unwrap()
+        };
+        assert_eq!(balance(&alice), 10);
+        assert_eq!(balance(&bob), 0);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_transfer_without_balance() {
+        let db_dir = test_db_dir("transfer_without_balance");
+
+        let acme = String::from("ACME");
+        let (alice, bob, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        // Nothing was issued to alice.
+        match process_create_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            3,
+            0,
+            false,
+        ) {
+            Err(Error::NoBalanceForTicker { user, ticker }) => {
+                assert_eq!((user, ticker), (alice, acme))
+            }
+            other => panic!("Expected NoBalanceForTicker, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_pending_counter_overflow() {
+        let mut ordering_state = OrderingState::new(7);
+        ordering_state.last_pending_tx_counter = u32::max_value() - 1;
+        assert_eq!(
+            ordering_state.next_pending_tx_counter(7).unwrap(),
+            u32::max_value()
+        );
+        ordering_state.last_pending_tx_counter = u32::max_value();
+        match ordering_state.next_pending_tx_counter(7) {
+            Err(Error::PendingCounterOverflow { tx_id }) => assert_eq!(tx_id, 7),
+            other => panic!("Expected PendingCounterOverflow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_first_transfer_ordering_state() {
+        let db_dir = test_db_dir("first_transfer_ordering_state");
+
+        let acme = String::from("ACME");
+        let (alice, bob, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+
+        // The base case: a fresh account has neither processed nor pending transactions.
+        let state = last_ordering_state(alice.clone(), None, 3, db_dir.clone()).unwrap();
+        assert_eq!(
+            (
+                state.last_processed_tx_counter,
+                state.last_pending_tx_counter
+            ),
+            (None, 0)
+        );
+
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        // Without pending transfers, the pending counter continues from the processed one, and
+        // the pending balance is the confirmed balance.
+        let account: OrderedPubAccount = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_file(&acme),
+        )
+        .unwrap();
+        assert_eq!(account.last_processed_tx_counter, Some(3));
+        let state = last_ordering_state(alice.clone(), Some(3), 4, db_dir.clone()).unwrap();
+        assert_eq!(
+            (
+                state.last_processed_tx_counter,
+                state.last_pending_tx_counter
+            ),
+            (Some(3), 3)
+        );
+        let balance: EncryptedAmount = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_balance_file(&acme),
+        )
+        .unwrap();
+        let pending_balance = compute_enc_pending_balance(
+            &alice,
+            &acme,
+            state,
+            Some(3),
+            ConfirmedBalance(balance.clone()),
+            0,
+            db_dir.clone(),
+        )
+        .unwrap();
+        assert_eq!(pending_balance.enc_amount(), &balance);
+
+        process_create_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            4,
+            0,
+            false,
+        )
+        .unwrap();
+        let state = last_ordering_state(alice.clone(), Some(3), 5, db_dir.clone()).unwrap();
+        assert_eq!(
+            (
+                state.last_processed_tx_counter,
+                state.last_pending_tx_counter
+            ),
+            (Some(3), 4)
+        );
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_pending_for_account() {
+        let db_dir = test_db_dir("pending_for_account");
+
+        let acme = String::from("ACME");
+        let (alice, bob, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        // tx-4 is finalized, and tx-5 is queued behind it.
+        for (tx_id, amount) in &[(4, 4), (5, 1)] {
+            process_create_tx(
+                gen_seed(),
+                db_dir.clone(),
+                alice.clone(),
+                bob.clone(),
+                mike.clone(),
+                acme.clone(),
+                *amount,
+                false,
+                Encoding::default(),
+                *tx_id,
+                0,
+                false,
+            )
+            .unwrap();
+        }
+        process_finalize_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            4,
+            false,
+        )
+        .unwrap();
+
+        let pending = |user: &String, ticker: &String| {
+            pending_for_account(db_dir.clone(), user, ticker).unwrap()
+        };
+        let init = TransferTxState::Initialization(TxSubstate::Started);
+        let finalized = TransferTxState::Finalization(TxSubstate::Started);
+        let justified = TransferTxState::Justification(TxSubstate::Started);
+        assert_eq!(pending(&alice, &acme), vec![(4, finalized), (5, init)]);
+        assert_eq!(pending(&bob, &acme), vec![(4, finalized), (5, init)]);
+        assert!(pending(&alice, &String::from("BETA")).is_empty());
+
+        justify_asset_transfer_transaction(
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            gen_seed(),
+            false,
+            Encoding::default(),
+            4,
+            None,
+            false,
+            false,
+        )
+        .unwrap();
+        assert_eq!(pending(&alice, &acme), vec![(4, justified), (5, init)]);
+
+        // Once tx-4 is validated, only tx-5 is left.
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(pending(&alice, &acme), vec![(5, init)]);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_query_sent() {
+        let db_dir = test_db_dir("query_sent");
+
+        let acme = String::from("ACME");
+        let (alice, bob, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        for (tx_id, amount) in &[(4, 7), (5, 3)] {
+            process_create_tx(
+                gen_seed(),
+                db_dir.clone(),
+                alice.clone(),
+                bob.clone(),
+                mike.clone(),
+                acme.clone(),
+                *amount,
+                false,
+                Encoding::default(),
+                *tx_id,
+                0,
+                false,
+            )
+            .unwrap();
+        }
+
+        // The on-chain instructions are not needed to recover the amounts.
+        std::fs::remove_dir_all(
+            construct_path(db_dir.clone(), ON_CHAIN_DIR, COMMON_OBJECTS_DIR, "").unwrap(),
+        )
+        .unwrap();
+        let query = |tx_id| process_query_sent(db_dir.clone(), alice.clone(), acme.clone(), tx_id);
+        assert_eq!(query(4).unwrap(), (bob.clone(), 7));
+        assert_eq!(query(5).unwrap(), (bob.clone(), 3));
+        // Only the sender keeps a record.
+        assert!(process_query_sent(db_dir.clone(), bob.clone(), acme.clone(), 4).is_err());
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_finalize_init_mismatch() {
+        let db_dir = test_db_dir("finalize_init_mismatch");
+
+        let acme = String::from("ACME");
+        let (alice, bob, carol, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("carol"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob), (3, &carol)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            4,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        // Alice's transfer to Carol, but Bob is asked to finalize it.
+        process_create_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            carol.clone(),
+            mike.clone(),
+            acme.clone(),
+            5,
+            false,
+            Encoding::default(),
+            5,
+            0,
+            false,
+        )
+        .unwrap();
+        let finalize = |receiver: &String| {
+            process_finalize_tx(
+                gen_seed(),
+                db_dir.clone(),
+                alice.clone(),
+                receiver.clone(),
+                acme.clone(),
+                5,
+                false,
+                Encoding::default(),
+                5,
+                false,
+            )
+        };
+        match finalize(&bob) {
+            Err(Error::FinalizeInitMismatch { tx_id, party }) => {
+                assert_eq!((tx_id, party.as_str()), (5, "receiver"))
+            }
+            result => panic!("Expected FinalizeInitMismatch, got {:?}", result),
+        }
+        finalize(&carol).unwrap();
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_interrupted_finalize() {
+        let db_dir = test_db_dir("interrupted_finalize");
+
+        let acme = String::from("ACME");
+        let (alice, bob, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_create_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            4,
+            0,
+            false,
+        )
+        .unwrap();
+        let finalize = |force_refinalize: bool| {
+            process_finalize_tx_with(
+                gen_seed(),
+                db_dir.clone(),
+                alice.clone(),
+                bob.clone(),
+                acme.clone(),
+                4,
+                false,
+                Encoding::default(),
+                4,
+                false,
+                force_refinalize,
+            )
+        };
+        finalize(false).unwrap();
+
+        // A re-run after a complete finalization has nothing to do.
+        let path = construct_path(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &confidential_transaction_file(
+                4,
+                &alice,
+                &acme,
+                TransferTxState::Finalization(TxSubstate::Started),
+            ),
+        )
+        .unwrap();
+        let complete = std::fs::read(&path).unwrap();
+        finalize(false).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), complete);
+
+        // The write was interrupted halfway.
+        std::fs::write(&path, &complete[..complete.len() / 2]).unwrap();
+        match finalize(false) {
+            Err(Error::PartialFinalizeDetected {
+                tx_id,
+                path: partial,
+            }) => assert_eq!((tx_id, partial), (4, path.clone())),
+            result => panic!("Expected PartialFinalizeDetected, got {:?}", result),
+        }
+        finalize(true).unwrap();
+        let instruction: OrderedTransferInstruction = load_object_from(path.clone()).unwrap();
+        assert!(FinalizedTransferTx::decode(&mut &instruction.data[..]).is_ok());
+
+        // The recovered transfer goes through.
+        justify_asset_transfer_transaction(
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            gen_seed(),
+            false,
+            Encoding::default(),
+            4,
+            None,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(
+            debug_decrypt_account_balance(bob.clone(), acme.clone(), db_dir.clone()).unwrap(),
+            4
+        );
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
@@ -0,0 +1,234 @@
+//! An append-only log of the state transitions of the instructions. Each line of the log is a
+//! JSON record that includes the hash of the previous line, making the log tamper-evident.
+
+use crate::{errors::Error, ChainDirs};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::{
+    fmt,
+    fs::{create_dir_all, File, OpenOptions},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+pub const AUDIT_LOG_FILE: &str = "audit.log";
+
+/// The `prev_hash` of the first record in the log.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// The `old_state` of a newly created instruction.
+const NO_STATE: &str = "none";
+
+/// The size of the chunks in which the end of the log is read to find its last record.
+const TAIL_CHUNK: u64 = 4096;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub tx_id: u32,
+    pub participant: String,
+    pub old_state: String,
+    pub new_state: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditRecord {
+    fn compute_hash(&self) -> String {
+        let mut hasher = Sha3_256::new();
+        hasher.input(self.prev_hash.as_bytes());
+        for field in &[
+            self.tx_id.to_string(),
+            self.participant.clone(),
+            self.old_state.clone(),
+            self.new_state.clone(),
+        ] {
+            hasher.input(b"|");
+            hasher.input(field.as_bytes());
+        }
+        hex::encode(hasher.result())
+    }
+}
+
+fn audit_log_path(db_dir: ChainDirs) -> PathBuf {
+    let mut path = db_dir.root;
+    path.push(AUDIT_LOG_FILE);
+    path
+}
+
+/// Reads all the records of the audit log. A missing log is treated as an empty one.
+pub fn load_audit_log(db_dir: ChainDirs) -> Result<Vec<AuditRecord>, Error> {
+    let path = audit_log_path(db_dir);
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let file = File::open(path.clone()).map_err(|error| Error::FileReadError {
+        error,
+        path: path.clone(),
+    })?;
+
+    let mut records = vec![];
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|error| Error::FileReadError {
+            error,
+            path: path.clone(),
+        })?;
+        let record =
+            serde_json::from_str(&line).map_err(|_| Error::AuditChainBroken { line: index + 1 })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Returns the hash of the last record of the audit log, or the genesis hash if there is none.
+/// Only the end of the log is read, so that an append does not get slower as the log grows.
+fn last_record_hash(db_dir: ChainDirs) -> Result<String, Error> {
+    let path = audit_log_path(db_dir.clone());
+    if !path.exists() {
+        return Ok(GENESIS_HASH.to_string());
+    }
+    let read_error = |error: std::io::Error| Error::FileReadError {
+        error,
+        path: path.clone(),
+    };
+    let mut file = File::open(&path).map_err(read_error)?;
+    let mut end = file.metadata().map_err(read_error)?.len();
+    let mut tail: Vec<u8> = vec![];
+    let last_line = loop {
+        let start = end.saturating_sub(TAIL_CHUNK);
+        let mut chunk = vec![0u8; (end - start) as usize];
+        file.seek(SeekFrom::Start(start)).map_err(read_error)?;
+        file.read_exact(&mut chunk).map_err(read_error)?;
+        chunk.extend(tail);
+        tail = chunk;
+        // Every record ends with a newline, which is not part of it.
+        let body = tail.strip_suffix(b"\n").unwrap_or(&tail[..]);
+        if let Some(newline) = body.iter().rposition(|byte| *byte == b'\n') {
+            break body[newline + 1..].to_vec();
+        }
+        if start == 0 {
+            break body.to_vec();
+        }
+        end = start;
+    };
+    if last_line.is_empty() {
+        return Ok(GENESIS_HASH.to_string());
+    }
+    match serde_json::from_slice::<AuditRecord>(&last_line) {
+        Ok(record) => Ok(record.hash),
+        // The full read reports the line of the broken record.
+        Err(_) => Ok(load_audit_log(db_dir)?
+            .last()
+            .map_or(GENESIS_HASH.to_string(), |record| record.hash.clone())),
+    }
+}
+
+/// Appends a record of an instruction moving from `old_state` to `new_state` to the audit log.
+/// `old_state` is `None` when the instruction is created.
+pub fn record_state_transition<S: fmt::Display>(
+    db_dir: ChainDirs,
+    tx_id: u32,
+    participant: &str,
+    old_state: Option<S>,
+    new_state: S,
+) -> Result<(), Error> {
+    let prev_hash = last_record_hash(db_dir.clone())?;
+
+    let mut record = AuditRecord {
+        tx_id,
+        participant: participant.to_string(),
+        old_state: old_state.map_or(NO_STATE.to_string(), |state| state.to_string()),
+        new_state: new_state.to_string(),
+        prev_hash,
+        hash: String::new(),
+    };
+    record.hash = record.compute_hash();
+
+    create_dir_all(&db_dir.root).map_err(|error| Error::FileCreationError {
+        error,
+        path: db_dir.root.clone(),
+    })?;
+    let path = audit_log_path(db_dir);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path.clone())
+        .map_err(|error| Error::FileCreationError {
+            error,
+            path: path.clone(),
+        })?;
+    let line = serde_json::to_string(&record).map_err(|error| Error::FileWriteError {
+        error,
+        path: path.clone(),
+    })?;
+    writeln!(file, "{}", line).map_err(|error| Error::FileCreationError { error, path })
+}
+
+/// Walks the hash chain of the audit log. Returns the number of records, or
+/// `Error::AuditChainBroken` with the 1-based line number of the first broken link.
+pub fn verify_audit_log(db_dir: ChainDirs) -> Result<usize, Error> {
+    let records = load_audit_log(db_dir)?;
+    let mut prev_hash = GENESIS_HASH.to_string();
+    for (index, record) in records.iter().enumerate() {
+        if record.prev_hash != prev_hash || record.hash != record.compute_hash() {
+            return Err(Error::AuditChainBroken { line: index + 1 });
+        }
+        prev_hash = record.hash.clone();
+    }
+    Ok(records.len())
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_db_dir;
+
+    #[test]
+    fn test_audit_chain() {
+        let db_dir = test_db_dir("audit");
+
+        assert_eq!(verify_audit_log(db_dir.clone()).unwrap(), 0);
+        record_state_transition(db_dir.clone(), 1, "alice", None, "init").unwrap();
+        record_state_transition(db_dir.clone(), 1, "bob", Some("init"), "finalize").unwrap();
+        record_state_transition(db_dir.clone(), 1, "mike", Some("finalize"), "justify").unwrap();
+        assert_eq!(verify_audit_log(db_dir.clone()).unwrap(), 3);
+
+        // Rewriting history breaks the chain at the tampered line.
+        let path = audit_log_path(db_dir.clone());
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, content.replacen("\"bob\"", "\"eve\"", 1)).unwrap();
+        match verify_audit_log(db_dir.clone()) {
+            Err(Error::AuditChainBroken { line }) => assert_eq!(line, 2),
+            other => panic!("Expected AuditChainBroken, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_append_across_tail_chunks() {
+        let db_dir = test_db_dir("audit_tail_chunks");
+
+        // The log grows past several chunks, and its last record is read from their ends.
+        for tx_id in 0..100 {
+            record_state_transition(db_dir.clone(), tx_id, "alice", Some("init"), "finalize")
+                .unwrap();
+        }
+        assert!(
+            std::fs::metadata(audit_log_path(db_dir.clone()))
+                .unwrap()
+                .len()
+                > 4 * TAIL_CHUNK
+        );
+        assert_eq!(verify_audit_log(db_dir.clone()).unwrap(), 100);
+        assert_eq!(
+            last_record_hash(db_dir.clone()).unwrap(),
+            load_audit_log(db_dir.clone()).unwrap()[99].hash
+        );
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
@@ -0,0 +1,112 @@
+//! The confirmed and the pending balances of an account. They are both encrypted amounts, and
+//! the types keep one from being passed where the other is expected.
+
+use crate::{
+    errors::Error, load_tx_between_counters, unconfirmed_credits, ChainDirs, CoreTransaction,
+    OrderingState,
+};
+use cryptography::mercat::EncryptedAmount;
+use log::debug;
+
+/// The balance of an account as it is stored on-chain, i.e., with only the validated
+/// transactions applied.
+#[derive(Clone, Debug)]
+pub struct ConfirmedBalance(pub EncryptedAmount);
+
+/// A confirmed balance minus the amounts of the pending outgoing transfers. The proofs of a
+/// transfer are made and verified against it. It can only be obtained from
+/// `compute_enc_pending_balance`.
+#[derive(Clone, Debug)]
+pub struct PendingBalance(pub(crate) EncryptedAmount);
+
+impl PendingBalance {
+    pub fn enc_amount(&self) -> &EncryptedAmount {
+        &self.0
+    }
+}
+
+/// Searches the on-chain data for all pending transactions that decreased the balance of the
+/// given user and computes the pending balance. The incoming transfers, issuances and mediator
+/// credits that were validated fewer than `min_confirmations` tx_ids before the current
+/// transaction are not spendable yet, and are excluded from the result. A `min_confirmations`
+/// of 0 excludes nothing.
+#[inline]
+pub fn compute_enc_pending_balance(
+    sender: &String,
+    ticker: &String,
+    ordering_state: OrderingState, // The state at the time of creating the last transaction.
+    last_processed_tx_counter: Option<u32>, // The current last processed tx counter.
+    enc_balance_in_account: ConfirmedBalance,
+    min_confirmations: u32,
+    db_dir: ChainDirs,
+) -> Result<PendingBalance, Error> {
+    let mut enc_balance_in_account = enc_balance_in_account.0;
+    if min_confirmations > 0 {
+        for credit in unconfirmed_credits(
+            sender,
+            ticker,
+            ordering_state.tx_id,
+            min_confirmations,
+            db_dir.clone(),
+        )? {
+            enc_balance_in_account -= credit;
+        }
+    }
+
+    if last_processed_tx_counter < ordering_state.last_processed_tx_counter {
+        return Err(Error::MismatchInProcessedCounter {
+            current: last_processed_tx_counter,
+            earliest: ordering_state.last_processed_tx_counter,
+        });
+    }
+    let mut start = 1;
+    if let Some(counter) = ordering_state.last_processed_tx_counter {
+        start = counter + 1;
+    }
+    let transfer_inits = load_tx_between_counters(
+        sender,
+        db_dir.clone(),
+        start,
+        ordering_state.last_pending_tx_counter,
+    )?
+    .into_iter()
+    .filter(|tx| tx.decreases_account_balance())
+    .collect::<Vec<CoreTransaction>>();
+
+    debug!(
+        "------------> found {} outgoing transactions",
+        transfer_inits.len()
+    );
+    if transfer_inits.len() == 0 {
+        // There are no pending transactions.
+        return Ok(PendingBalance(enc_balance_in_account));
+    }
+
+    // last_processed_tx_counter > ordering_state.last_processed_tx_counter &&  last_processed_tx_counter > pending -> pending has been skipped
+    // last_processed_tx_counter > ordering_state.last_processed_tx_counter &&  last_processed_tx_counter == pending -> error
+    // last_processed_tx_counter > ordering_state.last_processed_tx_counter &&  last_processed_tx_counter < pending
+    // last_processed_tx_counter == ordering_state.last_processed_tx_counter
+    // TODO: implementing the simple case for now where the last processed transaction inside the account
+    //       is the same as the last processed transaction inside the last transaction.
+    // The rest of the cases will be handled in CRYP-130
+    if last_processed_tx_counter != ordering_state.last_processed_tx_counter {
+        return Err(Error::NotImplemented {
+            story: "CRYP-130".to_string(),
+        });
+    }
+
+    let mut pending_balance = enc_balance_in_account;
+    for core_tx in transfer_inits {
+        if let CoreTransaction::TransferInit {
+            tx,
+            sender: _,
+            ordering_state: _,
+            tx_id,
+        } = core_tx
+        {
+            pending_balance -= tx.memo.enc_amount_using_sender;
+            debug!("------> decremented by the amount of tx-{}.", tx_id);
+        }
+    }
+    Ok(PendingBalance(pending_balance))
+}
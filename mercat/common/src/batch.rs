@@ -0,0 +1,330 @@
+//! Runs a list of independent operations that is read from a JSON file, for the `batch`
+//! subcommands of the CLIs. By default, the batch stops at the first failure. With
+//! `continue_on_error`, the remaining operations still run and all the failures are reported at
+//! the end.
+
+use crate::{
+    account_create::process_create_account, account_issue::process_issue_asset, encoding::Encoding,
+    errors::Error, gen_seed, justify::justify_asset_transfer_transaction, ChainDirs,
+};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+/// A single operation of a batch file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum BatchOp {
+    CreateAccount {
+        user: String,
+        ticker: String,
+        tx_id: u32,
+    },
+    Issue {
+        issuer: String,
+        ticker: String,
+        amount: u32,
+        tx_id: u32,
+    },
+    Justify {
+        sender: String,
+        receiver: String,
+        mediator: String,
+        ticker: String,
+        tx_id: u32,
+    },
+}
+
+impl fmt::Display for BatchOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BatchOp::CreateAccount {
+                user,
+                ticker,
+                tx_id,
+            } => write!(f, "tx-{}: create-account {} {}", tx_id, user, ticker),
+            BatchOp::Issue {
+                issuer,
+                ticker,
+                amount,
+                tx_id,
+            } => write!(f, "tx-{}: issue {} {} to {}", tx_id, amount, ticker, issuer),
+            BatchOp::Justify {
+                sender,
+                receiver,
+                mediator,
+                tx_id,
+                ..
+            } => write!(
+                f,
+                "tx-{}: justify {} -> {} by {}",
+                tx_id, sender, receiver, mediator
+            ),
+        }
+    }
+}
+
+impl BatchOp {
+    fn run(&self, db_dir: ChainDirs) -> Result<(), Error> {
+        match self.clone() {
+            BatchOp::CreateAccount {
+                user,
+                ticker,
+                tx_id,
+            } => process_create_account(
+                Some(gen_seed()),
+                db_dir,
+                ticker,
+                user,
+                false,
+                Encoding::default(),
+                tx_id,
+                false,
+                false,
+                false,
+            ),
+            BatchOp::Issue {
+                issuer,
+                ticker,
+                amount,
+                tx_id,
+            } => process_issue_asset(
+                gen_seed(),
+                db_dir,
+                issuer,
+                ticker,
+                amount,
+                false,
+                Encoding::default(),
+                tx_id,
+                false,
+            ),
+            BatchOp::Justify {
+                sender,
+                receiver,
+                mediator,
+                ticker,
+                tx_id,
+            } => justify_asset_transfer_transaction(
+                db_dir,
+                sender,
+                receiver,
+                mediator,
+                ticker,
+                gen_seed(),
+                false,
+                Encoding::default(),
+                tx_id,
+                None,
+                false,
+                false,
+            ),
+        }
+    }
+}
+
+/// The outcome of a batch. `written` lists the files that the batch added to the database
+/// directory, so that an operator can see what was already done before resuming.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    pub succeeded: Vec<String>,
+    pub failed: Vec<(String, Error)>,
+    pub skipped: Vec<String>,
+    pub written: Vec<PathBuf>,
+}
+
+impl BatchReport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty() && self.skipped.is_empty()
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (op, error) in &self.failed {
+            writeln!(f, "failed {}: {}", op, error)?;
+        }
+        for op in &self.skipped {
+            writeln!(f, "skipped {}", op)?;
+        }
+        for path in &self.written {
+            writeln!(f, "written {:?}", path)?;
+        }
+        write!(
+            f,
+            "{} succeeded, {} failed",
+            self.succeeded.len(),
+            self.failed.len()
+        )?;
+        if !self.skipped.is_empty() {
+            write!(f, ", {} skipped", self.skipped.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the operations of a batch from a JSON array.
+pub fn load_batch(path: &Path) -> Result<Vec<BatchOp>, Error> {
+    let file = File::open(path).map_err(|error| Error::FileReadError {
+        error,
+        path: path.to_path_buf(),
+    })?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|error| {
+        Error::ObjectDeserializationError {
+            error,
+            path: path.to_path_buf(),
+        }
+    })
+}
+
+fn all_files_under(dir: &Path, files: &mut HashSet<PathBuf>) {
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                all_files_under(&path, files);
+            } else {
+                files.insert(path);
+            }
+        }
+    }
+}
+
+/// Runs the operations in order. Without `continue_on_error`, the operations after the first
+/// failure are skipped.
+pub fn process_batch(db_dir: ChainDirs, ops: &[BatchOp], continue_on_error: bool) -> BatchReport {
+    let mut before = HashSet::new();
+    for dir in &[&db_dir.root, &db_dir.on_chain, &db_dir.off_chain] {
+        all_files_under(dir, &mut before);
+    }
+
+    let mut report = BatchReport::default();
+    for op in ops {
+        if !report.failed.is_empty() && !continue_on_error {
+            report.skipped.push(op.to_string());
+            continue;
+        }
+        match op.run(db_dir.clone()) {
+            Ok(()) => {
+                info!("Batch: {}", op);
+                report.succeeded.push(op.to_string());
+            }
+            Err(err) => {
+                error!("Batch: {} failed: {}", op, err);
+                report.failed.push((op.to_string(), err));
+            }
+        }
+    }
+
+    let mut after = HashSet::new();
+    for dir in &[&db_dir.root, &db_dir.on_chain, &db_dir.off_chain] {
+        all_files_under(dir, &mut after);
+    }
+    report.written = after.difference(&before).cloned().collect();
+    report.written.sort();
+    report
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asset_transaction_file, chain_dir, chain_setup::process_asset_id_creation,
+        test_utils::test_db_dir, validate::validate_all_pending, COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
+    };
+    use cryptography::mercat::{AssetTxState, TxSubstate};
+
+    fn issue(issuer: &str, amount: u32, tx_id: u32) -> BatchOp {
+        BatchOp::Issue {
+            issuer: issuer.to_string(),
+            ticker: String::from("ACME"),
+            amount,
+            tx_id,
+        }
+    }
+
+    #[test]
+    fn test_failing_middle_item() {
+        let db_dir = test_db_dir("batch_failing_middle_item");
+
+        process_asset_id_creation(db_dir.clone(), vec![String::from("ACME")]).unwrap();
+        let accounts = process_batch(
+            db_dir.clone(),
+            &[
+                BatchOp::CreateAccount {
+                    user: String::from("alice"),
+                    ticker: String::from("ACME"),
+                    tx_id: 1,
+                },
+                BatchOp::CreateAccount {
+                    user: String::from("bob"),
+                    ticker: String::from("ACME"),
+                    tx_id: 2,
+                },
+            ],
+            false,
+        );
+        assert!(accounts.is_success());
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        // An issuance of zero fails, the rest of the batch still runs.
+        let report = process_batch(
+            db_dir.clone(),
+            &[
+                issue("alice", 10, 3),
+                issue("alice", 0, 4),
+                issue("bob", 5, 5),
+            ],
+            true,
+        );
+        assert!(!report.is_success());
+        assert_eq!(report.succeeded.len(), 2);
+        assert!(report.skipped.is_empty());
+        match &report.failed[..] {
+            [(op, Error::ZeroIssuanceAmount { tx_id: 4 })] => {
+                assert_eq!(op, "tx-4: issue 0 ACME to alice")
+            }
+            failed => panic!("Unexpected failures: {:?}", failed),
+        }
+        let mut issued = chain_dir(db_dir.clone(), ON_CHAIN_DIR);
+        issued.push(COMMON_OBJECTS_DIR);
+        issued.push(asset_transaction_file(
+            5,
+            &String::from("bob"),
+            &String::from("ACME"),
+            AssetTxState::Initialization(TxSubstate::Started),
+        ));
+        assert!(report.written.contains(&issued));
+
+        // By default, the batch stops at the failure.
+        let report = process_batch(
+            db_dir.clone(),
+            &[
+                issue("alice", 1, 6),
+                issue("alice", 0, 7),
+                issue("bob", 1, 8),
+            ],
+            false,
+        );
+        assert_eq!(
+            (
+                report.succeeded.len(),
+                report.failed.len(),
+                report.skipped.clone()
+            ),
+            (1, 1, vec![String::from("tx-8: issue 1 ACME to bob")])
+        );
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
@@ -1,11 +1,23 @@
 use crate::{
-    errors::Error, save_to_file, AssetIdList, ASSET_ID_LIST_FILE, COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
+    errors::Error, get_asset_ids, load_from_file, load_object, save_to_file, AssetIdList,
+    ChainDirs, ASSET_ID_LIST_FILE, COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
 };
 use cryptography::{asset_id_from_ticker, mercat::account::convert_asset_ids, AssetId};
+use curve25519_dalek::scalar::Scalar;
 use metrics::timing;
-use std::{path::PathBuf, time::Instant};
+use sha3::{Digest, Sha3_256};
+use std::{collections::HashMap, time::Instant};
 
-pub fn process_asset_id_creation(db_dir: PathBuf, ticker_names: Vec<String>) -> Result<(), Error> {
+/// A JSON map from a ticker to the maximum number of bits of its amounts.
+pub const TICKER_MAX_BITS_FILE: &str = "ticker_max_bits.json";
+
+/// The bit-length of the amounts of the tickers without a `max_bits` setting.
+pub const FULL_AMOUNT_BITS: u32 = 32;
+
+pub fn process_asset_id_creation(
+    db_dir: ChainDirs,
+    ticker_names: Vec<String>,
+) -> Result<(), Error> {
     let start = Instant::now();
 
     let valid_asset_ids: Vec<AssetId> = ticker_names
@@ -33,3 +45,329 @@ pub fn process_asset_id_creation(db_dir: PathBuf, ticker_names: Vec<String>) ->
 
     Ok(())
 }
+
+/// Creates the asset registry with the given tickers, which may be none. An existing registry is
+/// left as it is. Returns whether the registry was created.
+pub fn process_init_registry(db_dir: ChainDirs, ticker_names: Vec<String>) -> Result<bool, Error> {
+    match get_asset_ids(db_dir.clone()) {
+        Err(Error::AssetRegistryNotInitialized) => {
+            process_asset_id_creation(db_dir, ticker_names)?;
+            Ok(true)
+        }
+        Err(error) => Err(error),
+        Ok(_) => Ok(false),
+    }
+}
+
+/// The commitment to the asset registry that an account was validated against. It is saved next
+/// to the validated public account.
+#[inline]
+pub fn registry_commitment_file(ticker: &String) -> String {
+    format!("{}_registry_commitment", ticker)
+}
+
+/// Returns the hash of the sorted asset ids, so that the order of the registry does not matter.
+pub fn registry_commitment(asset_ids: &[Scalar]) -> [u8; 32] {
+    let mut asset_ids: Vec<&[u8; 32]> = asset_ids.iter().map(|id| id.as_bytes()).collect();
+    asset_ids.sort();
+    let mut hasher = Sha3_256::new();
+    for asset_id in asset_ids {
+        hasher.input(asset_id);
+    }
+    let mut commitment = [0u8; 32];
+    commitment.copy_from_slice(&hasher.result());
+    commitment
+}
+
+/// Checks that the `user`'s `ticker` account was validated against the registry of the
+/// `tickers`, or against the current registry if no tickers are given.
+pub fn verify_registry_commitment(
+    db_dir: ChainDirs,
+    user: &str,
+    ticker: &String,
+    tickers: Option<Vec<String>>,
+) -> Result<(), Error> {
+    let asset_ids = match tickers {
+        Some(tickers) => convert_asset_ids(
+            tickers
+                .iter()
+                .map(|ticker| {
+                    asset_id_from_ticker(ticker).map_err(|error| Error::LibraryError { error })
+                })
+                .collect::<Result<Vec<AssetId>, Error>>()?,
+        ),
+        None => get_asset_ids(db_dir.clone())?,
+    };
+    let stored: [u8; 32] = load_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        user,
+        &registry_commitment_file(ticker),
+    )?;
+    if stored != registry_commitment(&asset_ids) {
+        return Err(Error::RegistryCommitmentMismatch {
+            user: user.to_string(),
+            ticker: ticker.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Restricts the amounts of the `ticker` to `max_bits` bits.
+pub fn set_ticker_max_bits(db_dir: ChainDirs, ticker: String, max_bits: u32) -> Result<(), Error> {
+    let mut max_bits_map: HashMap<String, u32> = load_from_file(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        TICKER_MAX_BITS_FILE,
+    )
+    .unwrap_or_default();
+    max_bits_map.insert(ticker, max_bits);
+    save_to_file(
+        db_dir,
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        TICKER_MAX_BITS_FILE,
+        &max_bits_map,
+    )
+}
+
+/// Returns the maximum number of bits of the `ticker`'s amounts.
+pub fn ticker_max_bits(db_dir: ChainDirs, ticker: &str) -> u32 {
+    load_from_file::<HashMap<String, u32>>(
+        db_dir,
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        TICKER_MAX_BITS_FILE,
+    )
+    .ok()
+    .and_then(|max_bits_map| max_bits_map.get(ticker).copied())
+    .unwrap_or(FULL_AMOUNT_BITS)
+}
+
+/// Returns an error if writing the `amount` takes more bits than the `ticker` allows.
+pub fn ensure_amount_in_ticker_range(
+    db_dir: ChainDirs,
+    ticker: &str,
+    amount: u32,
+) -> Result<(), Error> {
+    let max_bits = ticker_max_bits(db_dir, ticker);
+    if FULL_AMOUNT_BITS - amount.leading_zeros() > max_bits {
+        return Err(Error::AmountExceedsTickerRange {
+            ticker: ticker.to_string(),
+            amount,
+            max_bits,
+        });
+    }
+    Ok(())
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account, account_issue::process_issue_asset,
+        asset_transaction_file, construct_path, encoding::Encoding, gen_seed,
+        test_utils::test_db_dir, validate::validate_all_pending,
+    };
+    use cryptography::mercat::{AssetTxState, TxSubstate};
+
+    #[test]
+    fn test_init_registry() {
+        let db_dir = test_db_dir("init_registry");
+
+        let (acme, alice) = (String::from("ACME"), String::from("alice"));
+        let create_account = || {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                alice.clone(),
+                false,
+                Encoding::default(),
+                1,
+                false,
+                false,
+                false,
+            )
+        };
+        match create_account() {
+            Err(Error::AssetRegistryNotInitialized) => {}
+            other => panic!("Expected AssetRegistryNotInitialized, got {:?}", other),
+        }
+
+        assert!(process_init_registry(db_dir.clone(), vec![acme.clone()]).unwrap());
+        create_account().unwrap();
+
+        // An existing registry is not replaced.
+        assert!(!process_init_registry(db_dir.clone(), vec![]).unwrap());
+        assert_eq!(get_asset_ids(db_dir.clone()).unwrap().len(), 1);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_ticker_max_bits() {
+        let db_dir = test_db_dir("ticker_max_bits");
+
+        let acme = String::from("ACME");
+        let alice = String::from("alice");
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_account(
+            Some(gen_seed()),
+            db_dir.clone(),
+            acme.clone(),
+            alice.clone(),
+            false,
+            Encoding::default(),
+            1,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        // Without a setting, the full width is allowed.
+        assert_eq!(ticker_max_bits(db_dir.clone(), &acme), FULL_AMOUNT_BITS);
+        ensure_amount_in_ticker_range(db_dir.clone(), &acme, u32::MAX).unwrap();
+
+        // An issuance that was created before the setting is rejected by the validator.
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            16,
+            false,
+            Encoding::default(),
+            2,
+            false,
+        )
+        .unwrap();
+        set_ticker_max_bits(db_dir.clone(), acme.clone(), 4).unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        let validated_issuance = |tx_id| {
+            construct_path(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                &alice,
+                &asset_transaction_file(
+                    tx_id,
+                    &alice,
+                    &acme,
+                    AssetTxState::Justification(TxSubstate::Validated),
+                ),
+            )
+            .unwrap()
+            .exists()
+        };
+        assert!(!validated_issuance(2));
+
+        // At the bound.
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            15,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert!(validated_issuance(3));
+
+        // Just above the bound.
+        match process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            16,
+            false,
+            Encoding::default(),
+            4,
+            false,
+        ) {
+            Err(Error::AmountExceedsTickerRange {
+                ticker,
+                amount,
+                max_bits,
+            }) => {
+                assert_eq!(ticker, acme);
+                assert_eq!(amount, 16);
+                assert_eq!(max_bits, 4);
+            }
+            other => panic!("Expected AmountExceedsTickerRange, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_registry_commitment() {
+        let db_dir = test_db_dir("registry_commitment");
+
+        let (acme, beta) = (String::from("ACME"), String::from("BETA"));
+        let create_account = |user: &str, tx_id: u32| {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+            validate_all_pending(db_dir.clone()).unwrap();
+        };
+        let stored = |user: &str| -> [u8; 32] {
+            load_object(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                user,
+                &registry_commitment_file(&acme),
+            )
+            .unwrap()
+        };
+
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        create_account("alice", 1);
+        verify_registry_commitment(db_dir.clone(), "alice", &acme, None).unwrap();
+
+        // The registry changes after alice's account was validated.
+        process_asset_id_creation(db_dir.clone(), vec![beta.clone(), acme.clone()]).unwrap();
+        create_account("bob", 2);
+        assert_ne!(stored("alice"), stored("bob"));
+        verify_registry_commitment(db_dir.clone(), "bob", &acme, None).unwrap();
+        match verify_registry_commitment(db_dir.clone(), "alice", &acme, None) {
+            Err(Error::RegistryCommitmentMismatch { user, ticker }) => {
+                assert_eq!((user.as_str(), ticker), ("alice", acme.clone()))
+            }
+            other => panic!("Expected RegistryCommitmentMismatch, got {:?}", other),
+        }
+        // The snapshots can be given explicitly, in any order.
+        verify_registry_commitment(db_dir.clone(), "alice", &acme, Some(vec![acme.clone()]))
+            .unwrap();
+        verify_registry_commitment(
+            db_dir.clone(),
+            "bob",
+            &acme,
+            Some(vec![acme.clone(), beta.clone()]),
+        )
+        .unwrap();
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
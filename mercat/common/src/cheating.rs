@@ -0,0 +1,273 @@
+//! Deliberate tampering with the transactions, used to test that the validator and the
+//! mediator catch misbehaving parties. This module is only compiled in when the `cheating`
+//! feature is enabled, or when running the unit tests of this crate.
+
+use crate::{errors::Error, PrintableAccountId};
+use codec::Encode;
+use cryptography::{
+    asset_id_from_ticker,
+    asset_proofs::{CipherText, CommitmentWitness},
+    mercat::{
+        Account, EncryptedAssetId, FinalizedTransferTx, InitializedAssetTx, InitializedTransferTx,
+        JustifiedTransferTx, PubAccount, PubAccountTx, SecAccount,
+    },
+};
+use curve25519_dalek::{constants::RISTRETTO_BASEPOINT_POINT, scalar::Scalar};
+use log::{error, info};
+use rand::{CryptoRng, Rng, RngCore};
+#[cfg(test)]
+use std::cell::Cell;
+
+/// The steps of a transaction that a party can cheat in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheatOperation {
+    CreateAccount,
+    IssueAsset,
+    CreateTransaction,
+    FinalizeTransaction,
+    JustifyTransaction,
+}
+
+impl CheatOperation {
+    pub const ALL: &'static [CheatOperation] = &[
+        CheatOperation::CreateAccount,
+        CheatOperation::IssueAsset,
+        CheatOperation::CreateTransaction,
+        CheatOperation::FinalizeTransaction,
+        CheatOperation::JustifyTransaction,
+    ];
+}
+
+/// The cheating strategies. A new strategy is added by adding a variant here, listing it in
+/// `ALL` and `applies_to`, and handling it in the operations that it applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheatStrategy {
+    /// Replaces the asset id of a new account with an unlisted one.
+    OverwriteAccountAssetId,
+    /// Changes the account id of a new account.
+    BumpAccountId,
+    /// Changes the amount that the sender and the receiver have agreed upon.
+    ChangeAmount,
+    /// Overwrites the encrypted amount of an issuance.
+    OverwriteIssuedAmount,
+    /// Changes the sender's account id in an initialized transfer.
+    ChangeSenderAccountId,
+    /// Changes the receiver's account id in a finalized transfer.
+    ChangeReceiverAccountId,
+    /// Changes the sender's account id in a justified transfer.
+    ChangeJustifiedSenderAccountId,
+}
+
+impl CheatStrategy {
+    pub const ALL: &'static [CheatStrategy] = &[
+        CheatStrategy::OverwriteAccountAssetId,
+        CheatStrategy::BumpAccountId,
+        CheatStrategy::ChangeAmount,
+        CheatStrategy::OverwriteIssuedAmount,
+        CheatStrategy::ChangeSenderAccountId,
+        CheatStrategy::ChangeReceiverAccountId,
+        CheatStrategy::ChangeJustifiedSenderAccountId,
+    ];
+
+    /// Returns true if the strategy can be used when performing the `operation`.
+    // TODO: CRYP-111: Cheating on the issued amount is not detected since the mediator's
+    //       off-chain knowledge is not reflected in the MERCAT calls. Therefore,
+    //       `ChangeAmount` does not apply to the issuance.
+    pub fn applies_to(self, operation: CheatOperation) -> bool {
+        match self {
+            CheatStrategy::OverwriteAccountAssetId | CheatStrategy::BumpAccountId => {
+                operation == CheatOperation::CreateAccount
+            }
+            CheatStrategy::ChangeAmount => {
+                operation == CheatOperation::CreateTransaction
+                    || operation == CheatOperation::FinalizeTransaction
+            }
+            CheatStrategy::OverwriteIssuedAmount => operation == CheatOperation::IssueAsset,
+            CheatStrategy::ChangeSenderAccountId => operation == CheatOperation::CreateTransaction,
+            CheatStrategy::ChangeReceiverAccountId => {
+                operation == CheatOperation::FinalizeTransaction
+            }
+            CheatStrategy::ChangeJustifiedSenderAccountId => {
+                operation == CheatOperation::JustifyTransaction
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    static FORCED_STRATEGY: Cell<Option<CheatStrategy>> = Cell::new(None);
+}
+
+/// Makes `random_strategy` return `strategy` on the current thread, whenever it applies.
+#[cfg(test)]
+pub fn force_strategy(strategy: Option<CheatStrategy>) {
+    FORCED_STRATEGY.with(|forced| forced.set(strategy));
+}
+
+#[cfg(test)]
+fn forced_strategy() -> Option<CheatStrategy> {
+    FORCED_STRATEGY.with(|forced| forced.get())
+}
+
+#[cfg(not(test))]
+fn forced_strategy() -> Option<CheatStrategy> {
+    None
+}
+
+/// This is used for simulating cheating by increasing the account id.
+#[inline]
+pub fn non_empty_account_id() -> EncryptedAssetId {
+    CipherText {
+        x: RISTRETTO_BASEPOINT_POINT,
+        y: RISTRETTO_BASEPOINT_POINT,
+    }
+}
+
+/// To simplify the cheating selection process, we randomly choose a cheating strategy among
+/// the ones that apply to the `operation`, instead of requiring the caller to know of all the
+/// different cheating strategies.
+pub fn random_strategy<R: RngCore>(rng: &mut R, operation: CheatOperation) -> CheatStrategy {
+    if let Some(strategy) = forced_strategy() {
+        if strategy.applies_to(operation) {
+            return strategy;
+        }
+    }
+    let strategies: Vec<CheatStrategy> = CheatStrategy::ALL
+        .iter()
+        .copied()
+        .filter(|strategy| strategy.applies_to(operation))
+        .collect();
+    strategies[rng.gen_range(0, strategies.len())]
+}
+
+/// Overwrites either the asset id or the account id of a newly created account.
+pub fn cheat_on_account_creation<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    tx_id: u32,
+    ticker: &str,
+    secret_account: &SecAccount,
+    account_tx: PubAccountTx,
+) -> Result<PubAccountTx, Error> {
+    match random_strategy(rng, CheatOperation::CreateAccount) {
+        CheatStrategy::OverwriteAccountAssetId => {
+            info!("CLI log: tx-{}: Cheating by overwriting the asset id of the account. Correct ticker: {} and asset id: {:?}",
+                  tx_id, ticker, secret_account.asset_id_witness.value());
+            overwrite_account_asset_id(rng, secret_account, account_tx)
+        }
+        CheatStrategy::BumpAccountId => {
+            info!(
+                "CLI log: tx-{}: Cheating by overwriting the account id. Correct account id: {}",
+                tx_id,
+                PrintableAccountId(account_tx.pub_account.enc_asset_id.encode())
+            );
+            Ok(bump_account_id(account_tx))
+        }
+        _ => {
+            error!("CLI log: tx-{}: This should never happen!", tx_id);
+            Ok(account_tx)
+        }
+    }
+}
+
+/// Replaces the encrypted asset id with a well-formed encryption of an unlisted asset id.
+pub fn overwrite_account_asset_id<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    secret_account: &SecAccount,
+    mut account_tx: PubAccountTx,
+) -> Result<PubAccountTx, Error> {
+    let cheat_asset_id =
+        asset_id_from_ticker("CHEAT").map_err(|error| Error::LibraryError { error })?;
+    let cheat_asset_id_witness =
+        CommitmentWitness::new(cheat_asset_id.clone().into(), Scalar::random(rng));
+    let cheat_enc_asset_id = secret_account
+        .clone()
+        .enc_keys
+        .public
+        .encrypt(&cheat_asset_id_witness);
+    account_tx.pub_account.enc_asset_id = EncryptedAssetId::from(cheat_enc_asset_id);
+    Ok(account_tx)
+}
+
+/// Changes the account id, which breaks the proofs that are bound to it.
+pub fn bump_account_id(mut account_tx: PubAccountTx) -> PubAccountTx {
+    account_tx.pub_account.enc_asset_id += non_empty_account_id();
+    account_tx
+}
+
+/// Changes the amount that the parties have agreed upon.
+pub fn cheat_on_amount(tx_id: u32, amount: u32) -> u32 {
+    info!(
+        "CLI log: tx-{}: Cheating by changing the agreed upon amount. Correct amount: {}",
+        tx_id, amount
+    );
+    amount.wrapping_add(1)
+}
+
+/// Overwrites the encrypted issued amount of an asset issuance transaction.
+pub fn cheat_on_issued_amount<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    tx_id: u32,
+    ticker: &str,
+    issuer_account: &Account,
+    mut asset_tx: InitializedAssetTx,
+) -> Result<InitializedAssetTx, Error> {
+    info!("CLI log: tx-{}: Cheating by overwriting the encrypted issued amount. Correct ticker: {} and encrypted amount: {:?}",
+          tx_id, ticker, &asset_tx.memo.enc_issued_amount);
+    let cheat_asset_id =
+        asset_id_from_ticker("CHEAT").map_err(|error| Error::LibraryError { error })?;
+    let cheat_asset_id_witness =
+        CommitmentWitness::new(cheat_asset_id.clone().into(), Scalar::random(rng));
+    let cheat_enc_asset_id = issuer_account
+        .secret
+        .clone()
+        .enc_keys
+        .public
+        .encrypt(&cheat_asset_id_witness);
+
+    asset_tx.memo.enc_issued_amount = cheat_enc_asset_id;
+    Ok(asset_tx)
+}
+
+/// Changes the sender's account id in the memo of an initialized transfer.
+pub fn cheat_on_sender_account_id(
+    tx_id: u32,
+    sender_account: &PubAccount,
+    mut asset_tx: InitializedTransferTx,
+) -> InitializedTransferTx {
+    info!(
+        "CLI log: tx-{}: Cheating by changing the sender's account id. Correct account id: {}",
+        tx_id,
+        PrintableAccountId(sender_account.enc_asset_id.encode())
+    );
+    asset_tx.memo.sender_account_id += non_empty_account_id();
+    asset_tx
+}
+
+/// Changes the receiver's account id in the memo of a finalized transfer.
+pub fn cheat_on_receiver_account_id(
+    tx_id: u32,
+    receiver_account: &PubAccount,
+    mut asset_tx: FinalizedTransferTx,
+) -> FinalizedTransferTx {
+    info!(
+        "CLI log: tx-{}: Cheating by changing the receiver's account id. Correct account id: {}",
+        tx_id,
+        PrintableAccountId(receiver_account.enc_asset_id.encode())
+    );
+    asset_tx.init_data.memo.receiver_account_id += non_empty_account_id();
+    asset_tx
+}
+
+/// Overwrites the sender's account id of a justified transfer.
+pub fn cheat_on_justified_tx(
+    tx_id: u32,
+    mut justified_tx: JustifiedTransferTx,
+) -> JustifiedTransferTx {
+    info!(
+        "CLI log: tx-{}: Cheating by overwriting the sender's account id.",
+        tx_id
+    );
+    justified_tx.finalized_data.init_data.memo.sender_account_id += non_empty_account_id();
+    justified_tx
+}
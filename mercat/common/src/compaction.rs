@@ -0,0 +1,274 @@
+//! Removal of the intermediate transfer instruction files that are no longer needed once the
+//! transaction is validated or rejected. The final instruction of each transaction and the audit
+//! log are kept.
+
+use crate::{
+    all_tx_files, errors::Error, last_verified_tx_id, parse_tx_name, remove_file, ChainDirs,
+    COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
+};
+use cryptography::mercat::{TransferTxState, TxSubstate};
+use log::info;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+/// Returns true if the instruction in the `state` ends its transaction.
+fn is_final_state(state: &str) -> bool {
+    state == TransferTxState::Justification(TxSubstate::Validated).to_string()
+        || state == TransferTxState::Justification(TxSubstate::Rejected).to_string()
+}
+
+/// Returns true if the instruction in the `state` is superseded by the final instruction.
+fn is_superseded_state(state: &str) -> bool {
+    state == TransferTxState::Initialization(TxSubstate::Started).to_string()
+        || state == TransferTxState::Finalization(TxSubstate::Started).to_string()
+}
+
+/// Finds the initialization and finalization files of the transfers that are validated or
+/// rejected, up to the last validated transaction. The files are only removed if `apply` is
+/// set. Returns the paths of the superseded files, ordered by their transaction id.
+pub fn compact_tx_files(db_dir: ChainDirs, apply: bool) -> Result<Vec<String>, Error> {
+    let last_validated = last_verified_tx_id(db_dir.clone());
+
+    let mut finalized = BTreeSet::new();
+    let mut superseded: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for tx_file in all_tx_files(db_dir.clone())? {
+        let (tx_id, _, state, tx_file_path) = parse_tx_name(tx_file)?;
+        if i64::from(tx_id) > i64::from(last_validated) {
+            continue;
+        }
+        if is_final_state(&state) {
+            finalized.insert(tx_id);
+        } else if is_superseded_state(&state) {
+            superseded
+                .entry(tx_id)
+                .or_insert_with(Vec::new)
+                .push(tx_file_path);
+        }
+    }
+
+    let mut files = vec![];
+    for (tx_id, mut tx_files) in superseded {
+        if !finalized.contains(&tx_id) {
+            continue;
+        }
+        tx_files.sort();
+        for tx_file_path in tx_files {
+            if apply {
+                let file_name = Path::new(&tx_file_path)
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .ok_or(Error::PathBufConversionError)?;
+                remove_file(db_dir.clone(), ON_CHAIN_DIR, COMMON_OBJECTS_DIR, file_name)?;
+                info!("Removed {}", tx_file_path);
+            } else {
+                info!("Would remove {}", tx_file_path);
+            }
+            files.push(tx_file_path);
+        }
+    }
+    Ok(files)
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account,
+        account_issue::process_issue_asset,
+        account_transfer::{process_create_tx, process_finalize_tx},
+        audit::verify_audit_log,
+        chain_setup::process_asset_id_creation,
+        confidential_transaction_file, construct_path, debug_decrypt_account_balance,
+        encoding::Encoding,
+        gen_seed,
+        justify::{justify_asset_transfer_transaction, process_create_mediator},
+        test_utils::test_db_dir,
+        validate::validate_all_pending,
+        RejectionKind, RejectionReason,
+    };
+
+    #[test]
+    fn test_compact_tx_files() {
+        let db_dir = test_db_dir("compaction");
+
+        let acme = String::from("ACME");
+        let alice = String::from("alice");
+        let bob = String::from("bob");
+        let mike = String::from("mike");
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        let transfer = |sender: &String,
+                        receiver: &String,
+                        amount: u32,
+                        tx_id: u32,
+                        rejection_reason: Option<RejectionReason>| {
+            process_create_tx(
+                gen_seed(),
+                db_dir.clone(),
+                sender.clone(),
+                receiver.clone(),
+                mike.clone(),
+                acme.clone(),
+                amount,
+                false,
+                Encoding::default(),
+                tx_id,
+                0,
+                false,
+            )
+            .unwrap();
+            process_finalize_tx(
+                gen_seed(),
+                db_dir.clone(),
+                sender.clone(),
+                receiver.clone(),
+                acme.clone(),
+                amount,
+                false,
+                Encoding::default(),
+                tx_id,
+                false,
+            )
+            .unwrap();
+            justify_asset_transfer_transaction(
+                db_dir.clone(),
+                sender.clone(),
+                receiver.clone(),
+                mike.clone(),
+                acme.clone(),
+                gen_seed(),
+                false,
+                Encoding::default(),
+                tx_id,
+                rejection_reason,
+                false,
+                false,
+            )
+            .unwrap();
+            validate_all_pending(db_dir.clone()).unwrap();
+        };
+        transfer(&alice, &bob, 4, 4, None);
+        transfer(
+            &bob,
+            &alice,
+            1,
+            5,
+            Some(RejectionReason {
+                kind: RejectionKind::Other,
+                message: String::new(),
+            }),
+        );
+        transfer(&alice, &bob, 2, 6, None);
+
+        let file_exists = |tx_id, user: &String, state| {
+            construct_path(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                COMMON_OBJECTS_DIR,
+                &confidential_transaction_file(tx_id, user, &acme, state),
+            )
+            .unwrap()
+            .exists()
+        };
+        let init = TransferTxState::Initialization(TxSubstate::Started);
+        let finalize = TransferTxState::Finalization(TxSubstate::Started);
+        let justify = TransferTxState::Justification(TxSubstate::Started);
+        let validated = TransferTxState::Justification(TxSubstate::Validated);
+        let rejected = TransferTxState::Justification(TxSubstate::Rejected);
+
+        // The dry run does not remove anything.
+        let want: Vec<(u32, String)> = vec![
+            (4, alice.clone()),
+            (4, alice.clone()),
+            (5, bob.clone()),
+            (5, bob.clone()),
+            (6, alice.clone()),
+            (6, alice.clone()),
+        ];
+        let summarize = |files: Vec<String>| -> Vec<(u32, String)> {
+            files
+                .into_iter()
+                .map(|file| {
+                    let (tx_id, user, _, _) = parse_tx_name(file).unwrap();
+                    (tx_id, user)
+                })
+                .collect()
+        };
+        assert_eq!(
+            summarize(compact_tx_files(db_dir.clone(), false).unwrap()),
+            want
+        );
+        assert!(file_exists(4, &alice, init));
+        assert!(file_exists(4, &alice, finalize));
+
+        assert_eq!(
+            summarize(compact_tx_files(db_dir.clone(), true).unwrap()),
+            want
+        );
+        for (tx_id, sender) in &[(4, &alice), (5, &bob), (6, &alice)] {
+            assert!(!file_exists(*tx_id, sender, init));
+            assert!(!file_exists(*tx_id, sender, finalize));
+        }
+        // The final instructions, and the mediator's justifications, are kept.
+        assert!(file_exists(4, &mike, justify));
+        assert!(file_exists(4, &alice, validated));
+        assert!(file_exists(5, &bob, rejected));
+        assert!(file_exists(6, &mike, justify));
+        assert!(file_exists(6, &alice, validated));
+        assert!(compact_tx_files(db_dir.clone(), true).unwrap().is_empty());
+        verify_audit_log(db_dir.clone()).unwrap();
+
+        // The validation continues from the marker.
+        transfer(&bob, &alice, 1, 7, None);
+        let balance = |user: &String| {
+            debug_decrypt_account_balance(user.clone(), acme.clone(), db_dir.clone()).unwrap()
+        };
+        assert_eq!(balance(&alice), 5);
+        assert_eq!(balance(&bob), 5);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
@@ -0,0 +1,226 @@
+//! Discrete logarithm solvers used to recover the plain text value of an encrypted amount.
+//! The debug decryption uses the library's `decrypt` by default. Deployments with larger
+//! balance ranges can opt into a faster solver through `debug_decrypt_with`, e.g.,
+//! `BsgsSolver` or a precomputed-table solver.
+
+use crate::errors::Error;
+use cryptography::{asset_proofs::ElgamalSecretKey, mercat::EncryptedAmount};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT, ristretto::RistrettoPoint, scalar::Scalar,
+    traits::Identity,
+};
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// The number of search steps between two calls to the progress callback of
+/// `decrypt_with_progress`.
+pub const PROGRESS_INTERVAL: u64 = 1 << 16;
+
+/// Finds `value` in `[0, max]` such that `point == value * base`.
+pub trait DiscreteLogSolver {
+    fn solve(&self, point: RistrettoPoint, base: RistrettoPoint, max: u64) -> Option<u64>;
+}
+
+/// Brute forces all the values from zero up to `max`.
+#[derive(Default)]
+pub struct LinearSolver;
+
+impl DiscreteLogSolver for LinearSolver {
+    fn solve(&self, point: RistrettoPoint, base: RistrettoPoint, max: u64) -> Option<u64> {
+        let mut result = RistrettoPoint::identity();
+        for value in 0..=max {
+            if result == point {
+                return Some(value);
+            }
+            result += base;
+        }
+        None
+    }
+}
+
+/// Baby-step giant-step solver. Uses `O(sqrt(max))` memory and time.
+#[derive(Default)]
+pub struct BsgsSolver;
+
+impl DiscreteLogSolver for BsgsSolver {
+    fn solve(&self, point: RistrettoPoint, base: RistrettoPoint, max: u64) -> Option<u64> {
+        let step = (max as f64 + 1.0).sqrt().ceil() as u64;
+
+        // Baby steps: store `j * base` for all `j` in `[0, step)`.
+        let mut baby_steps: HashMap<[u8; 32], u64> = HashMap::with_capacity(step as usize);
+        let mut current = RistrettoPoint::identity();
+        for j in 0..step {
+            baby_steps.entry(current.compress().to_bytes()).or_insert(j);
+            current += base;
+        }
+
+        // Giant steps: subtract `step * base` until a baby step is hit.
+        let giant_step = Scalar::from(step) * base;
+        let mut gamma = point;
+        for i in 0..=step {
+            if let Some(j) = baby_steps.get(&gamma.compress().to_bytes()) {
+                let value = i * step + j;
+                return if value <= max { Some(value) } else { None };
+            }
+            gamma -= giant_step;
+        }
+        None
+    }
+}
+
+/// Decrypts `cipher` with a linear search over `[0, max]`. Calls `progress` with the number of
+/// values searched so far every `PROGRESS_INTERVAL` steps, and returns
+/// `Error::DecryptCancelled` as soon as `cancel` is set.
+pub fn decrypt_with_progress(
+    cipher: &EncryptedAmount,
+    secret: &ElgamalSecretKey,
+    max: u64,
+    mut progress: impl FnMut(u64),
+    cancel: &AtomicBool,
+) -> Result<u64, Error> {
+    // value * g = y - x / secret_key
+    let point = cipher.y - secret.secret.invert() * cipher.x;
+    let mut result = RistrettoPoint::identity();
+    for value in 0..=max {
+        if value % PROGRESS_INTERVAL == 0 {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(Error::DecryptCancelled);
+            }
+            progress(value);
+        }
+        if result == point {
+            progress(value);
+            return Ok(value);
+        }
+        result += RISTRETTO_BASEPOINT_POINT;
+    }
+    Err(Error::DiscreteLogNotFound)
+}
+
+/// Returns true if `cipher` encrypts zero. Unlike decryption, this takes constant time.
+pub fn is_zero(cipher: &EncryptedAmount, secret: &ElgamalSecretKey) -> bool {
+    cipher.y - secret.secret.invert() * cipher.x == RistrettoPoint::identity()
+}
+
+/// Returns the encryption of the negated amount, under the same key and with the negated
+/// randomness. `enc - other` and `enc + negate(&other)` are the same ciphertext.
+pub fn negate(enc: &EncryptedAmount) -> EncryptedAmount {
+    EncryptedAmount {
+        x: -enc.x,
+        y: -enc.y,
+    }
+}
+
+/// Asserts that `enc` decrypts to `expected`. Two encryptions of the same amount differ by
+/// their randomness, so the tests compare the plain text amounts instead of the ciphertexts.
+#[cfg(test)]
+pub fn assert_enc_balance_eq(enc: &EncryptedAmount, secret: &ElgamalSecretKey, expected: u64) {
+    let point = enc.y - secret.secret.invert() * enc.x;
+    match BsgsSolver.solve(
+        point,
+        RISTRETTO_BASEPOINT_POINT,
+        u64::from(u32::max_value()),
+    ) {
+        Some(actual) => assert_eq!(
+            actual, expected,
+            "the encrypted balance is {}, expected {}",
+            actual, expected
+        ),
+        None => panic!(
+            "the encrypted balance does not decrypt to an amount, expected {}",
+            expected
+        ),
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrypt_with_progress() {
+        let secret = ElgamalSecretKey::new(Scalar::from(7u64));
+        let value = 3 * PROGRESS_INTERVAL + 5;
+        let cipher = EncryptedAmount {
+            x: Scalar::from(11u64) * secret.secret * RISTRETTO_BASEPOINT_POINT,
+            y: Scalar::from(11u64 + value) * RISTRETTO_BASEPOINT_POINT,
+        };
+
+        let mut reports = vec![];
+        let cancel = AtomicBool::new(false);
+        let got = decrypt_with_progress(
+            &cipher,
+            &secret,
+            u64::from(u32::max_value()),
+            |done| reports.push(done),
+            &cancel,
+        );
+        assert_eq!(got.unwrap(), value);
+        assert_eq!(
+            reports,
+            vec![
+                0,
+                PROGRESS_INTERVAL,
+                2 * PROGRESS_INTERVAL,
+                3 * PROGRESS_INTERVAL,
+                value
+            ]
+        );
+
+        // A cancelled search bails out at the next progress report.
+        let cancel = AtomicBool::new(false);
+        let got = decrypt_with_progress(
+            &cipher,
+            &secret,
+            value,
+            |done| {
+                if done >= PROGRESS_INTERVAL {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            },
+            &cancel,
+        );
+        match got {
+            Err(Error::DecryptCancelled) => {}
+            other => panic!("Expected DecryptCancelled, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_negate() {
+        let secret = ElgamalSecretKey::new(Scalar::from(7u64));
+        let encrypt = |value: u64, randomness: u64| EncryptedAmount {
+            x: Scalar::from(randomness) * secret.secret * RISTRETTO_BASEPOINT_POINT,
+            y: Scalar::from(randomness + value) * RISTRETTO_BASEPOINT_POINT,
+        };
+        let (a, b) = (encrypt(12, 5), encrypt(4, 9));
+
+        assert!(is_zero(&(a + negate(&a)), &secret));
+        assert_eq!(a - b, a + negate(&b));
+        assert_enc_balance_eq(&(a + negate(&b)), &secret, 8);
+        assert_eq!(negate(&negate(&a)), a);
+    }
+
+    #[test]
+    fn test_linear_and_bsgs_solvers_agree() {
+        let base = RISTRETTO_BASEPOINT_POINT;
+        for value in [0u64, 1, 2, 99, 100, 12_345].iter() {
+            let point = Scalar::from(*value) * base;
+            let linear = LinearSolver.solve(point, base, 20_000);
+            let bsgs = BsgsSolver.solve(point, base, 20_000);
+            assert_eq!(linear, Some(*value));
+            assert_eq!(bsgs, linear);
+        }
+
+        // Out of range values are not found by either solver.
+        let point = Scalar::from(101u64) * base;
+        assert_eq!(LinearSolver.solve(point, base, 100), None);
+        assert_eq!(BsgsSolver.solve(point, base, 100), None);
+    }
+}
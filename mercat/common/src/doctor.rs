@@ -0,0 +1,514 @@
+//! A semantic integrity audit of a database directory. Unlike the validation, it does not
+//! verify any proofs. It checks that the state that the validator left behind is consistent:
+//! every balance decrypts, every account that a pending transfer refers to exists, and the last
+//! validated tx_id is not behind the validated instructions. The same evidence is used to
+//! reconstruct a lost last validated tx_id.
+
+use crate::{
+    all_unverified_tx_files, chain_dir,
+    discrete_log::{BsgsSolver, DiscreteLogSolver},
+    errors::Error,
+    last_verified_tx_id, load_account_map, load_object, load_tx_file,
+    mediator_credit::{ensure_distinct_signing_contexts, signing_contexts},
+    parse_tx_name, save_to_file,
+    secret_store::load_secret_object,
+    tx_balance_deltas, user_public_account_balance_file, user_public_account_file,
+    user_secret_account_file,
+    validate::is_validated_state,
+    ChainDirs, CoreTransaction, OrderedPubAccount, PrintableAccountId, COMMON_OBJECTS_DIR,
+    LAST_VALIDATED_TX_ID_FILE, ON_CHAIN_DIR, VALIDATOR_DIR,
+};
+use codec::Encode;
+use cryptography::mercat::{EncryptedAmount, SecAccount};
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// The findings of `process_doctor`. Each list holds the offending items of one class of
+/// inconsistency.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DoctorReport {
+    /// The sum of the balances of each ticker. The balances are encrypted under the keys of
+    /// their owners, so they are added up after the decryption.
+    pub ticker_totals: BTreeMap<String, u64>,
+    /// The `(user, ticker)` accounts whose balance cannot be decrypted to an amount.
+    pub undecryptable_balances: Vec<(String, String)>,
+    /// The `(tx_id, account id)` of the pending transfers that refer to an unknown account.
+    pub missing_accounts: Vec<(u32, String)>,
+    /// The validated instructions with a tx_id after the last validated tx_id.
+    pub validated_after_last: Vec<u32>,
+}
+
+impl DoctorReport {
+    pub fn is_healthy(&self) -> bool {
+        self.undecryptable_balances.is_empty()
+            && self.missing_accounts.is_empty()
+            && self.validated_after_last.is_empty()
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (ticker, total) in &self.ticker_totals {
+            writeln!(f, "{}: total balance {}", ticker, total)?;
+        }
+        writeln!(
+            f,
+            "undecryptable balances: {}",
+            self.undecryptable_balances.len()
+        )?;
+        for (user, ticker) in &self.undecryptable_balances {
+            writeln!(f, "  {}-{}", user, ticker)?;
+        }
+        writeln!(f, "missing accounts: {}", self.missing_accounts.len())?;
+        for (tx_id, account_id) in &self.missing_accounts {
+            writeln!(f, "  tx-{}: {}", tx_id, account_id)?;
+        }
+        write!(
+            f,
+            "validated after the last validated tx_id: {}",
+            self.validated_after_last.len()
+        )?;
+        for tx_id in &self.validated_after_last {
+            write!(f, "\n  tx-{}", tx_id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decrypts the balance of an account with a baby-step giant-step search, so that a balance
+/// that does not encrypt an amount is reported quickly.
+fn decrypt_balance(db_dir: ChainDirs, user: &str, ticker: &String) -> Result<u64, Error> {
+    let enc_balance: EncryptedAmount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        user,
+        &user_public_account_balance_file(ticker),
+    )?;
+    let secret: SecAccount = load_secret_object(db_dir, user, &user_secret_account_file(ticker))?;
+    let point = enc_balance.y - secret.enc_keys.secret.secret.invert() * enc_balance.x;
+    BsgsSolver
+        .solve(
+            point,
+            RISTRETTO_BASEPOINT_POINT,
+            u64::from(u32::max_value()),
+        )
+        .ok_or(Error::DiscreteLogNotFound)
+}
+
+fn account_exists(db_dir: ChainDirs, user: &str, ticker: &String) -> bool {
+    load_object::<OrderedPubAccount>(
+        db_dir,
+        ON_CHAIN_DIR,
+        user,
+        &user_public_account_file(ticker),
+    )
+    .is_ok()
+}
+
+/// A validated instruction, found by the name of its file.
+struct ValidatedInstruction {
+    tx_id: u32,
+    /// The user whose account the instruction was applied to: the owner of the directory, or
+    /// the user in the file name for the common directory.
+    user: String,
+    /// The ticker in the file name. The files of older versions do not have it.
+    ticker: Option<String>,
+}
+
+/// Returns the validated instructions, in the common directory and in the directories of the
+/// users.
+fn validated_instructions(db_dir: ChainDirs) -> Result<Vec<ValidatedInstruction>, Error> {
+    let ticker_re =
+        Regex::new(r"^tx_[0-9]+_([A-Z0-9]+)_[a-z]+_").map_err(|_| Error::RegexError {
+            reason: String::from("Failed to compile the ticker regex"),
+        })?;
+    let on_chain = chain_dir(db_dir, ON_CHAIN_DIR);
+    let mut instructions = vec![];
+    let dirs = std::fs::read_dir(&on_chain).map_err(|error| Error::FileReadError {
+        error,
+        path: on_chain.clone(),
+    })?;
+    for dir in dirs.flatten().filter(|entry| entry.path().is_dir()) {
+        let owner = dir.file_name().to_string_lossy().to_string();
+        for file in std::fs::read_dir(dir.path())
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let path = file.path().to_string_lossy().to_string();
+            if let Ok((tx_id, user, state, _)) = parse_tx_name(path) {
+                if is_validated_state(&state) {
+                    let file_name = file.file_name().to_string_lossy().to_string();
+                    instructions.push(ValidatedInstruction {
+                        tx_id,
+                        user: if owner == COMMON_OBJECTS_DIR {
+                            user
+                        } else {
+                            owner.clone()
+                        },
+                        ticker: ticker_re
+                            .captures(&file_name)
+                            .map(|caps| caps[1].to_string()),
+                    });
+                }
+            }
+        }
+    }
+    Ok(instructions)
+}
+
+/// Returns the tx_ids of the validated instructions, in the common directory and in the
+/// directories of the users.
+pub(crate) fn validated_tx_ids(db_dir: ChainDirs) -> Result<BTreeSet<u32>, Error> {
+    Ok(validated_instructions(db_dir)?
+        .into_iter()
+        .map(|instruction| instruction.tx_id)
+        .collect())
+}
+
+/// Returns the last validated tx_id that the validator left behind, from the validated
+/// instructions, the validated accounts, and the last processed counters of the accounts. The
+/// counters are only trusted if every account processed its validated instructions. Fails with
+/// `MarkerReconstructionAmbiguous` otherwise.
+pub fn reconstruct_last_validated_tx_id(db_dir: ChainDirs) -> Result<Option<u32>, Error> {
+    let mut last: Option<u32> = None;
+    let mut counters: BTreeMap<(String, String), Option<u32>> = BTreeMap::new();
+    for (_, (user, ticker, tx_id)) in load_account_map(db_dir.clone()) {
+        // An account is validated once its public account is saved.
+        let account: OrderedPubAccount = match load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &user,
+            &user_public_account_file(&ticker),
+        ) {
+            Ok(account) => account,
+            Err(_) => continue,
+        };
+        if account
+            .last_processed_tx_counter
+            .map_or(true, |counter| counter < tx_id)
+        {
+            return Err(Error::MarkerReconstructionAmbiguous {
+                tx_id,
+                user,
+                ticker,
+            });
+        }
+        last = last.max(account.last_processed_tx_counter);
+        counters.insert((user, ticker), account.last_processed_tx_counter);
+    }
+
+    for instruction in validated_instructions(db_dir)? {
+        last = last.max(Some(instruction.tx_id));
+        if let Some(ticker) = instruction.ticker {
+            let counter = counters
+                .get(&(instruction.user.clone(), ticker.clone()))
+                .cloned()
+                .flatten();
+            if counter.map_or(true, |counter| counter < instruction.tx_id) {
+                return Err(Error::MarkerReconstructionAmbiguous {
+                    tx_id: instruction.tx_id,
+                    user: instruction.user,
+                    ticker,
+                });
+            }
+        }
+    }
+    Ok(last)
+}
+
+/// Rewrites the last validated tx_id from the evidence on disk, see
+/// `reconstruct_last_validated_tx_id`. Returns the reconstructed tx_id.
+pub fn process_reconstruct_marker(db_dir: ChainDirs) -> Result<Option<u32>, Error> {
+    let last = reconstruct_last_validated_tx_id(db_dir.clone())?;
+    save_to_file(
+        db_dir,
+        VALIDATOR_DIR,
+        COMMON_OBJECTS_DIR,
+        LAST_VALIDATED_TX_ID_FILE,
+        &last,
+    )?;
+    Ok(last)
+}
+
+/// Audits the database directory. The inconsistencies are reported, not returned as errors.
+pub fn process_doctor(db_dir: ChainDirs) -> Result<DoctorReport, Error> {
+    // A misconfiguration of the signing contexts is not a property of the database, but it
+    // invalidates every signature in it.
+    ensure_distinct_signing_contexts(&signing_contexts())?;
+    let mut report = DoctorReport::default();
+    let account_map = load_account_map(db_dir.clone());
+
+    let mut accounts: Vec<&(String, String, u32)> = account_map.values().collect();
+    accounts.sort();
+    for (user, ticker, _) in accounts {
+        match decrypt_balance(db_dir.clone(), user, ticker) {
+            Ok(balance) => *report.ticker_totals.entry(ticker.clone()).or_default() += balance,
+            Err(_) => report
+                .undecryptable_balances
+                .push((user.clone(), ticker.clone())),
+        }
+    }
+
+    for tx_file in all_unverified_tx_files(db_dir.clone())? {
+        let tx = match parse_tx_name(tx_file)
+            .and_then(|(tx_id, user, state, path)| load_tx_file(tx_id, user, state, path))
+        {
+            Ok(tx) => tx,
+            Err(_) => continue,
+        };
+        let tx_id = match &tx {
+            CoreTransaction::TransferInit { tx_id, .. }
+            | CoreTransaction::TransferFinalize { tx_id, .. }
+            | CoreTransaction::TransferJustify { tx_id, .. } => *tx_id,
+            _ => continue,
+        };
+        for (account_id, _, _) in tx_balance_deltas(&tx) {
+            let printable = PrintableAccountId(account_id.encode()).to_string();
+            let exists = account_map
+                .get(&printable)
+                .map_or(false, |(user, ticker, _)| {
+                    account_exists(db_dir.clone(), user, ticker)
+                });
+            if !exists
+                && !report
+                    .missing_accounts
+                    .contains(&(tx_id, printable.clone()))
+            {
+                report.missing_accounts.push((tx_id, printable));
+            }
+        }
+    }
+
+    let last_validated = last_verified_tx_id(db_dir.clone());
+    report.validated_after_last = validated_tx_ids(db_dir)?
+        .into_iter()
+        .filter(|tx_id| i64::from(*tx_id) > i64::from(last_validated))
+        .collect();
+    Ok(report)
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account, account_issue::process_issue_asset,
+        account_transfer::process_create_tx, chain_setup::process_asset_id_creation,
+        construct_path, encoding::Encoding, gen_seed, justify::process_create_mediator,
+        save_object, save_to_file, test_utils::test_db_dir, validate::validate_all_pending,
+        COMMON_OBJECTS_DIR, LAST_VALIDATED_TX_ID_FILE,
+    };
+    use cryptography::asset_proofs::CipherText;
+    use curve25519_dalek::scalar::Scalar;
+
+    #[test]
+    fn test_doctor() {
+        let db_dir = test_db_dir("doctor");
+
+        let acme = String::from("ACME");
+        let (alice, bob, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_create_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            4,
+            0,
+            false,
+        )
+        .unwrap();
+
+        let report = process_doctor(db_dir.clone()).unwrap();
+        assert!(report.is_healthy(), "{:?}", report);
+        assert_eq!(report.ticker_totals.get(&acme), Some(&10));
+
+        // A balance that does not encrypt an amount.
+        let garbage = CipherText {
+            x: Scalar::from(5u64) * RISTRETTO_BASEPOINT_POINT,
+            y: Scalar::from(7u64) * RISTRETTO_BASEPOINT_POINT,
+        };
+        save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &bob,
+            &user_public_account_balance_file(&acme),
+            &garbage,
+        )
+        .unwrap();
+        // The receiver of the pending transfer disappears.
+        std::fs::remove_file(
+            construct_path(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                &bob,
+                &user_public_account_file(&acme),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        // The issuance is validated, but the last validated tx_id is behind it.
+        save_to_file(
+            db_dir.clone(),
+            VALIDATOR_DIR,
+            COMMON_OBJECTS_DIR,
+            LAST_VALIDATED_TX_ID_FILE,
+            &2i32,
+        )
+        .unwrap();
+
+        let report = process_doctor(db_dir.clone()).unwrap();
+        assert!(!report.is_healthy());
+        assert_eq!(report.ticker_totals.get(&acme), Some(&10));
+        assert_eq!(
+            report.undecryptable_balances,
+            vec![(bob.clone(), acme.clone())]
+        );
+        assert_eq!(
+            report
+                .missing_accounts
+                .iter()
+                .map(|(tx_id, _)| *tx_id)
+                .collect::<Vec<_>>(),
+            vec![4]
+        );
+        assert_eq!(report.validated_after_last, vec![3]);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+    #[test]
+    fn test_reconstruct_marker() {
+        let db_dir = test_db_dir("reconstruct_marker");
+
+        let acme = String::from("ACME");
+        let (alice, bob) = (String::from("alice"), String::from("bob"));
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(last_verified_tx_id(db_dir.clone()), 3);
+
+        std::fs::remove_file(
+            construct_path(
+                db_dir.clone(),
+                VALIDATOR_DIR,
+                COMMON_OBJECTS_DIR,
+                LAST_VALIDATED_TX_ID_FILE,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(last_verified_tx_id(db_dir.clone()), -1);
+        assert_eq!(process_reconstruct_marker(db_dir.clone()).unwrap(), Some(3));
+        assert_eq!(last_verified_tx_id(db_dir.clone()), 3);
+
+        // The issuer's account claims that it did not process the validated issuance.
+        let mut account: OrderedPubAccount = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_file(&acme),
+        )
+        .unwrap();
+        account.last_processed_tx_counter = Some(2);
+        save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_file(&acme),
+            &account,
+        )
+        .unwrap();
+        match process_reconstruct_marker(db_dir.clone()) {
+            Err(Error::MarkerReconstructionAmbiguous {
+                tx_id,
+                user,
+                ticker,
+            }) => assert_eq!((tx_id, user, ticker), (3, alice, acme)),
+            other => panic!("Expected MarkerReconstructionAmbiguous, got {:?}", other),
+        }
+        assert_eq!(last_verified_tx_id(db_dir.clone()), 3);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
@@ -0,0 +1,96 @@
+//! Text encodings used by the CLIs to print and read binary data such as keys, proofs, and
+//! transactions.
+
+use crate::errors::Error;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// The text encoding of the binary data that the CLIs emit and consume.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Encoding {
+    Hex,
+    Base64,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Hex
+    }
+}
+
+impl Encoding {
+    /// Encodes the given bytes to a string.
+    pub fn encode<T: AsRef<[u8]>>(&self, data: T) -> String {
+        match self {
+            Encoding::Hex => hex::encode(data),
+            Encoding::Base64 => base64::encode(data),
+        }
+    }
+
+    /// Decodes a string that was produced by `encode` back to bytes.
+    pub fn decode<T: AsRef<[u8]>>(&self, data: T) -> Result<Vec<u8>, Error> {
+        match self {
+            Encoding::Hex => hex::decode(data).map_err(|error| Error::HexDecodeError { error }),
+            Encoding::Base64 => {
+                base64::decode(data).map_err(|error| Error::Base64DecodeError { error })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Hex => write!(f, "hex"),
+            Encoding::Base64 => write!(f, "base64"),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = Error;
+
+    fn from_str(encoding: &str) -> Result<Self, Error> {
+        match encoding {
+            "hex" => Ok(Encoding::Hex),
+            "base64" => Ok(Encoding::Base64),
+            _ => Err(Error::InvalidEncoding {
+                encoding: encoding.to_string(),
+            }),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for encoding in [Encoding::Hex, Encoding::Base64].iter() {
+            for len in [0usize, 1, 32, 64].iter() {
+                let data: Vec<u8> = (0..*len).map(|i| i as u8).collect();
+                let encoded = encoding.encode(&data);
+                assert_eq!(encoding.decode(&encoded).unwrap(), data);
+            }
+        }
+        assert_eq!(Encoding::Hex.encode(&[0xde, 0xad]), "dead");
+        assert_eq!(Encoding::Base64.encode(&[0xde, 0xad]), "3q0=");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Encoding::from_str("hex").unwrap(), Encoding::Hex);
+        assert_eq!(Encoding::from_str("base64").unwrap(), Encoding::Base64);
+        assert_eq!(
+            Encoding::from_str(&Encoding::Base64.to_string()).unwrap(),
+            Encoding::Base64
+        );
+        assert!(Encoding::from_str("base58").is_err());
+        assert!(Encoding::Hex.decode("not hex").is_err());
+    }
+}
@@ -152,6 +152,425 @@ pub enum Error {
     )]
     InvalidLastProcessedTxCounter { value: i32 },
 
+    /// The outgoing transfers of an account exceeded its incoming transfers plus its starting
+    /// balance while applying the validation results.
+    #[fail(
+        display = "Balance of {}-{} becomes negative when applying tx-{}.",
+        user, ticker, tx_id
+    )]
+    NegativeBalanceDuringReduce {
+        user: String,
+        ticker: String,
+        tx_id: u32,
+    },
+
+    /// The discrete log solver could not find the plain text value of a cipher text.
+    #[fail(display = "Could not find the discrete log of the decrypted value.")]
+    DiscreteLogNotFound,
+
+    /// An error occurred while decoding a hex string.
+    #[fail(display = "Error in decoding the hex value: {:?}", error)]
+    HexDecodeError { error: hex::FromHexError },
+
+    /// An error occurred while decoding a base64 string.
+    #[fail(display = "Error in decoding the base64 value: {:?}", error)]
+    Base64DecodeError { error: base64::DecodeError },
+
+    /// The requested text encoding is not supported.
+    #[fail(
+        display = "Invalid encoding {:?}, expected either hex or base64.",
+        encoding
+    )]
+    InvalidEncoding { encoding: String },
+
+    /// The account id is not in the account map.
+    #[fail(display = "Account {} was not found.", account_id)]
+    AccountNotFound { account_id: String },
+
+    /// The sender and the receiver accounts of a transfer belong to different assets.
+    #[fail(
+        display = "The sender's ticker {} does not match the receiver's ticker {}.",
+        sender_ticker, receiver_ticker
+    )]
+    TickerMismatch {
+        sender_ticker: String,
+        receiver_ticker: String,
+    },
+
+    /// A record of the audit log does not chain to its previous record.
+    #[fail(display = "The audit log is broken at line {}.", line)]
+    AuditChainBroken { line: usize },
+
+    /// The blinding factor of a new account has already been used by another account.
+    #[fail(
+        display = "Detected reuse of the encryption randomness in tx-{}.",
+        tx_id
+    )]
+    RandomnessReuseDetected { tx_id: u32 },
+
+    /// The decryption was cancelled before the value was found.
+    #[fail(display = "The decryption was cancelled.")]
+    DecryptCancelled,
+
+    /// The cached outcome of an earlier verification of the same content is a failure.
+    #[fail(display = "The verification of tx-{} failed in an earlier run.", tx_id)]
+    CachedVerificationFailure { tx_id: u32 },
+
+    /// The rejection kind is not one of the known kinds.
+    #[fail(
+        display = "Invalid rejection kind {:?}, expected one of compliance, wrong-amount, unknown-counterparty, or other.",
+        kind
+    )]
+    InvalidRejectionKind { kind: String },
+
+    /// The error strategy of the validator is not one of the supported values.
+    #[fail(
+        display = "Invalid error strategy {:?}, expected one of skip or abort.",
+        strategy
+    )]
+    InvalidErrorStrategy { strategy: String },
+
+    /// The user does not have an account for the ticker.
+    #[fail(display = "{} does not have a {} account.", user, ticker)]
+    UserAccountNotFound { user: String, ticker: String },
+
+    /// The pending balance that a transfer is verified against differs from the recomputed one.
+    #[fail(
+        display = "The pending balance of tx-{} does not match the recomputed pending balance.",
+        tx_id
+    )]
+    PendingBalanceMismatch { tx_id: u32 },
+
+    /// The payment request does not match its digest, e.g., the amount was changed after the
+    /// receiver created it.
+    #[fail(
+        display = "The content of the payment request tx-{} does not match its digest.",
+        tx_id
+    )]
+    PaymentRequestContentMismatch { tx_id: u32 },
+
+    /// The payment request was addressed to a different sender.
+    #[fail(
+        display = "The payment request tx-{} is addressed to {}, not {}.",
+        tx_id, requested, sender
+    )]
+    PaymentRequestSenderMismatch {
+        tx_id: u32,
+        requested: String,
+        sender: String,
+    },
+
+    /// The payment request has already been turned into a transfer.
+    #[fail(display = "The payment request tx-{} is already approved.", tx_id)]
+    PaymentRequestAlreadyApproved { tx_id: u32 },
+
+    /// The instruction is not in the state that the validator expects.
+    #[fail(display = "tx-{}: Unexpected instruction state {}.", tx_id, state)]
+    UnexpectedTransactionState { tx_id: u32, state: String },
+
+    /// The account id in the transaction differs from the one in the user's public account.
+    #[fail(
+        display = "tx-{}: The account id does not match the public account of {}.",
+        tx_id, user
+    )]
+    AccountIdMismatch { tx_id: u32, user: String },
+
+    /// The ticker is not in the list of valid asset ids.
+    #[fail(
+        display = "The ticker {} is not in the list of valid asset ids.",
+        ticker
+    )]
+    TickerNotAllowed { ticker: String },
+
+    /// The mediator is not in the list of the mediators who may credit the ticker.
+    #[fail(display = "{} is not authorized to credit {}.", mediator, ticker)]
+    MediatorNotAuthorized { mediator: String, ticker: String },
+
+    /// The signature of a mediator credit does not match the content or the mediator.
+    #[fail(display = "tx-{}: invalid mediator signature on the credit.", tx_id)]
+    InvalidMediatorSignature { tx_id: u32 },
+
+    /// The encrypted amount of a mediator credit does not encrypt the credited amount.
+    #[fail(
+        display = "tx-{}: invalid correctness proof of the credited amount.",
+        tx_id
+    )]
+    InvalidCreditProof { tx_id: u32 },
+
+    /// The account map resolves the account id to a user whose on-chain account has another id.
+    #[fail(
+        display = "The account map entry of {} is stale. The on-chain account has another id.",
+        account_id
+    )]
+    StaleAccountMapping { account_id: String },
+
+    /// There are more unverified transaction files than the configured maximum.
+    #[fail(
+        display = "Found {} pending transactions, which is more than the limit of {}.",
+        count, limit
+    )]
+    TooManyPendingTransactions { count: usize, limit: usize },
+
+    /// The mediator has already justified or rejected the transaction.
+    #[fail(
+        display = "tx-{} is already justified. Pass --re-justify to justify it again.",
+        tx_id
+    )]
+    TransactionAlreadyJustified { tx_id: u32 },
+
+    /// The amount does not fit in the bit-length that is configured for the ticker.
+    #[fail(
+        display = "The amount {} of {} does not fit in {} bits",
+        amount, ticker, max_bits
+    )]
+    AmountExceedsTickerRange {
+        ticker: String,
+        amount: u32,
+        max_bits: u32,
+    },
+
+    /// A component of a proof given on the command line is not well-formed.
+    #[fail(display = "Malformed {}: {}", field, reason)]
+    MalformedProofInput { field: String, reason: String },
+
+    /// One of several proofs of the same statement proves a different value or ciphertext.
+    #[fail(
+        display = "Proof {} proves a different (value, cipher) than the verifier",
+        index
+    )]
+    ProofDisagreement { index: usize },
+
+    /// One of several proofs of the same statement does not verify.
+    #[fail(display = "Proof {} does not verify", index)]
+    InvalidCorrectnessProof { index: usize },
+
+    /// A pending transfer was already justified by the mediator that is being replaced.
+    #[fail(
+        display = "tx-{} is already justified by mediator {}, and cannot be moved to another mediator",
+        tx_id, mediator
+    )]
+    MediatorMigrationConflict { tx_id: u32, mediator: String },
+
+    /// The name of a transaction file does not have the `tx_<id>_<TICKER>_<user>_<state>.json`
+    /// layout.
+    #[fail(display = "Malformed transaction file name: {}", file_name)]
+    MalformedTxFilename { file_name: String },
+
+    /// The id in the name of a transaction file is not a number.
+    #[fail(display = "The transaction id of {} is not a number", file_name)]
+    NonNumericTxId { file_name: String },
+
+    /// The state in the name of a transaction file is not one that the CLIs write.
+    #[fail(
+        display = "Unknown state {} in transaction file name {}",
+        state, file_name
+    )]
+    UnknownState { file_name: String, state: String },
+
+    /// An explicit transaction id is already used by an instruction.
+    #[fail(
+        display = "The transaction id {} is already used. Pass --tx-id auto to allocate a free one",
+        tx_id
+    )]
+    TxIdAlreadyUsed { tx_id: u32 },
+
+    /// Another command holds the lock of the transaction id counter for too long. A lock that
+    /// is older than a minute is broken automatically.
+    #[fail(
+        display = "Timed out waiting for the lock {:?}. Remove it if no other command is running",
+        path
+    )]
+    TxIdLockTimeout { path: PathBuf },
+
+    /// A user or a file name contains a path separator or `..`, and could escape the database
+    /// directory.
+    #[fail(display = "Unsafe name component {:?}", component)]
+    UnsafeNameComponent { component: String },
+
+    /// An issuance of zero would use up a transaction id without changing any balance.
+    #[fail(display = "tx-{}: The issued amount must not be zero", tx_id)]
+    ZeroIssuanceAmount { tx_id: u32 },
+
+    /// An instruction was about to be written in a state that does not follow the current
+    /// state of the transaction.
+    #[fail(
+        display = "tx-{}: Illegal state transition from {} to {}",
+        tx_id, from, to
+    )]
+    IllegalStateTransition {
+        tx_id: u32,
+        from: String,
+        to: String,
+    },
+
+    /// The initialized transfer that the receiver is about to finalize is not between the
+    /// expected accounts, e.g., because the init file was swapped.
+    #[fail(
+        display = "tx-{}: the {} account id of the initialized transfer does not match",
+        tx_id, party
+    )]
+    FinalizeInitMismatch { tx_id: u32, party: String },
+
+    /// The finalization of a transfer was interrupted while it was being written.
+    #[fail(
+        display = "tx-{}: the finalization {:?} is incomplete, re-run with --force-refinalize",
+        tx_id, path
+    )]
+    PartialFinalizeDetected { tx_id: u32, path: PathBuf },
+
+    /// Two purposes share a signing context, so their signatures are interchangeable.
+    #[fail(
+        display = "the {} and {} signatures use the same signing context",
+        first, second
+    )]
+    SigningContextCollision { first: String, second: String },
+
+    /// The sum of the issuances of a ticker is beyond the search bound of the decryption.
+    #[fail(display = "the total supply of {} cannot be decrypted", ticker)]
+    SupplyDecryptionFailed { ticker: String },
+
+    /// A historical balance is queried after the last validated transaction.
+    #[fail(
+        display = "the balance as of tx-{} is not known, the last validated tx_id is {}",
+        as_of_tx_id, last_validated
+    )]
+    AsOfBeyondHistory {
+        as_of_tx_id: u32,
+        last_validated: i32,
+    },
+
+    /// An account was not validated against the given asset registry.
+    #[fail(
+        display = "the account {}-{} was validated against a different asset registry",
+        user, ticker
+    )]
+    RegistryCommitmentMismatch { user: String, ticker: String },
+
+    /// A signature verifies, but in the signing context of another purpose.
+    #[fail(
+        display = "tx-{}: the {} signature was made in the {} signing context",
+        tx_id, expected, found
+    )]
+    WrongSignatureContext {
+        tx_id: u32,
+        expected: String,
+        found: String,
+    },
+
+    /// An issuance to an account whose creation has not been validated.
+    #[fail(
+        display = "tx-{}: the account {}-{} is not validated yet",
+        tx_id, issuer, ticker
+    )]
+    IssuanceToUnvalidatedAccount {
+        tx_id: u32,
+        issuer: String,
+        ticker: String,
+    },
+
+    /// The last validated tx_id cannot be reconstructed, since an account did not process one of
+    /// its validated transactions.
+    #[fail(
+        display = "tx-{} is validated, but the account {}-{} did not process it. The last validated tx_id is ambiguous",
+        tx_id, user, ticker
+    )]
+    MarkerReconstructionAmbiguous {
+        tx_id: u32,
+        user: String,
+        ticker: String,
+    },
+
+    /// The asset registry does not exist yet, e.g., in a fresh database directory.
+    #[fail(
+        display = "The asset registry is not initialized. Run `mercat-chain-setup init-registry` first."
+    )]
+    AssetRegistryNotInitialized,
+
+    /// The validation would move the last processed counter of an account back.
+    #[fail(
+        display = "Refusing to move the last processed counter of {}-{} back from {} to {}",
+        user, ticker, old, new
+    )]
+    CounterRegression {
+        user: String,
+        ticker: String,
+        old: u32,
+        new: u32,
+    },
+
+    /// The pending tx counter of the account would exceed its maximum.
+    #[fail(
+        display = "tx-{}: the pending tx counter of the account overflows",
+        tx_id
+    )]
+    PendingCounterOverflow { tx_id: u32 },
+
+    /// The owner asked to close an account whose balance is not zero.
+    #[fail(
+        display = "The account {}-{} cannot be closed, its balance is not zero.",
+        user, ticker
+    )]
+    AccountNotEmpty { user: String, ticker: String },
+
+    /// The validator rejected the request to close an account.
+    #[fail(
+        display = "The closure of the account {}-{} was rejected: {}",
+        user, ticker, reason
+    )]
+    AccountClosureRejected {
+        user: String,
+        ticker: String,
+        reason: String,
+    },
+
+    /// A transaction refers to an account that has been closed.
+    #[fail(display = "The account {}-{} is closed.", user, ticker)]
+    AccountClosed { user: String, ticker: String },
+
+    /// An issuance exceeds the limits of the signed issuance policy of its ticker.
+    #[fail(
+        display = "tx-{}: the issuance violates the issuance policy: {}",
+        tx_id, reason
+    )]
+    IssuancePolicyViolation { tx_id: u32, reason: String },
+
+    /// The sender has no balance to transfer, e.g., because the asset was never issued to them.
+    #[fail(display = "{} has no balance for the ticker {}.", user, ticker)]
+    NoBalanceForTicker { user: String, ticker: String },
+
+    /// The amount of a transfer exceeds the balance that excludes the incoming transfers that are
+    /// still within the confirmation window.
+    #[fail(
+        display = "tx-{}: Cannot transfer {}, only {} is spendable after {} confirmations.",
+        tx_id, amount, spendable, min_confirmations
+    )]
+    UnconfirmedFunds {
+        tx_id: u32,
+        amount: u32,
+        spendable: u32,
+        min_confirmations: u32,
+    },
+
+    /// The passphrase of the secret files could not be read from the terminal.
+    #[fail(display = "Failed to read the passphrase: {:?}", error)]
+    PassphraseReadError { error: std::io::Error },
+
+    /// A secret file could not be encrypted.
+    #[fail(display = "Failed to encrypt the secret file: {}", reason)]
+    SecretEncryptionFailed { reason: String },
+
+    /// A secret file could not be decrypted, either because the passphrase is wrong or because
+    /// the file is corrupted.
+    #[fail(
+        display = "Failed to decrypt the secret file {:?}. Is the passphrase correct?",
+        path
+    )]
+    SecretDecryptionFailed { path: PathBuf },
+
+    /// The caller asked for cheating, but this binary was built without the `cheating` feature.
+    #[fail(display = "cheating support not compiled in")]
+    CheatingNotSupported,
+
     #[fail(display = "Not implemented, story: {}", story)]
     NotImplemented { story: String },
 }
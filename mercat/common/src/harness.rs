@@ -2,14 +2,16 @@ use crate::{
     account_create::process_create_account,
     account_issue::process_issue_asset,
     account_transfer::{process_create_tx, process_finalize_tx},
+    chain_dir,
     chain_setup::process_asset_id_creation,
     create_rng_from_seed, debug_decrypt_account_balance,
+    encoding::Encoding,
     errors::Error,
     gen_seed, gen_seed_from,
     justify::{justify_asset_transfer_transaction, process_create_mediator},
     user_public_account_file,
     validate::validate_all_pending,
-    COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
+    ChainDirs, RejectionKind, RejectionReason, COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
 };
 use linked_hash_map::LinkedHashMap;
 use log::{error, info, warn};
@@ -220,7 +222,7 @@ pub struct TestCase {
     accounts_outcome: HashSet<InputAccount>,
 
     /// The directory that will act as the chain datastore.
-    chain_db_dir: PathBuf,
+    chain_db_dir: ChainDirs,
 }
 
 // --------------------------------------------------------------------------------------------------
@@ -231,7 +233,7 @@ impl Transaction {
     fn operations_order<T: RngCore + CryptoRng>(
         &self,
         rng: &mut T,
-        chain_db_dir: PathBuf,
+        chain_db_dir: ChainDirs,
     ) -> Vec<StepFunc> {
         match self {
             Transaction::Validate(validate) => validate.operations_order(chain_db_dir),
@@ -243,7 +245,7 @@ impl Transaction {
 }
 
 impl Transfer {
-    pub fn send<T: RngCore + CryptoRng>(&self, rng: &mut T, chain_db_dir: PathBuf) -> StepFunc {
+    pub fn send<T: RngCore + CryptoRng>(&self, rng: &mut T, chain_db_dir: ChainDirs) -> StepFunc {
         let seed = gen_seed_from(rng);
         let value = format!(
             "tx-{}: $ mercat-account create-transaction --account-id-from-ticker {} --amount {} --sender {} --receiver {} \
@@ -256,7 +258,7 @@ impl Transfer {
             self.mediator.name,
             self.tx_id,
             seed,
-            path_to_string(&chain_db_dir),
+            path_to_string(&chain_db_dir.root),
             cheater_flag(self.sender.cheater)
         );
         let ticker = self.ticker.clone();
@@ -277,14 +279,20 @@ impl Transfer {
                 ticker.clone(),
                 amount,
                 false, // Do not print the transaction data to stdout.
+                Encoding::default(),
                 tx_id,
+                0,
                 cheat,
             )?;
             Ok(value.clone())
         });
     }
 
-    pub fn receive<T: RngCore + CryptoRng>(&self, rng: &mut T, chain_db_dir: PathBuf) -> StepFunc {
+    pub fn receive<T: RngCore + CryptoRng>(
+        &self,
+        rng: &mut T,
+        chain_db_dir: ChainDirs,
+    ) -> StepFunc {
         let seed = gen_seed_from(rng);
         let value = format!(
             "tx-{}: $ mercat-account finalize-transaction --account-id-from-ticker {} --amount {} --sender {} --receiver {} --tx-id {} \
@@ -296,7 +304,7 @@ impl Transfer {
             self.receiver.name,
             self.tx_id,
             seed,
-            path_to_string(&chain_db_dir),
+            path_to_string(&chain_db_dir.root),
             cheater_flag(self.receiver.cheater)
         );
         let ticker = self.ticker.clone();
@@ -315,6 +323,7 @@ impl Transfer {
                 ticker.clone(),
                 amount,
                 false, // Do not print the transaction data to stdout.
+                Encoding::default(),
                 tx_id,
                 cheat,
             )?;
@@ -322,7 +331,11 @@ impl Transfer {
         });
     }
 
-    pub fn mediate<T: RngCore + CryptoRng>(&self, rng: &mut T, chain_db_dir: PathBuf) -> StepFunc {
+    pub fn mediate<T: RngCore + CryptoRng>(
+        &self,
+        rng: &mut T,
+        chain_db_dir: ChainDirs,
+    ) -> StepFunc {
         let seed = gen_seed_from(rng);
         let value = format!(
             "tx-{}: $ mercat-mediator justify-transaction --sender {} --receiver {} --mediator {} --ticker {} --tx-id {} --seed {} --db-dir {} {}",
@@ -333,7 +346,7 @@ impl Transfer {
             self.ticker,
             self.tx_id,
             seed,
-            path_to_string(&chain_db_dir),
+            path_to_string(&chain_db_dir.root),
             cheater_flag(self.mediator.cheater)
         );
         let ticker = self.ticker.clone();
@@ -341,7 +354,14 @@ impl Transfer {
         let receiver = self.receiver.name.clone();
         let mediator = self.mediator.name.clone();
         let tx_id = self.tx_id;
-        let reject = !self.mediator_approves;
+        let rejection_reason = if self.mediator_approves {
+            None
+        } else {
+            Some(RejectionReason {
+                kind: RejectionKind::Other,
+                message: String::from("Rejected by the test scenario."),
+            })
+        };
         let cheat = self.mediator.cheater;
         return Box::new(move || {
             info!("Running: {}", value.clone());
@@ -353,8 +373,10 @@ impl Transfer {
                 ticker.clone(),
                 seed.clone(),
                 false, // Do not print the transaction data to stdout.
+                Encoding::default(),
                 tx_id,
-                reject,
+                rejection_reason.clone(),
+                false,
                 cheat,
             )?;
             Ok(value.clone())
@@ -364,7 +386,7 @@ impl Transfer {
     pub fn operations_order<T: RngCore + CryptoRng>(
         &self,
         rng: &mut T,
-        chain_db_dir: PathBuf,
+        chain_db_dir: ChainDirs,
     ) -> Vec<StepFunc> {
         vec![
             self.send(rng, chain_db_dir.clone()),
@@ -378,7 +400,7 @@ impl Create {
     pub fn create_account<T: RngCore + CryptoRng>(
         &self,
         rng: &mut T,
-        chain_db_dir: PathBuf,
+        chain_db_dir: ChainDirs,
     ) -> StepFunc {
         let seed = gen_seed_from(rng);
         if let Some(ticker) = self.ticker.clone() {
@@ -389,7 +411,7 @@ impl Create {
                 ticker,
                 self.owner.name,
                 seed,
-                path_to_string(&chain_db_dir),
+                path_to_string(&chain_db_dir.root),
                 self.tx_id,
                 cheater_flag(self.owner.cheater)
             );
@@ -405,8 +427,11 @@ impl Create {
                     ticker.clone(),
                     owner.clone(),
                     false, // Do not print the transaction data to stdout.
+                    Encoding::default(),
                     tx_id,
                     cheat,
+                    false, // Do not check for reuse of the encryption randomness.
+                    false, // Do not encrypt the secret account.
                 )?;
                 Ok(value.clone())
             });
@@ -417,13 +442,19 @@ impl Create {
                 self.tx_id,
                 self.owner.name,
                 seed,
-                path_to_string(&chain_db_dir),
+                path_to_string(&chain_db_dir.root),
                 cheater_flag(self.owner.cheater)
             );
             let owner = self.owner.name.clone();
             return Box::new(move || {
                 info!("Running: {}", value.clone());
-                process_create_mediator(seed.clone(), chain_db_dir.clone(), owner.clone())?;
+                process_create_mediator(
+                    seed.clone(),
+                    chain_db_dir.clone(),
+                    owner.clone(),
+                    Encoding::default(),
+                    false, // Do not encrypt the secret account.
+                )?;
                 Ok(value.clone())
             });
         }
@@ -432,14 +463,14 @@ impl Create {
     pub fn operations_order<T: RngCore + CryptoRng>(
         &self,
         rng: &mut T,
-        chain_db_dir: PathBuf,
+        chain_db_dir: ChainDirs,
     ) -> Vec<StepFunc> {
         vec![self.create_account(rng, chain_db_dir.clone())]
     }
 }
 
 impl Issue {
-    pub fn issue<T: RngCore + CryptoRng>(&self, rng: &mut T, chain_db_dir: PathBuf) -> StepFunc {
+    pub fn issue<T: RngCore + CryptoRng>(&self, rng: &mut T, chain_db_dir: ChainDirs) -> StepFunc {
         let seed = gen_seed_from(rng);
         let value = format!(
             "tx-{}: $ mercat-account issue --account-id-from-ticker {} --amount {} --issuer {} --tx-id {} --seed {} --db-dir {} {}",
@@ -449,7 +480,7 @@ impl Issue {
             self.issuer.name,
             self.tx_id,
             seed,
-            path_to_string(&chain_db_dir),
+            path_to_string(&chain_db_dir.root),
             cheater_flag(self.issuer.cheater)
         );
         let ticker = self.ticker.clone();
@@ -466,6 +497,7 @@ impl Issue {
                 ticker.clone(),
                 amount,
                 false, // Do not print the transaction data to stdout.
+                Encoding::default(),
                 tx_id,
                 cheat,
             )?;
@@ -476,18 +508,18 @@ impl Issue {
     pub fn operations_order<T: RngCore + CryptoRng>(
         &self,
         rng: &mut T,
-        chain_db_dir: PathBuf,
+        chain_db_dir: ChainDirs,
     ) -> Vec<StepFunc> {
         vec![self.issue(rng, chain_db_dir.clone())]
     }
 }
 
 impl Validate {
-    pub fn validate(&self, chain_db_dir: PathBuf) -> StepFunc {
+    pub fn validate(&self, chain_db_dir: ChainDirs) -> StepFunc {
         // validate a normal account
         let value = format!(
             "tx-NA: $ mercat-validator validate --db-dir {}",
-            path_to_string(&chain_db_dir),
+            path_to_string(&chain_db_dir.root),
         );
         return Box::new(move || {
             info!("Running: {}", value.clone());
@@ -496,7 +528,7 @@ impl Validate {
         });
     }
 
-    pub fn operations_order(&self, chain_db_dir: PathBuf) -> Vec<StepFunc> {
+    pub fn operations_order(&self, chain_db_dir: ChainDirs) -> Vec<StepFunc> {
         vec![self.validate(chain_db_dir)]
     }
 }
@@ -505,7 +537,7 @@ impl TransactionMode {
     fn sequence<T: RngCore + CryptoRng>(
         &self,
         rng: &mut T,
-        chain_db_dir: PathBuf,
+        chain_db_dir: ChainDirs,
     ) -> Vec<StepFunc> {
         match self {
             TransactionMode::Transaction(transaction) => {
@@ -563,7 +595,7 @@ impl TestCase {
         info!(
             "tx-N/A: $ mercat-chain-setup --ticker-names {} --db-dir {}",
             self.ticker_names.join(" "),
-            path_to_string(&self.chain_db_dir.clone()),
+            path_to_string(&self.chain_db_dir.root),
         );
 
         for transaction in self
@@ -590,8 +622,7 @@ impl TestCase {
     /// the balance with the secret account from the off-chain directory.
     fn resulting_accounts(&self) -> Result<HashSet<InputAccount>, Error> {
         let mut accounts: HashSet<InputAccount> = HashSet::new();
-        let mut path = self.chain_db_dir.clone();
-        path.push(ON_CHAIN_DIR);
+        let path = chain_dir(self.chain_db_dir.clone(), ON_CHAIN_DIR);
 
         for dir in all_dirs_in_dir(path)? {
             if let Some(user) = dir.file_name().and_then(|user| user.to_str()) {
@@ -938,7 +969,7 @@ fn parse_config(path: PathBuf, chain_db_dir: PathBuf) -> Result<TestCase, Error>
         ticker_names,
         transactions,
         accounts_outcome,
-        chain_db_dir,
+        chain_db_dir: ChainDirs::new(chain_db_dir),
     })
 }
 
@@ -972,34 +1003,40 @@ fn run_from(mode: &str) {
 
             let mut separate_chain_db_dir = chain_db_dir.clone();
             separate_chain_db_dir.push(file_name);
-            let testcase = &parse_config(config, separate_chain_db_dir).unwrap();
-            info!("----------------------------------------------------------------------------------");
-            info!("- Running test case: {}.", testcase.title);
-            info!("----------------------------------------------------------------------------------");
-            let want = &testcase.accounts_outcome;
-            let got = testcase.run();
-            if let Err(error) = got {
-                assert!(
-                    false,
-                    format!(
-                        "Test was expected to succeed, but failed with {:#?}.",
-                        error
-                    )
-                );
-            } else {
-                let got = got.unwrap();
-                assert!(
-                    accounts_are_equal(want, &got),
-                    format!(
-                        "Test failed due to account value mismatch.\nWant: {:#?}, got: {:#?}",
-                        want, got
-                    )
-                );
-            }
+            run_scenario(config, separate_chain_db_dir);
         }
     }
 }
 
+/// Runs a single test case and asserts that the accounts end up as the test case expects.
+#[allow(unused)]
+fn run_scenario(config: PathBuf, chain_db_dir: PathBuf) {
+    let testcase = &parse_config(config, chain_db_dir).unwrap();
+    info!("----------------------------------------------------------------------------------");
+    info!("- Running test case: {}.", testcase.title);
+    info!("----------------------------------------------------------------------------------");
+    let want = &testcase.accounts_outcome;
+    let got = testcase.run();
+    if let Err(error) = got {
+        assert!(
+            false,
+            format!(
+                "Test was expected to succeed, but failed with {:#?}.",
+                error
+            )
+        );
+    } else {
+        let got = got.unwrap();
+        assert!(
+            accounts_are_equal(want, &got),
+            format!(
+                "Test failed due to account value mismatch.\nWant: {:#?}, got: {:#?}",
+                want, got
+            )
+        );
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 // -                                            Tests                                             -
 // ------------------------------------------------------------------------------------------------
@@ -1007,7 +1044,11 @@ fn run_from(mode: &str) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::init_print_logger;
+    use crate::{
+        cheating::{self, CheatOperation, CheatStrategy},
+        init_print_logger,
+        test_utils::unittest_dir,
+    };
     use env_logger;
     use log::debug;
     use std::sync::Once;
@@ -1044,6 +1085,40 @@ mod tests {
         run_from("node");
     }
 
+    #[test]
+    fn test_every_cheat_strategy_is_detected() {
+        initialize();
+        let chain_db_dir = unittest_dir("cheat_strategies");
+
+        // The outcome of each of these scenarios requires the cheater's transaction to fail.
+        let scenario = |operation: CheatOperation| match operation {
+            CheatOperation::CreateAccount => "cheat_in_account.yml",
+            CheatOperation::IssueAsset => "issuer_cheats_in_issue_single.yml",
+            CheatOperation::CreateTransaction => "sender_cheats_in_single_transaction.yml",
+            CheatOperation::FinalizeTransaction => "receiver_cheats_in_single_transaction.yml",
+            CheatOperation::JustifyTransaction => "mediator_cheats_in_single_transaction.yml",
+        };
+        for strategy in CheatStrategy::ALL {
+            let operations: Vec<&CheatOperation> = CheatOperation::ALL
+                .iter()
+                .filter(|operation| strategy.applies_to(**operation))
+                .collect();
+            assert!(!operations.is_empty(), "{:?} is never used.", strategy);
+
+            cheating::force_strategy(Some(*strategy));
+            for operation in operations {
+                let mut config = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+                config.push("scenarios/unittest/node");
+                config.push(scenario(*operation));
+                let mut separate_chain_db_dir = chain_db_dir.clone();
+                separate_chain_db_dir.push(format!("{:?}_{:?}", strategy, operation));
+                run_scenario(config, separate_chain_db_dir);
+            }
+        }
+        cheating::force_strategy(None);
+        let _ = std::fs::remove_dir_all(&chain_db_dir);
+    }
+
     #[wasm_bindgen_test]
     fn test_on_wasm() {
         cleanup_previous_run("wasm");
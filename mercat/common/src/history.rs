@@ -0,0 +1,482 @@
+//! Read-only reconstruction of an account's history from the transaction files. Both the
+//! validated and the unvalidated transactions are included.
+
+use crate::{
+    all_tx_files, debug_decrypt_with, discrete_log::BsgsSolver, doctor::validated_tx_ids,
+    errors::Error, last_verified_tx_id, load_account_map, load_tx_file, parse_tx_name,
+    tx_balance_deltas, ChainDirs, CoreTransaction, Direction, PrintableAccountId,
+};
+use codec::Encode;
+use cryptography::mercat::{EncryptedAmount, EncryptedAssetId, TransferTxState, TxSubstate};
+use std::collections::BTreeMap;
+
+/// The position of a transaction file's state among the steps of its transaction.
+fn step_of(state: &str) -> u8 {
+    if state == TransferTxState::Finalization(TxSubstate::Started).to_string() {
+        1
+    } else if state == TransferTxState::Justification(TxSubstate::Started).to_string()
+        || state == TransferTxState::Justification(TxSubstate::Rejected).to_string()
+    {
+        2
+    } else {
+        // Account creation, asset issuance, and transfer initialization.
+        0
+    }
+}
+
+/// Returns the account ids that a transaction refers to.
+fn referenced_account_ids(tx: &CoreTransaction) -> Vec<EncryptedAssetId> {
+    match tx {
+        CoreTransaction::Account { account_tx, .. } => {
+            vec![account_tx.pub_account.enc_asset_id.clone()]
+        }
+        CoreTransaction::IssueInit { issue_tx, .. } => vec![issue_tx.account_id.clone()],
+        CoreTransaction::TransferInit { tx, .. } => vec![
+            tx.memo.sender_account_id.clone(),
+            tx.memo.receiver_account_id.clone(),
+        ],
+        CoreTransaction::TransferFinalize { tx, .. } => {
+            let memo = &tx.init_data.memo;
+            vec![
+                memo.sender_account_id.clone(),
+                memo.receiver_account_id.clone(),
+            ]
+        }
+        CoreTransaction::TransferJustify { tx, .. } => {
+            let memo = &tx.finalized_data.init_data.memo;
+            vec![
+                memo.sender_account_id.clone(),
+                memo.receiver_account_id.clone(),
+            ]
+        }
+        CoreTransaction::MediatorCredit { credit, .. } => {
+            vec![credit.credit.beneficiary_account_id.clone()]
+        }
+        CoreTransaction::TransferRejected { .. } | CoreTransaction::Invalid => vec![],
+    }
+}
+
+/// Loads the files of a single transaction. If any of them refers to `account_id`, all of them
+/// are returned. Otherwise, nothing is returned.
+fn load_if_referenced(
+    files: Vec<(u32, String, String, String)>,
+    account_id: &str,
+) -> Vec<Result<CoreTransaction, Error>> {
+    let txs: Vec<Result<CoreTransaction, Error>> = files
+        .into_iter()
+        .map(|(tx_id, user, state, path)| load_tx_file(tx_id, user, state, path))
+        .collect();
+    let is_referenced = txs.iter().any(|tx| match tx {
+        Ok(tx) => referenced_account_ids(tx)
+            .into_iter()
+            .any(|id| PrintableAccountId(id.encode()).to_string() == account_id),
+        // Surface the failures instead of silently dropping a possibly relevant transaction.
+        Err(_) => true,
+    });
+    if is_referenced {
+        txs
+    } else {
+        vec![]
+    }
+}
+
+fn history_files(
+    db_dir: ChainDirs,
+    user: &str,
+    ticker: &str,
+) -> Result<(String, Vec<Vec<(u32, String, String, String)>>), Error> {
+    let account_id = load_account_map(db_dir.clone())
+        .into_iter()
+        .find(|(_, (account_user, account_ticker, _))| {
+            account_user == user && account_ticker == ticker
+        })
+        .map(|(account_id, _)| account_id)
+        .ok_or(Error::UserAccountNotFound {
+            user: user.to_string(),
+            ticker: ticker.to_string(),
+        })?;
+
+    let mut txs: BTreeMap<u32, Vec<(u32, String, String, String)>> = BTreeMap::new();
+    for tx_file in all_tx_files(db_dir)? {
+        let parsed = parse_tx_name(tx_file)?;
+        txs.entry(parsed.0).or_insert_with(Vec::new).push(parsed);
+    }
+    let txs = txs
+        .into_iter()
+        .map(|(_, mut files)| {
+            files.sort_by_key(|(_, _, state, _)| step_of(state));
+            files
+        })
+        .collect();
+    Ok((account_id, txs))
+}
+
+/// Returns every instruction that refers to the `user`'s `ticker` account, i.e., its creation,
+/// the issuances, and the incoming and outgoing transfers. The instructions are ordered by their
+/// transaction id, and by the steps within each transaction. The files are only loaded as the
+/// iterator advances.
+pub fn account_history(
+    db_dir: ChainDirs,
+    user: &str,
+    ticker: &str,
+) -> impl Iterator<Item = Result<CoreTransaction, Error>> {
+    let (account_id, txs, error) = match history_files(db_dir, user, ticker) {
+        Ok((account_id, txs)) => (account_id, txs, None),
+        Err(error) => (String::new(), vec![], Some(error)),
+    };
+    error.into_iter().map(Err).chain(
+        txs.into_iter()
+            .flat_map(move |files| load_if_referenced(files, &account_id)),
+    )
+}
+
+/// Returns the balance of the `user`'s `ticker` account right after the transaction
+/// `as_of_tx_id`. The balance is reconstructed from the initial balance of the account by
+/// replaying the balance changes of the validated instructions in its history, and is decrypted
+/// with the secret account. Fails with `AsOfBeyondHistory` if `as_of_tx_id` is after the last
+/// validated transaction.
+pub fn process_balance_as_of(
+    db_dir: ChainDirs,
+    user: &str,
+    ticker: &str,
+    as_of_tx_id: u32,
+) -> Result<u32, Error> {
+    let last_validated = last_verified_tx_id(db_dir.clone());
+    if as_of_tx_id as i32 > last_validated {
+        return Err(Error::AsOfBeyondHistory {
+            as_of_tx_id,
+            last_validated,
+        });
+    }
+    let validated = validated_tx_ids(db_dir.clone())?;
+
+    let mut account: Option<(EncryptedAssetId, EncryptedAmount)> = None;
+    for tx in account_history(db_dir.clone(), user, ticker) {
+        let tx = tx?;
+        let tx_id = match &tx {
+            CoreTransaction::Account {
+                account_tx, tx_id, ..
+            } => {
+                if *tx_id <= as_of_tx_id {
+                    account = Some((
+                        account_tx.pub_account.enc_asset_id,
+                        account_tx.initial_balance,
+                    ));
+                }
+                continue;
+            }
+            // Only the final instruction of a transaction changes the balances.
+            CoreTransaction::IssueInit { tx_id, .. }
+            | CoreTransaction::TransferJustify { tx_id, .. }
+            | CoreTransaction::MediatorCredit { tx_id, .. } => *tx_id,
+            _ => continue,
+        };
+        if tx_id > as_of_tx_id {
+            break;
+        }
+        if let Some((account_id, balance)) = account.as_mut() {
+            if !validated.contains(&tx_id) {
+                continue;
+            }
+            for (delta_account_id, direction, amount) in tx_balance_deltas(&tx) {
+                if delta_account_id == *account_id {
+                    match direction {
+                        Direction::Incoming => *balance += amount,
+                        Direction::Outgoing => *balance -= amount,
+                    }
+                }
+            }
+        }
+    }
+
+    match account {
+        Some((account_id, balance)) => debug_decrypt_with(account_id, balance, db_dir, &BsgsSolver),
+        // The account did not exist yet.
+        None => Ok(0),
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account,
+        account_issue::process_issue_asset,
+        account_transfer::{process_create_tx, process_finalize_tx},
+        chain_setup::process_asset_id_creation,
+        debug_decrypt_account_balance,
+        encoding::Encoding,
+        gen_seed,
+        justify::{justify_asset_transfer_transaction, process_create_mediator},
+        test_utils::test_db_dir,
+        validate::validate_all_pending,
+    };
+
+    /// Returns the tx_id and the step of each instruction, e.g., (5, "init").
+    fn summarize(history: Vec<Result<CoreTransaction, Error>>) -> Vec<(u32, &'static str)> {
+        history
+            .into_iter()
+            .map(|tx| match tx.unwrap() {
+                CoreTransaction::Account { tx_id, .. } => (tx_id, "account"),
+                CoreTransaction::IssueInit { tx_id, .. } => (tx_id, "issue"),
+                CoreTransaction::TransferInit { tx_id, .. } => (tx_id, "init"),
+                CoreTransaction::TransferFinalize { tx_id, .. } => (tx_id, "finalize"),
+                CoreTransaction::TransferJustify { tx_id, .. } => (tx_id, "justify"),
+                other => panic!("Unexpected instruction {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_account_history() {
+        let db_dir = test_db_dir("history");
+
+        let acme = String::from("ACME");
+        let alice = String::from("alice");
+        let bob = String::from("bob");
+        let mike = String::from("mike");
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone(), String::from("BETA")])
+            .unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user, ticker) in &[(1, &alice, "ACME"), (2, &bob, "ACME"), (3, &alice, "BETA")]
+        {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                ticker.to_string(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            4,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        // The transfer is left unvalidated.
+        process_create_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            5,
+            0,
+            false,
+        )
+        .unwrap();
+        process_finalize_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            5,
+            false,
+        )
+        .unwrap();
+        justify_asset_transfer_transaction(
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            gen_seed(),
+            false,
+            Encoding::default(),
+            5,
+            None,
+            false,
+            false,
+        )
+        .unwrap();
+
+        let transfer = vec![(5, "init"), (5, "finalize"), (5, "justify")];
+        let mut want = vec![(1, "account"), (4, "issue")];
+        want.extend(transfer.clone());
+        assert_eq!(
+            summarize(account_history(db_dir.clone(), "alice", "ACME").collect()),
+            want
+        );
+        let mut want = vec![(2, "account")];
+        want.extend(transfer);
+        assert_eq!(
+            summarize(account_history(db_dir.clone(), "bob", "ACME").collect()),
+            want
+        );
+        assert_eq!(
+            summarize(account_history(db_dir.clone(), "alice", "BETA").collect()),
+            vec![(3, "account")]
+        );
+        match account_history(db_dir.clone(), "bob", "BETA").next() {
+            Some(Err(Error::UserAccountNotFound { user, ticker })) => {
+                assert_eq!((user.as_str(), ticker.as_str()), ("bob", "BETA"))
+            }
+            other => panic!("Expected UserAccountNotFound, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_balance_as_of() {
+        let db_dir = test_db_dir("balance_as_of");
+
+        let acme = String::from("ACME");
+        let (alice, bob, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        let balances = |db_dir: &ChainDirs| {
+            (
+                debug_decrypt_account_balance(alice.clone(), acme.clone(), db_dir.clone()).unwrap(),
+                debug_decrypt_account_balance(bob.clone(), acme.clone(), db_dir.clone()).unwrap(),
+            )
+        };
+        let mut want = vec![(2, (0, 0)), (3, balances(&db_dir))];
+        for (tx_id, amount) in &[(4, 4), (5, 3)] {
+            process_create_tx(
+                gen_seed(),
+                db_dir.clone(),
+                alice.clone(),
+                bob.clone(),
+                mike.clone(),
+                acme.clone(),
+                *amount,
+                false,
+                Encoding::default(),
+                *tx_id,
+                0,
+                false,
+            )
+            .unwrap();
+            process_finalize_tx(
+                gen_seed(),
+                db_dir.clone(),
+                alice.clone(),
+                bob.clone(),
+                acme.clone(),
+                *amount,
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+            )
+            .unwrap();
+            justify_asset_transfer_transaction(
+                db_dir.clone(),
+                alice.clone(),
+                bob.clone(),
+                mike.clone(),
+                acme.clone(),
+                gen_seed(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                None,
+                false,
+                false,
+            )
+            .unwrap();
+            validate_all_pending(db_dir.clone()).unwrap();
+            want.push((*tx_id, balances(&db_dir)));
+        }
+        assert_eq!(want[3].1, (6, 4));
+        assert_eq!(want[4].1, (3, 7));
+
+        for (tx_id, (alice_balance, bob_balance)) in want {
+            assert_eq!(
+                process_balance_as_of(db_dir.clone(), &alice, &acme, tx_id).unwrap(),
+                alice_balance
+            );
+            assert_eq!(
+                process_balance_as_of(db_dir.clone(), &bob, &acme, tx_id).unwrap(),
+                bob_balance
+            );
+        }
+        match process_balance_as_of(db_dir.clone(), &alice, &acme, 6) {
+            Err(Error::AsOfBeyondHistory {
+                as_of_tx_id,
+                last_validated,
+            }) => assert_eq!((as_of_tx_id, last_validated), (6, 5)),
+            other => panic!("Expected AsOfBeyondHistory, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
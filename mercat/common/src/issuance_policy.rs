@@ -0,0 +1,278 @@
+//! Per-ticker issuance policies. A policy bounds the amount of a single issuance and the set of
+//! the accounts that may receive issuances. The policies are signed by the governance key and
+//! kept on-chain, so that the validator enforces them without any configuration of its own.
+
+use crate::{
+    create_rng_from_seed,
+    errors::Error,
+    load_object,
+    mediator_credit::{ensure_signing_context, sign, verify_signature, MediatorSignature},
+    save_object, ChainDirs, COMMON_OBJECTS_DIR, OFF_CHAIN_DIR, ON_CHAIN_DIR,
+};
+use codec::{Decode, Encode};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT, ristretto::CompressedRistretto, scalar::Scalar,
+};
+use log::info;
+
+/// The off-chain directory of the governance secret key.
+pub const GOVERNANCE_DIR: &str = "governance";
+pub const GOVERNANCE_SECRET_KEY_FILE: &str = "governance_secret_key";
+pub const GOVERNANCE_PUBLIC_KEY_FILE: &str = "governance_public_key";
+
+pub(crate) const POLICY_SIGNATURE_LABEL: &[u8] = b"mercat-issuance-policy-signature";
+
+#[inline]
+pub fn issuance_policy_file(ticker: &str) -> String {
+    format!("issuance_policy_{}", ticker)
+}
+
+/// The limits of the issuances of a ticker. An empty list of beneficiaries allows any issuer.
+#[derive(Clone, Debug, Default, Encode, Decode)]
+pub struct IssuancePolicy {
+    pub ticker: String,
+    pub max_amount: u32,
+    pub beneficiaries: Vec<String>,
+}
+
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct SignedIssuancePolicy {
+    pub policy: IssuancePolicy,
+    pub signature: MediatorSignature,
+}
+
+/// Creates the governance key pair. The public key is published on-chain.
+pub fn process_create_governance_key(seed: String, db_dir: ChainDirs) -> Result<(), Error> {
+    let mut rng = create_rng_from_seed(Some(seed))?;
+    let secret_key = Scalar::random(&mut rng);
+    save_object(
+        db_dir.clone(),
+        OFF_CHAIN_DIR,
+        GOVERNANCE_DIR,
+        GOVERNANCE_SECRET_KEY_FILE,
+        &secret_key.to_bytes(),
+    )?;
+    save_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        GOVERNANCE_PUBLIC_KEY_FILE,
+        &(secret_key * RISTRETTO_BASEPOINT_POINT)
+            .compress()
+            .to_bytes(),
+    )
+}
+
+/// Signs the `policy` with the governance key and publishes it, replacing the previous policy
+/// of its ticker.
+pub fn process_set_issuance_policy(
+    seed: String,
+    db_dir: ChainDirs,
+    policy: IssuancePolicy,
+) -> Result<(), Error> {
+    let mut rng = create_rng_from_seed(Some(seed))?;
+    let secret_key: [u8; 32] = load_object(
+        db_dir.clone(),
+        OFF_CHAIN_DIR,
+        GOVERNANCE_DIR,
+        GOVERNANCE_SECRET_KEY_FILE,
+    )?;
+    let secret_key = Scalar::from_bits(secret_key);
+    let signature = sign(
+        &mut rng,
+        POLICY_SIGNATURE_LABEL,
+        &secret_key,
+        &(secret_key * RISTRETTO_BASEPOINT_POINT),
+        &policy.encode(),
+    );
+    save_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &issuance_policy_file(&policy.ticker),
+        &SignedIssuancePolicy {
+            policy: policy.clone(),
+            signature,
+        },
+    )?;
+    info!(
+        "CLI log: Set the issuance policy of {}: at most {} per issuance, to {}.",
+        policy.ticker,
+        policy.max_amount,
+        if policy.beneficiaries.is_empty() {
+            String::from("any account")
+        } else {
+            policy.beneficiaries.join(", ")
+        }
+    );
+    Ok(())
+}
+
+/// Returns an error if the ticker has a policy and the issuance of `amount` to the `issuer`
+/// violates it, or if the policy is not signed by the governance key. A ticker without a policy
+/// is not restricted.
+pub fn ensure_issuance_policy(
+    db_dir: ChainDirs,
+    ticker: &str,
+    issuer: &str,
+    amount: u32,
+    tx_id: u32,
+) -> Result<(), Error> {
+    let violation = |reason: String| Error::IssuancePolicyViolation { tx_id, reason };
+    let signed_policy: SignedIssuancePolicy = match load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &issuance_policy_file(ticker),
+    ) {
+        Ok(signed_policy) => signed_policy,
+        Err(_) => return Ok(()),
+    };
+
+    let pub_key: [u8; 32] = load_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        GOVERNANCE_PUBLIC_KEY_FILE,
+    )
+    .map_err(|_| violation(String::from("there is no governance key")))?;
+    let message = signed_policy.policy.encode();
+    let pub_key = CompressedRistretto(pub_key).decompress();
+    let is_signed = pub_key.map_or(false, |pub_key| {
+        verify_signature(
+            POLICY_SIGNATURE_LABEL,
+            &pub_key,
+            &message,
+            &signed_policy.signature,
+        )
+    });
+    if let (false, Some(pub_key)) = (is_signed, pub_key) {
+        ensure_signing_context(
+            tx_id,
+            POLICY_SIGNATURE_LABEL,
+            &pub_key,
+            &message,
+            &signed_policy.signature,
+        )?;
+    }
+    let policy = signed_policy.policy;
+    if !is_signed || policy.ticker != ticker {
+        return Err(violation(format!(
+            "the policy of {} is not signed by the governance key",
+            ticker
+        )));
+    }
+
+    if amount > policy.max_amount {
+        return Err(violation(format!(
+            "{} exceeds the maximum issuance of {}",
+            amount, policy.max_amount
+        )));
+    }
+    if !policy.beneficiaries.is_empty() && !policy.beneficiaries.iter().any(|b| b == issuer) {
+        return Err(violation(format!(
+            "{} may not receive issuances of {}",
+            issuer, ticker
+        )));
+    }
+    Ok(())
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account, account_issue::process_issue_asset,
+        chain_setup::process_asset_id_creation, debug_decrypt_account_balance, encoding::Encoding,
+        gen_seed, test_utils::test_db_dir, validate::validate_all_pending,
+    };
+
+    #[test]
+    fn test_issuance_policy() {
+        let db_dir = test_db_dir("issuance_policy");
+
+        let acme = String::from("ACME");
+        let (alice, bob) = (String::from("alice"), String::from("bob"));
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        process_create_governance_key(gen_seed(), db_dir.clone()).unwrap();
+        let policy = IssuancePolicy {
+            ticker: acme.clone(),
+            max_amount: 100,
+            beneficiaries: vec![alice.clone()],
+        };
+        process_set_issuance_policy(gen_seed(), db_dir.clone(), policy).unwrap();
+
+        let ensure =
+            |issuer: &str, amount| ensure_issuance_policy(db_dir.clone(), &acme, issuer, amount, 3);
+        ensure(&alice, 100).unwrap();
+        for (issuer, amount) in &[(&alice, 101), (&bob, 1)] {
+            match ensure(issuer, *amount) {
+                Err(Error::IssuancePolicyViolation { tx_id, .. }) => assert_eq!(tx_id, 3),
+                result => panic!("Expected IssuancePolicyViolation, got {:?}", result),
+            }
+        }
+
+        // The validator applies the compliant issuance and rejects the violating one.
+        for (tx_id, amount) in &[(3, 60), (4, 200)] {
+            process_issue_asset(
+                gen_seed(),
+                db_dir.clone(),
+                alice.clone(),
+                acme.clone(),
+                *amount,
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(
+            debug_decrypt_account_balance(alice.clone(), acme.clone(), db_dir.clone()).unwrap(),
+            60
+        );
+
+        // A policy that is changed without the governance key is refused altogether.
+        let mut forged: SignedIssuancePolicy = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &issuance_policy_file(&acme),
+        )
+        .unwrap();
+        forged.policy.max_amount = 1000;
+        save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &issuance_policy_file(&acme),
+            &forged,
+        )
+        .unwrap();
+        assert!(ensure(&alice, 10).is_err());
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
@@ -1,11 +1,19 @@
+#[cfg(any(test, feature = "cheating"))]
+use crate::cheating;
 use crate::{
-    compute_enc_pending_balance, confidential_transaction_file, construct_path,
-    create_rng_from_seed, errors::Error, last_ordering_state, load_object, non_empty_account_id,
-    save_object, user_public_account_balance_file, user_public_account_file, OrderedPubAccount,
-    OrderedTransferInstruction, TransferInstruction, COMMON_OBJECTS_DIR,
-    MEDIATOR_PUBLIC_ACCOUNT_FILE, OFF_CHAIN_DIR, ON_CHAIN_DIR, SECRET_ACCOUNT_FILE,
+    audit::record_state_transition,
+    balances::{compute_enc_pending_balance, ConfirmedBalance},
+    confidential_transaction_file, construct_path, create_rng_from_seed,
+    encoding::Encoding,
+    ensure_cheating_supported,
+    errors::Error,
+    last_ordering_state, load_object, outcome_label, save_object,
+    secret_store::{load_secret_object, passphrase, save_secret_object},
+    tx_state::save_instruction,
+    user_public_account_balance_file, user_public_account_file, ChainDirs, OrderedPubAccount,
+    OrderedTransferInstruction, RejectionReason, TransferInstruction, COMMON_OBJECTS_DIR,
+    MEDIATOR_PUBLIC_ACCOUNT_FILE, ON_CHAIN_DIR, SECRET_ACCOUNT_FILE,
 };
-use base64;
 use codec::{Decode, Encode};
 use cryptography::{
     asset_id_from_ticker,
@@ -20,7 +28,7 @@ use curve25519_dalek::scalar::Scalar;
 use log::info;
 use metrics::timing;
 use rand::{CryptoRng, RngCore};
-use std::{path::PathBuf, time::Instant};
+use std::time::Instant;
 
 fn generate_mediator_keys<R: RngCore + CryptoRng>(
     rng: &mut R,
@@ -39,7 +47,18 @@ fn generate_mediator_keys<R: RngCore + CryptoRng>(
     )
 }
 
-pub fn process_create_mediator(seed: String, db_dir: PathBuf, user: String) -> Result<(), Error> {
+pub fn process_create_mediator(
+    seed: String,
+    db_dir: ChainDirs,
+    user: String,
+    encoding: Encoding,
+    encrypt_secrets: bool,
+) -> Result<(), Error> {
+    let passphrase = if encrypt_secrets {
+        Some(passphrase()?)
+    } else {
+        None
+    };
     // Setup the rng.
     let mut rng = create_rng_from_seed(Some(seed))?;
 
@@ -62,16 +81,17 @@ pub fn process_create_mediator(seed: String, db_dir: PathBuf, user: String) -> R
         &public_account,
     )?;
 
-    save_object(
+    save_secret_object(
         db_dir,
-        OFF_CHAIN_DIR,
         &user,
         SECRET_ACCOUNT_FILE,
         &private_account,
+        passphrase.as_deref(),
     )?;
     info!(
-        "CLI log: Mediator keys as base64:\n{}\n",
-        base64::encode(public_account.encode())
+        "CLI log: Mediator keys as {}:\n{}\n",
+        encoding,
+        encoding.encode(public_account.encode())
     );
     timing!(
         "mediator.save_keys",
@@ -83,18 +103,53 @@ pub fn process_create_mediator(seed: String, db_dir: PathBuf, user: String) -> R
     Ok(())
 }
 
+/// Returns true if the mediator has already justified or rejected the transaction.
+fn is_already_justified(
+    db_dir: ChainDirs,
+    sender: &String,
+    mediator: &String,
+    ticker: &String,
+    tx_id: u32,
+) -> bool {
+    let justified = confidential_transaction_file(
+        tx_id,
+        mediator,
+        ticker,
+        TransferTxState::Justification(TxSubstate::Started),
+    );
+    let rejected = confidential_transaction_file(
+        tx_id,
+        sender,
+        ticker,
+        TransferTxState::Justification(TxSubstate::Rejected),
+    );
+    [justified, rejected].iter().any(|file_name| {
+        construct_path(db_dir.clone(), ON_CHAIN_DIR, COMMON_OBJECTS_DIR, file_name)
+            .map_or(false, |path| path.exists())
+    })
+}
+
+/// Justifies or rejects a transfer. Fails with `Error::TransactionAlreadyJustified` if the
+/// transaction was already justified or rejected, unless `re_justify` is set.
 pub fn justify_asset_transfer_transaction(
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     sender: String,
     receiver: String,
     mediator: String,
     ticker: String,
     seed: String,
     stdout: bool,
+    encoding: Encoding,
     tx_id: u32,
-    reject: bool,
+    rejection_reason: Option<RejectionReason>,
+    re_justify: bool,
     cheat: bool,
 ) -> Result<(), Error> {
+    ensure_cheating_supported(cheat)?;
+    if !re_justify && is_already_justified(db_dir.clone(), &sender, &mediator, &ticker, tx_id) {
+        return Err(Error::TransactionAlreadyJustified { tx_id });
+    }
+
     // Load the transaction, mediator's credentials, and issuer's public account.
     let justify_load_objects_timer = Instant::now();
     let mut rng = create_rng_from_seed(Some(seed))?;
@@ -102,6 +157,7 @@ pub fn justify_asset_transfer_transaction(
     let instruction_path = confidential_transaction_file(
         tx_id,
         &sender,
+        &ticker,
         TransferTxState::Finalization(TxSubstate::Started),
     );
     let instruction: OrderedTransferInstruction = load_object(
@@ -111,24 +167,21 @@ pub fn justify_asset_transfer_transaction(
         &instruction_path,
     )?;
 
+    let instruction_path = construct_path(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &instruction_path,
+    )?;
     let asset_tx = FinalizedTransferTx::decode(&mut &instruction.data[..]).map_err(|error| {
         Error::ObjectLoadError {
             error,
-            path: construct_path(
-                db_dir.clone(),
-                ON_CHAIN_DIR,
-                COMMON_OBJECTS_DIR,
-                &instruction_path,
-            ),
+            path: instruction_path,
         }
     })?;
 
-    let mediator_account: MediatorAccount = load_object(
-        db_dir.clone(),
-        OFF_CHAIN_DIR,
-        &mediator,
-        SECRET_ACCOUNT_FILE,
-    )?;
+    let mediator_account: MediatorAccount =
+        load_secret_object(db_dir.clone(), &mediator, SECRET_ACCOUNT_FILE)?;
 
     let sender_ordered_pub_account: OrderedPubAccount = load_object(
         db_dir.clone(),
@@ -172,64 +225,83 @@ pub fn justify_asset_transfer_transaction(
 
     let pending_balance = compute_enc_pending_balance(
         &sender,
+        &ticker,
         ordering_state,
         last_processed_tx_counter,
-        last_processed_account_balance,
+        ConfirmedBalance(last_processed_account_balance),
+        0,
         db_dir.clone(),
     )?;
 
     let asset_id = asset_id_from_ticker(&ticker).map_err(|error| Error::LibraryError { error })?;
-    let mut justified_tx = CtxMediator
+    let justified_tx = CtxMediator
         .justify_transaction(
             asset_tx.clone(),
             &mediator_account.encryption_key,
             &sender_ordered_pub_account.pub_account,
-            &pending_balance,
+            pending_balance.enc_amount(),
             &receiver_ordered_pub_account.pub_account,
             &[],
             asset_id,
             &mut rng,
         )
-        .map_err(|error| Error::LibraryError { error })?;
-
-    if cheat {
-        info!(
-            "CLI log: tx-{}: Cheating by overwriting the sender's account id.",
-            tx_id
-        );
-
-        justified_tx.finalized_data.init_data.memo.sender_account_id += non_empty_account_id();
-    }
+        .map_err(|error| Error::LibraryError { error });
 
     timing!(
         "mediator.justify_tx.library",
         justify_library_timer,
         Instant::now(),
-        "tx_id" => tx_id.to_string()
+        "tx_id" => tx_id.to_string(),
+        "result" => outcome_label(&justified_tx)
     );
+    let justified_tx = justified_tx?;
+
+    #[cfg(any(test, feature = "cheating"))]
+    let justified_tx = if cheat
+        && cheating::random_strategy(&mut rng, cheating::CheatOperation::JustifyTransaction)
+            == cheating::CheatStrategy::ChangeJustifiedSenderAccountId
+    {
+        cheating::cheat_on_justified_tx(tx_id, justified_tx)
+    } else {
+        justified_tx
+    };
 
     let next_instruction;
     let justify_save_objects_timer = Instant::now();
-    // If the `reject` flag is set, save the transaction as rejected.
-    if reject {
+    // If a rejection reason is given, save the transaction as rejected along with the reason.
+    if let Some(rejection_reason) = rejection_reason {
+        info!(
+            "CLI log: tx-{}: Rejecting the transaction. Reason: {}",
+            tx_id, rejection_reason
+        );
         let rejected_state = TransferTxState::Justification(TxSubstate::Rejected);
         next_instruction = TransferInstruction {
             data: asset_tx.encode().to_vec(),
             state: rejected_state,
+            rejection_reason: Some(rejection_reason),
         };
 
-        save_object(
+        save_instruction(
             db_dir.clone(),
-            ON_CHAIN_DIR,
             COMMON_OBJECTS_DIR,
-            &confidential_transaction_file(tx_id, &sender, rejected_state),
+            tx_id,
+            &ticker,
+            &sender,
             &next_instruction,
         )?;
+        record_state_transition(
+            db_dir,
+            tx_id,
+            &mediator,
+            Some(TransferTxState::Finalization(TxSubstate::Started)),
+            rejected_state,
+        )?;
         if stdout {
             info!(
-                "CLI log: tx-{}: Transaction as base64:\n{}\n",
+                "CLI log: tx-{}: Transaction as {}:\n{}\n",
                 tx_id,
-                base64::encode(asset_tx.encode())
+                encoding,
+                encoding.encode(asset_tx.encode())
             );
         }
     } else {
@@ -238,20 +310,30 @@ pub fn justify_asset_transfer_transaction(
         next_instruction = TransferInstruction {
             data: justified_tx.encode().to_vec(),
             state: new_state,
+            rejection_reason: None,
         };
 
-        save_object(
-            db_dir,
-            ON_CHAIN_DIR,
+        save_instruction(
+            db_dir.clone(),
             COMMON_OBJECTS_DIR,
-            &confidential_transaction_file(tx_id, &mediator, new_state),
+            tx_id,
+            &ticker,
+            &mediator,
             &next_instruction,
         )?;
+        record_state_transition(
+            db_dir,
+            tx_id,
+            &mediator,
+            Some(TransferTxState::Finalization(TxSubstate::Started)),
+            new_state,
+        )?;
         if stdout {
             info!(
-                "CLI log: tx-{}: Transaction as base64:\n{}\n",
+                "CLI log: tx-{}: Transaction as {}:\n{}\n",
                 tx_id,
-                base64::encode(justified_tx.encode())
+                encoding,
+                encoding.encode(justified_tx.encode())
             );
         }
     }
@@ -265,3 +347,175 @@ pub fn justify_asset_transfer_transaction(
 
     Ok(())
 }
+
+/// Returns the reason that the mediator gave for rejecting the sender's transaction.
+pub fn transfer_rejection_reason(
+    db_dir: ChainDirs,
+    sender: String,
+    ticker: String,
+    tx_id: u32,
+) -> Result<Option<RejectionReason>, Error> {
+    let instruction: TransferInstruction = load_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &confidential_transaction_file(
+            tx_id,
+            &sender,
+            &ticker,
+            TransferTxState::Justification(TxSubstate::Rejected),
+        ),
+    )?;
+    Ok(instruction.rejection_reason)
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account,
+        account_issue::process_issue_asset,
+        account_transfer::{process_create_tx, process_finalize_tx},
+        chain_setup::process_asset_id_creation,
+        gen_seed, load_object_from,
+        test_utils::test_db_dir,
+        validate::validate_all_pending,
+        RejectionKind,
+    };
+
+    #[test]
+    fn test_double_justification() {
+        let db_dir = test_db_dir("double_justification");
+
+        let acme = String::from("ACME");
+        let (alice, bob, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_create_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            4,
+            0,
+            false,
+        )
+        .unwrap();
+        process_finalize_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            4,
+            false,
+        )
+        .unwrap();
+
+        let justify = |rejection_reason: Option<RejectionReason>, re_justify: bool| {
+            justify_asset_transfer_transaction(
+                db_dir.clone(),
+                alice.clone(),
+                bob.clone(),
+                mike.clone(),
+                acme.clone(),
+                gen_seed(),
+                false,
+                Encoding::default(),
+                4,
+                rejection_reason,
+                re_justify,
+                false,
+            )
+        };
+        let justified_path = construct_path(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &confidential_transaction_file(
+                4,
+                &mike,
+                &acme,
+                TransferTxState::Justification(TxSubstate::Started),
+            ),
+        )
+        .unwrap();
+
+        justify(None, false).unwrap();
+        let first: TransferInstruction = load_object_from(justified_path.clone()).unwrap();
+
+        // Neither a second justification nor a rejection overwrites the first one.
+        let rejection = RejectionReason {
+            kind: RejectionKind::Other,
+            message: String::from("second thoughts"),
+        };
+        for rejection_reason in vec![None, Some(rejection)] {
+            match justify(rejection_reason, false) {
+                Err(Error::TransactionAlreadyJustified { tx_id }) => assert_eq!(tx_id, 4),
+                other => panic!("Expected TransactionAlreadyJustified, got {:?}", other),
+            }
+        }
+        let unchanged: TransferInstruction = load_object_from(justified_path.clone()).unwrap();
+        assert_eq!(unchanged.data, first.data);
+
+        // Re-justifying on purpose replaces the justification.
+        justify(None, true).unwrap();
+        let second: TransferInstruction = load_object_from(justified_path).unwrap();
+        assert_ne!(second.data, first.data);
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
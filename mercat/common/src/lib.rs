@@ -1,41 +1,71 @@
 //! A common library for utility functions.
 
+pub mod account_close;
 pub mod account_create;
 pub mod account_issue;
+pub mod account_reset;
 pub mod account_transfer;
+pub mod audit;
+pub mod balances;
+pub mod batch;
 pub mod chain_setup;
+#[cfg(any(test, feature = "cheating"))]
+pub mod cheating;
+pub mod compaction;
+pub mod discrete_log;
+pub mod doctor;
+pub mod encoding;
 pub mod errors;
 mod harness;
+pub mod history;
+pub mod issuance_policy;
 pub mod justify;
+pub mod mediator_credit;
+pub mod migration;
+pub mod payment_request;
+pub mod profile;
+pub mod secret_store;
+pub mod snapshot;
+pub mod supply;
+#[cfg(test)]
+mod test_utils;
+pub mod tx_ids;
+pub mod tx_state;
 pub mod validate;
+pub mod verification_cache;
+pub mod verify_proof;
 
 use base64;
 use codec::{Decode, Encode};
-use cryptography::{
-    asset_proofs::CipherText,
-    mercat::{
-        Account, AssetTxState, EncryptedAmount, EncryptedAssetId, FinalizedTransferTx,
-        InitializedAssetTx, InitializedTransferTx, JustifiedTransferTx, PubAccount, PubAccountTx,
-        SecAccount, TransferTxState, TxSubstate,
-    },
+use cryptography::mercat::{
+    Account, AssetTxState, EncryptedAmount, EncryptedAssetId, FinalizedTransferTx,
+    InitializedAssetTx, InitializedTransferTx, JustifiedTransferTx, PubAccount, PubAccountTx,
+    SecAccount, TransferTxMemo, TransferTxState, TxSubstate,
 };
 use curve25519_dalek::{constants::RISTRETTO_BASEPOINT_POINT, scalar::Scalar};
+use discrete_log::{decrypt_with_progress, DiscreteLogSolver};
+use encoding::Encoding;
 use errors::Error;
-use log::{debug, error, info};
+use log::{error, info};
+use mediator_credit::{SignedMediatorCredit, CREDIT_STARTED_STATE, CREDIT_VALIDATED_STATE};
 use metrics::Recorder;
 use metrics_core::Key;
+use profile::MetricsCollector;
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use rand::{CryptoRng, RngCore};
 use regex::Regex;
+use secret_store::{load_secret_object, load_secret_object_from, passphrase};
 use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     convert::TryInto,
     fmt,
     fs::{create_dir_all, File},
     hash::Hash,
     io::BufReader,
     path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc, Once},
 };
 
 pub const ON_CHAIN_DIR: &str = "on-chain";
@@ -46,8 +76,17 @@ pub const VALIDATED_PUBLIC_ACCOUNT_BALANCE_FILE: &str = "validated_public_accoun
 pub const SECRET_ACCOUNT_FILE: &str = "secret_account";
 pub const ASSET_ID_LIST_FILE: &str = "valid_asset_ids.json";
 pub const COMMON_OBJECTS_DIR: &str = "common";
+/// The directory of the validator's own bookkeeping, i.e., the last validated tx_ids and the
+/// verification cache, in the database directory. It is neither on-chain, where anyone could
+/// forge it, nor in the off-chain directory of the users.
+pub const VALIDATOR_DIR: &str = "validator";
+/// The single-file account map of the earlier versions. It is split into the shards on first use.
 pub const USER_ACCOUNT_MAP: &str = "user_ticker_to_account_id.json";
+pub const ACCOUNT_MAP_SHARD_PREFIX: &str = "account_map_";
 pub const LAST_VALIDATED_TX_ID_FILE: &str = "last_validated_tx_id_file.json";
+/// The prefix of the last validated tx_id of the validation and of each of the tickers.
+pub const LAST_VALIDATED_TX_ID_PREFIX: &str = "last_validated_tx_id_";
+pub const BLINDING_REGISTRY_FILE: &str = "blinding_registry.json";
 
 /// A wrapper around MERCAT api which holds the transaction data, the transaction id,
 /// and the user who initiated the transaction. Some transactions also hold the
@@ -83,32 +122,90 @@ pub enum CoreTransaction {
         mediator: String,
         tx_id: u32,
     },
+    TransferRejected {
+        sender: String,
+        reason: Option<RejectionReason>,
+        tx_id: u32,
+    },
+    MediatorCredit {
+        credit: SignedMediatorCredit,
+        mediator: String,
+        tx_id: u32,
+    },
     Invalid,
 }
 
+/// Whether a transaction can be verified by the network validators.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ReadinessStatus {
+    Ready,
+    /// The transaction waits for a party to write the file in `missing_state`. It is `None` if
+    /// no further step makes the transaction ready, e.g., because it was rejected.
+    NotReady {
+        missing_state: Option<String>,
+    },
+}
+
+impl ReadinessStatus {
+    fn waiting_for(state: TransferTxState) -> Self {
+        ReadinessStatus::NotReady {
+            missing_state: Some(state.to_string()),
+        }
+    }
+
+    /// How far along the steps of a transaction this status is. Of the files of a single
+    /// transaction, the one that is furthest along tells what the transaction waits for.
+    fn progress(&self) -> u8 {
+        match self {
+            ReadinessStatus::Ready => 3,
+            ReadinessStatus::NotReady {
+                missing_state: Some(state),
+            } if *state == TransferTxState::Justification(TxSubstate::Started).to_string() => 2,
+            ReadinessStatus::NotReady {
+                missing_state: Some(_),
+            } => 1,
+            ReadinessStatus::NotReady {
+                missing_state: None,
+            } => 0,
+        }
+    }
+
+    /// Combines the statuses of two files of the same transaction.
+    pub fn merge(self, other: ReadinessStatus) -> ReadinessStatus {
+        if other.progress() > self.progress() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// Returns whether the transaction is ready for validation, and if not, the state of the file
+/// that it waits for.
+pub fn readiness_reason(tx: &CoreTransaction) -> ReadinessStatus {
+    match tx {
+        CoreTransaction::Account { .. }
+        | CoreTransaction::IssueInit { .. }
+        | CoreTransaction::TransferJustify { .. }
+        | CoreTransaction::MediatorCredit { .. } => ReadinessStatus::Ready,
+        CoreTransaction::TransferInit { .. } => {
+            ReadinessStatus::waiting_for(TransferTxState::Finalization(TxSubstate::Started))
+        }
+        CoreTransaction::TransferFinalize { .. } => {
+            ReadinessStatus::waiting_for(TransferTxState::Justification(TxSubstate::Started))
+        }
+        CoreTransaction::TransferRejected { .. } | CoreTransaction::Invalid => {
+            ReadinessStatus::NotReady {
+                missing_state: None,
+            }
+        }
+    }
+}
+
 impl CoreTransaction {
     /// Returns true for transactions that can be verified by the network validators.
     fn is_ready_for_validation(&self) -> bool {
-        match self {
-            CoreTransaction::Account {
-                account_tx: _,
-                ordering_state: _,
-                tx_id: _,
-            } => true,
-            CoreTransaction::IssueInit {
-                issue_tx: _,
-                issuer: _,
-                tx_id: _,
-                ordering_state: _,
-                amount: _,
-            } => true,
-            CoreTransaction::TransferJustify {
-                tx: _,
-                mediator: _,
-                tx_id: _,
-            } => true,
-            _ => false,
-        }
+        readiness_reason(self) == ReadinessStatus::Ready
     }
 
     /// Returns true for outgoing transactions.
@@ -155,7 +252,7 @@ impl CoreTransaction {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Direction {
     Incoming,
     Outgoing,
@@ -168,17 +265,102 @@ pub struct ValidationResult {
     ticker: String,
     direction: Direction,
     amount: Option<EncryptedAmount>,
+    tx_id: u32,
 }
 
 impl ValidationResult {
+    pub fn new(
+        user: &str,
+        ticker: &str,
+        direction: Direction,
+        amount: Option<EncryptedAmount>,
+        tx_id: u32,
+    ) -> Self {
+        Self {
+            user: user.to_string(),
+            ticker: ticker.to_string(),
+            direction,
+            amount,
+            tx_id,
+        }
+    }
+
     /// Creates the error value. An amount of None, indicates that an error has occurred.
-    fn error(user: &str, ticker: &str) -> Self {
+    fn error(user: &str, ticker: &str, tx_id: u32) -> Self {
         Self {
             user: user.to_string(),
             ticker: ticker.to_string(),
             direction: Direction::Incoming,
             amount: None,
+            tx_id,
+        }
+    }
+
+    /// The user whose account is updated.
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// The ticker of the account that is updated.
+    pub fn ticker(&self) -> &str {
+        &self.ticker
+    }
+
+    /// Whether the account balance increases or decreases.
+    pub fn direction(&self) -> &Direction {
+        &self.direction
+    }
+
+    /// The encrypted amount of the update, or None if the validation failed.
+    pub fn amount(&self) -> Option<&EncryptedAmount> {
+        self.amount.as_ref()
+    }
+
+    /// The id of the validated transaction.
+    pub fn tx_id(&self) -> u32 {
+        self.tx_id
+    }
+}
+
+/// Returns the encrypted amounts that the validation of the `tx` adds to, or subtracts from,
+/// the balance of each account. These are the same memo fields that the validator applies, but
+/// nothing is verified. The amounts of a transfer are already known from its initialization.
+pub fn tx_balance_deltas(
+    tx: &CoreTransaction,
+) -> Vec<(EncryptedAssetId, Direction, EncryptedAmount)> {
+    let transfer_deltas = |memo: &TransferTxMemo| {
+        vec![
+            (
+                memo.sender_account_id,
+                Direction::Outgoing,
+                memo.enc_amount_using_sender,
+            ),
+            (
+                memo.receiver_account_id,
+                Direction::Incoming,
+                memo.enc_amount_using_receiver,
+            ),
+        ]
+    };
+    match tx {
+        CoreTransaction::IssueInit { issue_tx, .. } => vec![(
+            issue_tx.account_id,
+            Direction::Incoming,
+            issue_tx.memo.enc_issued_amount,
+        )],
+        CoreTransaction::TransferInit { tx, .. } => transfer_deltas(&tx.memo),
+        CoreTransaction::TransferFinalize { tx, .. } => transfer_deltas(&tx.init_data.memo),
+        CoreTransaction::TransferJustify { tx, .. } => {
+            transfer_deltas(&tx.finalized_data.init_data.memo)
         }
+        CoreTransaction::MediatorCredit { credit, .. } => vec![(
+            credit.credit.beneficiary_account_id,
+            Direction::Incoming,
+            credit.credit.enc_amount,
+        )],
+        CoreTransaction::Account { .. }
+        | CoreTransaction::TransferRejected { .. }
+        | CoreTransaction::Invalid => vec![],
     }
 }
 
@@ -200,6 +382,14 @@ impl OrderingState {
             tx_id: tx_id,
         }
     }
+
+    /// The pending tx counter of the transaction `tx_id` that follows this state. Fails with
+    /// `PendingCounterOverflow` instead of wrapping around.
+    pub fn next_pending_tx_counter(&self, tx_id: u32) -> Result<u32, Error> {
+        self.last_pending_tx_counter
+            .checked_add(1)
+            .ok_or(Error::PendingCounterOverflow { tx_id })
+    }
 }
 
 /// A wrapper around the MERCAT PubAccount that stores the last processed transaction counter
@@ -250,8 +440,65 @@ pub struct TransferInstruction {
     pub state: TransferTxState,
     #[serde(with = "serde_bytes")]
     pub data: Vec<u8>,
+    /// Set by the mediator when the transaction is rejected.
+    pub rejection_reason: Option<RejectionReason>,
+}
+
+/// The category of a mediator's rejection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub enum RejectionKind {
+    Compliance,
+    WrongAmount,
+    UnknownCounterparty,
+    Other,
+}
+
+impl fmt::Display for RejectionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectionKind::Compliance => write!(f, "compliance"),
+            RejectionKind::WrongAmount => write!(f, "wrong-amount"),
+            RejectionKind::UnknownCounterparty => write!(f, "unknown-counterparty"),
+            RejectionKind::Other => write!(f, "other"),
+        }
+    }
+}
+
+impl std::str::FromStr for RejectionKind {
+    type Err = Error;
+
+    fn from_str(kind: &str) -> Result<Self, Error> {
+        match kind {
+            "compliance" => Ok(RejectionKind::Compliance),
+            "wrong-amount" => Ok(RejectionKind::WrongAmount),
+            "unknown-counterparty" => Ok(RejectionKind::UnknownCounterparty),
+            "other" => Ok(RejectionKind::Other),
+            _ => Err(Error::InvalidRejectionKind {
+                kind: kind.to_string(),
+            }),
+        }
+    }
+}
+
+/// Why a mediator declined a transaction. Persisted in the rejected instruction, so that
+/// the sender can learn why their transfer was declined.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct RejectionReason {
+    pub kind: RejectionKind,
+    pub message: String,
 }
 
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{}: {}", self.kind, self.message)
+        }
+    }
+}
+
+/// An encoded account id. It is displayed in base64, as the keys of the account map are.
 #[derive(PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PrintableAccountId(pub Vec<u8>);
 
@@ -265,16 +512,34 @@ impl PrintableAccountId {
     fn to_string(&self) -> String {
         format!("{}", base64::encode(self.0.clone()))
     }
+
+    /// Returns the account id in the `encoding` that the CLIs print and read.
+    pub fn encode_with(&self, encoding: Encoding) -> String {
+        encoding.encode(&self.0)
+    }
 }
 
+/// The ticker is part of the name, so that the transactions of different assets with the same
+/// tx_id do not collide.
 #[inline]
-pub fn asset_transaction_file(tx_id: u32, user: &String, state: AssetTxState) -> String {
-    format!("tx_{}_{}_{}.json", tx_id, user, state)
+pub fn asset_transaction_file(
+    tx_id: u32,
+    user: &String,
+    ticker: &String,
+    state: AssetTxState,
+) -> String {
+    format!("tx_{}_{}_{}_{}.json", tx_id, ticker, user, state)
 }
 
+/// Uses the same layout as `asset_transaction_file`.
 #[inline]
-pub fn confidential_transaction_file(tx_id: u32, user: &String, state: TransferTxState) -> String {
-    format!("tx_{}_{}_{}.json", tx_id, user, state)
+pub fn confidential_transaction_file(
+    tx_id: u32,
+    user: &String,
+    ticker: &String,
+    state: TransferTxState,
+) -> String {
+    format!("tx_{}_{}_{}_{}.json", tx_id, ticker, user, state)
 }
 
 #[inline]
@@ -297,19 +562,37 @@ pub fn user_secret_account_file(ticker: &String) -> String {
     format!("{}_{}", ticker, SECRET_ACCOUNT_FILE)
 }
 
-/// This is used for simulating cheating by increasing the account id.
+/// The sender's record of the transfer `tx_id`, kept in the sender's off-chain directory.
+#[inline]
+pub fn sent_record_file(tx_id: u32, ticker: &String) -> String {
+    format!("sent_{}_{}", tx_id, ticker)
+}
+
+/// Returns an error if the caller asks for cheating, but the cheating support is not compiled in.
 #[inline]
-pub fn non_empty_account_id() -> EncryptedAssetId {
-    CipherText {
-        x: RISTRETTO_BASEPOINT_POINT,
-        y: RISTRETTO_BASEPOINT_POINT,
+pub fn ensure_cheating_supported(cheat: bool) -> Result<(), Error> {
+    if cheat && !cfg!(any(test, feature = "cheating")) {
+        return Err(Error::CheatingNotSupported);
     }
+    Ok(())
+}
+
+/// Returns true if the `state` of a transaction file name is one that the CLIs write.
+fn is_known_tx_state(state: &str) -> bool {
+    (state.starts_with("ticker#") && state.len() > "ticker#".len())
+        || state == CREDIT_STARTED_STATE
+        || state == CREDIT_VALIDATED_STATE
+        || tx_state::all_tx_states()
+            .into_iter()
+            .any(|known| known.to_string() == state)
 }
 
 /// Parses the transaction file name and returns: (tx_id, user_name, state, the_input_file_path).
+/// Both the `tx_<id>_<TICKER>_<user>_<state>.json` names and the older names without the ticker
+/// are accepted. The latter can be renamed with `migration::migrate_tx_file_names`.
 #[inline]
 pub fn parse_tx_name(tx_file_path: String) -> Result<(u32, String, String, String), Error> {
-    let re = Regex::new(r"^tx_([0-9]+)_([a-z]+)_([a-zA-Z-#0-9]+).json$").map_err(|_| {
+    let re = Regex::new(r"^tx_([^_]+)_(?:[A-Z0-9]+_)?([a-z]+)_([^_]+)\.json$").map_err(|_| {
         Error::RegexError {
             reason: String::from("Failed to compile the transaction file name regex"),
         }
@@ -319,26 +602,42 @@ pub fn parse_tx_name(tx_file_path: String) -> Result<(u32, String, String, Strin
         .expect("It is a file and therefore, this should never fail!")
         .to_str()
         .ok_or(Error::PathBufConversionError)?;
-    let caps = re.captures(&file_name).ok_or(Error::RegexError {
-        reason: format!("Transaction info pattern did not match {}", file_name),
-    })?;
-    let tx_id = caps[1]
-        .to_string()
-        .parse::<u32>()
-        .map_err(|_| Error::RegexError {
-            reason: String::from("failed to convert amount to u32."),
+    let caps = re
+        .captures(&file_name)
+        .ok_or_else(|| Error::MalformedTxFilename {
+            file_name: file_name.to_string(),
         })?;
+    let tx_id = caps[1].parse::<u32>().map_err(|_| Error::NonNumericTxId {
+        file_name: file_name.to_string(),
+    })?;
     let user = caps[2].to_string();
     let state = caps[3].to_string();
+    if !is_known_tx_state(&state) {
+        return Err(Error::UnknownState {
+            file_name: file_name.to_string(),
+            state,
+        });
+    }
     Ok((tx_id, user, state, tx_file_path))
 }
 
-// -------------------------------------- Metric recording ------------------------------------------------
-#[allow(dead_code)]
-static RECORDER: PrintRecorder = PrintRecorder;
+/// Returns true for the errors of `parse_tx_name` that are caused by the name of the file.
+pub fn is_tx_name_error(error: &Error) -> bool {
+    match error {
+        Error::MalformedTxFilename { .. }
+        | Error::NonNumericTxId { .. }
+        | Error::UnknownState { .. } => true,
+        _ => false,
+    }
+}
 
+// -------------------------------------- Metric recording ------------------------------------------------
+/// Logs the metrics. The durations are also added to the `profile` collector, if profiling is
+/// enabled.
 #[derive(Default)]
-pub struct PrintRecorder;
+pub struct PrintRecorder {
+    profile: Option<Arc<MetricsCollector>>,
+}
 
 impl Recorder for PrintRecorder {
     fn increment_counter(&self, key: Key, value: u64) {
@@ -356,6 +655,9 @@ impl Recorder for PrintRecorder {
     }
 
     fn record_histogram(&self, key: Key, value: u64) {
+        if let Some(profile) = self.profile.as_ref().filter(|profile| profile.enabled()) {
+            profile.record(&key.name(), value);
+        }
         info!(
             "histogram: {{ \"counter\": {{ \"name\": \"{}\", \"value\": {} }} }}",
             key, value
@@ -363,35 +665,159 @@ impl Recorder for PrintRecorder {
     }
 }
 
-#[cfg(feature = "std")]
+static INIT_RECORDER: Once = Once::new();
+
+/// Counts the transactions that passed the validation.
+pub const TX_ACCEPTED_COUNTER: &str = "validator.tx.accepted";
+/// Counts the transactions that failed the validation.
+pub const TX_REJECTED_COUNTER: &str = "validator.tx.rejected";
+
+/// The value of the `result` label of a `timing!` site.
+pub fn outcome_label<T>(result: &Result<T, Error>) -> &'static str {
+    match result {
+        Ok(_) => "ok",
+        Err(_) => "error",
+    }
+}
+
+/// Installs the metrics recorder. It is only installed once, so this can be called again, e.g.,
+/// by several tests.
 pub fn init_print_logger() {
-    let recorder = PrintRecorder::default();
-    metrics::set_boxed_recorder(Box::new(recorder)).unwrap()
+    install_recorder(PrintRecorder::default());
+}
+
+/// Installs the metrics recorder like `init_print_logger`, and returns the collector of the
+/// durations for the profile of the command. Profiling is enabled if the `MERCAT_PROFILE`
+/// environment variable is set, and can be enabled later through the collector. The CLIs call
+/// this once, before any other metrics recorder is installed.
+pub fn init_profiling_logger() -> Arc<MetricsCollector> {
+    let collector = Arc::new(MetricsCollector::default());
+    collector.set_enabled(std::env::var_os(profile::PROFILE_ENV_VAR).is_some());
+    install_recorder(PrintRecorder {
+        profile: Some(collector.clone()),
+    });
+    collector
+}
+
+#[cfg(feature = "std")]
+fn install_recorder(recorder: PrintRecorder) {
+    INIT_RECORDER.call_once(|| {
+        #[cfg(test)]
+        let recorder = profile::CapturingRecorder(recorder);
+        metrics::set_boxed_recorder(Box::new(recorder)).unwrap()
+    });
 }
 
 #[cfg(not(feature = "std"))]
-pub fn init_print_logger() {
-    metrics::set_recorder(&RECORDER).unwrap()
+fn install_recorder(recorder: PrintRecorder) {
+    INIT_RECORDER.call_once(|| {
+        #[cfg(test)]
+        let recorder = profile::CapturingRecorder(recorder);
+        metrics::set_recorder(Box::leak(Box::new(recorder))).unwrap()
+    });
+}
+
+/// When this environment variable is set, the default panic hook is kept.
+pub const PANIC_DETAILS_ENV_VAR: &str = "MERCAT_PANIC_DETAILS";
+
+/// Replaces the default panic hook with one that only reports where the panic happened.
+/// The message of a panicking `unwrap()` holds the `Debug` of the error, and the backtrace
+/// holds the arguments of the frames, either of which may include secret values.
+pub fn install_panic_hook() {
+    if std::env::var_os(PANIC_DETAILS_ENV_VAR).is_some() {
+        return;
+    }
+    std::panic::set_hook(Box::new(|info| {
+        let location = info.location().map_or_else(
+            || String::from("an unknown location"),
+            |location| format!("{}:{}", location.file(), location.line()),
+        );
+        eprintln!(
+            "The program panicked at {}. The details are hidden since they may contain secrets. \
+            Set {} to show them.",
+            location, PANIC_DETAILS_ENV_VAR
+        );
+    }));
 }
 
 // -------------------------------------- Metric recording ------------------------------------------------
 
+/// The directories of the on-chain and the off-chain data of a database. By default, these are
+/// the `ON_CHAIN_DIR` and `OFF_CHAIN_DIR` sub-directories of the database directory. Overriding
+/// them allows, e.g., keeping the on-chain data on a shared storage and the secrets on a local
+/// disk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainDirs {
+    /// The database directory, which holds the data that is neither on-chain nor off-chain.
+    pub root: PathBuf,
+    pub on_chain: PathBuf,
+    pub off_chain: PathBuf,
+}
+
+impl ChainDirs {
+    /// Uses the default sub-directories of `db_dir`.
+    pub fn new(db_dir: PathBuf) -> Self {
+        Self::with_overrides(db_dir, None, None)
+    }
+
+    /// Uses `on_chain_dir` and `off_chain_dir` instead of the default sub-directories of
+    /// `db_dir`. A `None` keeps the default sub-directory.
+    pub fn with_overrides(
+        db_dir: PathBuf,
+        on_chain_dir: Option<PathBuf>,
+        off_chain_dir: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            on_chain: on_chain_dir.unwrap_or_else(|| db_dir.join(ON_CHAIN_DIR)),
+            off_chain: off_chain_dir.unwrap_or_else(|| db_dir.join(OFF_CHAIN_DIR)),
+            root: db_dir,
+        }
+    }
+}
+
+/// Returns the directory of the on-chain or the off-chain data of `db_dir`.
+#[inline]
+pub fn chain_dir(db_dir: ChainDirs, on_off_chain: &str) -> PathBuf {
+    match on_off_chain {
+        ON_CHAIN_DIR => db_dir.on_chain,
+        OFF_CHAIN_DIR => db_dir.off_chain,
+        _ => db_dir.root.join(on_off_chain),
+    }
+}
+
+/// Fails if the `component` of a path could escape its directory, e.g., a user name `../../etc`.
+fn ensure_safe_name_component(component: &str) -> Result<(), Error> {
+    if component.contains('/') || component.contains('\\') || component.contains("..") {
+        return Err(Error::UnsafeNameComponent {
+            component: component.to_string(),
+        });
+    }
+    Ok(())
+}
+
 /// Utility function to construct the path based user name, file name, and whether the file
-/// should be stored on or off chain.
+/// should be stored on or off chain. Fails if the user or the file name contain a path separator
+/// or `..`, so that the path stays inside the database directory.
 #[inline]
-pub fn construct_path(db_dir: PathBuf, on_off_chain: &str, user: &str, file_name: &str) -> PathBuf {
-    let mut file_path = db_dir;
-    file_path.push(on_off_chain);
+pub fn construct_path(
+    db_dir: ChainDirs,
+    on_off_chain: &str,
+    user: &str,
+    file_name: &str,
+) -> Result<PathBuf, Error> {
+    ensure_safe_name_component(user)?;
+    ensure_safe_name_component(file_name)?;
+    let mut file_path = chain_dir(db_dir, on_off_chain);
     file_path.push(user);
     file_path.push(file_name);
-    file_path
+    Ok(file_path)
 }
 
 /// Utility function to save a serializable data to a location inside the database directory,
 /// for a particular user.
 #[inline]
 pub fn save_to_file<T>(
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     on_off_chain: &str,
     user: &str,
     file_name: &str,
@@ -400,9 +826,8 @@ pub fn save_to_file<T>(
 where
     T: ?Sized + serde::Serialize,
 {
-    let mut file_path = db_dir;
-    file_path.push(on_off_chain);
-    file_path.push(user);
+    let mut file_path = construct_path(db_dir, on_off_chain, user, "")?;
+    ensure_safe_name_component(file_name)?;
 
     // The file_path is now the path to the user directory. Create it if it does not exist.
     create_dir_all(file_path.clone()).map_err(|error| Error::FileCreationError {
@@ -427,12 +852,12 @@ where
 /// for a particular user.
 #[inline]
 pub fn load_from_file<T: serde::de::DeserializeOwned>(
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     on_off_chain: &str,
     user: &str,
     file_name: &str,
 ) -> Result<T, Error> {
-    let file_path = construct_path(db_dir, on_off_chain, user, file_name);
+    let file_path = construct_path(db_dir, on_off_chain, user, file_name)?;
     let file = File::open(file_path.clone()).map_err(|error| Error::FileReadError {
         error,
         path: file_path.clone(),
@@ -449,12 +874,12 @@ pub fn load_from_file<T: serde::de::DeserializeOwned>(
 /// Utility function to remove a file from the database directory for a particular user.
 #[inline]
 pub fn remove_file(
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     on_off_chain: &str,
     user: &str,
     file_name: &str,
 ) -> Result<(), Error> {
-    let file_path = construct_path(db_dir, on_off_chain, user, file_name);
+    let file_path = construct_path(db_dir, on_off_chain, user, file_name)?;
     std::fs::remove_file(file_path.clone()).map_err(|error| Error::FileRemovalError {
         error,
         path: file_path,
@@ -468,11 +893,14 @@ pub struct AssetIdList(pub Vec<Scalar>);
 
 /// Utility function to read the asset ids from the database directory.
 #[inline]
-pub fn get_asset_ids(db_dir: PathBuf) -> Result<Vec<Scalar>, Error> {
-    let file_path = construct_path(db_dir, ON_CHAIN_DIR, COMMON_OBJECTS_DIR, ASSET_ID_LIST_FILE);
-    let file = File::open(file_path).map_err(|error| Error::FileReadError {
-        error,
-        path: ASSET_ID_LIST_FILE.into(),
+pub fn get_asset_ids(db_dir: ChainDirs) -> Result<Vec<Scalar>, Error> {
+    let file_path = construct_path(db_dir, ON_CHAIN_DIR, COMMON_OBJECTS_DIR, ASSET_ID_LIST_FILE)?;
+    let file = File::open(file_path).map_err(|error| match error.kind() {
+        std::io::ErrorKind::NotFound => Error::AssetRegistryNotInitialized,
+        _ => Error::FileReadError {
+            error,
+            path: ASSET_ID_LIST_FILE.into(),
+        },
     })?;
     let mut de = serde_json::Deserializer::from_reader(file);
 
@@ -486,15 +914,14 @@ pub fn get_asset_ids(db_dir: PathBuf) -> Result<Vec<Scalar>, Error> {
 /// Utility function to save an object that implements the Decode trait to file.
 #[inline]
 pub fn save_object<T: Encode>(
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     on_off_chain: &str,
     user: &str,
     file_name: &str,
     data: &T,
 ) -> Result<(), Error> {
-    let mut file_path = db_dir;
-    file_path.push(on_off_chain);
-    file_path.push(user);
+    let mut file_path = construct_path(db_dir, on_off_chain, user, "")?;
+    ensure_safe_name_component(file_name)?;
 
     // The file_path is now the path to the user directory. Create it if it does not exist.
     create_dir_all(file_path.clone()).map_err(|error| Error::FileCreationError {
@@ -515,12 +942,12 @@ pub fn save_object<T: Encode>(
 /// Utility function to read an object that implements the Encode trait from file.
 #[inline]
 pub fn load_object<T: Decode>(
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     on_off_chain: &str,
     user: &str,
     file_name: &str,
 ) -> Result<T, Error> {
-    let file_path = construct_path(db_dir, on_off_chain, user, file_name);
+    let file_path = construct_path(db_dir, on_off_chain, user, file_name)?;
     load_object_from(file_path)
 }
 
@@ -586,47 +1013,212 @@ pub fn create_rng_from_seed(seed: Option<String>) -> Result<StdRng, Error> {
     Ok(StdRng::from_seed(seed))
 }
 
-/// Reads the account mapping from disk. Returns a map of account id to (user_name, ticker, tx_id).
+const SUBSEED_DOMAIN: &[u8] = b"mercat-subseed";
+
+/// Derives the seed of one operation, e.g., of the account of one ticker, from the base64
+/// `master` seed. It follows HKDF, with Sha3_256 in place of the HMAC: a pseudorandom key is
+/// extracted from the master seed, and expanded with the `label`. The subseed only depends on
+/// the master seed and the label, so the keys are recovered by deriving it again, while a
+/// leaked subseed does not reveal the master seed or the subseeds of the other labels.
+pub fn derive_subseed(master: &str, label: &str) -> [u8; 32] {
+    let mut extract = Sha3_256::new();
+    extract.input(SUBSEED_DOMAIN);
+    extract.input(master.as_bytes());
+    let prk = extract.result();
+
+    let mut expand = Sha3_256::new();
+    expand.input(&prk);
+    expand.input(&(label.len() as u64).to_le_bytes());
+    expand.input(label.as_bytes());
+    expand.input(&[1u8]);
+    let mut subseed = [0u8; 32];
+    subseed.copy_from_slice(&expand.result());
+    subseed
+}
+
+/// Helper function to create an RNG from the subseed of `label`. The master seed must be a
+/// valid seed for `create_rng_from_seed`.
+pub fn create_rng_from_subseed(seed: Option<String>, label: &str) -> Result<StdRng, Error> {
+    let seed = seed.ok_or(Error::EmptySeed)?;
+    create_rng_from_seed(Some(seed.clone()))?;
+    Ok(StdRng::from_seed(derive_subseed(&seed, label)))
+}
+
+/// The account map is split into shards by the first byte of the encoded account id, so that
+/// looking up or adding an account only reads and rewrites the shard of that account.
 #[inline]
-pub fn load_account_map(db_dir: PathBuf) -> HashMap<String, (String, String, u32)> {
+pub fn account_map_shard_file(encoded_account_id: &[u8]) -> String {
+    let prefix = &encoded_account_id[..std::cmp::min(1, encoded_account_id.len())];
+    format!("{}{}.json", ACCOUNT_MAP_SHARD_PREFIX, hex::encode(prefix))
+}
+
+fn load_account_map_shard(
+    db_dir: ChainDirs,
+    shard_file: &str,
+) -> HashMap<String, (String, String, u32)> {
     let mapping: Result<HashMap<String, (String, String, u32)>, Error> =
-        load_from_file(db_dir, OFF_CHAIN_DIR, COMMON_OBJECTS_DIR, USER_ACCOUNT_MAP);
+        load_from_file(db_dir, OFF_CHAIN_DIR, COMMON_OBJECTS_DIR, shard_file);
     match mapping {
         Err(_error) => HashMap::new(),
         Ok(mapping) => mapping,
     }
 }
 
-/// Updates the account mapping file with a new record.
+/// Returns the directory that a file of the validation state is kept in, or `None` for the other
+/// files. The account map is kept off-chain, since it links the accounts to their users. The
+/// last validated tx_ids and the verification cache are kept in `VALIDATOR_DIR`.
+fn validation_state_dir(file_name: &str) -> Option<&'static str> {
+    if file_name == USER_ACCOUNT_MAP || file_name.starts_with(ACCOUNT_MAP_SHARD_PREFIX) {
+        Some(OFF_CHAIN_DIR)
+    } else if file_name == verification_cache::VERIFICATION_CACHE_FILE
+        || file_name.starts_with(LAST_VALIDATED_TX_ID_PREFIX)
+    {
+        Some(VALIDATOR_DIR)
+    } else {
+        None
+    }
+}
+
+/// Moves the files of the validation state that the earlier versions kept in the on-chain or the
+/// off-chain common directory to the directory of `validation_state_dir`. A file that already
+/// exists there is not replaced. This is run once, by `mercat-validator migrate-validation-state`,
+/// before the first validation with this version. Returns the number of the moved files.
+pub fn migrate_validation_state(db_dir: ChainDirs) -> Result<usize, Error> {
+    let mut moved = 0;
+    for from_dir in &[ON_CHAIN_DIR, OFF_CHAIN_DIR] {
+        let from = construct_path(db_dir.clone(), from_dir, COMMON_OBJECTS_DIR, "")?;
+        let entries = match std::fs::read_dir(&from) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let to_dir = match validation_state_dir(&file_name) {
+                Some(to_dir) if to_dir != *from_dir => to_dir,
+                _ => continue,
+            };
+            let to = construct_path(db_dir.clone(), to_dir, COMMON_OBJECTS_DIR, &file_name)?;
+            if to.exists() {
+                continue;
+            }
+            let dir = construct_path(db_dir.clone(), to_dir, COMMON_OBJECTS_DIR, "")?;
+            create_dir_all(&dir).map_err(|error| Error::FileCreationError { error, path: dir })?;
+            // Copied and removed, instead of renamed, since the directories may be on different
+            // file systems.
+            std::fs::copy(entry.path(), &to).map_err(|error| Error::FileCreationError {
+                error,
+                path: to.clone(),
+            })?;
+            remove_file(db_dir.clone(), from_dir, COMMON_OBJECTS_DIR, &file_name)?;
+            moved += 1;
+        }
+    }
+    if moved > 0 {
+        info!("Moved {} files of the validation state.", moved);
+    }
+    Ok(moved)
+}
+
+/// Splits the single-file account map of the earlier versions into the shards, and removes it.
+/// Returns the number of the moved records, which is zero if there is nothing to migrate.
+pub fn migrate_account_map(db_dir: ChainDirs) -> Result<usize, Error> {
+    let legacy_path = construct_path(
+        db_dir.clone(),
+        OFF_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        USER_ACCOUNT_MAP,
+    )?;
+    if !legacy_path.exists() {
+        return Ok(0);
+    }
+    let legacy: HashMap<String, (String, String, u32)> = load_from_file(
+        db_dir.clone(),
+        OFF_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        USER_ACCOUNT_MAP,
+    )?;
+    let count = legacy.len();
+    let mut shards: BTreeMap<String, HashMap<String, (String, String, u32)>> = BTreeMap::new();
+    for (account_id, record) in legacy {
+        let encoded =
+            base64::decode(&account_id).map_err(|error| Error::Base64DecodeError { error })?;
+        shards
+            .entry(account_map_shard_file(&encoded))
+            .or_default()
+            .insert(account_id, record);
+    }
+    for (shard_file, records) in shards {
+        let mut mapping = load_account_map_shard(db_dir.clone(), &shard_file);
+        mapping.extend(records);
+        save_to_file(
+            db_dir.clone(),
+            OFF_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &shard_file,
+            &mapping,
+        )?;
+    }
+    remove_file(db_dir, OFF_CHAIN_DIR, COMMON_OBJECTS_DIR, USER_ACCOUNT_MAP)?;
+    info!("Moved {} records of the account map into shards.", count);
+    Ok(count)
+}
+
+/// Reads the account mapping from disk. Returns a map of account id to (user_name, ticker, tx_id).
+/// All the shards are read, which is only needed by the commands that go over every account.
+pub fn load_account_map(db_dir: ChainDirs) -> HashMap<String, (String, String, u32)> {
+    if let Err(error) = migrate_account_map(db_dir.clone()) {
+        error!("Failed to migrate the account map: {:#?}", error);
+    }
+    let mut mapping = HashMap::new();
+    let dir = construct_path(db_dir.clone(), OFF_CHAIN_DIR, COMMON_OBJECTS_DIR, "");
+    let entries = match dir.map(std::fs::read_dir) {
+        Ok(Ok(entries)) => entries,
+        _ => return mapping,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if file_name.starts_with(ACCOUNT_MAP_SHARD_PREFIX) && file_name.ends_with(".json") {
+            mapping.extend(load_account_map_shard(db_dir.clone(), &file_name));
+        }
+    }
+    mapping
+}
+
+/// Updates the account mapping shard of the `account_id` with a new record.
 #[inline]
 pub fn update_account_map(
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     user: String,
     ticker: String,
     account_id: EncryptedAssetId,
     tx_id: u32,
 ) -> Result<(), Error> {
-    let mut mapping = load_account_map(db_dir.clone());
+    migrate_account_map(db_dir.clone())?;
+    let encoded = account_id.encode();
+    let shard_file = account_map_shard_file(&encoded);
+    let mut mapping = load_account_map_shard(db_dir.clone(), &shard_file);
     mapping.insert(
-        PrintableAccountId(account_id.encode()).to_string(),
+        PrintableAccountId(encoded).to_string(),
         (user, ticker, tx_id),
     );
     save_to_file(
         db_dir,
         OFF_CHAIN_DIR,
         COMMON_OBJECTS_DIR,
-        USER_ACCOUNT_MAP,
+        &shard_file,
         &mapping,
     )
 }
 
-/// Reads the account mapping file and returns (user_name, ticker, tx_id) of the given account id.
+/// Reads the account mapping shard of the given account id and returns its
+/// (user_name, ticker, tx_id).
 #[inline]
 pub fn get_user_ticker_from(
     account_id: EncryptedAssetId,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
 ) -> Result<(String, String, u32), Error> {
-    let mapping = load_account_map(db_dir.clone());
+    migrate_account_map(db_dir.clone())?;
+    let mapping = load_account_map_shard(db_dir, &account_map_shard_file(&account_id.encode()));
     let (user, ticker, tx_id) = mapping
         .get(&PrintableAccountId(account_id.encode()).to_string())
         .ok_or(Error::AccountIdNotFound {
@@ -635,14 +1227,40 @@ pub fn get_user_ticker_from(
     Ok((user.clone(), ticker.clone(), tx_id.clone()))
 }
 
+/// Same as `get_user_ticker_from`, but also checks that the on-chain public account of the
+/// resolved user and ticker has the given account id. Otherwise, the mapping is stale, e.g.,
+/// because it was updated after a pending transaction referred to the account.
+pub fn get_checked_user_ticker_from(
+    account_id: EncryptedAssetId,
+    db_dir: ChainDirs,
+) -> Result<(String, String, u32), Error> {
+    let (user, ticker, tx_id) = get_user_ticker_from(account_id, db_dir.clone())?;
+    let ordered_pub_account: OrderedPubAccount = load_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        &user,
+        &user_public_account_file(&ticker),
+    )?;
+    if ordered_pub_account.pub_account.enc_asset_id != account_id {
+        return Err(Error::StaleAccountMapping {
+            account_id: PrintableAccountId(account_id.encode()).to_string(),
+        });
+    }
+    Ok((user, ticker, tx_id))
+}
+
 /// Searches the on-chain transactions to find the last transaction that the give user has submitted
 /// before `current_tx_id`. If such a transaction is found, its ordering state is returned.
+/// Otherwise, the ordering state follows from the `last_processed_tx_counter_from_account`, and the
+/// pending counter continues from it. For a fresh account, whose creation is not validated yet,
+/// this is an ordering state with no processed transaction and a pending counter of 0, so that the
+/// first transaction of the account has the pending counter 1.
 #[inline]
 pub fn last_ordering_state(
     user: String,
     last_processed_tx_counter_from_account: Option<u32>,
     current_tx_id: u32,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
 ) -> Result<OrderingState, Error> {
     let all_tx_files = all_unverified_tx_files(db_dir)?;
 
@@ -701,19 +1319,19 @@ pub fn last_ordering_state(
     if let Some(_) = prev_error {
         return Err(Error::LastTransactionNotFound { user });
     }
-    if last_pending_tx_counter == None {
+    match last_pending_tx_counter {
         // No pending transactions found, return the ordering state from the account.
-        return Ok(OrderingState {
+        None => Ok(OrderingState {
             last_processed_tx_counter: last_processed_tx_counter_from_account,
-            last_pending_tx_counter: last_processed_tx_counter_from_account.unwrap_or_default(),
+            last_pending_tx_counter: last_processed_tx_counter_from_account.unwrap_or(0),
             tx_id: current_tx_id,
-        });
+        }),
+        Some(last_pending_tx_counter) => Ok(OrderingState {
+            last_processed_tx_counter,
+            last_pending_tx_counter,
+            tx_id: current_tx_id,
+        }),
     }
-    Ok(OrderingState {
-        last_processed_tx_counter,
-        last_pending_tx_counter: last_pending_tx_counter.unwrap_or_default(),
-        tx_id: current_tx_id,
-    })
 }
 
 /// Searches the chain for the transactions of the given user, whose pending transaction counter is
@@ -721,7 +1339,7 @@ pub fn last_ordering_state(
 #[inline]
 pub fn load_tx_between_counters(
     user: &String,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     start: u32,
     end: u32,
 ) -> Result<Vec<CoreTransaction>, Error> {
@@ -753,83 +1371,241 @@ pub fn load_tx_between_counters(
         .collect()
 }
 
-/// Searches the on-chain data for all pending transactions that decreased the balance of the
-/// given user and computes the pending balance.
-#[inline]
-pub fn compute_enc_pending_balance(
-    sender: &String,
-    ordering_state: OrderingState, // The state at the time of creating the last transaction.
-    last_processed_tx_counter: Option<u32>, // The current last processed tx counter.
-    enc_balance_in_account: EncryptedAmount,
-    db_dir: PathBuf,
-) -> Result<EncryptedAmount, Error> {
-    if last_processed_tx_counter < ordering_state.last_processed_tx_counter {
-        return Err(Error::MismatchInProcessedCounter {
-            current: last_processed_tx_counter,
-            earliest: ordering_state.last_processed_tx_counter,
-        });
-    }
-    let mut start = 1;
-    if let Some(counter) = ordering_state.last_processed_tx_counter {
-        start = counter + 1;
+/// Returns the paths of the transaction files in the on-chain directory of the `user`, where the
+/// validated issuances and mediator credits of the user's accounts are saved.
+fn user_tx_files(db_dir: ChainDirs, user: &str) -> Result<Vec<String>, Error> {
+    let dir = construct_path(db_dir, ON_CHAIN_DIR, user, "")?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(vec![]),
+    };
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            !path.is_dir()
+                && path
+                    .file_name()
+                    .map_or(false, |name| name.to_string_lossy().starts_with("tx_"))
+        })
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect())
+}
+
+/// Returns the amounts of the validated incoming transfers, issuances and mediator credits of the
+/// `user`'s `ticker` account whose tx_id is fewer than `min_confirmations` tx_ids before `tx_id`.
+pub(crate) fn unconfirmed_credits(
+    user: &String,
+    ticker: &String,
+    tx_id: u32,
+    min_confirmations: u32,
+    db_dir: ChainDirs,
+) -> Result<Vec<EncryptedAmount>, Error> {
+    let validated_transfer = TransferTxState::Justification(TxSubstate::Validated).to_string();
+    let validated_issuance = AssetTxState::Justification(TxSubstate::Validated).to_string();
+    let mut tx_files = all_tx_files(db_dir.clone())?;
+    tx_files.extend(user_tx_files(db_dir.clone(), user)?);
+    let mut credits = vec![];
+    for tx_file in tx_files {
+        // The stray files are skipped, as they are by the validation.
+        let (credit_tx_id, _, state, path) = match parse_tx_name(tx_file) {
+            Ok(parsed) => parsed,
+            Err(_) => continue,
+        };
+        if credit_tx_id >= tx_id || credit_tx_id.saturating_add(min_confirmations) <= tx_id {
+            continue;
+        }
+        let (account_id, enc_amount) = if state == validated_transfer {
+            let instruction: TransferInstruction = load_object_from(PathBuf::from(path))?;
+            let tx = JustifiedTransferTx::decode(&mut &instruction.data[..])
+                .map_err(|_| Error::DecodeError)?;
+            let memo = tx.finalized_data.init_data.memo;
+            (memo.receiver_account_id, memo.enc_amount_using_receiver)
+        } else if state == validated_issuance {
+            let instruction: AssetInstruction = load_object_from(PathBuf::from(path))?;
+            let tx = InitializedAssetTx::decode(&mut &instruction.data[..])
+                .map_err(|_| Error::DecodeError)?;
+            (tx.account_id, tx.memo.enc_issued_amount)
+        } else if state == CREDIT_VALIDATED_STATE {
+            let credit: SignedMediatorCredit = load_object_from(PathBuf::from(path))?;
+            (
+                credit.credit.beneficiary_account_id,
+                credit.credit.enc_amount,
+            )
+        } else {
+            continue;
+        };
+        let (receiver, receiver_ticker, _) = get_user_ticker_from(account_id, db_dir.clone())?;
+        if &receiver == user && &receiver_ticker == ticker {
+            credits.push(enc_amount);
+        }
     }
-    let transfer_inits = load_tx_between_counters(
-        sender,
-        db_dir.clone(),
-        start,
-        ordering_state.last_pending_tx_counter,
-    )?
-    .into_iter()
-    .filter(|tx| tx.decreases_account_balance())
-    .collect::<Vec<CoreTransaction>>();
-
-    debug!(
-        "------------> found {} outgoing transactions",
-        transfer_inits.len()
-    );
-    if transfer_inits.len() == 0 {
-        // There are no pending transactions.
-        return Ok(enc_balance_in_account);
+    Ok(credits)
+}
+
+/// Orders the states of a transfer by how far the transfer has progressed.
+#[inline]
+fn transfer_progress(state: TransferTxState) -> u8 {
+    match state {
+        TransferTxState::Initialization(_) => 0,
+        TransferTxState::Finalization(_) => 1,
+        TransferTxState::Justification(_) => 2,
     }
+}
 
-    // last_processed_tx_counter > ordering_state.last_processed_tx_counter &&  last_processed_tx_counter > pending -> pending has been skipped
-    // last_processed_tx_counter > ordering_state.last_processed_tx_counter &&  last_processed_tx_counter == pending -> error
-    // last_processed_tx_counter > ordering_state.last_processed_tx_counter &&  last_processed_tx_counter < pending
-    // last_processed_tx_counter == ordering_state.last_processed_tx_counter
-    // TODO: implementing the simple case for now where the last processed transaction inside the account
-    //       is the same as the last processed transaction inside the last transaction.
-    // The rest of the cases will be handled in CRYP-130
-    if last_processed_tx_counter != ordering_state.last_processed_tx_counter {
-        return Err(Error::NotImplemented {
-            story: "CRYP-130".to_string(),
-        });
+/// Returns the un-validated transfers that involve the `user`'s `ticker` account as (tx_id, state)
+/// pairs. Each transfer is listed once, in the furthest state it has reached. The transfers are
+/// sorted by the pending transaction counter of the account, i.e., in the order in which
+/// `compute_enc_pending_balance` walks them. The transfers in which the account has not signed
+/// anything yet do not have a counter, and are listed last in the order of their tx_id.
+pub fn pending_for_account(
+    db_dir: ChainDirs,
+    user: &String,
+    ticker: &String,
+) -> Result<Vec<(u32, TransferTxState)>, Error> {
+    let is_account = |account_id: EncryptedAssetId| -> Result<bool, Error> {
+        let (account_user, account_ticker, _) = get_user_ticker_from(account_id, db_dir.clone())?;
+        Ok(&account_user == user && &account_ticker == ticker)
+    };
+    let pending_states = [
+        TransferTxState::Initialization(TxSubstate::Started),
+        TransferTxState::Finalization(TxSubstate::Started),
+        TransferTxState::Justification(TxSubstate::Started),
+    ];
+
+    let mut pending: HashMap<u32, (Option<u32>, TransferTxState)> = HashMap::new();
+    for tx_file in all_unverified_tx_files(db_dir.clone())? {
+        let (tx_id, tx_user, state, path) = parse_tx_name(tx_file)?;
+        if !pending_states.iter().any(|s| s.to_string() == state) {
+            continue;
+        }
+        // The counter is only taken from the part of the transfer signed by the account.
+        let (state, counter) = match load_tx_file(tx_id, tx_user, state, path)? {
+            CoreTransaction::TransferInit {
+                tx, ordering_state, ..
+            } => {
+                let is_sender = is_account(tx.memo.sender_account_id)?;
+                if !is_sender && !is_account(tx.memo.receiver_account_id)? {
+                    continue;
+                }
+                let counter = if is_sender {
+                    Some(ordering_state.last_pending_tx_counter)
+                } else {
+                    None
+                };
+                (pending_states[0], counter)
+            }
+            CoreTransaction::TransferFinalize {
+                tx, ordering_state, ..
+            } => {
+                let memo = tx.init_data.memo;
+                let is_receiver = is_account(memo.receiver_account_id)?;
+                if !is_receiver && !is_account(memo.sender_account_id)? {
+                    continue;
+                }
+                let counter = if is_receiver {
+                    Some(ordering_state.last_pending_tx_counter)
+                } else {
+                    None
+                };
+                (pending_states[1], counter)
+            }
+            CoreTransaction::TransferJustify { tx, .. } => {
+                let memo = tx.finalized_data.init_data.memo;
+                if !is_account(memo.sender_account_id)? && !is_account(memo.receiver_account_id)? {
+                    continue;
+                }
+                (pending_states[2], None)
+            }
+            _ => continue,
+        };
+
+        let entry = pending.entry(tx_id).or_insert((counter, state));
+        if entry.0.is_none() {
+            entry.0 = counter;
+        }
+        if transfer_progress(state) > transfer_progress(entry.1) {
+            entry.1 = state;
+        }
     }
 
-    let mut pending_balance = enc_balance_in_account;
-    for core_tx in transfer_inits {
-        if let CoreTransaction::TransferInit {
-            tx,
-            sender: _,
-            ordering_state: _,
-            tx_id: _,
-        } = core_tx
-        {
-            pending_balance -= tx.memo.enc_amount_using_sender;
-            let account_id = tx.memo.sender_account_id;
-            debug!(
-                "------> decremented by {}.",
-                debug_decrypt(account_id, tx.memo.enc_amount_using_sender, db_dir.clone())?
-            );
+    let mut pending: Vec<(Option<u32>, u32, TransferTxState)> = pending
+        .into_iter()
+        .map(|(tx_id, (counter, state))| (counter, tx_id, state))
+        .collect();
+    pending.sort_by_key(|(counter, tx_id, _)| (counter.is_none(), *counter, *tx_id));
+    Ok(pending
+        .into_iter()
+        .map(|(_, tx_id, state)| (tx_id, state))
+        .collect())
+}
+
+/// The default of the maximum number of unverified transaction files. This is far above the
+/// number of transactions that are pending at once in normal use.
+pub const DEFAULT_MAX_PENDING: usize = 100_000;
+
+/// What the validation does with a transaction that cannot be loaded or decoded. The
+/// transactions that fail the verification are always skipped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorStrategy {
+    /// Log the error and continue with the rest of the transactions.
+    Skip,
+    /// Stop the validation and return the error.
+    Abort,
+}
+
+impl std::str::FromStr for ErrorStrategy {
+    type Err = Error;
+
+    fn from_str(strategy: &str) -> Result<Self, Error> {
+        match strategy {
+            "skip" => Ok(ErrorStrategy::Skip),
+            "abort" => Ok(ErrorStrategy::Abort),
+            _ => Err(Error::InvalidErrorStrategy {
+                strategy: strategy.to_string(),
+            }),
         }
     }
-    Ok(pending_balance)
+}
+
+impl Default for ErrorStrategy {
+    fn default() -> Self {
+        ErrorStrategy::Skip
+    }
 }
 
 /// Searches the on-chain data and returns all the transactions since the last verification.
-pub fn all_unverified_tx_files(db_dir: PathBuf) -> Result<Vec<String>, Error> {
+/// Fails if there are more of them than `DEFAULT_MAX_PENDING`.
+pub fn all_unverified_tx_files(db_dir: ChainDirs) -> Result<Vec<String>, Error> {
+    all_unverified_tx_files_with_limit(db_dir, DEFAULT_MAX_PENDING)
+}
+
+/// Same as `all_unverified_tx_files`, but with an explicit limit. This guards against loading
+/// every object of a database directory that is littered with stray files.
+pub fn all_unverified_tx_files_with_limit(
+    db_dir: ChainDirs,
+    limit: usize,
+) -> Result<Vec<String>, Error> {
     let start = last_verified_tx_id(db_dir.clone());
-    let mut dir = db_dir.clone();
-    dir.push(ON_CHAIN_DIR);
+    let files = tx_files_after(db_dir, start)?;
+    if files.len() > limit {
+        return Err(Error::TooManyPendingTransactions {
+            count: files.len(),
+            limit,
+        });
+    }
+    Ok(files)
+}
+
+/// Returns the paths of all the transaction files, including the validated ones.
+#[inline]
+pub fn all_tx_files(db_dir: ChainDirs) -> Result<Vec<String>, Error> {
+    tx_files_after(db_dir, -1)
+}
+
+/// Returns the paths of the transaction files whose tx_id is greater than `start`.
+fn tx_files_after(db_dir: ChainDirs, start: i32) -> Result<Vec<String>, Error> {
+    let mut dir = chain_dir(db_dir, ON_CHAIN_DIR);
     dir.push(COMMON_OBJECTS_DIR);
 
     let mut files = vec![];
@@ -852,16 +1628,11 @@ pub fn all_unverified_tx_files(db_dir: PathBuf) -> Result<Vec<String>, Error> {
                 let re = Regex::new(r"^tx_([0-9]+)_.*$").map_err(|_| Error::RegexError {
                     reason: String::from("Failed to compile the transaction id regex"),
                 })?;
-                let caps = re.captures(&file_name).ok_or(Error::RegexError {
-                    reason: format!("Pattern did not match {}", file_name),
-                })?;
-                let tx_id = caps[1]
-                    .to_string()
-                    .parse::<u32>()
-                    .map_err(|_| Error::RegexError {
-                        reason: String::from("failed to convert amount to u32."),
-                    })?;
-                if tx_id as i32 > start {
+                // The files without a valid id are kept, for `parse_tx_name` to report them.
+                let tx_id = re
+                    .captures(&file_name)
+                    .and_then(|caps| caps[1].parse::<u32>().ok());
+                if tx_id.map_or(true, |tx_id| tx_id as i32 > start) {
                     files.push(String::from(
                         path.to_str().ok_or(Error::PathBufConversionError)?,
                     ));
@@ -872,13 +1643,13 @@ pub fn all_unverified_tx_files(db_dir: PathBuf) -> Result<Vec<String>, Error> {
     Ok(files)
 }
 
-/// Loads the tx_id of the last verified transaction from an off-chain file.
+/// Loads the tx_id of the last verified transaction from the validator's directory.
 #[inline]
-pub fn last_verified_tx_id(db_dir: PathBuf) -> i32 {
+pub fn last_verified_tx_id(db_dir: ChainDirs) -> i32 {
     // The file and updated after verification is done.
     let last_verified: Result<i32, Error> = load_from_file(
         db_dir,
-        OFF_CHAIN_DIR,
+        VALIDATOR_DIR,
         COMMON_OBJECTS_DIR,
         LAST_VALIDATED_TX_ID_FILE,
     );
@@ -888,6 +1659,32 @@ pub fn last_verified_tx_id(db_dir: PathBuf) -> i32 {
     }
 }
 
+/// Returns true if a transaction file in the given state, as it appears in the file name, is
+/// ready for validation. This is the file name equivalent of
+/// `CoreTransaction::is_ready_for_validation` and does not load the file.
+#[inline]
+pub fn is_ready_for_validation_state(state: &str) -> bool {
+    state == AssetTxState::Initialization(TxSubstate::Started).to_string()
+        || state == TransferTxState::Justification(TxSubstate::Started).to_string()
+        || state.starts_with("ticker#")
+        || state == CREDIT_STARTED_STATE
+}
+
+/// The file name equivalent of `readiness_reason`.
+pub fn state_readiness(state: &str) -> ReadinessStatus {
+    if is_ready_for_validation_state(state) {
+        ReadinessStatus::Ready
+    } else if state == TransferTxState::Initialization(TxSubstate::Started).to_string() {
+        ReadinessStatus::waiting_for(TransferTxState::Finalization(TxSubstate::Started))
+    } else if state == TransferTxState::Finalization(TxSubstate::Started).to_string() {
+        ReadinessStatus::waiting_for(TransferTxState::Justification(TxSubstate::Started))
+    } else {
+        ReadinessStatus::NotReady {
+            missing_state: None,
+        }
+    }
+}
+
 /// Reads a transaction file and returns the corresponding object.
 #[inline]
 pub fn load_tx_file(
@@ -934,6 +1731,19 @@ pub fn load_tx_file(
             mediator: user,
             tx_id,
         }
+    } else if state == TransferTxState::Justification(TxSubstate::Rejected).to_string() {
+        let instruction: TransferInstruction = load_object_from(PathBuf::from(tx_file_path))?;
+        CoreTransaction::TransferRejected {
+            sender: user,
+            reason: instruction.rejection_reason,
+            tx_id,
+        }
+    } else if state == CREDIT_STARTED_STATE {
+        CoreTransaction::MediatorCredit {
+            credit: load_object_from(PathBuf::from(tx_file_path))?,
+            mediator: user,
+            tx_id,
+        }
     } else if state.starts_with("ticker#") {
         let ordered_account_tx: OrderedPubAccountTx =
             load_object_from(PathBuf::from(tx_file_path))?;
@@ -953,8 +1763,26 @@ pub fn load_tx_file(
 fn debug_decrypt(
     account_id: EncryptedAssetId,
     enc_balance: EncryptedAmount,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
 ) -> Result<u32, Error> {
+    let account = debug_load_account(account_id, db_dir)?;
+    decrypt_amount(&account.secret, enc_balance)
+}
+
+/// Use only for debugging purposes. Same as `debug_decrypt`, but uses the given `solver`
+/// to find the discrete log of the decrypted value.
+#[inline]
+pub fn debug_decrypt_with(
+    account_id: EncryptedAssetId,
+    enc_balance: EncryptedAmount,
+    db_dir: ChainDirs,
+    solver: &dyn DiscreteLogSolver,
+) -> Result<u32, Error> {
+    let account = debug_load_account(account_id, db_dir)?;
+    solve_amount(&account.secret, enc_balance, solver)
+}
+
+fn debug_load_account(account_id: EncryptedAssetId, db_dir: ChainDirs) -> Result<Account, Error> {
     let (user, ticker, _) = get_user_ticker_from(account_id, db_dir.clone())?;
     let ordered_pub_account: OrderedPubAccount = load_object(
         db_dir.clone(),
@@ -962,29 +1790,64 @@ fn debug_decrypt(
         &user,
         &user_public_account_file(&ticker),
     )?;
-    let account = Account {
-        secret: load_object(
-            db_dir.clone(),
-            OFF_CHAIN_DIR,
-            &user,
-            &user_secret_account_file(&ticker),
-        )?,
+    Ok(Account {
+        secret: load_secret_object(db_dir, &user, &user_secret_account_file(&ticker))?,
         public: ordered_pub_account.pub_account,
+    })
+}
+
+/// Use only for debugging purposes. Same as `debug_decrypt`, but loads the secret key from the
+/// `key_dir`, which has the layout of the off-chain directory. Returns `None`, without loading
+/// any secret, if there is no key directory.
+pub(crate) fn debug_decrypt_balance(
+    account_id: EncryptedAssetId,
+    enc_balance: EncryptedAmount,
+    db_dir: ChainDirs,
+    key_dir: Option<&PathBuf>,
+) -> Result<Option<u32>, Error> {
+    let mut secret_path = match key_dir {
+        Some(key_dir) => key_dir.clone(),
+        None => return Ok(None),
     };
-    account
-        .secret
+    let (user, ticker, _) = get_user_ticker_from(account_id, db_dir)?;
+    ensure_safe_name_component(&user)?;
+    secret_path.push(&user);
+    secret_path.push(user_secret_account_file(&ticker));
+    let secret: SecAccount = load_secret_object_from(secret_path, passphrase)?;
+    decrypt_amount(&secret, enc_balance).map(Some)
+}
+
+fn decrypt_amount(secret: &SecAccount, enc_balance: EncryptedAmount) -> Result<u32, Error> {
+    secret
         .enc_keys
         .secret
         .decrypt(&enc_balance)
         .map_err(|error| Error::LibraryError { error })
 }
 
+fn solve_amount(
+    secret: &SecAccount,
+    enc_balance: EncryptedAmount,
+    solver: &dyn DiscreteLogSolver,
+) -> Result<u32, Error> {
+    // value * g = y - x / secret_key
+    let value_point = enc_balance.y - secret.enc_keys.secret.secret.invert() * enc_balance.x;
+    let value = solver
+        .solve(
+            value_point,
+            RISTRETTO_BASEPOINT_POINT,
+            u64::from(u32::max_value()),
+        )
+        .ok_or(Error::DiscreteLogNotFound)?;
+    value.try_into().map_err(|_| Error::BalanceTooBig)
+}
+
 /// Use only for debugging purposes.
 #[inline]
 pub fn debug_decrypt_account_balance(
     user: String,
     ticker: String,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
 ) -> Result<u32, Error> {
     let enc_balance: EncryptedAmount = load_object(
         db_dir.clone(),
@@ -992,12 +1855,8 @@ pub fn debug_decrypt_account_balance(
         &user,
         &user_public_account_balance_file(&ticker),
     )?;
-    let secret: SecAccount = load_object(
-        db_dir.clone(),
-        OFF_CHAIN_DIR,
-        &user,
-        &user_secret_account_file(&ticker),
-    )?;
+    let secret: SecAccount =
+        load_secret_object(db_dir.clone(), &user, &user_secret_account_file(&ticker))?;
     secret
         .enc_keys
         .secret
@@ -1005,22 +1864,46 @@ pub fn debug_decrypt_account_balance(
         .map_err(|error| Error::LibraryError { error })
 }
 
+/// Use only for debugging purposes. Same as `debug_decrypt_account_balance`, but reports the
+/// progress of the search and can be cancelled. See `decrypt_with_progress`.
+pub fn debug_decrypt_account_balance_with_progress(
+    user: String,
+    ticker: String,
+    db_dir: ChainDirs,
+    progress: impl FnMut(u64),
+    cancel: &AtomicBool,
+) -> Result<u32, Error> {
+    let enc_balance: EncryptedAmount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        &user,
+        &user_public_account_balance_file(&ticker),
+    )?;
+    let secret: SecAccount =
+        load_secret_object(db_dir.clone(), &user, &user_secret_account_file(&ticker))?;
+    let value = decrypt_with_progress(
+        &enc_balance,
+        &secret.enc_keys.secret,
+        u64::from(u32::max_value()),
+        progress,
+        cancel,
+    )?;
+    value.try_into().map_err(|_| Error::BalanceTooBig)
+}
+
 /// Use only for debugging purposes.
 #[inline]
-pub fn debug_decrypt_base64_account_balance(
+pub fn debug_decrypt_encoded_account_balance(
     user: String,
     encrypted_value: String,
+    encoding: Encoding,
     ticker: String,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
 ) -> Result<u32, Error> {
-    let mut data: &[u8] = &base64::decode(encrypted_value).unwrap();
+    let mut data: &[u8] = &encoding.decode(encrypted_value)?;
     let enc_balance = EncryptedAmount::decode(&mut data).unwrap();
-    let scrt: SecAccount = load_object(
-        db_dir.clone(),
-        OFF_CHAIN_DIR,
-        &user,
-        &user_secret_account_file(&ticker),
-    )?;
+    let scrt: SecAccount =
+        load_secret_object(db_dir.clone(), &user, &user_secret_account_file(&ticker))?;
     scrt.enc_keys
         .secret
         .decrypt(&enc_balance)
@@ -0,0 +1,608 @@
+//! Credits of deposits that enter MERCAT from outside, e.g., through an on-ramp. Such a credit
+//! has no sending MERCAT account. Instead, a mediator who is authorized for the ticker encrypts
+//! the deposited amount under the beneficiary's key, proves that the ciphertext encrypts that
+//! amount, and signs the result with the mediator key.
+
+use crate::{
+    account_close::ensure_not_closed, audit::record_state_transition,
+    chain_setup::ensure_amount_in_ticker_range, create_rng_from_seed, errors::Error,
+    get_checked_user_ticker_from, issuance_policy::POLICY_SIGNATURE_LABEL, load_from_file,
+    load_object, save_object, save_to_file, secret_store::load_secret_object,
+    user_public_account_file, ChainDirs, OrderedPubAccount, COMMON_OBJECTS_DIR,
+    MEDIATOR_PUBLIC_ACCOUNT_FILE, ON_CHAIN_DIR, SECRET_ACCOUNT_FILE,
+};
+use codec::{Decode, Encode};
+use cryptography::mercat::{EncryptedAmount, EncryptedAssetId, EncryptionPubKey, MediatorAccount};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+};
+use log::info;
+use metrics::timing;
+use rand::{CryptoRng, RngCore};
+use sha3::{Digest, Sha3_512};
+use std::{collections::HashMap, time::Instant};
+
+/// The on-chain list of the mediators that may credit each ticker.
+pub const CREDIT_MEDIATORS_FILE: &str = "credit_mediators.json";
+
+/// The state of a credit instruction in its file name, before and after the validation.
+pub const CREDIT_STARTED_STATE: &str = "credit-started";
+pub const CREDIT_VALIDATED_STATE: &str = "credit-validated";
+
+const CORRECTNESS_LABEL: &[u8] = b"mercat-mediator-credit-correctness";
+const SIGNATURE_LABEL: &[u8] = b"mercat-mediator-credit-signature";
+
+/// Uses the same layout as `confidential_transaction_file`.
+#[inline]
+pub fn mediator_credit_file(tx_id: u32, mediator: &str, ticker: &str, state: &str) -> String {
+    format!("tx_{}_{}_{}_{}.json", tx_id, ticker, mediator, state)
+}
+
+/// A proof that `enc_amount` is an encryption of the public amount under the beneficiary's key.
+/// It proves the knowledge of the randomness `r` of the encryption, i.e., that
+/// `x = r * pub_key` and `y - amount * g = r * g`, with the same `r` in both.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct CreditCorrectnessProof {
+    pub pub_key_commitment: [u8; 32],
+    pub base_commitment: [u8; 32],
+    pub response: [u8; 32],
+}
+
+/// A Schnorr signature, e.g., with the mediator's key.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct MediatorSignature {
+    pub commitment: [u8; 32],
+    pub response: [u8; 32],
+}
+
+/// The content of a credit that the mediator signs.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct MediatorCredit {
+    pub beneficiary_account_id: EncryptedAssetId,
+    pub amount: u32,
+    pub enc_amount: EncryptedAmount,
+    pub proof: CreditCorrectnessProof,
+}
+
+/// The on-chain instruction of a credit.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct SignedMediatorCredit {
+    pub credit: MediatorCredit,
+    pub signature: MediatorSignature,
+}
+
+pub(crate) fn challenge(label: &[u8], parts: &[&[u8]]) -> Scalar {
+    let mut hasher = Sha3_512::new();
+    hasher.input(label);
+    for part in parts {
+        hasher.input(&(part.len() as u64).to_le_bytes());
+        hasher.input(part);
+    }
+    Scalar::from_hash(hasher)
+}
+
+pub(crate) fn decompress(bytes: &[u8; 32]) -> Option<RistrettoPoint> {
+    CompressedRistretto(*bytes).decompress()
+}
+
+fn correctness_challenge(
+    pub_key: &RistrettoPoint,
+    amount: u32,
+    enc_amount: &EncryptedAmount,
+    pub_key_commitment: &[u8; 32],
+    base_commitment: &[u8; 32],
+) -> Scalar {
+    challenge(
+        CORRECTNESS_LABEL,
+        &[
+            pub_key.compress().as_bytes(),
+            &amount.encode(),
+            &enc_amount.encode(),
+            pub_key_commitment,
+            base_commitment,
+        ],
+    )
+}
+
+/// Encrypts `amount` under `pub_key` and proves the correctness of the encryption.
+fn encrypt_with_proof<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    pub_key: &RistrettoPoint,
+    amount: u32,
+) -> (EncryptedAmount, CreditCorrectnessProof) {
+    let blinding = Scalar::random(rng);
+    let enc_amount = EncryptedAmount {
+        x: blinding * pub_key,
+        y: (blinding + Scalar::from(amount)) * RISTRETTO_BASEPOINT_POINT,
+    };
+
+    let nonce = Scalar::random(rng);
+    let pub_key_commitment = (nonce * pub_key).compress().to_bytes();
+    let base_commitment = (nonce * RISTRETTO_BASEPOINT_POINT).compress().to_bytes();
+    let c = correctness_challenge(
+        pub_key,
+        amount,
+        &enc_amount,
+        &pub_key_commitment,
+        &base_commitment,
+    );
+    let proof = CreditCorrectnessProof {
+        pub_key_commitment,
+        base_commitment,
+        response: (nonce + c * blinding).to_bytes(),
+    };
+    (enc_amount, proof)
+}
+
+/// Verifies that `credit.enc_amount` encrypts `credit.amount` under `pub_key`.
+fn verify_correctness(pub_key: &RistrettoPoint, credit: &MediatorCredit) -> bool {
+    let proof = &credit.proof;
+    let (pub_key_commitment, base_commitment) = match (
+        decompress(&proof.pub_key_commitment),
+        decompress(&proof.base_commitment),
+    ) {
+        (Some(pub_key_commitment), Some(base_commitment)) => (pub_key_commitment, base_commitment),
+        _ => return false,
+    };
+    let response = match Scalar::from_canonical_bytes(proof.response) {
+        Some(response) => response,
+        None => return false,
+    };
+    let c = correctness_challenge(
+        pub_key,
+        credit.amount,
+        &credit.enc_amount,
+        &proof.pub_key_commitment,
+        &proof.base_commitment,
+    );
+    let enc_amount = &credit.enc_amount;
+    response * pub_key == pub_key_commitment + c * enc_amount.x
+        && response * RISTRETTO_BASEPOINT_POINT
+            == base_commitment
+                + c * (enc_amount.y - Scalar::from(credit.amount) * RISTRETTO_BASEPOINT_POINT)
+}
+
+/// The labels of the signatures that are made with the keys of the mediators and the governance,
+/// by their purpose. The issuance and transfer contexts of the mediator's justifications are
+/// internal to the cryptography library.
+pub fn signing_contexts() -> Vec<(&'static str, &'static [u8])> {
+    vec![
+        ("mediator credit", SIGNATURE_LABEL),
+        ("issuance policy", POLICY_SIGNATURE_LABEL),
+    ]
+}
+
+/// Fails if two signing contexts are equal. A signature in one context could otherwise be
+/// replayed as a signature in the other.
+pub fn ensure_distinct_signing_contexts(contexts: &[(&str, &[u8])]) -> Result<(), Error> {
+    for (i, (first, first_label)) in contexts.iter().enumerate() {
+        for (second, second_label) in &contexts[i + 1..] {
+            if first_label == second_label {
+                return Err(Error::SigningContextCollision {
+                    first: first.to_string(),
+                    second: second.to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fails with `WrongSignatureContext` if a `signature` that does not verify under the `expected`
+/// context verifies under another one of the `signing_contexts`. This tells a signature that was
+/// made for the wrong purpose apart from an invalid one.
+pub(crate) fn ensure_signing_context(
+    tx_id: u32,
+    expected: &[u8],
+    pub_key: &RistrettoPoint,
+    message: &[u8],
+    signature: &MediatorSignature,
+) -> Result<(), Error> {
+    let contexts = signing_contexts();
+    let purpose_of = |label: &[u8]| {
+        contexts
+            .iter()
+            .find(|(_, context)| *context == label)
+            .map_or("unknown", |(purpose, _)| *purpose)
+    };
+    match contexts.iter().find(|(_, context)| {
+        *context != expected && verify_signature(context, pub_key, message, signature)
+    }) {
+        Some((found, _)) => Err(Error::WrongSignatureContext {
+            tx_id,
+            expected: purpose_of(expected).to_string(),
+            found: found.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Signs the `message` with a Schnorr signature. The `label` separates the messages of
+/// different purposes that are signed with the same key.
+pub(crate) fn sign<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    label: &[u8],
+    secret_key: &Scalar,
+    pub_key: &RistrettoPoint,
+    message: &[u8],
+) -> MediatorSignature {
+    let nonce = Scalar::random(rng);
+    let commitment = (nonce * RISTRETTO_BASEPOINT_POINT).compress().to_bytes();
+    let c = challenge(
+        label,
+        &[pub_key.compress().as_bytes(), &commitment, message],
+    );
+    MediatorSignature {
+        commitment,
+        response: (nonce + c * secret_key).to_bytes(),
+    }
+}
+
+pub(crate) fn verify_signature(
+    label: &[u8],
+    pub_key: &RistrettoPoint,
+    message: &[u8],
+    signature: &MediatorSignature,
+) -> bool {
+    let (commitment, response) = match (
+        decompress(&signature.commitment),
+        Scalar::from_canonical_bytes(signature.response),
+    ) {
+        (Some(commitment), Some(response)) => (commitment, response),
+        _ => return false,
+    };
+    let c = challenge(
+        label,
+        &[
+            pub_key.compress().as_bytes(),
+            &signature.commitment,
+            message,
+        ],
+    );
+    response * RISTRETTO_BASEPOINT_POINT == commitment + c * pub_key
+}
+
+/// Allows the `mediator` to credit the `ticker` accounts.
+pub fn authorize_credit_mediator(
+    db_dir: ChainDirs,
+    ticker: String,
+    mediator: String,
+) -> Result<(), Error> {
+    let mut mediators: HashMap<String, Vec<String>> = load_from_file(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        CREDIT_MEDIATORS_FILE,
+    )
+    .unwrap_or_default();
+    let ticker_mediators = mediators.entry(ticker).or_insert_with(Vec::new);
+    if !ticker_mediators.contains(&mediator) {
+        ticker_mediators.push(mediator);
+    }
+    save_to_file(
+        db_dir,
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        CREDIT_MEDIATORS_FILE,
+        &mediators,
+    )
+}
+
+/// Returns an error if the `mediator` is not allowed to credit the `ticker` accounts.
+pub fn ensure_credit_mediator(
+    db_dir: ChainDirs,
+    mediator: &str,
+    ticker: &str,
+) -> Result<(), Error> {
+    let mediators: HashMap<String, Vec<String>> = load_from_file(
+        db_dir,
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        CREDIT_MEDIATORS_FILE,
+    )
+    .unwrap_or_default();
+    let is_authorized = mediators.get(ticker).map_or(false, |ticker_mediators| {
+        ticker_mediators.iter().any(|m| m == mediator)
+    });
+    if !is_authorized {
+        return Err(Error::MediatorNotAuthorized {
+            mediator: mediator.to_string(),
+            ticker: ticker.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Credits `amount` to the `beneficiary`'s `ticker` account. The credit is added to the balance
+/// once the validator has verified it.
+pub fn process_mediator_credit(
+    seed: String,
+    db_dir: ChainDirs,
+    mediator: String,
+    beneficiary: String,
+    ticker: String,
+    amount: u32,
+    tx_id: u32,
+) -> Result<(), Error> {
+    ensure_credit_mediator(db_dir.clone(), &mediator, &ticker)?;
+    ensure_not_closed(db_dir.clone(), &beneficiary, &ticker)?;
+    let mut rng = create_rng_from_seed(Some(seed))?;
+
+    let load_objects_timer = Instant::now();
+    let beneficiary_account: OrderedPubAccount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        &beneficiary,
+        &user_public_account_file(&ticker),
+    )?;
+    let mediator_account: MediatorAccount =
+        load_secret_object(db_dir.clone(), &mediator, SECRET_ACCOUNT_FILE)?;
+    timing!(
+        "mediator.credit.load_objects",
+        load_objects_timer,
+        Instant::now(),
+        "tx_id" => tx_id.to_string()
+    );
+
+    let credit_timer = Instant::now();
+    let (enc_amount, proof) = encrypt_with_proof(
+        &mut rng,
+        &beneficiary_account.pub_account.owner_enc_pub_key.pub_key,
+        amount,
+    );
+    let credit = MediatorCredit {
+        beneficiary_account_id: beneficiary_account.pub_account.enc_asset_id,
+        amount,
+        enc_amount,
+        proof,
+    };
+    let signature = sign(
+        &mut rng,
+        SIGNATURE_LABEL,
+        &mediator_account.encryption_key.secret.secret,
+        &mediator_account.encryption_key.public.pub_key,
+        &credit.encode(),
+    );
+    timing!(
+        "mediator.credit.create",
+        credit_timer,
+        Instant::now(),
+        "tx_id" => tx_id.to_string()
+    );
+
+    save_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &mediator_credit_file(tx_id, &mediator, &ticker, CREDIT_STARTED_STATE),
+        &SignedMediatorCredit { credit, signature },
+    )?;
+    record_state_transition(db_dir, tx_id, &mediator, None, CREDIT_STARTED_STATE)?;
+    info!(
+        "CLI log: tx-{}: {} credited {} {} to {}.",
+        tx_id, mediator, amount, ticker, beneficiary
+    );
+    Ok(())
+}
+
+/// Verifies the mediator's signature and the correctness proof of a credit. Returns the
+/// beneficiary and the ticker of the credited account.
+pub fn verify_mediator_credit(
+    db_dir: ChainDirs,
+    signed_credit: &SignedMediatorCredit,
+    mediator: &str,
+    tx_id: u32,
+) -> Result<(String, String), Error> {
+    let credit = &signed_credit.credit;
+    let (beneficiary, ticker, _) =
+        get_checked_user_ticker_from(credit.beneficiary_account_id, db_dir.clone())?;
+    ensure_credit_mediator(db_dir.clone(), mediator, &ticker)?;
+    ensure_amount_in_ticker_range(db_dir.clone(), &ticker, credit.amount)?;
+
+    let mediator_pub_key: EncryptionPubKey = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        mediator,
+        MEDIATOR_PUBLIC_ACCOUNT_FILE,
+    )?;
+    let message = credit.encode();
+    if !verify_signature(
+        SIGNATURE_LABEL,
+        &mediator_pub_key.pub_key,
+        &message,
+        &signed_credit.signature,
+    ) {
+        ensure_signing_context(
+            tx_id,
+            SIGNATURE_LABEL,
+            &mediator_pub_key.pub_key,
+            &message,
+            &signed_credit.signature,
+        )?;
+        return Err(Error::InvalidMediatorSignature { tx_id });
+    }
+
+    let beneficiary_account: OrderedPubAccount = load_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        &beneficiary,
+        &user_public_account_file(&ticker),
+    )?;
+    if !verify_correctness(
+        &beneficiary_account.pub_account.owner_enc_pub_key.pub_key,
+        credit,
+    ) {
+        return Err(Error::InvalidCreditProof { tx_id });
+    }
+    Ok((beneficiary, ticker))
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account, chain_setup::process_asset_id_creation,
+        debug_decrypt_account_balance, encoding::Encoding, gen_seed,
+        justify::process_create_mediator, test_utils::test_db_dir, validate::validate_all_pending,
+    };
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_signing_contexts_are_distinct() {
+        ensure_distinct_signing_contexts(&signing_contexts()).unwrap();
+
+        let contexts = [
+            ("issue", &b"mercat-signature"[..]),
+            ("transfer", &b"mercat-other-signature"[..]),
+            ("transaction", &b"mercat-signature"[..]),
+        ];
+        match ensure_distinct_signing_contexts(&contexts) {
+            Err(Error::SigningContextCollision { first, second }) => {
+                assert_eq!((first.as_str(), second.as_str()), ("issue", "transaction"))
+            }
+            other => panic!("Expected SigningContextCollision, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_mediator_credit() {
+        let db_dir = test_db_dir("mediator_credit");
+
+        let acme = String::from("ACME");
+        let (alice, mike, eve) = (
+            String::from("alice"),
+            String::from("mike"),
+            String::from("eve"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        for mediator in &[&mike, &eve] {
+            process_create_mediator(
+                gen_seed(),
+                db_dir.clone(),
+                mediator.to_string(),
+                Encoding::default(),
+                false,
+            )
+            .unwrap();
+        }
+        process_create_account(
+            Some(gen_seed()),
+            db_dir.clone(),
+            acme.clone(),
+            alice.clone(),
+            false,
+            Encoding::default(),
+            1,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        authorize_credit_mediator(db_dir.clone(), acme.clone(), mike.clone()).unwrap();
+
+        // Only the authorized mediators can credit.
+        match process_mediator_credit(
+            gen_seed(),
+            db_dir.clone(),
+            eve.clone(),
+            alice.clone(),
+            acme.clone(),
+            5,
+            2,
+        ) {
+            Err(Error::MediatorNotAuthorized { mediator, ticker }) => {
+                assert_eq!((mediator, ticker), (eve.clone(), acme.clone()))
+            }
+            other => panic!("Expected MediatorNotAuthorized, got {:?}", other),
+        }
+
+        process_mediator_credit(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            alice.clone(),
+            acme.clone(),
+            5,
+            2,
+        )
+        .unwrap();
+        let file_name = mediator_credit_file(2, &mike, &acme, CREDIT_STARTED_STATE);
+        let signed_credit: SignedMediatorCredit =
+            load_object(db_dir.clone(), ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &file_name).unwrap();
+        assert_eq!(
+            verify_mediator_credit(db_dir.clone(), &signed_credit, &mike, 2).unwrap(),
+            (alice.clone(), acme.clone())
+        );
+
+        // A different amount breaks both the signature and the proof.
+        let mut tampered = signed_credit.clone();
+        tampered.credit.amount = 500;
+        match verify_mediator_credit(db_dir.clone(), &tampered, &mike, 2) {
+            Err(Error::InvalidMediatorSignature { tx_id }) => assert_eq!(tx_id, 2),
+            other => panic!("Expected InvalidMediatorSignature, got {:?}", other),
+        }
+        // Re-signing by an unauthorized mediator does not help.
+        let eve_account: MediatorAccount =
+            load_secret_object(db_dir.clone(), &eve, SECRET_ACCOUNT_FILE).unwrap();
+        tampered.signature = sign(
+            &mut OsRng,
+            SIGNATURE_LABEL,
+            &eve_account.encryption_key.secret.secret,
+            &eve_account.encryption_key.public.pub_key,
+            &tampered.credit.encode(),
+        );
+        match verify_mediator_credit(db_dir.clone(), &tampered, &eve, 2) {
+            Err(Error::MediatorNotAuthorized { .. }) => {}
+            other => panic!("Expected MediatorNotAuthorized, got {:?}", other),
+        }
+        // An authorized mediator cannot credit more than what the ciphertext encrypts.
+        let mike_account: MediatorAccount =
+            load_secret_object(db_dir.clone(), &mike, SECRET_ACCOUNT_FILE).unwrap();
+        tampered.signature = sign(
+            &mut OsRng,
+            SIGNATURE_LABEL,
+            &mike_account.encryption_key.secret.secret,
+            &mike_account.encryption_key.public.pub_key,
+            &tampered.credit.encode(),
+        );
+        match verify_mediator_credit(db_dir.clone(), &tampered, &mike, 2) {
+            Err(Error::InvalidCreditProof { tx_id }) => assert_eq!(tx_id, 2),
+            other => panic!("Expected InvalidCreditProof, got {:?}", other),
+        }
+        // A signature of the credit in the context of the issuance policies is reported as such.
+        let mut misdirected = signed_credit.clone();
+        misdirected.signature = sign(
+            &mut OsRng,
+            POLICY_SIGNATURE_LABEL,
+            &mike_account.encryption_key.secret.secret,
+            &mike_account.encryption_key.public.pub_key,
+            &misdirected.credit.encode(),
+        );
+        match verify_mediator_credit(db_dir.clone(), &misdirected, &mike, 2) {
+            Err(Error::WrongSignatureContext {
+                tx_id,
+                expected,
+                found,
+            }) => assert_eq!(
+                (tx_id, expected.as_str(), found.as_str()),
+                (2, "mediator credit", "issuance policy")
+            ),
+            other => panic!("Expected WrongSignatureContext, got {:?}", other),
+        }
+
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(
+            debug_decrypt_account_balance(alice.clone(), acme.clone(), db_dir.clone()).unwrap(),
+            5
+        );
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
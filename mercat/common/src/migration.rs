@@ -0,0 +1,460 @@
+//! Migrations of the on-chain data written by older versions of the CLIs.
+
+use crate::{
+    account_transfer::{process_create_tx, process_finalize_tx},
+    all_unverified_tx_files, chain_dir, debug_decrypt,
+    encoding::Encoding,
+    errors::Error,
+    gen_seed, get_user_ticker_from, load_object, load_object_from, load_tx_file, parse_tx_name,
+    remove_file, ChainDirs, CoreTransaction, OrderedAssetInstruction, OrderedTransferInstruction,
+    TransferInstruction, COMMON_OBJECTS_DIR, MEDIATOR_PUBLIC_ACCOUNT_FILE, ON_CHAIN_DIR,
+};
+use codec::Decode;
+use cryptography::mercat::{
+    AssetTxState, EncryptedAssetId, EncryptionPubKey, FinalizedTransferTx, InitializedAssetTx,
+    InitializedTransferTx, JustifiedTransferTx, TransferTxState, TxSubstate,
+};
+use log::info;
+use regex::Regex;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Returns the id of the account that initiated the transaction in the `state`.
+fn initiator_account_id(state: &str, path: PathBuf) -> Result<EncryptedAssetId, Error> {
+    if state == AssetTxState::Initialization(TxSubstate::Started).to_string() {
+        let instruction: OrderedAssetInstruction = load_object_from(path)?;
+        let tx = InitializedAssetTx::decode(&mut &instruction.data[..])
+            .map_err(|_| Error::DecodeError)?;
+        Ok(tx.account_id)
+    } else if state == TransferTxState::Initialization(TxSubstate::Started).to_string() {
+        let instruction: OrderedTransferInstruction = load_object_from(path)?;
+        let tx = InitializedTransferTx::decode(&mut &instruction.data[..])
+            .map_err(|_| Error::DecodeError)?;
+        Ok(tx.memo.sender_account_id)
+    } else if state == TransferTxState::Finalization(TxSubstate::Started).to_string() {
+        let instruction: OrderedTransferInstruction = load_object_from(path)?;
+        let tx = FinalizedTransferTx::decode(&mut &instruction.data[..])
+            .map_err(|_| Error::DecodeError)?;
+        Ok(tx.init_data.memo.sender_account_id)
+    } else if state == TransferTxState::Justification(TxSubstate::Rejected).to_string() {
+        // The mediator stores the finalized transaction when rejecting.
+        let instruction: TransferInstruction = load_object_from(path)?;
+        let tx = FinalizedTransferTx::decode(&mut &instruction.data[..])
+            .map_err(|_| Error::DecodeError)?;
+        Ok(tx.init_data.memo.sender_account_id)
+    } else if state == TransferTxState::Justification(TxSubstate::Started).to_string()
+        || state == TransferTxState::Justification(TxSubstate::Validated).to_string()
+    {
+        let instruction: TransferInstruction = load_object_from(path)?;
+        let tx = JustifiedTransferTx::decode(&mut &instruction.data[..])
+            .map_err(|_| Error::DecodeError)?;
+        Ok(tx.finalized_data.init_data.memo.sender_account_id)
+    } else {
+        Err(Error::InvalidTransactionFile {
+            path: path.to_string_lossy().to_string(),
+        })
+    }
+}
+
+/// Renames the transaction files that were named without the ticker, i.e.,
+/// `tx_<id>_<user>_<state>.json`, to `tx_<id>_<TICKER>_<user>_<state>.json`. The ticker is found
+/// through the account map. Account creation files already include the ticker and are left as
+/// they are. Returns the number of renamed files.
+pub fn migrate_tx_file_names(db_dir: ChainDirs) -> Result<usize, Error> {
+    let re = Regex::new(r"^tx_([0-9]+)_([a-z]+)_([a-zA-Z-0-9]+).json$").map_err(|_| {
+        Error::RegexError {
+            reason: String::from("Failed to compile the legacy transaction file name regex"),
+        }
+    })?;
+    let mut dir = chain_dir(db_dir.clone(), ON_CHAIN_DIR);
+    dir.push(COMMON_OBJECTS_DIR);
+
+    let mut renamed = 0;
+    for entry in std::fs::read_dir(dir.clone()).map_err(|error| Error::FileReadError {
+        error,
+        path: dir.clone(),
+    })? {
+        let path = entry
+            .map_err(|error| Error::FileReadError {
+                error,
+                path: dir.clone(),
+            })?
+            .path();
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or(Error::PathBufConversionError)?
+            .to_string();
+        let caps = match re.captures(&file_name) {
+            Some(caps) => caps,
+            None => continue,
+        };
+        let tx_id = caps[1].parse::<u32>().map_err(|_| Error::RegexError {
+            reason: format!("Invalid tx_id in {}", file_name),
+        })?;
+        let user = caps[2].to_string();
+        let state = caps[3].to_string();
+
+        let account_id = initiator_account_id(&state, path.clone())?;
+        let (_, ticker, _) = get_user_ticker_from(account_id, db_dir.clone())?;
+        // The same layout as `asset_transaction_file` and `confidential_transaction_file`.
+        let new_file_name = format!("tx_{}_{}_{}_{}.json", tx_id, ticker, user, state);
+
+        let mut new_path = dir.clone();
+        new_path.push(&new_file_name);
+        std::fs::rename(&path, &new_path).map_err(|error| Error::FileCreationError {
+            error,
+            path: new_path.clone(),
+        })?;
+        info!("Renamed {} to {}.", file_name, new_file_name);
+        renamed += 1;
+    }
+    Ok(renamed)
+}
+
+/// Moves the pending transfers from the `old_mediator` to the `new_mediator`, e.g., after the
+/// mediator has rotated its keys. The amount and the asset id that the mediator reads are
+/// encrypted under its key and bound by the sender's proofs. Therefore, the initialization of
+/// each transfer that is not justified yet is created again with the new mediator's key, and
+/// the finalization too if the receiver had finalized it. A transfer does not record its
+/// mediator, so all of them are taken to be waiting for the `old_mediator`. The validated
+/// transactions are not touched. Returns the ids of the moved transfers.
+pub fn process_migrate_mediator(
+    db_dir: ChainDirs,
+    old_mediator: String,
+    new_mediator: String,
+) -> Result<Vec<u32>, Error> {
+    // Fail early if the new mediator does not exist.
+    let _: EncryptionPubKey = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        &new_mediator,
+        MEDIATOR_PUBLIC_ACCOUNT_FILE,
+    )?;
+
+    let init_state = TransferTxState::Initialization(TxSubstate::Started).to_string();
+    let finalize_state = TransferTxState::Finalization(TxSubstate::Started).to_string();
+    let justify_state = TransferTxState::Justification(TxSubstate::Started).to_string();
+    let rejected_state = TransferTxState::Justification(TxSubstate::Rejected).to_string();
+
+    let mut txs: BTreeMap<u32, Vec<(String, String, String)>> = BTreeMap::new();
+    for tx_file in all_unverified_tx_files(db_dir.clone())? {
+        let (tx_id, user, state, tx_file_path) = parse_tx_name(tx_file)?;
+        txs.entry(tx_id)
+            .or_insert_with(Vec::new)
+            .push((user, state, tx_file_path));
+    }
+
+    // Check all the transfers before changing any of them.
+    let mut pending = vec![];
+    for (tx_id, files) in txs {
+        if files
+            .iter()
+            .any(|(user, state, _)| state == &justify_state && user == &old_mediator)
+        {
+            return Err(Error::MediatorMigrationConflict {
+                tx_id,
+                mediator: old_mediator,
+            });
+        }
+        let is_pending = files.iter().any(|(_, state, _)| state == &init_state)
+            && !files
+                .iter()
+                .any(|(_, state, _)| state == &justify_state || state == &rejected_state);
+        if is_pending {
+            pending.push((tx_id, files));
+        }
+    }
+
+    let mut migrated = vec![];
+    for (tx_id, files) in pending {
+        let (user, state, tx_file_path) = files
+            .iter()
+            .find(|(_, state, _)| state == &init_state)
+            .cloned()
+            .expect("The pending transfers have an initialization file");
+        let (tx, sender) = match load_tx_file(tx_id, user, state, tx_file_path.clone())? {
+            CoreTransaction::TransferInit { tx, sender, .. } => (tx, sender),
+            _ => return Err(Error::InvalidTransactionFile { path: tx_file_path }),
+        };
+        let (_, ticker, _) = get_user_ticker_from(tx.memo.sender_account_id, db_dir.clone())?;
+        let (receiver, _, _) = get_user_ticker_from(tx.memo.receiver_account_id, db_dir.clone())?;
+        // The sender reads the amount with their own key.
+        let amount = debug_decrypt(
+            tx.memo.sender_account_id,
+            tx.memo.enc_amount_using_sender,
+            db_dir.clone(),
+        )?;
+        let is_finalized = files.iter().any(|(_, state, _)| state == &finalize_state);
+
+        for (_, state, tx_file_path) in &files {
+            if state == &init_state || state == &finalize_state {
+                let file_name = Path::new(tx_file_path)
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .ok_or(Error::PathBufConversionError)?;
+                remove_file(db_dir.clone(), ON_CHAIN_DIR, COMMON_OBJECTS_DIR, file_name)?;
+            }
+        }
+        process_create_tx(
+            gen_seed(),
+            db_dir.clone(),
+            sender.clone(),
+            receiver.clone(),
+            new_mediator.clone(),
+            ticker.clone(),
+            amount,
+            false,
+            Encoding::default(),
+            tx_id,
+            0,
+            false,
+        )?;
+        if is_finalized {
+            process_finalize_tx(
+                gen_seed(),
+                db_dir.clone(),
+                sender,
+                receiver,
+                ticker,
+                amount,
+                false,
+                Encoding::default(),
+                tx_id,
+                false,
+            )?;
+        }
+        info!(
+            "Moved tx-{} from mediator {} to {}.",
+            tx_id, old_mediator, new_mediator
+        );
+        migrated.push(tx_id);
+    }
+    Ok(migrated)
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account,
+        account_issue::process_issue_asset,
+        asset_transaction_file,
+        chain_setup::process_asset_id_creation,
+        confidential_transaction_file, construct_path, debug_decrypt_account_balance,
+        justify::{justify_asset_transfer_transaction, process_create_mediator},
+        test_utils::test_db_dir,
+        validate::validate_all_pending,
+    };
+
+    #[test]
+    fn test_migrate_tx_file_names() {
+        let db_dir = test_db_dir("migrate_tx_file_names");
+
+        let (alice, acme) = (String::from("alice"), String::from("ACME"));
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_account(
+            Some(gen_seed()),
+            db_dir.clone(),
+            acme.clone(),
+            alice.clone(),
+            false,
+            Encoding::default(),
+            1,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            2,
+            false,
+        )
+        .unwrap();
+
+        // Rename the issuance to the legacy name.
+        let state = AssetTxState::Initialization(TxSubstate::Started);
+        let dir = chain_dir(db_dir.clone(), ON_CHAIN_DIR).join(COMMON_OBJECTS_DIR);
+        let new_path = dir.join(asset_transaction_file(2, &alice, &acme, state));
+        let legacy_path = dir.join(format!("tx_2_alice_{}.json", state));
+        std::fs::rename(&new_path, &legacy_path).unwrap();
+
+        assert_eq!(migrate_tx_file_names(db_dir.clone()).unwrap(), 1);
+        assert!(new_path.exists() && !legacy_path.exists());
+        // Running it again is a no-op.
+        assert_eq!(migrate_tx_file_names(db_dir.clone()).unwrap(), 0);
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_migrate_mediator() {
+        let db_dir = test_db_dir("migrate_mediator");
+
+        let acme = String::from("ACME");
+        let (alice, bob, mike, nina) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+            String::from("nina"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        for mediator in &[&mike, &nina] {
+            process_create_mediator(
+                gen_seed(),
+                db_dir.clone(),
+                mediator.to_string(),
+                Encoding::default(),
+                false,
+            )
+            .unwrap();
+        }
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        let create = |sender: &String, receiver: &String, mediator: &String, amount, tx_id| {
+            process_create_tx(
+                gen_seed(),
+                db_dir.clone(),
+                sender.clone(),
+                receiver.clone(),
+                mediator.clone(),
+                acme.clone(),
+                amount,
+                false,
+                Encoding::default(),
+                tx_id,
+                0,
+                false,
+            )
+            .unwrap()
+        };
+        let finalize = |sender: &String, receiver: &String, amount, tx_id| {
+            process_finalize_tx(
+                gen_seed(),
+                db_dir.clone(),
+                sender.clone(),
+                receiver.clone(),
+                acme.clone(),
+                amount,
+                false,
+                Encoding::default(),
+                tx_id,
+                false,
+            )
+            .unwrap()
+        };
+        let justify = |sender: &String, receiver: &String, mediator: &String, tx_id| {
+            justify_asset_transfer_transaction(
+                db_dir.clone(),
+                sender.clone(),
+                receiver.clone(),
+                mediator.clone(),
+                acme.clone(),
+                gen_seed(),
+                false,
+                Encoding::default(),
+                tx_id,
+                None,
+                false,
+                false,
+            )
+            .unwrap()
+        };
+
+        // tx-4 is validated before the migration. tx-5 is finalized, and tx-6 is only created.
+        create(&alice, &bob, &mike, 4, 4);
+        finalize(&alice, &bob, 4, 4);
+        justify(&alice, &bob, &mike, 4);
+        validate_all_pending(db_dir.clone()).unwrap();
+        create(&alice, &bob, &mike, 3, 5);
+        finalize(&alice, &bob, 3, 5);
+        create(&bob, &alice, &mike, 1, 6);
+
+        let validated_path = construct_path(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &confidential_transaction_file(
+                4,
+                &alice,
+                &acme,
+                TransferTxState::Justification(TxSubstate::Validated),
+            ),
+        )
+        .unwrap();
+        let validated = std::fs::read(&validated_path).unwrap();
+
+        assert_eq!(
+            process_migrate_mediator(db_dir.clone(), mike.clone(), nina.clone()).unwrap(),
+            vec![5, 6]
+        );
+        assert_eq!(std::fs::read(&validated_path).unwrap(), validated);
+
+        // The new mediator justifies the moved transfers.
+        justify(&alice, &bob, &nina, 5);
+        finalize(&bob, &alice, 1, 6);
+        justify(&bob, &alice, &nina, 6);
+        validate_all_pending(db_dir.clone()).unwrap();
+        let balance = |user: &String| {
+            debug_decrypt_account_balance(user.clone(), acme.clone(), db_dir.clone()).unwrap()
+        };
+        assert_eq!(balance(&alice), 4);
+        assert_eq!(balance(&bob), 6);
+
+        // A transfer that the replaced mediator has already justified cannot be moved.
+        create(&alice, &bob, &nina, 1, 7);
+        finalize(&alice, &bob, 1, 7);
+        justify(&alice, &bob, &nina, 7);
+        match process_migrate_mediator(db_dir.clone(), nina.clone(), mike.clone()) {
+            Err(Error::MediatorMigrationConflict { tx_id, mediator }) => {
+                assert_eq!((tx_id, mediator), (7, nina))
+            }
+            other => panic!("Expected MediatorMigrationConflict, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
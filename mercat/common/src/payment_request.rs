@@ -0,0 +1,328 @@
+//! Receiver-initiated transfers. The receiver publishes a request for a payment, which the
+//! sender approves by initializing an ordinary transfer with the requested amount. The request
+//! does not affect any balance, and the validator never reads it.
+
+use crate::{
+    account_transfer::process_create_tx, audit::record_state_transition, encoding::Encoding,
+    errors::Error, load_from_file, load_object, save_to_file, user_public_account_file, ChainDirs,
+    OrderedPubAccount, COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
+};
+use codec::Encode;
+use log::info;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaymentRequestState {
+    Pending,
+    Approved,
+}
+
+impl fmt::Display for PaymentRequestState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaymentRequestState::Pending => write!(f, "payment-request-pending"),
+            PaymentRequestState::Approved => write!(f, "payment-request-approved"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    /// The id that the resulting transfer will use.
+    pub tx_id: u32,
+    pub receiver: String,
+    pub sender: String,
+    pub ticker: String,
+    pub amount: u32,
+    pub state: PaymentRequestState,
+    /// The hex encoded digest of the receiver's account and the terms of the request. It stands
+    /// in for the receiver's signature.
+    pub digest: String,
+}
+
+impl PaymentRequest {
+    fn compute_digest(&self, receiver_account: &OrderedPubAccount) -> String {
+        let mut hasher = Sha3_256::new();
+        hasher.input(&receiver_account.pub_account.encode());
+        for field in &[
+            self.tx_id.to_string(),
+            self.receiver.clone(),
+            self.sender.clone(),
+            self.ticker.clone(),
+            self.amount.to_string(),
+        ] {
+            hasher.input(b"|");
+            hasher.input(field.as_bytes());
+        }
+        hex::encode(hasher.result())
+    }
+}
+
+#[inline]
+pub fn payment_request_file(tx_id: u32) -> String {
+    format!("payment_request_{}.json", tx_id)
+}
+
+fn load_receiver_account(
+    db_dir: ChainDirs,
+    receiver: &String,
+    ticker: &String,
+) -> Result<OrderedPubAccount, Error> {
+    load_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        receiver,
+        &user_public_account_file(ticker),
+    )
+}
+
+/// Publishes the `receiver`'s request to be paid `amount` of `ticker` by the `sender`. The
+/// transfer that approves the request will use `tx_id`.
+pub fn process_create_payment_request(
+    db_dir: ChainDirs,
+    receiver: String,
+    sender: String,
+    ticker: String,
+    amount: u32,
+    tx_id: u32,
+) -> Result<(), Error> {
+    let receiver_account = load_receiver_account(db_dir.clone(), &receiver, &ticker)?;
+    let mut request = PaymentRequest {
+        tx_id,
+        receiver,
+        sender,
+        ticker,
+        amount,
+        state: PaymentRequestState::Pending,
+        digest: String::new(),
+    };
+    request.digest = request.compute_digest(&receiver_account);
+
+    save_to_file(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &payment_request_file(tx_id),
+        &request,
+    )?;
+    record_state_transition(db_dir, tx_id, &request.receiver, None, request.state)?;
+    info!(
+        "CLI log: tx-{}: {} requested {} {} from {}.",
+        tx_id, request.receiver, request.amount, request.ticker, request.sender
+    );
+    Ok(())
+}
+
+/// Approves the payment request `tx_id` by initializing a transfer of the requested amount from
+/// the `sender` to the requesting receiver. From then on, the transfer is finalized, justified,
+/// and validated like any other transfer.
+pub fn process_approve_payment_request(
+    seed: String,
+    db_dir: ChainDirs,
+    sender: String,
+    mediator: String,
+    stdout: bool,
+    encoding: Encoding,
+    tx_id: u32,
+    min_confirmations: u32,
+    cheat: bool,
+) -> Result<(), Error> {
+    let mut request: PaymentRequest = load_from_file(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &payment_request_file(tx_id),
+    )?;
+    let receiver_account =
+        load_receiver_account(db_dir.clone(), &request.receiver, &request.ticker)?;
+    if request.digest != request.compute_digest(&receiver_account) {
+        return Err(Error::PaymentRequestContentMismatch { tx_id });
+    }
+    if request.sender != sender {
+        return Err(Error::PaymentRequestSenderMismatch {
+            tx_id,
+            requested: request.sender,
+            sender,
+        });
+    }
+    if request.state != PaymentRequestState::Pending {
+        return Err(Error::PaymentRequestAlreadyApproved { tx_id });
+    }
+
+    process_create_tx(
+        seed,
+        db_dir.clone(),
+        sender,
+        request.receiver.clone(),
+        mediator,
+        request.ticker.clone(),
+        request.amount,
+        stdout,
+        encoding,
+        tx_id,
+        min_confirmations,
+        cheat,
+    )?;
+
+    let old_state = request.state;
+    request.state = PaymentRequestState::Approved;
+    save_to_file(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &payment_request_file(tx_id),
+        &request,
+    )?;
+    record_state_transition(
+        db_dir,
+        tx_id,
+        &request.sender,
+        Some(old_state),
+        request.state,
+    )
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account,
+        account_issue::process_issue_asset,
+        account_transfer::process_finalize_tx,
+        chain_setup::process_asset_id_creation,
+        debug_decrypt_account_balance, gen_seed,
+        justify::{justify_asset_transfer_transaction, process_create_mediator},
+        test_utils::test_db_dir,
+        validate::validate_all_pending,
+    };
+
+    #[test]
+    fn test_payment_request_flow() {
+        let db_dir = test_db_dir("payment_request");
+
+        let acme = String::from("ACME");
+        let (alice, bob, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        // Bob requests a payment from Alice.
+        process_create_payment_request(
+            db_dir.clone(),
+            bob.clone(),
+            alice.clone(),
+            acme.clone(),
+            4,
+            4,
+        )
+        .unwrap();
+        let approve = |sender: &String| {
+            process_approve_payment_request(
+                gen_seed(),
+                db_dir.clone(),
+                sender.clone(),
+                mike.clone(),
+                false,
+                Encoding::default(),
+                4,
+                0,
+                false,
+            )
+        };
+        match approve(&mike) {
+            Err(Error::PaymentRequestSenderMismatch { requested, .. }) => {
+                assert_eq!(requested, alice)
+            }
+            other => panic!("Expected PaymentRequestSenderMismatch, got {:?}", other),
+        }
+        approve(&alice).unwrap();
+        match approve(&alice) {
+            Err(Error::PaymentRequestAlreadyApproved { tx_id }) => assert_eq!(tx_id, 4),
+            other => panic!("Expected PaymentRequestAlreadyApproved, got {:?}", other),
+        }
+
+        // The rest of the transfer is the same as a sender-initiated one.
+        process_finalize_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            4,
+            false,
+        )
+        .unwrap();
+        justify_asset_transfer_transaction(
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            gen_seed(),
+            false,
+            Encoding::default(),
+            4,
+            None,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        let balance = |user: &String| {
+            debug_decrypt_account_balance(user.clone(), acme.clone(), db_dir.clone()).unwrap()
+        };
+        assert_eq!(balance(&alice), 6);
+        assert_eq!(balance(&bob), 4);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
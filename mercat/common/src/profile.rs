@@ -0,0 +1,290 @@
+//! Collects the durations recorded by the `timing!` sites, to report a breakdown of where the
+//! time of a command went. Nothing is collected unless profiling is enabled.
+
+#[cfg(test)]
+use metrics::Recorder;
+#[cfg(test)]
+use metrics_core::Key;
+#[cfg(test)]
+use std::cell::RefCell;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// When this environment variable is set, the CLIs print the profile at exit.
+pub const PROFILE_ENV_VAR: &str = "MERCAT_PROFILE";
+
+// The recorder is global, so the tests capture the metrics of their own thread only.
+#[cfg(test)]
+thread_local! {
+    static CAPTURED_COUNTERS: RefCell<HashMap<String, u64>> = RefCell::new(HashMap::new());
+    static CAPTURED_TIMINGS: MetricsCollector = MetricsCollector::default();
+}
+
+/// The aggregated durations of one phase, in nanoseconds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PhaseTiming {
+    pub phase: String,
+    pub count: u64,
+    pub total: u64,
+}
+
+impl PhaseTiming {
+    pub fn mean(&self) -> u64 {
+        if self.count == 0 {
+            0
+        } else {
+            self.total / self.count
+        }
+    }
+}
+
+/// The collected phases, the most expensive first. It is displayed as a table.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProfileReport(pub Vec<PhaseTiming>);
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<50} {:>8} {:>14} {:>14}",
+            "phase", "count", "total (us)", "mean (us)"
+        )?;
+        for timing in &self.0 {
+            writeln!(
+                f,
+                "{:<50} {:>8} {:>14} {:>14}",
+                timing.phase,
+                timing.count,
+                timing.total / 1_000,
+                timing.mean() / 1_000
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Aggregates the recorded durations by the name of the phase. The labels, e.g., the tx_id,
+/// are not part of the phase, so that the phases of all transactions are added up.
+#[derive(Default)]
+pub struct MetricsCollector {
+    enabled: AtomicBool,
+    phases: Mutex<HashMap<String, PhaseTiming>>,
+}
+
+impl MetricsCollector {
+    /// Enables or disables the collection of the phase durations by the metrics recorder.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn record(&self, phase: &str, nanos: u64) {
+        let mut phases = self
+            .phases
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let timing = phases
+            .entry(phase.to_string())
+            .or_insert_with(|| PhaseTiming {
+                phase: phase.to_string(),
+                ..PhaseTiming::default()
+            });
+        timing.count += 1;
+        timing.total += nanos;
+    }
+
+    /// Returns the phases collected so far, the most expensive first.
+    pub fn report(&self) -> ProfileReport {
+        let phases = self
+            .phases
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut report: Vec<PhaseTiming> = phases.values().cloned().collect();
+        report.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.phase.cmp(&b.phase)));
+        ProfileReport(report)
+    }
+
+    /// Returns the report, if profiling is enabled.
+    pub fn report_if_enabled(&self) -> Option<ProfileReport> {
+        if self.enabled() {
+            Some(self.report())
+        } else {
+            None
+        }
+    }
+}
+
+/// Wraps the recorder that the tests install, to also capture the counters and the durations
+/// recorded by the current thread.
+#[cfg(test)]
+pub(crate) struct CapturingRecorder<R: Recorder>(pub R);
+
+#[cfg(test)]
+impl<R: Recorder> Recorder for CapturingRecorder<R> {
+    fn increment_counter(&self, key: Key, value: u64) {
+        CAPTURED_COUNTERS.with(|counters| {
+            *counters
+                .borrow_mut()
+                .entry(key.name().to_string())
+                .or_default() += value;
+        });
+        self.0.increment_counter(key, value);
+    }
+
+    fn update_gauge(&self, key: Key, value: i64) {
+        self.0.update_gauge(key, value);
+    }
+
+    fn record_histogram(&self, key: Key, value: u64) {
+        CAPTURED_TIMINGS.with(|timings| timings.record(&key.name(), value));
+        self.0.record_histogram(key, value);
+    }
+}
+
+/// Returns the value of a counter incremented by the current thread.
+#[cfg(test)]
+pub(crate) fn captured_counter(name: &str) -> u64 {
+    CAPTURED_COUNTERS.with(|counters| counters.borrow().get(name).cloned().unwrap_or_default())
+}
+
+/// Returns the phases recorded by the current thread, the most expensive first.
+#[cfg(test)]
+pub(crate) fn captured_profile() -> ProfileReport {
+    CAPTURED_TIMINGS.with(|timings| timings.report())
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account, account_issue::process_issue_asset,
+        account_transfer::process_create_tx, chain_setup::process_asset_id_creation,
+        encoding::Encoding, gen_seed, init_print_logger, justify::process_create_mediator,
+        test_utils::test_db_dir, validate::validate_all_pending,
+    };
+
+    #[test]
+    fn test_collector_aggregates_by_phase() {
+        let collector = MetricsCollector::default();
+        collector.record("b", 10);
+        collector.record("a", 20);
+        collector.record("b", 30);
+        let report = collector.report();
+        assert_eq!(
+            report
+                .0
+                .iter()
+                .map(|timing| (
+                    timing.phase.as_str(),
+                    timing.count,
+                    timing.total,
+                    timing.mean()
+                ))
+                .collect::<Vec<_>>(),
+            vec![("b", 2, 40, 20), ("a", 1, 20, 20)]
+        );
+        let table = report.to_string();
+        assert_eq!(table.lines().count(), 3);
+        assert!(table.lines().nth(1).unwrap().starts_with("b "));
+
+        // The report is only returned once profiling is enabled.
+        assert_eq!(collector.report_if_enabled(), None);
+        collector.set_enabled(true);
+        assert_eq!(collector.report_if_enabled(), Some(report));
+    }
+
+    #[test]
+    fn test_profile_of_create_tx() {
+        let db_dir = test_db_dir("profile_create_tx");
+        init_print_logger();
+
+        let acme = String::from("ACME");
+        let (alice, bob, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            db_dir.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            3,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_create_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            4,
+            0,
+            false,
+        )
+        .unwrap();
+
+        let phases: Vec<String> = captured_profile()
+            .0
+            .into_iter()
+            .map(|timing| timing.phase)
+            .collect();
+        for phase in &[
+            "account.create_tx.load_from_file",
+            "account.create_tx.calc_pending_state",
+            "account.create_tx.create",
+            "account.create_tx.save_to_file",
+        ] {
+            assert!(phases.contains(&phase.to_string()), "missing {}", phase);
+        }
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
@@ -0,0 +1,201 @@
+//! Optional passphrase-based encryption of the off-chain secret files. An encrypted file starts
+//! with `ENCRYPTED_SECRET_MAGIC`, followed by the salt of the key derivation, the nonce, and the
+//! XChaCha20-Poly1305 ciphertext of the SCALE encoded object. The loaders accept both the
+//! encrypted and the plain files, and only ask for the passphrase when it is needed.
+
+use crate::{chain_dir, construct_path, errors::Error, ChainDirs, OFF_CHAIN_DIR};
+use chacha20poly1305::{
+    aead::{generic_array::GenericArray, Aead, NewAead},
+    XChaCha20Poly1305,
+};
+use codec::{Decode, Encode};
+use rand::{rngs::OsRng, RngCore};
+use std::{fs::create_dir_all, path::PathBuf};
+
+/// The environment variable that holds the passphrase of the secret files. If it is not set,
+/// the passphrase is read from the terminal.
+pub const PASSPHRASE_ENV_VAR: &str = "MERCAT_PASSPHRASE";
+
+const ENCRYPTED_SECRET_MAGIC: &[u8] = b"MERCAT-ENC-V1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// Returns the passphrase from `PASSPHRASE_ENV_VAR`, or prompts for it.
+pub fn passphrase() -> Result<String, Error> {
+    match std::env::var(PASSPHRASE_ENV_VAR) {
+        Ok(passphrase) => Ok(passphrase),
+        Err(_) => rpassword::read_password_from_tty(Some("Passphrase of the secret files: "))
+            .map_err(|error| Error::PassphraseReadError { error }),
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Vec<u8>, argon2::Error> {
+    argon2::hash_raw(passphrase.as_bytes(), salt, &argon2::Config::default())
+}
+
+fn encrypt(passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let key = derive_key(passphrase, &salt).map_err(|error| Error::SecretEncryptionFailed {
+        reason: error.to_string(),
+    })?;
+    let ciphertext = XChaCha20Poly1305::new(GenericArray::from_slice(&key))
+        .encrypt(GenericArray::from_slice(&nonce), plaintext)
+        .map_err(|_| Error::SecretEncryptionFailed {
+            reason: String::from("AEAD encryption failed"),
+        })?;
+
+    let mut data = ENCRYPTED_SECRET_MAGIC.to_vec();
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+    data.extend(ciphertext);
+    Ok(data)
+}
+
+/// Decrypts the content of the file at `path`. Also fails if the file is truncated.
+fn decrypt(passphrase: &str, data: &[u8], path: PathBuf) -> Result<Vec<u8>, Error> {
+    let data = &data[ENCRYPTED_SECRET_MAGIC.len()..];
+    if data.len() < SALT_LEN + NONCE_LEN {
+        return Err(Error::SecretDecryptionFailed { path });
+    }
+    let (salt, data) = data.split_at(SALT_LEN);
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let key = derive_key(passphrase, salt)
+        .map_err(|_| Error::SecretDecryptionFailed { path: path.clone() })?;
+    XChaCha20Poly1305::new(GenericArray::from_slice(&key))
+        .decrypt(GenericArray::from_slice(nonce), ciphertext)
+        .map_err(|_| Error::SecretDecryptionFailed { path })
+}
+
+#[inline]
+fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(ENCRYPTED_SECRET_MAGIC)
+}
+
+/// Saves a secret object in the `user`'s off-chain directory. The object is encrypted if a
+/// `passphrase` is given, and is saved in plain SCALE encoding otherwise.
+pub fn save_secret_object<T: Encode>(
+    db_dir: ChainDirs,
+    user: &str,
+    file_name: &str,
+    data: &T,
+    passphrase: Option<&str>,
+) -> Result<(), Error> {
+    let mut dir = chain_dir(db_dir.clone(), OFF_CHAIN_DIR);
+    dir.push(user);
+    create_dir_all(dir.clone()).map_err(|error| Error::FileCreationError { error, path: dir })?;
+
+    let file_path = construct_path(db_dir, OFF_CHAIN_DIR, user, file_name)?;
+    let content = match passphrase {
+        Some(passphrase) => encrypt(passphrase, &data.encode())?,
+        None => data.encode(),
+    };
+    std::fs::write(file_path.clone(), content).map_err(|error| Error::ObjectSaveError {
+        error,
+        path: file_path,
+    })
+}
+
+/// Loads a secret object from the `user`'s off-chain directory. If the file is encrypted, the
+/// passphrase is taken from `passphrase()`.
+#[inline]
+pub fn load_secret_object<T: Decode>(
+    db_dir: ChainDirs,
+    user: &str,
+    file_name: &str,
+) -> Result<T, Error> {
+    load_secret_object_with(db_dir, user, file_name, passphrase)
+}
+
+/// Same as `load_secret_object`, but calls `get_passphrase` if the file is encrypted.
+pub fn load_secret_object_with<T: Decode>(
+    db_dir: ChainDirs,
+    user: &str,
+    file_name: &str,
+    get_passphrase: impl FnOnce() -> Result<String, Error>,
+) -> Result<T, Error> {
+    let file_path = construct_path(db_dir, OFF_CHAIN_DIR, user, file_name)?;
+    load_secret_object_from(file_path, get_passphrase)
+}
+
+/// Same as `load_secret_object_with`, but reads the file at `file_path`.
+pub fn load_secret_object_from<T: Decode>(
+    file_path: PathBuf,
+    get_passphrase: impl FnOnce() -> Result<String, Error>,
+) -> Result<T, Error> {
+    let data = std::fs::read(file_path.clone()).map_err(|error| Error::FileReadError {
+        error,
+        path: file_path.clone(),
+    })?;
+    let data = if is_encrypted(&data) {
+        decrypt(&get_passphrase()?, &data, file_path.clone())?
+    } else {
+        data
+    };
+    T::decode(&mut &data[..]).map_err(|error| Error::ObjectLoadError {
+        error,
+        path: file_path,
+    })
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{load_object, test_utils::test_db_dir};
+
+    #[test]
+    fn test_secret_object_round_trip() {
+        let db_dir = test_db_dir("secret_store");
+
+        let secret: Vec<u8> = b"the secret account".to_vec();
+        let correct = || Ok(String::from("correct horse battery staple"));
+        let wrong = || Ok(String::from("wrong"));
+
+        // Plain files are loaded without asking for a passphrase.
+        save_secret_object(db_dir.clone(), "alice", "plain", &secret, None).unwrap();
+        let loaded: Vec<u8> = load_secret_object_with(db_dir.clone(), "alice", "plain", || {
+            panic!("The passphrase is not needed for plain files")
+        })
+        .unwrap();
+        assert_eq!(loaded, secret);
+
+        save_secret_object(
+            db_dir.clone(),
+            "alice",
+            "encrypted",
+            &secret,
+            Some(&correct().unwrap()),
+        )
+        .unwrap();
+        let path = construct_path(db_dir.clone(), OFF_CHAIN_DIR, "alice", "encrypted").unwrap();
+        let content = std::fs::read(&path).unwrap();
+        assert!(is_encrypted(&content));
+        assert!(!content
+            .windows(secret.len())
+            .any(|window| window == &secret[..]));
+        // The plain loader cannot make sense of the encrypted file.
+        assert!(
+            load_object::<Vec<u8>>(db_dir.clone(), OFF_CHAIN_DIR, "alice", "encrypted")
+                .map_or(true, |loaded| loaded != secret)
+        );
+
+        let loaded: Vec<u8> =
+            load_secret_object_with(db_dir.clone(), "alice", "encrypted", correct).unwrap();
+        assert_eq!(loaded, secret);
+        match load_secret_object_with::<Vec<u8>>(db_dir.clone(), "alice", "encrypted", wrong) {
+            Err(Error::SecretDecryptionFailed { path: error_path }) => {
+                assert_eq!(error_path, path)
+            }
+            other => panic!("Expected SecretDecryptionFailed, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
@@ -0,0 +1,287 @@
+//! Snapshots of the validated accounts of a ticker, and the deltas between them. A follower
+//! node can fast-forward its account files from one validation checkpoint to the next by
+//! applying the delta, instead of copying every account or re-validating the transactions.
+
+use crate::{
+    construct_path, errors::Error, load_account_map, load_object, save_object,
+    user_public_account_balance_file, user_public_account_file, ChainDirs, OrderedPubAccount,
+    ON_CHAIN_DIR,
+};
+use codec::{Decode, Encode};
+use cryptography::mercat::{EncryptedAmount, PubAccount};
+use std::collections::BTreeMap;
+
+/// The validated state of an account.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct AccountState {
+    pub user: String,
+    pub pub_account: PubAccount,
+    pub enc_balance: EncryptedAmount,
+    pub last_processed_tx_counter: Option<u32>,
+}
+
+/// The validated accounts of a ticker, keyed by their printable account id.
+#[derive(Clone, Debug, Default, Encode, Decode)]
+pub struct Snapshot {
+    pub ticker: String,
+    pub accounts: BTreeMap<String, AccountState>,
+}
+
+/// The accounts that were added or changed between two snapshots of the same ticker, with
+/// their new state.
+#[derive(Clone, Debug, Default, Encode, Decode)]
+pub struct SnapshotDelta {
+    pub ticker: String,
+    pub changed: Vec<(String, AccountState)>,
+}
+
+/// Reads the validated accounts of the `ticker`. The accounts that are not validated yet are
+/// not part of the snapshot.
+pub fn account_snapshot(db_dir: ChainDirs, ticker: &str) -> Result<Snapshot, Error> {
+    let mut accounts = BTreeMap::new();
+    for (account_id, (user, account_ticker, _)) in load_account_map(db_dir.clone()) {
+        let account_file = user_public_account_file(&account_ticker);
+        if account_ticker != ticker
+            || !construct_path(db_dir.clone(), ON_CHAIN_DIR, &user, &account_file)?.exists()
+        {
+            continue;
+        }
+        let ordered_pub_account: OrderedPubAccount =
+            load_object(db_dir.clone(), ON_CHAIN_DIR, &user, &account_file)?;
+        let enc_balance: EncryptedAmount = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &user,
+            &user_public_account_balance_file(&account_ticker),
+        )?;
+        accounts.insert(
+            account_id,
+            AccountState {
+                user,
+                pub_account: ordered_pub_account.pub_account,
+                enc_balance,
+                last_processed_tx_counter: ordered_pub_account.last_processed_tx_counter,
+            },
+        );
+    }
+    Ok(Snapshot {
+        ticker: ticker.to_string(),
+        accounts,
+    })
+}
+
+/// Returns the accounts of `new` that are missing from `old`, or whose state differs.
+pub fn diff_snapshots(old: &Snapshot, new: &Snapshot) -> Result<SnapshotDelta, Error> {
+    if old.ticker != new.ticker && !old.accounts.is_empty() {
+        return Err(Error::TickerMismatch {
+            sender_ticker: old.ticker.clone(),
+            receiver_ticker: new.ticker.clone(),
+        });
+    }
+    let changed = new
+        .accounts
+        .iter()
+        .filter(|(account_id, state)| {
+            old.accounts
+                .get(*account_id)
+                .map_or(true, |old_state| old_state.encode() != state.encode())
+        })
+        .map(|(account_id, state)| (account_id.clone(), state.clone()))
+        .collect();
+    Ok(SnapshotDelta {
+        ticker: new.ticker.clone(),
+        changed,
+    })
+}
+
+/// Writes the account files of the changed accounts in the `delta`.
+pub fn apply_delta(db_dir: ChainDirs, delta: &SnapshotDelta) -> Result<(), Error> {
+    for (_, state) in &delta.changed {
+        save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &state.user,
+            &user_public_account_file(&delta.ticker),
+            &OrderedPubAccount {
+                last_processed_tx_counter: state.last_processed_tx_counter,
+                pub_account: state.pub_account.clone(),
+            },
+        )?;
+        save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &state.user,
+            &user_public_account_balance_file(&delta.ticker),
+            &state.enc_balance,
+        )?;
+    }
+    Ok(())
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account,
+        account_issue::process_issue_asset,
+        account_transfer::{process_create_tx, process_finalize_tx},
+        chain_setup::process_asset_id_creation,
+        encoding::Encoding,
+        gen_seed,
+        justify::{justify_asset_transfer_transaction, process_create_mediator},
+        test_utils::test_db_dir,
+        validate::validate_all_pending,
+    };
+
+    /// Asserts that the account files of the users are byte-identical in both directories.
+    fn assert_same_account_files(leader: &ChainDirs, follower: &ChainDirs, users: &[&String]) {
+        let acme = String::from("ACME");
+        for user in users {
+            for file_name in &[
+                user_public_account_file(&acme),
+                user_public_account_balance_file(&acme),
+            ] {
+                let read = |db_dir: &ChainDirs| {
+                    std::fs::read(
+                        construct_path(db_dir.clone(), ON_CHAIN_DIR, user, file_name).unwrap(),
+                    )
+                    .unwrap()
+                };
+                assert_eq!(read(leader), read(follower), "{}/{}", user, file_name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_snapshot_delta() {
+        let (leader, follower) = (
+            test_db_dir("snapshot_delta_leader"),
+            test_db_dir("snapshot_delta_follower"),
+        );
+
+        let acme = String::from("ACME");
+        let (alice, bob, carol, mike) = (
+            String::from("alice"),
+            String::from("bob"),
+            String::from("carol"),
+            String::from("mike"),
+        );
+        process_asset_id_creation(leader.clone(), vec![acme.clone()]).unwrap();
+        process_create_mediator(
+            gen_seed(),
+            leader.clone(),
+            mike.clone(),
+            Encoding::default(),
+            false,
+        )
+        .unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob), (3, &carol)] {
+            process_create_account(
+                Some(gen_seed()),
+                leader.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(leader.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            leader.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            4,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(leader.clone()).unwrap();
+
+        // The follower starts from the first checkpoint.
+        let first = account_snapshot(leader.clone(), &acme).unwrap();
+        assert_eq!(first.accounts.len(), 3);
+        apply_delta(
+            follower.clone(),
+            &diff_snapshots(&Snapshot::default(), &first).unwrap(),
+        )
+        .unwrap();
+        assert_same_account_files(&leader, &follower, &[&alice, &bob, &carol]);
+
+        process_create_tx(
+            gen_seed(),
+            leader.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            5,
+            0,
+            false,
+        )
+        .unwrap();
+        process_finalize_tx(
+            gen_seed(),
+            leader.clone(),
+            alice.clone(),
+            bob.clone(),
+            acme.clone(),
+            4,
+            false,
+            Encoding::default(),
+            5,
+            false,
+        )
+        .unwrap();
+        justify_asset_transfer_transaction(
+            leader.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            gen_seed(),
+            false,
+            Encoding::default(),
+            5,
+            None,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(leader.clone()).unwrap();
+
+        // Only the accounts that took part in the transfer are in the delta.
+        let second = account_snapshot(leader.clone(), &acme).unwrap();
+        let delta = diff_snapshots(&first, &second).unwrap();
+        let mut changed_users: Vec<String> = delta
+            .changed
+            .iter()
+            .map(|(_, state)| state.user.clone())
+            .collect();
+        changed_users.sort();
+        assert_eq!(changed_users, vec![alice.clone(), bob.clone()]);
+
+        // The delta survives the round trip through its binary encoding.
+        let delta = SnapshotDelta::decode(&mut &delta.encode()[..]).unwrap();
+        apply_delta(follower.clone(), &delta).unwrap();
+        assert_same_account_files(&leader, &follower, &[&alice, &bob, &carol]);
+        assert!(diff_snapshots(&second, &second).unwrap().changed.is_empty());
+
+        let _ = std::fs::remove_dir_all(&leader.root);
+        let _ = std::fs::remove_dir_all(&follower.root);
+    }
+}
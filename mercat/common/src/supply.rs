@@ -0,0 +1,162 @@
+//! The total issued supply of a ticker. The validator appends every validated issuance to an
+//! on-chain log of its ticker, so the supply does not depend on the balances, which also change
+//! with the transfers. The issued amounts are encrypted under the keys of their issuers, so the
+//! issuances of each issuer are summed homomorphically and each sum is decrypted with the key of
+//! its issuer.
+
+use crate::{
+    discrete_log::{BsgsSolver, DiscreteLogSolver},
+    errors::Error,
+    load_object, save_object,
+    secret_store::load_secret_object,
+    user_secret_account_file, ChainDirs, COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
+};
+use codec::{Decode, Encode};
+use cryptography::mercat::{EncryptedAmount, SecAccount};
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use std::collections::BTreeMap;
+
+#[inline]
+pub fn issuance_log_file(ticker: &str) -> String {
+    format!("issuance_log_{}", ticker)
+}
+
+/// A validated issuance in the issuance log of its ticker.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct IssuanceRecord {
+    pub tx_id: u32,
+    pub issuer: String,
+    pub enc_issued_amount: EncryptedAmount,
+}
+
+fn load_issuance_log(db_dir: ChainDirs, ticker: &str) -> Vec<IssuanceRecord> {
+    load_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &issuance_log_file(ticker),
+    )
+    .unwrap_or_default()
+}
+
+/// Appends a validated issuance to the issuance log of the ticker. An issuance that is already
+/// in the log is not added again.
+pub fn record_issuance(
+    db_dir: ChainDirs,
+    ticker: &str,
+    record: IssuanceRecord,
+) -> Result<(), Error> {
+    let mut log = load_issuance_log(db_dir.clone(), ticker);
+    if log.iter().any(|existing| existing.tx_id == record.tx_id) {
+        return Ok(());
+    }
+    log.push(record);
+    save_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &issuance_log_file(ticker),
+        &log,
+    )
+}
+
+/// Returns the total amount issued in the ticker. Needs the secret accounts of the issuers.
+/// Fails with `SupplyDecryptionFailed` if the sum of the issuances of an issuer is beyond the
+/// search bound of the decryption.
+pub fn process_total_supply(db_dir: ChainDirs, ticker: String) -> Result<u64, Error> {
+    let mut sums: BTreeMap<String, EncryptedAmount> = BTreeMap::new();
+    for record in load_issuance_log(db_dir.clone(), &ticker) {
+        match sums.get_mut(&record.issuer) {
+            Some(sum) => *sum += record.enc_issued_amount,
+            None => {
+                sums.insert(record.issuer, record.enc_issued_amount);
+            }
+        }
+    }
+
+    let mut total = 0u64;
+    for (issuer, sum) in sums {
+        let secret: SecAccount =
+            load_secret_object(db_dir.clone(), &issuer, &user_secret_account_file(&ticker))?;
+        let point = sum.y - secret.enc_keys.secret.secret.invert() * sum.x;
+        total += BsgsSolver
+            .solve(
+                point,
+                RISTRETTO_BASEPOINT_POINT,
+                u64::from(u32::max_value()),
+            )
+            .ok_or_else(|| Error::SupplyDecryptionFailed {
+                ticker: ticker.clone(),
+            })?;
+    }
+    Ok(total)
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::process_create_account, account_issue::process_issue_asset,
+        chain_setup::process_asset_id_creation, encoding::Encoding, gen_seed,
+        test_utils::test_db_dir, validate::validate_all_pending,
+    };
+
+    #[test]
+    fn test_total_supply() {
+        let db_dir = test_db_dir("total_supply");
+
+        let acme = String::from("ACME");
+        let (alice, bob) = (String::from("alice"), String::from("bob"));
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(
+            process_total_supply(db_dir.clone(), acme.clone()).unwrap(),
+            0
+        );
+
+        for (tx_id, issuer, amount) in &[(3, &alice, 10), (4, &bob, 20), (5, &alice, 30)] {
+            process_issue_asset(
+                gen_seed(),
+                db_dir.clone(),
+                issuer.to_string(),
+                acme.clone(),
+                *amount,
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+            )
+            .unwrap();
+            validate_all_pending(db_dir.clone()).unwrap();
+        }
+        assert_eq!(
+            process_total_supply(db_dir.clone(), acme.clone()).unwrap(),
+            60
+        );
+
+        // Validating again does not count the issuances twice.
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(process_total_supply(db_dir.clone(), acme).unwrap(), 60);
+
+        std::fs::remove_dir_all(&db_dir.root).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+//! Fixtures shared by the unit tests of the modules.
+//!
+//! The end-to-end flows that only check the resulting balances live in the scenario files,
+//! and are run by the test harness.
+
+use crate::{
+    account_create::process_create_account,
+    account_issue::process_issue_asset,
+    account_transfer::{process_create_tx, process_finalize_tx},
+    chain_setup::process_asset_id_creation,
+    confidential_transaction_file, construct_path,
+    discrete_log::assert_enc_balance_eq,
+    encoding::Encoding,
+    gen_seed,
+    justify::{justify_asset_transfer_transaction, process_create_mediator},
+    load_object, load_object_from, load_tx_file,
+    secret_store::load_secret_object,
+    user_public_account_balance_file, user_secret_account_file,
+    validate::validate_all_pending,
+    ChainDirs, CoreTransaction, TransferInstruction, COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
+};
+use cryptography::mercat::{
+    EncryptedAmount, JustifiedTransferTx, SecAccount, TransferTxState, TxSubstate,
+};
+use std::path::PathBuf;
+
+/// Returns the directory of the test `name` under `chain_dir/unittest`, after removing what a
+/// previous run left in it.
+pub fn unittest_dir(name: &str) -> PathBuf {
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("chain_dir/unittest");
+    dir.push(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Returns an empty database directory for the test `name`.
+pub fn test_db_dir(name: &str) -> ChainDirs {
+    ChainDirs::new(unittest_dir(name))
+}
+
+/// Copies the files of the `from` directory tree to `to`.
+pub fn copy_dir(from: &PathBuf, to: &PathBuf) {
+    std::fs::create_dir_all(to).unwrap();
+    for entry in std::fs::read_dir(from).unwrap() {
+        let path = entry.unwrap().path();
+        let target = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &target);
+        } else {
+            std::fs::copy(&path, &target).unwrap();
+        }
+    }
+}
+
+/// Asserts that the ACME balance of the `user` decrypts to `expected`.
+pub fn assert_balance(db_dir: &ChainDirs, user: &str, expected: u64) {
+    let acme = String::from("ACME");
+    let enc_balance: EncryptedAmount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        user,
+        &user_public_account_balance_file(&acme),
+    )
+    .unwrap();
+    let secret: SecAccount =
+        load_secret_object(db_dir.clone(), user, &user_secret_account_file(&acme)).unwrap();
+    assert_enc_balance_eq(&enc_balance, &secret.enc_keys.secret, expected);
+}
+
+/// Runs alice's transfer of 4 ACME to bob, with mike as the mediator, as tx-4 up to the
+/// justification. Returns the justified transaction, its instruction, and the path of the
+/// instruction.
+pub fn justified_transfer(
+    db_dir: &ChainDirs,
+) -> (JustifiedTransferTx, TransferInstruction, PathBuf) {
+    let acme = String::from("ACME");
+    let (alice, bob, mike) = (
+        String::from("alice"),
+        String::from("bob"),
+        String::from("mike"),
+    );
+    process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+    process_create_mediator(
+        gen_seed(),
+        db_dir.clone(),
+        mike.clone(),
+        Encoding::default(),
+        false,
+    )
+    .unwrap();
+    for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+        process_create_account(
+            Some(gen_seed()),
+            db_dir.clone(),
+            acme.clone(),
+            user.to_string(),
+            false,
+            Encoding::default(),
+            *tx_id,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+    }
+    validate_all_pending(db_dir.clone()).unwrap();
+    process_issue_asset(
+        gen_seed(),
+        db_dir.clone(),
+        alice.clone(),
+        acme.clone(),
+        10,
+        false,
+        Encoding::default(),
+        3,
+        false,
+    )
+    .unwrap();
+    validate_all_pending(db_dir.clone()).unwrap();
+    assert_balance(db_dir, &alice, 10);
+    assert_balance(db_dir, &bob, 0);
+    process_create_tx(
+        gen_seed(),
+        db_dir.clone(),
+        alice.clone(),
+        bob.clone(),
+        mike.clone(),
+        acme.clone(),
+        4,
+        false,
+        Encoding::default(),
+        4,
+        0,
+        false,
+    )
+    .unwrap();
+    process_finalize_tx(
+        gen_seed(),
+        db_dir.clone(),
+        alice.clone(),
+        bob.clone(),
+        acme.clone(),
+        4,
+        false,
+        Encoding::default(),
+        4,
+        false,
+    )
+    .unwrap();
+    justify_asset_transfer_transaction(
+        db_dir.clone(),
+        alice.clone(),
+        bob.clone(),
+        mike.clone(),
+        acme.clone(),
+        gen_seed(),
+        false,
+        Encoding::default(),
+        4,
+        None,
+        false,
+        false,
+    )
+    .unwrap();
+
+    let state = TransferTxState::Justification(TxSubstate::Started);
+    let file_name = confidential_transaction_file(4, &mike, &acme, state);
+    let path =
+        construct_path(db_dir.clone(), ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &file_name).unwrap();
+    let tx = match load_tx_file(
+        4,
+        mike.clone(),
+        state.to_string(),
+        path.to_str().unwrap().to_string(),
+    )
+    .unwrap()
+    {
+        CoreTransaction::TransferJustify { tx, .. } => tx,
+        other => panic!("Expected TransferJustify, got {:?}", other),
+    };
+    let instruction: TransferInstruction = load_object_from(path.clone()).unwrap();
+    (tx, instruction, path)
+}
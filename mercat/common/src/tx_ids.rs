@@ -0,0 +1,283 @@
+//! Allocation of the transaction ids. The commands that start a new transaction accept
+//! `--tx-id auto`, so that the users of a database directory do not have to keep track of the
+//! last used id themselves.
+
+use crate::{
+    all_tx_files, construct_path, errors::Error, load_from_file, parse_tx_name, save_to_file,
+    ChainDirs, COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{create_dir_all, OpenOptions},
+    io::{ErrorKind, Write},
+    num::ParseIntError,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// The file that stores the last allocated transaction id.
+pub const LAST_ALLOCATED_TX_ID_FILE: &str = "last_allocated_tx_id.json";
+
+/// The file that serializes the allocations of the concurrent commands.
+const TX_ID_LOCK_FILE: &str = "last_allocated_tx_id.lock";
+
+/// How long to wait for the other commands to release the lock.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The age after which a lock is assumed to be left behind by a command that crashed. The lock
+/// is only held while a few small files are read and written.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(60);
+
+/// The `--tx-id` argument of the commands that start a new transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TxIdArg {
+    /// Allocate the next free transaction id.
+    Auto,
+    /// Use the given transaction id. It must not be used by any instruction yet.
+    Explicit(u32),
+}
+
+impl FromStr for TxIdArg {
+    type Err = ParseIntError;
+
+    fn from_str(value: &str) -> Result<Self, ParseIntError> {
+        if value == "auto" {
+            return Ok(TxIdArg::Auto);
+        }
+        value.parse().map(TxIdArg::Explicit)
+    }
+}
+
+/// Holds the lock file of the transaction id counter and removes it on drop. The file records
+/// the pid of the holder and the time it was taken, as `<pid> <unix seconds>`.
+struct TxIdLock {
+    path: PathBuf,
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Returns the content of the lock file if it was taken more than `STALE_LOCK_AGE` ago. A lock
+/// whose content is not written yet is never stale.
+fn stale_lock_content(path: &PathBuf) -> Option<String> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let taken_at: u64 = content.split_whitespace().nth(1)?.parse().ok()?;
+    if unix_secs().saturating_sub(taken_at) > STALE_LOCK_AGE.as_secs() {
+        Some(content)
+    } else {
+        None
+    }
+}
+
+impl TxIdLock {
+    fn acquire(db_dir: ChainDirs) -> Result<Self, Error> {
+        let dir = construct_path(db_dir.clone(), ON_CHAIN_DIR, COMMON_OBJECTS_DIR, "")?;
+        create_dir_all(&dir).map_err(|error| Error::FileCreationError {
+            error,
+            path: dir.clone(),
+        })?;
+        let path = construct_path(db_dir, ON_CHAIN_DIR, COMMON_OBJECTS_DIR, TX_ID_LOCK_FILE)?;
+        let start = Instant::now();
+        loop {
+            // Creating a new file either succeeds for exactly one of the commands, or fails.
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    let lock = Self { path };
+                    write!(file, "{} {}", std::process::id(), unix_secs()).map_err(|error| {
+                        Error::FileCreationError {
+                            error,
+                            path: lock.path.clone(),
+                        }
+                    })?;
+                    return Ok(lock);
+                }
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                    if start.elapsed() > LOCK_TIMEOUT {
+                        return Err(Error::TxIdLockTimeout { path });
+                    }
+                    if let Some(content) = stale_lock_content(&path) {
+                        error!("Breaking the stale lock {:?} of \"{}\".", path, content);
+                        let _ = std::fs::remove_file(&path);
+                        continue;
+                    }
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                Err(error) => return Err(Error::FileCreationError { error, path }),
+            }
+        }
+    }
+}
+
+impl Drop for TxIdLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Returns the ids of the instructions in the common directory. The files with malformed names
+/// are ignored.
+fn used_tx_ids(db_dir: ChainDirs) -> Result<Vec<u32>, Error> {
+    Ok(all_tx_files(db_dir)?
+        .into_iter()
+        .filter_map(|tx_file| parse_tx_name(tx_file).ok())
+        .map(|(tx_id, _, _, _)| tx_id)
+        .collect())
+}
+
+/// Returns the next free transaction id and records it, so that no other call returns it.
+pub fn allocate_tx_id(db_dir: ChainDirs) -> Result<u32, Error> {
+    let _lock = TxIdLock::acquire(db_dir.clone())?;
+    // The ids that were used explicitly, or before the counter existed, are skipped too.
+    let last_allocated: u32 = load_from_file(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        LAST_ALLOCATED_TX_ID_FILE,
+    )
+    .unwrap_or(0);
+    let last_used = used_tx_ids(db_dir.clone())?.into_iter().max().unwrap_or(0);
+    let tx_id = std::cmp::max(last_allocated, last_used) + 1;
+    save_to_file(
+        db_dir,
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        LAST_ALLOCATED_TX_ID_FILE,
+        &tx_id,
+    )?;
+    Ok(tx_id)
+}
+
+/// Returns the transaction id of a new transaction. An explicit id must not be used by an
+/// existing instruction. It is checked under the lock of the counter, and the counter is moved
+/// past it, so that a concurrent allocation cannot return it before its instruction is written.
+pub fn resolve_tx_id(db_dir: ChainDirs, tx_id: TxIdArg) -> Result<u32, Error> {
+    match tx_id {
+        TxIdArg::Auto => allocate_tx_id(db_dir),
+        TxIdArg::Explicit(tx_id) => {
+            let _lock = TxIdLock::acquire(db_dir.clone())?;
+            if used_tx_ids(db_dir.clone())?.contains(&tx_id) {
+                return Err(Error::TxIdAlreadyUsed { tx_id });
+            }
+            let last_allocated: u32 = load_from_file(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                COMMON_OBJECTS_DIR,
+                LAST_ALLOCATED_TX_ID_FILE,
+            )
+            .unwrap_or(0);
+            if tx_id > last_allocated {
+                save_to_file(
+                    db_dir,
+                    ON_CHAIN_DIR,
+                    COMMON_OBJECTS_DIR,
+                    LAST_ALLOCATED_TX_ID_FILE,
+                    &tx_id,
+                )?;
+            }
+            Ok(tx_id)
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{account_create_transaction_file, save_object, test_utils::test_db_dir};
+    use std::{collections::BTreeSet, thread};
+
+    #[test]
+    fn test_resolve_tx_id() {
+        let db_dir = test_db_dir("resolve_tx_id");
+
+        assert_eq!("auto".parse::<TxIdArg>().unwrap(), TxIdArg::Auto);
+        assert_eq!("5".parse::<TxIdArg>().unwrap(), TxIdArg::Explicit(5));
+        assert!("five".parse::<TxIdArg>().is_err());
+
+        assert_eq!(resolve_tx_id(db_dir.clone(), TxIdArg::Auto).unwrap(), 1);
+        let file_name =
+            account_create_transaction_file(5, &String::from("alice"), &String::from("ACME"));
+        save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &file_name,
+            &0u32,
+        )
+        .unwrap();
+
+        match resolve_tx_id(db_dir.clone(), TxIdArg::Explicit(5)) {
+            Err(Error::TxIdAlreadyUsed { tx_id }) => assert_eq!(tx_id, 5),
+            other => panic!("Expected TxIdAlreadyUsed, got {:?}", other),
+        }
+        assert_eq!(
+            resolve_tx_id(db_dir.clone(), TxIdArg::Explicit(4)).unwrap(),
+            4
+        );
+        // The explicitly used id is skipped.
+        assert_eq!(resolve_tx_id(db_dir.clone(), TxIdArg::Auto).unwrap(), 6);
+        assert_eq!(resolve_tx_id(db_dir.clone(), TxIdArg::Auto).unwrap(), 7);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_stale_lock_is_broken() {
+        let db_dir = test_db_dir("stale_tx_id_lock");
+
+        // A lock left behind by a command that crashed long ago.
+        let lock_path = construct_path(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            TX_ID_LOCK_FILE,
+        )
+        .unwrap();
+        create_dir_all(lock_path.parent().unwrap()).unwrap();
+        std::fs::write(&lock_path, format!("1 {}", unix_secs() - 3600)).unwrap();
+        assert_eq!(resolve_tx_id(db_dir.clone(), TxIdArg::Auto).unwrap(), 1);
+        assert!(!lock_path.exists());
+
+        // An explicit id is not allocated again.
+        assert_eq!(
+            resolve_tx_id(db_dir.clone(), TxIdArg::Explicit(3)).unwrap(),
+            3
+        );
+        assert_eq!(resolve_tx_id(db_dir.clone(), TxIdArg::Auto).unwrap(), 4);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_parallel_allocations_never_collide() {
+        let db_dir = test_db_dir("parallel_tx_ids");
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let db_dir = db_dir.clone();
+                thread::spawn(move || {
+                    (0..10)
+                        .map(|_| allocate_tx_id(db_dir.clone()).unwrap())
+                        .collect::<Vec<u32>>()
+                })
+            })
+            .collect();
+        let mut all_tx_ids = vec![];
+        for thread in threads {
+            all_tx_ids.extend(thread.join().unwrap());
+        }
+        let unique: BTreeSet<u32> = all_tx_ids.iter().cloned().collect();
+        assert_eq!(unique.len(), all_tx_ids.len());
+        assert_eq!(unique, (1..=80).collect());
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
@@ -0,0 +1,392 @@
+//! The state machine of the asset issuance and the transfer instructions. The state of an
+//! instruction is part of its file name, and an instruction is only written in a state that
+//! follows the most advanced state of the same transaction on disk.
+
+use crate::{
+    asset_transaction_file, chain_dir, confidential_transaction_file, errors::Error, parse_tx_name,
+    save_object, AssetInstruction, ChainDirs, OrderedAssetInstruction, OrderedTransferInstruction,
+    TransferInstruction, COMMON_OBJECTS_DIR, ON_CHAIN_DIR,
+};
+use codec::Encode;
+use cryptography::mercat::{AssetTxState, TransferTxState, TxSubstate};
+use std::fmt;
+
+/// The state of either an asset issuance or a transfer instruction.
+#[derive(Clone, Copy, Debug)]
+pub enum TxState {
+    Asset(AssetTxState),
+    Transfer(TransferTxState),
+}
+
+impl fmt::Display for TxState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxState::Asset(state) => write!(f, "{}", state),
+            TxState::Transfer(state) => write!(f, "{}", state),
+        }
+    }
+}
+
+impl From<AssetTxState> for TxState {
+    fn from(state: AssetTxState) -> Self {
+        TxState::Asset(state)
+    }
+}
+
+impl From<TransferTxState> for TxState {
+    fn from(state: TransferTxState) -> Self {
+        TxState::Transfer(state)
+    }
+}
+
+/// Returns all the states of the asset issuance and the transfer instructions.
+pub fn all_tx_states() -> Vec<TxState> {
+    let substates = [
+        TxSubstate::Started,
+        TxSubstate::Validated,
+        TxSubstate::Rejected,
+    ];
+    let asset_states = substates.iter().flat_map(|substate| {
+        vec![
+            TxState::Asset(AssetTxState::Initialization(*substate)),
+            TxState::Asset(AssetTxState::Justification(*substate)),
+        ]
+    });
+    let transfer_states = substates.iter().flat_map(|substate| {
+        vec![
+            TxState::Transfer(TransferTxState::Initialization(*substate)),
+            TxState::Transfer(TransferTxState::Finalization(*substate)),
+            TxState::Transfer(TransferTxState::Justification(*substate)),
+        ]
+    });
+    asset_states.chain(transfer_states).collect()
+}
+
+/// Returns true if a new instruction can be created in the `state`.
+pub fn is_initial_state(state: TxState) -> bool {
+    match state {
+        TxState::Asset(AssetTxState::Initialization(TxSubstate::Started))
+        | TxState::Transfer(TransferTxState::Initialization(TxSubstate::Started)) => true,
+        _ => false,
+    }
+}
+
+/// Returns true if an instruction in the `from` state can move to the `to` state. An issuance
+/// is validated or rejected right after its initialization. A transfer is finalized by the
+/// receiver, justified or rejected by the mediator, and then validated or rejected. A mediator
+/// can justify the same transfer again, but a validated or rejected instruction is final.
+pub fn is_valid_transition(from: TxState, to: TxState) -> bool {
+    use TxSubstate::{Rejected, Started, Validated};
+    match (from, to) {
+        (TxState::Asset(from), TxState::Asset(to)) => match (from, to) {
+            (AssetTxState::Initialization(Started), AssetTxState::Justification(Validated))
+            | (AssetTxState::Initialization(Started), AssetTxState::Justification(Rejected)) => {
+                true
+            }
+            _ => false,
+        },
+        (TxState::Transfer(from), TxState::Transfer(to)) => match (from, to) {
+            (TransferTxState::Initialization(Started), TransferTxState::Finalization(Started))
+            | (TransferTxState::Finalization(Started), TransferTxState::Justification(Started))
+            | (TransferTxState::Finalization(Started), TransferTxState::Justification(Rejected))
+            | (TransferTxState::Justification(Started), TransferTxState::Justification(Started))
+            | (
+                TransferTxState::Justification(Started),
+                TransferTxState::Justification(Validated),
+            )
+            | (TransferTxState::Justification(Started), TransferTxState::Justification(Rejected)) => {
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Orders the states of a transaction by how far the transaction has progressed.
+fn progress(state: TxState) -> u8 {
+    match state {
+        TxState::Asset(AssetTxState::Initialization(_)) => 0,
+        TxState::Asset(AssetTxState::Justification(_)) => 2,
+        TxState::Transfer(TransferTxState::Initialization(_)) => 0,
+        TxState::Transfer(TransferTxState::Finalization(_)) => 1,
+        TxState::Transfer(TransferTxState::Justification(TxSubstate::Started)) => 2,
+        TxState::Transfer(TransferTxState::Justification(_)) => 3,
+    }
+}
+
+/// Returns the most advanced state of the `ticker` transaction `tx_id` among the instructions
+/// in the on-chain common directory, or `None` if the transaction has no instructions yet.
+pub fn current_tx_state(
+    db_dir: ChainDirs,
+    tx_id: u32,
+    ticker: &String,
+) -> Result<Option<TxState>, Error> {
+    let mut dir = chain_dir(db_dir, ON_CHAIN_DIR);
+    dir.push(COMMON_OBJECTS_DIR);
+    if !dir.exists() {
+        return Ok(None);
+    }
+    let prefix = format!("tx_{}_{}_", tx_id, ticker);
+    let states = all_tx_states();
+    let mut current: Option<TxState> = None;
+    for entry in std::fs::read_dir(dir.clone()).map_err(|error| Error::FileReadError {
+        error,
+        path: dir.clone(),
+    })? {
+        let entry = entry.map_err(|error| Error::FileReadError {
+            error,
+            path: dir.clone(),
+        })?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if !file_name.starts_with(&prefix) {
+            continue;
+        }
+        // Other kinds of files of the same transaction, e.g., credits, have no `TxState`.
+        let state = match parse_tx_name(file_name) {
+            Ok((_, _, state, _)) => state,
+            Err(_) => continue,
+        };
+        if let Some(state) = states.iter().find(|known| known.to_string() == state) {
+            if current.map_or(true, |current| progress(*state) > progress(current)) {
+                current = Some(*state);
+            }
+        }
+    }
+    Ok(current)
+}
+
+/// An instruction that is saved under its state.
+pub trait Instruction: Encode {
+    fn tx_state(&self) -> TxState;
+}
+
+impl Instruction for OrderedAssetInstruction {
+    fn tx_state(&self) -> TxState {
+        self.state.into()
+    }
+}
+
+impl Instruction for AssetInstruction {
+    fn tx_state(&self) -> TxState {
+        self.state.into()
+    }
+}
+
+impl Instruction for OrderedTransferInstruction {
+    fn tx_state(&self) -> TxState {
+        self.state.into()
+    }
+}
+
+impl Instruction for TransferInstruction {
+    fn tx_state(&self) -> TxState {
+        self.state.into()
+    }
+}
+
+/// Saves the `instruction` of the `ticker` transaction `tx_id` under the name of the
+/// `participant` and its state, in the on-chain directory `dir`. Fails with
+/// `Error::IllegalStateTransition` if its state does not follow the current state of the
+/// transaction on disk.
+pub fn save_instruction<T: Instruction>(
+    db_dir: ChainDirs,
+    dir: &str,
+    tx_id: u32,
+    ticker: &String,
+    participant: &String,
+    instruction: &T,
+) -> Result<(), Error> {
+    let to = instruction.tx_state();
+    let from = current_tx_state(db_dir.clone(), tx_id, ticker)?;
+    let is_legal = match from {
+        None => is_initial_state(to),
+        Some(from) => is_valid_transition(from, to),
+    };
+    if !is_legal {
+        return Err(Error::IllegalStateTransition {
+            tx_id,
+            from: from.map_or(String::from("none"), |from| from.to_string()),
+            to: to.to_string(),
+        });
+    }
+
+    let file_name = match to {
+        TxState::Asset(state) => asset_transaction_file(tx_id, participant, ticker, state),
+        TxState::Transfer(state) => {
+            confidential_transaction_file(tx_id, participant, ticker, state)
+        }
+    };
+    save_object(db_dir, ON_CHAIN_DIR, dir, &file_name, instruction)
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{construct_path, test_utils::test_db_dir};
+
+    #[test]
+    fn test_transitions() {
+        use TxSubstate::{Rejected, Started, Validated};
+        let legal: Vec<(TxState, TxState)> = vec![
+            (
+                AssetTxState::Initialization(Started).into(),
+                AssetTxState::Justification(Validated).into(),
+            ),
+            (
+                AssetTxState::Initialization(Started).into(),
+                AssetTxState::Justification(Rejected).into(),
+            ),
+            (
+                TransferTxState::Initialization(Started).into(),
+                TransferTxState::Finalization(Started).into(),
+            ),
+            (
+                TransferTxState::Finalization(Started).into(),
+                TransferTxState::Justification(Started).into(),
+            ),
+            (
+                TransferTxState::Finalization(Started).into(),
+                TransferTxState::Justification(Rejected).into(),
+            ),
+            (
+                TransferTxState::Justification(Started).into(),
+                TransferTxState::Justification(Started).into(),
+            ),
+            (
+                TransferTxState::Justification(Started).into(),
+                TransferTxState::Justification(Validated).into(),
+            ),
+            (
+                TransferTxState::Justification(Started).into(),
+                TransferTxState::Justification(Rejected).into(),
+            ),
+        ];
+        let is_listed = |from: TxState, to: TxState| {
+            legal.iter().any(|(legal_from, legal_to)| {
+                legal_from.to_string() == from.to_string() && legal_to.to_string() == to.to_string()
+            })
+        };
+
+        let states = all_tx_states();
+        assert_eq!(states.len(), 15);
+        let mut count = 0;
+        for from in states.iter() {
+            for to in states.iter() {
+                assert_eq!(
+                    is_valid_transition(*from, *to),
+                    is_listed(*from, *to),
+                    "{} -> {}",
+                    from,
+                    to
+                );
+                if is_valid_transition(*from, *to) {
+                    count += 1;
+                }
+            }
+        }
+        assert_eq!(count, legal.len());
+
+        let initial: Vec<String> = states
+            .into_iter()
+            .filter(|state| is_initial_state(*state))
+            .map(|state| state.to_string())
+            .collect();
+        assert_eq!(
+            initial,
+            vec![
+                AssetTxState::Initialization(Started).to_string(),
+                TransferTxState::Initialization(Started).to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_save_instruction() {
+        let db_dir = test_db_dir("save_instruction");
+
+        let (acme, alice, mike) = (
+            String::from("ACME"),
+            String::from("alice"),
+            String::from("mike"),
+        );
+        let instruction = |state: TransferTxState| TransferInstruction {
+            state,
+            data: vec![1, 2, 3],
+            rejection_reason: None,
+        };
+        let save = |tx_id: u32, participant: &String, state: TransferTxState| {
+            save_instruction(
+                db_dir.clone(),
+                COMMON_OBJECTS_DIR,
+                tx_id,
+                &acme,
+                participant,
+                &instruction(state),
+            )
+        };
+        let exists = |tx_id: u32, participant: &String, state: TransferTxState| {
+            construct_path(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                COMMON_OBJECTS_DIR,
+                &confidential_transaction_file(tx_id, participant, &acme, state),
+            )
+            .unwrap()
+            .exists()
+        };
+        let init = TransferTxState::Initialization(TxSubstate::Started);
+        let finalized = TransferTxState::Finalization(TxSubstate::Started);
+        let justified = TransferTxState::Justification(TxSubstate::Started);
+        let validated = TransferTxState::Justification(TxSubstate::Validated);
+
+        // A crafted instruction cannot claim to be validated without the earlier steps.
+        for (tx_id, state) in &[(1, validated), (2, finalized), (3, justified)] {
+            match save(*tx_id, &alice, *state) {
+                Err(Error::IllegalStateTransition {
+                    tx_id: id,
+                    from,
+                    to,
+                }) => {
+                    assert_eq!(
+                        (id, from, to),
+                        (*tx_id, "none".to_string(), state.to_string())
+                    )
+                }
+                other => panic!("Expected IllegalStateTransition, got {:?}", other),
+            }
+            assert!(!exists(*tx_id, &alice, *state));
+        }
+
+        save(1, &alice, init).unwrap();
+        match save(1, &alice, validated) {
+            Err(Error::IllegalStateTransition { from, to, .. }) => {
+                assert_eq!((from, to), (init.to_string(), validated.to_string()))
+            }
+            other => panic!("Expected IllegalStateTransition, got {:?}", other),
+        }
+        save(1, &alice, finalized).unwrap();
+        save(1, &mike, justified).unwrap();
+        save(1, &alice, validated).unwrap();
+        assert_eq!(
+            current_tx_state(db_dir.clone(), 1, &acme)
+                .unwrap()
+                .unwrap()
+                .to_string(),
+            validated.to_string()
+        );
+        // A validated transfer is final, and its id cannot be initialized again.
+        assert!(save(1, &mike, justified).is_err());
+        assert!(save(1, &alice, init).is_err());
+        // The same id on another ticker is a different transaction.
+        assert!(current_tx_state(db_dir.clone(), 1, &String::from("TSLA"))
+            .unwrap()
+            .is_none());
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
@@ -1,28 +1,128 @@
 use crate::{
-    account_create_transaction_file, all_unverified_tx_files, asset_transaction_file,
-    compute_enc_pending_balance, confidential_transaction_file, debug_decrypt, errors::Error,
-    get_asset_ids, get_user_ticker_from, last_ordering_state, load_object, load_tx_file,
-    parse_tx_name, save_object, save_to_file, user_public_account_balance_file,
-    user_public_account_file, AssetInstruction, CoreTransaction, Direction, OrderedPubAccount,
-    OrderedPubAccountTx, PrintableAccountId, TransferInstruction, ValidationResult,
-    COMMON_OBJECTS_DIR, LAST_VALIDATED_TX_ID_FILE, OFF_CHAIN_DIR, ON_CHAIN_DIR,
+    account_close::{ensure_not_closed, validate_pending_closures},
+    account_create_transaction_file, all_unverified_tx_files, all_unverified_tx_files_with_limit,
+    asset_transaction_file,
+    audit::record_state_transition,
+    balances::{compute_enc_pending_balance, ConfirmedBalance, PendingBalance},
+    chain_setup::{ensure_amount_in_ticker_range, registry_commitment, registry_commitment_file},
+    confidential_transaction_file, construct_path, debug_decrypt_balance,
+    errors::Error,
+    get_asset_ids, get_checked_user_ticker_from, get_user_ticker_from, is_tx_name_error,
+    issuance_policy::ensure_issuance_policy,
+    last_ordering_state, last_verified_tx_id, load_from_file, load_object, load_tx_file,
+    mediator_credit::{
+        mediator_credit_file, verify_mediator_credit, SignedMediatorCredit, CREDIT_STARTED_STATE,
+        CREDIT_VALIDATED_STATE,
+    },
+    outcome_label, parse_tx_name, save_object, save_to_file, state_readiness,
+    supply::{record_issuance, IssuanceRecord},
+    tx_state::save_instruction,
+    user_public_account_balance_file, user_public_account_file,
+    verification_cache::{content_hash, VerificationCache},
+    AssetInstruction, ChainDirs, CoreTransaction, Direction, ErrorStrategy, OrderedPubAccount,
+    OrderedPubAccountTx, PrintableAccountId, ReadinessStatus, RejectionReason, TransferInstruction,
+    ValidationResult, COMMON_OBJECTS_DIR, DEFAULT_MAX_PENDING, LAST_VALIDATED_TX_ID_FILE,
+    LAST_VALIDATED_TX_ID_PREFIX, ON_CHAIN_DIR, TX_ACCEPTED_COUNTER, TX_REJECTED_COUNTER,
+    VALIDATOR_DIR,
 };
 use codec::{Decode, Encode};
-use cryptography::mercat::{
-    account::AccountValidator, asset::AssetValidator, transaction::TransactionValidator,
-    AccountCreatorVerifier, AssetTransactionVerifier, AssetTxState, EncryptedAmount,
-    EncryptedAssetId, InitializedAssetTx, JustifiedTransferTx, PubAccount,
-    TransferTransactionVerifier, TransferTxState, TxSubstate,
+use cryptography::{
+    asset_id_from_ticker,
+    mercat::{
+        account::AccountValidator, asset::AssetValidator, transaction::TransactionValidator,
+        AccountCreatorVerifier, AssetTransactionVerifier, AssetTxState, EncryptedAmount,
+        EncryptedAssetId, InitializedAssetTx, JustifiedTransferTx, PubAccount,
+        TransferTransactionVerifier, TransferTxState, TxSubstate,
+    },
 };
-use log::{debug, error, info};
-use metrics::timing;
+use curve25519_dalek::scalar::Scalar;
+use log::{debug, error, info, warn};
+use metrics::{counter, timing};
 use rand::rngs::OsRng;
-use std::{collections::HashSet, path::PathBuf, time::Instant};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::PathBuf,
+    time::Instant,
+};
+
+/// The participant name of the validator in the audit log.
+const VALIDATOR: &str = "validator";
+
+/// The suffix of the account files that are written, but not yet renamed over the old ones.
+const STAGED_SUFFIX: &str = ".staged";
+
+/// The on-chain directory of the transactions that the approver has denied.
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+/// The state of a denied transaction in the audit log.
+pub const QUARANTINED_STATE: &str = "quarantined";
+
+/// Same as `VerificationCache::verify`, but skips the verification of the transactions before
+/// the `assume_valid_before` checkpoint.
+fn verify_unless_assumed_valid<F>(
+    cache: &mut VerificationCache,
+    assume_valid_before: Option<u32>,
+    tx_id: u32,
+    content_hash: String,
+    verify: F,
+) -> Result<(), Error>
+where
+    F: FnOnce() -> Result<(), Error>,
+{
+    if assume_valid_before.map_or(false, |checkpoint| tx_id < checkpoint) {
+        warn!(
+            "tx-{}: Assumed valid. The cryptographic verification is skipped!",
+            tx_id
+        );
+        return Ok(());
+    }
+    cache.verify(tx_id, content_hash, verify)
+}
+
+/// The decision of an approver on a transaction that has passed the verification.
+#[derive(Clone, Debug)]
+pub enum Decision {
+    Approve,
+    Deny(RejectionReason),
+}
+
+/// A callback that approves or denies each verified transaction before its balance changes are
+/// applied, e.g., against an external sanctions list. It is called once per account update, and
+/// a transaction is denied if any of its updates is denied.
+pub type Approver<'a> = &'a dyn Fn(&ValidationResult) -> Decision;
+
+/// The record of a denied transaction, saved in the quarantine directory.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QuarantinedTransaction {
+    pub tx_id: u32,
+    pub reason: RejectionReason,
+}
+
+/// The name of the record of a denied transaction.
+pub fn quarantine_file(tx_id: u32) -> String {
+    format!("tx_{}_quarantined.json", tx_id)
+}
 
-fn load_all_unverified_and_ready(db_dir: PathBuf) -> Result<Vec<CoreTransaction>, Error> {
-    all_unverified_tx_files(db_dir)?
+/// Loads the transactions that are ready for validation. Unless `strict` is set, the files whose
+/// names cannot be parsed are logged and skipped, instead of failing the whole batch. Fails if
+/// there are more than `max_pending` unverified transaction files.
+fn load_all_unverified_and_ready(
+    db_dir: ChainDirs,
+    strict: bool,
+    max_pending: usize,
+) -> Result<Vec<CoreTransaction>, Error> {
+    all_unverified_tx_files_with_limit(db_dir, max_pending)?
         .into_iter()
         .map(|tx| parse_tx_name(tx))
+        .filter(|res| match res {
+            Err(error) if !strict && is_tx_name_error(error) => {
+                error!("Skipping a transaction file: {}", error);
+                false
+            }
+            _ => true,
+        })
         .map(|res| match res {
             Err(error) => Err(error),
             Ok((tx_id, user, state, tx_file_path)) => {
@@ -33,14 +133,250 @@ fn load_all_unverified_and_ready(db_dir: PathBuf) -> Result<Vec<CoreTransaction>
         .collect()
 }
 
-pub fn validate_all_pending(db_dir: PathBuf) -> Result<(), Error> {
+/// A summary of how far the validation has progressed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ValidationStatus {
+    /// The id of the last validated transaction, if any.
+    pub last_validated_tx_id: Option<u32>,
+    /// The number of unvalidated transactions that are ready for validation.
+    pub pending_ready_count: usize,
+    /// The number of unvalidated transactions that are waiting for a party to act.
+    pub pending_not_ready_count: usize,
+    /// The unvalidated transactions that are waiting for a party to act, with the state of the
+    /// file that they wait for.
+    pub not_ready: Vec<NotReadyTransaction>,
+    /// The transactions that the mediators have rejected. These are skipped by the validator.
+    pub rejected: Vec<RejectedTransaction>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NotReadyTransaction {
+    pub tx_id: u32,
+    pub missing_state: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RejectedTransaction {
+    pub tx_id: u32,
+    pub sender: String,
+    pub reason: Option<RejectionReason>,
+}
+
+/// Computes the validation status from the transaction file names, without loading them.
+pub fn process_validation_status(db_dir: ChainDirs) -> Result<ValidationStatus, Error> {
+    let last_validated_tx_id = last_verified_tx_id(db_dir.clone());
+
+    // A transaction consists of one file per step. It is ready once any of them is.
+    // Only the rejected transactions are loaded, to read the mediator's reason.
+    let mut pending: BTreeMap<u32, ReadinessStatus> = BTreeMap::new();
+    let mut rejected = vec![];
+    for tx_file in all_unverified_tx_files(db_dir)? {
+        let (tx_id, user, state, tx_file_path) = parse_tx_name(tx_file)?;
+        if state == TransferTxState::Justification(TxSubstate::Rejected).to_string() {
+            if let CoreTransaction::TransferRejected { sender, reason, .. } =
+                load_tx_file(tx_id, user, state, tx_file_path)?
+            {
+                rejected.push(RejectedTransaction {
+                    tx_id,
+                    sender,
+                    reason,
+                });
+            }
+            continue;
+        }
+        let readiness = state_readiness(&state);
+        let merged = match pending.remove(&tx_id) {
+            Some(current) => current.merge(readiness),
+            None => readiness,
+        };
+        pending.insert(tx_id, merged);
+    }
+    for rejected_tx in rejected.iter() {
+        pending.remove(&rejected_tx.tx_id);
+    }
+    let not_ready: Vec<NotReadyTransaction> = pending
+        .iter()
+        .filter_map(|(tx_id, readiness)| match readiness {
+            ReadinessStatus::Ready => None,
+            ReadinessStatus::NotReady { missing_state } => Some(NotReadyTransaction {
+                tx_id: *tx_id,
+                missing_state: missing_state.clone(),
+            }),
+        })
+        .collect();
+    let pending_ready_count = pending.len() - not_ready.len();
+
+    Ok(ValidationStatus {
+        last_validated_tx_id: if last_validated_tx_id < 0 {
+            None
+        } else {
+            Some(last_validated_tx_id as u32)
+        },
+        pending_ready_count,
+        pending_not_ready_count: not_ready.len(),
+        not_ready,
+        rejected,
+    })
+}
+
+/// The digest of a validation run.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ValidationSummary {
+    /// The number of transactions that were validated, accepted or rejected.
+    pub processed: usize,
+    pub accepted: usize,
+    pub rejected: usize,
+    /// The accounts whose balances were updated.
+    pub updated_accounts: Vec<AccountUpdate>,
+    /// The last validated tx_id after the run.
+    pub last_validated_tx_id: Option<u32>,
+}
+
+/// The balance update of an account. The balances are only decrypted with `--debug-balances`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccountUpdate {
+    pub user: String,
+    pub ticker: String,
+    pub balance_before: Option<u32>,
+    pub balance_after: Option<u32>,
+}
+
+impl ValidationSummary {
+    /// Counts the outcome of a validated transaction, also in the metrics.
+    fn count(&mut self, accepted: bool) {
+        self.processed += 1;
+        if accepted {
+            self.accepted += 1;
+            counter!(TX_ACCEPTED_COUNTER, 1);
+        } else {
+            self.rejected += 1;
+            counter!(TX_REJECTED_COUNTER, 1);
+        }
+    }
+}
+
+impl fmt::Display for ValidationSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "processed: {}", self.processed)?;
+        writeln!(f, "accepted: {}", self.accepted)?;
+        writeln!(f, "rejected: {}", self.rejected)?;
+        writeln!(f, "updated accounts: {}", self.updated_accounts.len())?;
+        for update in &self.updated_accounts {
+            match (update.balance_before, update.balance_after) {
+                (Some(before), Some(after)) => writeln!(
+                    f,
+                    "  {}-{}: {} -> {}",
+                    update.user, update.ticker, before, after
+                )?,
+                _ => writeln!(f, "  {}-{}", update.user, update.ticker)?,
+            }
+        }
+        write!(f, "last validated tx_id: {:?}", self.last_validated_tx_id)
+    }
+}
+
+/// The options of a validation run. The defaults are the safe choices of the CLI.
+#[derive(Clone, Debug)]
+pub struct ValidationOptions {
+    /// Fail on the transaction files with unexpected names, instead of skipping them.
+    pub strict_filenames: bool,
+    /// What to do with a transaction that cannot be loaded or decoded.
+    pub error_strategy: ErrorStrategy,
+    /// Skips the cryptographic verification of the issuances and transfers whose id is less
+    /// than this checkpoint, while still applying their balance changes. This speeds up
+    /// bootstrapping from a trusted checkpoint, at the cost of accepting any invalid transaction
+    /// before it. `None` verifies everything.
+    pub assume_valid_before: Option<u32>,
+    /// Allows moving the last processed counter of an account back, e.g., when an earlier slice
+    /// of the transactions is validated again. Refused by default.
+    pub allow_regress: bool,
+    /// The directory of the secret keys, with the layout of the off-chain directory, to decrypt
+    /// the balances for the debug logs and to track them in plain text. `None` loads no secret.
+    pub debug_balances: Option<PathBuf>,
+    /// The maximum number of unverified transaction files to load. The validation fails with
+    /// `TooManyPendingTransactions` if there are more.
+    pub max_pending: usize,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        ValidationOptions {
+            strict_filenames: false,
+            error_strategy: ErrorStrategy::default(),
+            assume_valid_before: None,
+            allow_regress: false,
+            debug_balances: None,
+            max_pending: DEFAULT_MAX_PENDING,
+        }
+    }
+}
+
+pub fn validate_all_pending(db_dir: ChainDirs) -> Result<(), Error> {
+    validate_all_pending_with_approver(db_dir, None)
+}
+
+/// Validates all the pending transactions like `validate_all_pending`. The balance changes of
+/// the verified transactions are only applied if the `approver` approves them. The denied
+/// transactions are moved to the quarantine directory.
+pub fn validate_all_pending_with_approver(
+    db_dir: ChainDirs,
+    approver: Option<Approver>,
+) -> Result<(), Error> {
+    validate_all_pending_with_summary(db_dir, approver).map(|_| ())
+}
+
+/// Same as `validate_all_pending_with_approver`, but returns the digest of the run.
+pub fn validate_all_pending_with_summary(
+    db_dir: ChainDirs,
+    approver: Option<Approver>,
+) -> Result<ValidationSummary, Error> {
+    validate_all_pending_with_options(db_dir, approver, &ValidationOptions::default())
+}
+
+/// Same as `validate_all_pending_with_summary`, with the given `options`.
+pub fn validate_all_pending_with_options(
+    db_dir: ChainDirs,
+    approver: Option<Approver>,
+    options: &ValidationOptions,
+) -> Result<ValidationSummary, Error> {
     // TODO: This function should be called when any justify is called. To be fixed in CRYP-131.
-    let all_unverified_and_ready = load_all_unverified_and_ready(db_dir.clone())?;
+    if let Some(checkpoint) = options.assume_valid_before {
+        warn!(
+            "The transactions before tx-{} are assumed to be valid. Their proofs are NOT verified, \
+             which is only safe if the checkpoint is trusted.",
+            checkpoint
+        );
+    }
+    let all_unverified_and_ready = load_all_unverified_and_ready(
+        db_dir.clone(),
+        options.strict_filenames,
+        options.max_pending,
+    )?;
+    let mut cache = VerificationCache::load(db_dir.clone());
     let mut last_tx_id: Option<u32> = None;
+    let mut summary = ValidationSummary::default();
+    // The first and the last tx_id of the transactions of each ticker in this run.
+    let mut ticker_tx_ids: BTreeMap<String, (u32, u32)> = BTreeMap::new();
+    let mut ticker_markers: BTreeMap<String, Option<u32>> = BTreeMap::new();
 
-    let mut results: Vec<ValidationResult> = vec![];
+    // A transfer results in two updates, one for each party.
+    let mut results: Vec<ValidationResult> = Vec::with_capacity(2 * all_unverified_and_ready.len());
     // For each of them call the validate function and process as needed
     for tx in all_unverified_and_ready {
+        // After a failed ticker, the last validated tx_id is held back and the transactions of
+        // the other tickers are listed again. Their per-ticker markers tell them apart.
+        if let Some((tx_id, ticker)) = tx_ticker(db_dir.clone(), &tx) {
+            let marker = ticker_markers
+                .entry(ticker.clone())
+                .or_insert_with(|| last_validated_tx_id_of_ticker(db_dir.clone(), &ticker));
+            if marker.map_or(false, |marker| tx_id <= marker) {
+                debug!("tx-{}: Already applied to {}, skipping it.", tx_id, ticker);
+                last_tx_id = Some(std::cmp::max(last_tx_id.unwrap_or_default(), tx_id));
+                continue;
+            }
+            let range = ticker_tx_ids.entry(ticker).or_insert((tx_id, tx_id));
+            *range = (std::cmp::min(range.0, tx_id), std::cmp::max(range.1, tx_id));
+        }
         match tx {
             CoreTransaction::IssueInit {
                 issue_tx,
@@ -49,8 +385,15 @@ pub fn validate_all_pending(db_dir: PathBuf) -> Result<(), Error> {
                 ordering_state: _,
                 amount,
             } => {
-                let result =
-                    validate_asset_issuance(db_dir.clone(), amount, issue_tx.clone(), tx_id);
+                let result = validate_asset_issuance(
+                    db_dir.clone(),
+                    amount,
+                    issue_tx.clone(),
+                    tx_id,
+                    &mut cache,
+                    options.assume_valid_before,
+                );
+                summary.count(result.amount.is_some());
                 results.push(result);
                 last_tx_id = Some(std::cmp::max(last_tx_id.unwrap_or_default(), tx_id));
             }
@@ -60,41 +403,48 @@ pub fn validate_all_pending(db_dir: PathBuf) -> Result<(), Error> {
                 mediator,
             } => {
                 let account_id = tx.finalized_data.init_data.memo.sender_account_id;
-                let (sender, ticker, _) = get_user_ticker_from(account_id, db_dir.clone())?;
-                let sender_ordered_pub_account: OrderedPubAccount = load_object(
+                match validate_justified_transfer(
                     db_dir.clone(),
-                    ON_CHAIN_DIR,
-                    &sender,
-                    &user_public_account_file(&ticker),
-                )?;
-                let sender_account_balance: EncryptedAmount = load_object(
-                    db_dir.clone(),
-                    ON_CHAIN_DIR,
-                    &sender,
-                    &user_public_account_balance_file(&ticker),
-                )?;
-                let ordering_state = last_ordering_state(
-                    sender.clone(),
-                    sender_ordered_pub_account.last_processed_tx_counter,
-                    tx_id,
-                    db_dir.clone(),
-                )?;
-                let pending_balance = compute_enc_pending_balance(
-                    &sender,
-                    ordering_state,
-                    sender_ordered_pub_account.last_processed_tx_counter,
-                    sender_account_balance,
-                    db_dir.clone(),
-                )?;
-                debug!(
-                    "------------> validating tx: {}, pending transfer balance: {}",
+                    tx,
+                    mediator,
                     tx_id,
-                    debug_decrypt(account_id, pending_balance.clone(), db_dir.clone())?
-                );
-                let (sender_result, receiver_result) =
-                    validate_transaction(db_dir.clone(), tx, mediator, pending_balance, tx_id);
-                results.push(sender_result);
-                results.push(receiver_result);
+                    &mut cache,
+                    options,
+                ) {
+                    Ok((sender_result, receiver_result)) => {
+                        summary.count(sender_result.amount.is_some());
+                        results.push(sender_result);
+                        results.push(receiver_result);
+                    }
+                    Err(error) if options.error_strategy == ErrorStrategy::Abort => {
+                        cache.save(db_dir.clone())?;
+                        return Err(error);
+                    }
+                    Err(error) => {
+                        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+                        error!("tx-{}: Ignoring the validation error and continuing the with rest of the validations.", tx_id);
+                        summary.count(false);
+                        // The sender is unknown if the error is the lookup of its account.
+                        results.push(
+                            match get_checked_user_ticker_from(account_id, db_dir.clone()) {
+                                Ok((sender, ticker, _)) => {
+                                    ValidationResult::error(&sender, &ticker, tx_id)
+                                }
+                                Err(_) => ValidationResult::error("n/a", "n/a", tx_id),
+                            },
+                        );
+                    }
+                }
+                last_tx_id = Some(std::cmp::max(last_tx_id.unwrap_or_default(), tx_id));
+            }
+            CoreTransaction::MediatorCredit {
+                credit,
+                mediator,
+                tx_id,
+            } => {
+                let result = validate_mediator_credit(db_dir.clone(), credit, mediator, tx_id);
+                summary.count(result.amount.is_some());
+                results.push(result);
                 last_tx_id = Some(std::cmp::max(last_tx_id.unwrap_or_default(), tx_id));
             }
             CoreTransaction::Account {
@@ -106,8 +456,9 @@ pub fn validate_all_pending(db_dir: PathBuf) -> Result<(), Error> {
                     Err(error) => {
                         error!("Error in validation of tx-{}: {:#?}", tx_id, error);
                         error!("tx-{}: Ignoring the validation error and continuing the with rest of the validations.", tx_id);
+                        summary.count(false);
                     }
-                    Ok(_) => (),
+                    Ok(_) => summary.count(true),
                 };
                 last_tx_id = Some(std::cmp::max(last_tx_id.unwrap_or_default(), tx_id));
             }
@@ -116,142 +467,551 @@ pub fn validate_all_pending(db_dir: PathBuf) -> Result<(), Error> {
             }
         }
     }
+    cache.save(db_dir.clone())?;
 
-    // TODO: CRYP-134, use a more elegant way of writing the following code.
+    if let Some(approver) = approver {
+        deny_unapproved(db_dir.clone(), &mut results, approver)?;
+    }
 
-    // find all users
-    let mut users: Vec<String> = vec![];
-    for result in results.clone() {
-        if result.user != "n/a" {
-            users.push(result.user);
-        }
+    // The balances are updated ticker by ticker. The new balances of a ticker are staged and
+    // only written if all of its accounts can be updated. A failure in one ticker does not stop
+    // the others, and only the tickers whose update succeeded advance their markers.
+    let mut accounts_by_ticker: BTreeMap<String, Vec<String>> = ticker_tx_ids
+        .keys()
+        .map(|ticker| (ticker.clone(), vec![]))
+        .collect();
+    for (user, ticker) in accounts_to_update(&results) {
+        accounts_by_ticker.entry(ticker).or_default().push(user);
     }
-    // find all accounts
-    let mut accounts: HashSet<(String, String)> = HashSet::new();
-    for user in users {
-        for result in results.clone() {
-            if result.user == user {
-                accounts.insert((result.user, result.ticker));
+    let mut first_error: Option<Error> = None;
+    let mut validated_tx_id = last_tx_id;
+    for (ticker, users) in accounts_by_ticker {
+        let updated = users
+            .iter()
+            .map(|user| {
+                stage_balance_update(
+                    db_dir.clone(),
+                    user,
+                    &ticker,
+                    &results,
+                    last_tx_id,
+                    options.allow_regress,
+                    options.debug_balances.as_ref(),
+                )
+            })
+            .collect::<Result<Vec<StagedBalance>, Error>>()
+            .and_then(|staged| save_staged_balances(db_dir.clone(), staged))
+            .and_then(|updates| match ticker_tx_ids.get(&ticker) {
+                Some((_, last)) => save_ticker_last_validated_tx_id(db_dir.clone(), &ticker, *last)
+                    .map(|_| updates),
+                None => Ok(updates),
+            });
+        match updated {
+            Ok(updates) => summary.updated_accounts.extend(updates),
+            Err(error) => {
+                if options.error_strategy == ErrorStrategy::Abort {
+                    return Err(error);
+                }
+                error!("Error in the balance updates of {}: {:#?}", ticker, error);
+                error!(
+                    "{}: Continuing with the balance updates of the other tickers.",
+                    ticker
+                );
+                // The transactions of the ticker are validated again in the next run.
+                if let Some((first, _)) = ticker_tx_ids.get(&ticker) {
+                    validated_tx_id = std::cmp::min(validated_tx_id, first.checked_sub(1));
+                }
+                first_error = first_error.or(Some(error));
             }
         }
     }
 
-    for (user, ticker) in accounts.clone() {
-        let ordered_pub_account: OrderedPubAccount = load_object(
+    save_to_file(
+        db_dir.clone(),
+        VALIDATOR_DIR,
+        COMMON_OBJECTS_DIR,
+        LAST_VALIDATED_TX_ID_FILE,
+        &validated_tx_id,
+    )?;
+    summary.last_validated_tx_id = validated_tx_id;
+
+    // The closures are checked against the balances that were just updated.
+    validate_pending_closures(db_dir)?;
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(summary),
+    }
+}
+
+/// Looks up the sender of a justified transfer, computes its pending balance and validates the
+/// transfer against it. All of the errors are subject to the `error_strategy` of the `options`.
+fn validate_justified_transfer(
+    db_dir: ChainDirs,
+    tx: JustifiedTransferTx,
+    mediator: String,
+    tx_id: u32,
+    cache: &mut VerificationCache,
+    options: &ValidationOptions,
+) -> Result<(ValidationResult, ValidationResult), Error> {
+    let account_id = tx.finalized_data.init_data.memo.sender_account_id;
+    let (sender, ticker, _) = get_checked_user_ticker_from(account_id, db_dir.clone())?;
+    let pending_balance = sender_pending_balance(db_dir.clone(), &sender, &ticker, tx_id)?;
+    debug!(
+        "------------> validating tx: {}, pending transfer balance: {:?}",
+        tx_id,
+        debug_decrypt_balance(
+            account_id,
+            pending_balance.enc_amount().clone(),
+            db_dir.clone(),
+            options.debug_balances.as_ref(),
+        )?
+    );
+    validate_transaction(
+        db_dir,
+        tx,
+        mediator,
+        pending_balance,
+        tx_id,
+        cache,
+        options.assume_valid_before,
+    )
+}
+
+/// Returns the ticker of the account that the transaction `tx` changes, with its tx_id, or
+/// `None` if the transaction is not validated by `validate_all_pending`.
+fn tx_ticker(db_dir: ChainDirs, tx: &CoreTransaction) -> Option<(u32, String)> {
+    let (tx_id, account_id) = match tx {
+        CoreTransaction::Account {
+            account_tx, tx_id, ..
+        } => (*tx_id, account_tx.pub_account.enc_asset_id),
+        CoreTransaction::IssueInit {
+            issue_tx, tx_id, ..
+        } => (*tx_id, issue_tx.account_id),
+        CoreTransaction::TransferJustify { tx, tx_id, .. } => {
+            (*tx_id, tx.finalized_data.init_data.memo.sender_account_id)
+        }
+        CoreTransaction::MediatorCredit { credit, tx_id, .. } => {
+            (*tx_id, credit.credit.beneficiary_account_id)
+        }
+        _ => return None,
+    };
+    match get_user_ticker_from(account_id, db_dir) {
+        Ok((_, ticker, _)) => Some((tx_id, ticker)),
+        Err(error) => {
+            // The validation of the transaction fails on the same lookup, so it changes no
+            // balance, and it is not tracked by the marker of any ticker.
+            warn!(
+                "tx-{}: Cannot find the ticker of the transaction: {:#?}",
+                tx_id, error
+            );
+            None
+        }
+    }
+}
+
+/// The new state of an account, which is only written once all the accounts of its ticker
+/// could be updated.
+#[derive(Debug)]
+struct StagedBalance {
+    account: OrderedPubAccount,
+    balance: EncryptedAmount,
+    update: AccountUpdate,
+}
+
+impl StagedBalance {
+    /// Writes the account and the balance files under their staged names. Returns the paths of
+    /// the staged files and the paths that they replace.
+    fn write(&self, db_dir: ChainDirs) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+        let (user, ticker) = (&self.update.user, &self.update.ticker);
+        let account_file = user_public_account_file(ticker);
+        let balance_file = user_public_account_balance_file(ticker);
+        save_object(
             db_dir.clone(),
             ON_CHAIN_DIR,
-            &user,
-            &user_public_account_file(&ticker),
+            user,
+            &staged_file(&account_file),
+            &self.account,
         )?;
-        let mut new_balance: EncryptedAmount = load_object(
+        save_object(
             db_dir.clone(),
             ON_CHAIN_DIR,
-            &user,
-            &user_public_account_balance_file(&ticker),
+            user,
+            &staged_file(&balance_file),
+            &self.balance,
         )?;
-        debug!(
-            "------------> Validation complete, updating {}-{}. Starting balance: {}",
-            &user,
-            &ticker,
-            debug_decrypt(
-                ordered_pub_account.pub_account.enc_asset_id,
-                new_balance.clone(),
-                db_dir.clone()
-            )?
-        );
-        for result in results.clone() {
-            if result.user == user && result.ticker == ticker {
-                match result.direction {
-                    Direction::Incoming => {
-                        if let Some(amount) = result.amount {
-                            debug!(
-                                "---------------------> updating {}-{} increasing by {}",
-                                &user,
-                                &ticker,
-                                debug_decrypt(
-                                    ordered_pub_account.pub_account.enc_asset_id,
-                                    amount.clone(),
-                                    db_dir.clone()
-                                )?
-                            );
-                            new_balance += amount.clone();
-                        } else {
-                            // based on the reason and the strategy, we can break the loop or ignore
-                            // TODO: add strategy selection to the config. CRYP-132
-                        }
+        [account_file, balance_file]
+            .iter()
+            .map(|file_name| -> Result<(PathBuf, PathBuf), Error> {
+                Ok((
+                    construct_path(db_dir.clone(), ON_CHAIN_DIR, user, &staged_file(file_name))?,
+                    construct_path(db_dir.clone(), ON_CHAIN_DIR, user, file_name)?,
+                ))
+            })
+            .collect()
+    }
+}
+
+#[inline]
+fn staged_file(file_name: &str) -> String {
+    format!("{}{}", file_name, STAGED_SUFFIX)
+}
+
+/// Writes the `staged` balances of a ticker. All the files are written under their staged names
+/// first, and only renamed once all of them are written, so that a failed write leaves every
+/// account of the ticker unchanged. The renames are not atomic as a group, so a failure between
+/// two of them still leaves the ticker partially updated.
+fn save_staged_balances(
+    db_dir: ChainDirs,
+    staged: Vec<StagedBalance>,
+) -> Result<Vec<AccountUpdate>, Error> {
+    let mut renames: Vec<(PathBuf, PathBuf)> = Vec::with_capacity(2 * staged.len());
+    let written = staged.iter().try_for_each(|staged| -> Result<(), Error> {
+        renames.extend(staged.write(db_dir.clone())?);
+        Ok(())
+    });
+    if let Err(error) = written {
+        for (staged_path, _) in &renames {
+            let _ = std::fs::remove_file(staged_path);
+        }
+        return Err(error);
+    }
+    for (staged_path, path) in renames {
+        std::fs::rename(&staged_path, &path).map_err(|error| Error::FileCreationError {
+            error,
+            path: path.clone(),
+        })?;
+    }
+    Ok(staged.into_iter().map(|staged| staged.update).collect())
+}
+
+/// Computes the balance of the `user`'s `ticker` account after the changes of the `results`,
+/// with its processed counter moved to `last_tx_id`. Nothing is written until the returned
+/// balance is saved. Fails with `CounterRegression` if that moves the counter back, unless
+/// `allow_regress` is set. With a `debug_balances` key directory, the balances are decrypted
+/// and checked to never become negative.
+fn stage_balance_update(
+    db_dir: ChainDirs,
+    user: &str,
+    ticker: &String,
+    results: &[ValidationResult],
+    last_tx_id: Option<u32>,
+    allow_regress: bool,
+    debug_balances: Option<&PathBuf>,
+) -> Result<StagedBalance, Error> {
+    let ordered_pub_account: OrderedPubAccount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        user,
+        &user_public_account_file(ticker),
+    )?;
+    if let (Some(old), Some(new)) = (ordered_pub_account.last_processed_tx_counter, last_tx_id) {
+        if new < old && !allow_regress {
+            return Err(Error::CounterRegression {
+                user: user.to_string(),
+                ticker: ticker.clone(),
+                old,
+                new,
+            });
+        }
+    }
+    let mut new_balance: EncryptedAmount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        user,
+        &user_public_account_balance_file(ticker),
+    )?;
+    // With `--debug-balances`, the running balance is tracked in plain text to make sure
+    // that the outgoing transfers never exceed the starting balance plus the incoming
+    // transfers of this batch. The check needs the decrypted amounts, so without the flag it
+    // does not run: no secret key is loaded and only the range proofs of the transfers count.
+    let decrypt = |amount: &EncryptedAmount| {
+        debug_decrypt_balance(
+            ordered_pub_account.pub_account.enc_asset_id,
+            amount.clone(),
+            db_dir.clone(),
+            debug_balances,
+        )
+    };
+    let balance_before = decrypt(&new_balance)?;
+    let mut running_balance: Option<i64> = balance_before.map(i64::from);
+    debug!(
+        "------------> Validation complete, updating {}-{}. Starting balance: {:?}",
+        user, ticker, running_balance
+    );
+    // The results are applied in the order of their tx_ids, and not in the order in which the
+    // transaction files were listed, so that a credit is applied before a later spend of it.
+    let mut account_results: Vec<&ValidationResult> = results
+        .iter()
+        .filter(|result| result.user == user && &result.ticker == ticker)
+        .collect();
+    account_results.sort_by_key(|result| result.tx_id);
+    for result in account_results {
+        match result.direction {
+            Direction::Incoming => {
+                if let Some(amount) = &result.amount {
+                    if let (Some(running_balance), Some(plain_amount)) =
+                        (running_balance.as_mut(), decrypt(amount)?)
+                    {
+                        debug!(
+                            "---------------------> updating {}-{} increasing by {}",
+                            user, ticker, plain_amount
+                        );
+                        *running_balance += i64::from(plain_amount);
                     }
-                    Direction::Outgoing => {
-                        if let Some(amount) = result.amount {
-                            debug!(
-                                "---------------------> updating {}-{} decreasing by {}",
-                                &user,
-                                &ticker,
-                                debug_decrypt(
-                                    ordered_pub_account.pub_account.enc_asset_id,
-                                    amount.clone(),
-                                    db_dir.clone()
-                                )?
-                            );
-                            new_balance -= amount.clone();
-                        } else {
-                            // based on the reason and the strategy, we can break the loop or ignore
+                    new_balance += amount.clone();
+                } else {
+                    // based on the reason and the strategy, we can break the loop or ignore
+                    // TODO: add strategy selection to the config. CRYP-132
+                }
+            }
+            Direction::Outgoing => {
+                if let Some(amount) = &result.amount {
+                    if let (Some(running_balance), Some(plain_amount)) =
+                        (running_balance.as_mut(), decrypt(amount)?)
+                    {
+                        debug!(
+                            "---------------------> updating {}-{} decreasing by {}",
+                            user, ticker, plain_amount
+                        );
+                        *running_balance -= i64::from(plain_amount);
+                        if *running_balance < 0 {
+                            return Err(Error::NegativeBalanceDuringReduce {
+                                user: user.to_string(),
+                                ticker: ticker.to_string(),
+                                tx_id: result.tx_id,
+                            });
                         }
                     }
+                    new_balance -= amount.clone();
+                } else {
+                    // based on the reason and the strategy, we can break the loop or ignore
                 }
             }
         }
+    }
 
-        save_object(
-            db_dir.clone(),
-            ON_CHAIN_DIR,
-            &user,
-            &user_public_account_file(&ticker),
-            &OrderedPubAccount {
-                last_processed_tx_counter: last_tx_id,
-                pub_account: PubAccount {
-                    enc_asset_id: ordered_pub_account.pub_account.enc_asset_id,
-                    owner_enc_pub_key: ordered_pub_account.pub_account.owner_enc_pub_key,
-                },
+    let balance_after = decrypt(&new_balance)?;
+    Ok(StagedBalance {
+        account: OrderedPubAccount {
+            last_processed_tx_counter: last_tx_id,
+            pub_account: PubAccount {
+                enc_asset_id: ordered_pub_account.pub_account.enc_asset_id,
+                owner_enc_pub_key: ordered_pub_account.pub_account.owner_enc_pub_key,
             },
-        )?;
-        save_object(
-            db_dir.clone(),
-            ON_CHAIN_DIR,
-            &user,
-            &user_public_account_balance_file(&ticker),
-            &new_balance,
-        )?;
-    }
+        },
+        balance: new_balance,
+        update: AccountUpdate {
+            user: user.to_string(),
+            ticker: ticker.clone(),
+            balance_before,
+            balance_after,
+        },
+    })
+}
+
+#[inline]
+pub fn ticker_last_validated_tx_id_file(ticker: &str) -> String {
+    format!("{}{}.json", LAST_VALIDATED_TX_ID_PREFIX, ticker)
+}
+
+/// Returns the tx_id of the last transaction of the `ticker` whose balance changes were applied,
+/// or `None` if there is none yet.
+pub fn last_validated_tx_id_of_ticker(db_dir: ChainDirs, ticker: &str) -> Option<u32> {
+    load_from_file(
+        db_dir,
+        VALIDATOR_DIR,
+        COMMON_OBJECTS_DIR,
+        &ticker_last_validated_tx_id_file(ticker),
+    )
+    .ok()
+}
 
+fn save_ticker_last_validated_tx_id(
+    db_dir: ChainDirs,
+    ticker: &str,
+    last_tx_id: u32,
+) -> Result<(), Error> {
     save_to_file(
         db_dir,
-        OFF_CHAIN_DIR,
+        VALIDATOR_DIR,
         COMMON_OBJECTS_DIR,
-        LAST_VALIDATED_TX_ID_FILE,
+        &ticker_last_validated_tx_id_file(ticker),
         &last_tx_id,
+    )
+}
+
+/// Asks the `approver` about the verified transactions. The denied ones are quarantined and
+/// their results are turned into errors, so that their balance changes are skipped, while the
+/// counters of the accounts still move past them.
+fn deny_unapproved(
+    db_dir: ChainDirs,
+    results: &mut [ValidationResult],
+    approver: Approver,
+) -> Result<(), Error> {
+    let mut denied: BTreeMap<u32, RejectionReason> = BTreeMap::new();
+    for result in results.iter().filter(|result| result.amount.is_some()) {
+        if denied.contains_key(&result.tx_id) {
+            continue;
+        }
+        if let Decision::Deny(reason) = approver(result) {
+            info!("tx-{}: Denied by the approver: {}", result.tx_id, reason);
+            denied.insert(result.tx_id, reason);
+        }
+    }
+
+    for (tx_id, reason) in denied {
+        let users: BTreeSet<String> = results
+            .iter()
+            .filter(|result| result.tx_id == tx_id)
+            .map(|result| result.user.clone())
+            .collect();
+        quarantine_tx(db_dir.clone(), tx_id, &users, reason)?;
+        for result in results.iter_mut().filter(|result| result.tx_id == tx_id) {
+            result.amount = None;
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if the instruction in the `state` is the output of a successful validation.
+pub(crate) fn is_validated_state(state: &str) -> bool {
+    state == AssetTxState::Justification(TxSubstate::Validated).to_string()
+        || state == TransferTxState::Justification(TxSubstate::Validated).to_string()
+        || state == CREDIT_VALIDATED_STATE
+}
+
+/// Returns true if an earlier run already saved the validated instruction `file_name` in the
+/// on-chain directory `dir`. That run could not update the balances of the ticker, so the
+/// transaction is validated again, but its instruction and its audit record are not repeated.
+fn is_already_validated(db_dir: ChainDirs, dir: &str, file_name: &str) -> Result<bool, Error> {
+    Ok(construct_path(db_dir, ON_CHAIN_DIR, dir, file_name)?.exists())
+}
+
+/// Moves the validated instructions of the transaction, from the common directory and the
+/// directories of its `users`, to the quarantine directory and records the `reason`.
+fn quarantine_tx(
+    db_dir: ChainDirs,
+    tx_id: u32,
+    users: &BTreeSet<String>,
+    reason: RejectionReason,
+) -> Result<(), Error> {
+    save_to_file(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        QUARANTINE_DIR,
+        &quarantine_file(tx_id),
+        &QuarantinedTransaction { tx_id, reason },
     )?;
+
+    let prefix = format!("tx_{}_", tx_id);
+    for dir in std::iter::once(COMMON_OBJECTS_DIR).chain(users.iter().map(String::as_str)) {
+        let dir_path = construct_path(db_dir.clone(), ON_CHAIN_DIR, dir, "")?;
+        if !dir_path.is_dir() {
+            continue;
+        }
+        let entries = std::fs::read_dir(&dir_path).map_err(|error| Error::FileReadError {
+            error,
+            path: dir_path.clone(),
+        })?;
+        for entry in entries {
+            let path = entry
+                .map_err(|error| Error::FileReadError {
+                    error,
+                    path: dir_path.clone(),
+                })?
+                .path();
+            let file_name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or(Error::PathBufConversionError)?
+                .to_string();
+            let state = match parse_tx_name(file_name.clone()) {
+                Ok((_, _, state, _)) if file_name.starts_with(&prefix) => state,
+                _ => continue,
+            };
+            if !is_validated_state(&state) {
+                continue;
+            }
+            let quarantined_path =
+                construct_path(db_dir.clone(), ON_CHAIN_DIR, QUARANTINE_DIR, &file_name)?;
+            std::fs::rename(&path, &quarantined_path).map_err(|error| {
+                Error::FileCreationError {
+                    error,
+                    path: quarantined_path.clone(),
+                }
+            })?;
+            record_state_transition(
+                db_dir.clone(),
+                tx_id,
+                VALIDATOR,
+                Some(state.as_str()),
+                QUARANTINED_STATE,
+            )?;
+        }
+    }
     Ok(())
 }
 
+/// Returns the accounts that the validation `results` refer to. They are ordered by the user
+/// and the ticker, so that the balances are updated, and logged, in the same order on every run.
+/// The names are only copied once per account, not once per result.
+pub fn accounts_to_update(results: &[ValidationResult]) -> Vec<(String, String)> {
+    let mut accounts: Vec<(&str, &str)> = Vec::with_capacity(results.len());
+    accounts.extend(
+        results
+            .iter()
+            .filter(|result| result.user != "n/a")
+            .map(|result| (result.user.as_str(), result.ticker.as_str())),
+    );
+    accounts.sort_unstable();
+    accounts.dedup();
+    accounts
+        .into_iter()
+        .map(|(user, ticker)| (user.to_string(), ticker.to_string()))
+        .collect()
+}
+
+/// Validates an issuance. The outcome of the verification is looked up in and added to the
+/// `cache`. The proofs are not verified if `tx_id` is before `assume_valid_before`.
 pub fn validate_asset_issuance(
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     amount: u32,
     asset_tx: InitializedAssetTx,
     tx_id: u32,
+    cache: &mut VerificationCache,
+    assume_valid_before: Option<u32>,
 ) -> ValidationResult {
     let load_objects_timer = Instant::now();
 
     let issuer_account_id = asset_tx.account_id;
-    let res = get_user_ticker_from(issuer_account_id, db_dir.clone());
+    let res = get_checked_user_ticker_from(issuer_account_id, db_dir.clone());
     if let Err(error) = res {
         error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-        return ValidationResult::error("n/a", "n/a");
+        return ValidationResult::error("n/a", "n/a", tx_id);
     }
     let (issuer, ticker, _) = res.unwrap();
     info!(
         "Validating asset issuance{{tx_id: {}, issuer: {}, ticker: {}}}",
         tx_id, issuer, ticker,
     );
+    if let Err(error) = ensure_amount_in_ticker_range(db_dir.clone(), &ticker, amount) {
+        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+        return ValidationResult::error(&issuer, &ticker, tx_id);
+    }
+    if amount == 0 {
+        error!(
+            "Error in validation of tx-{}: {:#?}",
+            tx_id,
+            Error::ZeroIssuanceAmount { tx_id }
+        );
+        return ValidationResult::error(&issuer, &ticker, tx_id);
+    }
+    if let Err(error) = ensure_issuance_policy(db_dir.clone(), &ticker, &issuer, amount, tx_id)
+        .and_then(|_| ensure_not_closed(db_dir.clone(), &issuer, &ticker))
+    {
+        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+        return ValidationResult::error(&issuer, &ticker, tx_id);
+    }
 
     let issuer_ordered_pub_account: Result<OrderedPubAccount, Error> = load_object(
         db_dir.clone(),
@@ -261,7 +1021,7 @@ pub fn validate_asset_issuance(
     );
     if let Err(error) = issuer_ordered_pub_account {
         error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-        return ValidationResult::error(&issuer, &ticker);
+        return ValidationResult::error(&issuer, &ticker, tx_id);
     }
     let issuer_ordered_pub_account = issuer_ordered_pub_account.unwrap();
 
@@ -273,7 +1033,7 @@ pub fn validate_asset_issuance(
     );
     if let Err(error) = issuer_account_balance {
         error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-        return ValidationResult::error(&issuer, &ticker);
+        return ValidationResult::error(&issuer, &ticker, tx_id);
     }
     let issuer_account_balance = issuer_account_balance.unwrap();
 
@@ -287,31 +1047,40 @@ pub fn validate_asset_issuance(
     let validate_issuance_transaction_timer = Instant::now();
 
     let validator = AssetValidator;
+    let issuance_hash = content_hash(&[
+        &asset_tx.encode(),
+        &amount.encode(),
+        &issuer_ordered_pub_account.pub_account.encode(),
+        &issuer_account_balance.encode(),
+    ]);
     // TODO: CRYP-165: This requires more work to handle properly. At the moment, I am ignoring the the balance returned.
-    let _ = match validator
-        .verify_asset_transaction(
-            amount,
-            &asset_tx,
-            &issuer_ordered_pub_account.pub_account,
-            &issuer_account_balance,
-            &[],
-        )
-        .map_err(|error| Error::LibraryError { error })
-    {
-        Err(error) => {
-            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-            return ValidationResult::error(&issuer, &ticker);
-        }
-        Ok(pub_account) => pub_account,
-    };
+    let verified =
+        verify_unless_assumed_valid(cache, assume_valid_before, tx_id, issuance_hash, || {
+            validator
+                .verify_asset_transaction(
+                    amount,
+                    &asset_tx,
+                    &issuer_ordered_pub_account.pub_account,
+                    &issuer_account_balance,
+                    &[],
+                )
+                .map(|_| ())
+                .map_err(|error| Error::LibraryError { error })
+        });
 
     timing!(
         "validator.issuance.transaction",
         validate_issuance_transaction_timer,
         Instant::now(),
-        "tx_id" => tx_id.to_string()
+        "tx_id" => tx_id.to_string(),
+        "result" => outcome_label(&verified)
     );
 
+    if let Err(error) = verified {
+        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+        return ValidationResult::error(&issuer, &ticker, tx_id);
+    }
+
     let save_objects_timer = Instant::now();
     // Save the transaction under the new state.
     let new_state = AssetTxState::Justification(TxSubstate::Validated);
@@ -319,15 +1088,46 @@ pub fn validate_asset_issuance(
         state: new_state,
         data: asset_tx.encode().to_vec(),
     };
-    if let Err(error) = save_object(
+    if let Err(error) = is_already_validated(
         db_dir.clone(),
-        ON_CHAIN_DIR,
         &issuer,
-        &asset_transaction_file(tx_id, &issuer, new_state),
-        &instruction,
-    ) {
+        &asset_transaction_file(tx_id, &issuer, &ticker, new_state),
+    )
+    .and_then(|already_validated| {
+        if already_validated {
+            return Ok(());
+        }
+        save_instruction(
+            db_dir.clone(),
+            &issuer,
+            tx_id,
+            &ticker,
+            &issuer,
+            &instruction,
+        )
+        .and_then(|_| {
+            record_state_transition(
+                db_dir.clone(),
+                tx_id,
+                VALIDATOR,
+                Some(AssetTxState::Initialization(TxSubstate::Started)),
+                new_state,
+            )
+        })
+    })
+    .and_then(|_| {
+        record_issuance(
+            db_dir.clone(),
+            &ticker,
+            IssuanceRecord {
+                tx_id,
+                issuer: issuer.clone(),
+                enc_issued_amount: asset_tx.memo.enc_issued_amount,
+            },
+        )
+    }) {
         error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-        return ValidationResult::error(&issuer, &ticker);
+        return ValidationResult::error(&issuer, &ticker, tx_id);
     }
 
     timing!(
@@ -342,29 +1142,121 @@ pub fn validate_asset_issuance(
         ticker,
         amount: Some(asset_tx.memo.enc_issued_amount),
         direction: Direction::Incoming,
+        tx_id,
     }
 }
 
-pub fn validate_account(db_dir: PathBuf, account_id: EncryptedAssetId) -> Result<(), Error> {
-    // Load the user's public account.
-    let load_objects_timer = Instant::now();
-
-    let (user, ticker, tx_id) = get_user_ticker_from(account_id, db_dir.clone())?;
-    info!(
-        "Validating account{{tx_id: {}, account_id: {}, user: {}, ticker: {}}}",
+/// Validates a mediator credit. The credited amount is only added to the beneficiary's balance
+/// if the mediator is authorized for the ticker and the signature and the proof verify.
+pub fn validate_mediator_credit(
+    db_dir: ChainDirs,
+    credit: SignedMediatorCredit,
+    mediator: String,
+    tx_id: u32,
+) -> ValidationResult {
+    let validate_credit_timer = Instant::now();
+    let verified = verify_mediator_credit(db_dir.clone(), &credit, &mediator, tx_id);
+    timing!(
+        "validator.credit",
+        validate_credit_timer,
+        Instant::now(),
+        "tx_id" => tx_id.to_string(),
+        "result" => outcome_label(&verified)
+    );
+    let (beneficiary, ticker) = match verified {
+        Err(error) => {
+            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+            return ValidationResult::error("n/a", "n/a", tx_id);
+        }
+        Ok(ok) => ok,
+    };
+    info!(
+        "Validating mediator credit{{tx_id: {}, mediator: {}, beneficiary: {}, ticker: {}}}",
+        tx_id, mediator, beneficiary, ticker,
+    );
+    if let Err(error) = ensure_not_closed(db_dir.clone(), &beneficiary, &ticker) {
+        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+        return ValidationResult::error(&beneficiary, &ticker, tx_id);
+    }
+
+    // Save the credit under the new state.
+    let credit_file = mediator_credit_file(tx_id, &mediator, &ticker, CREDIT_VALIDATED_STATE);
+    if let Err(error) = is_already_validated(db_dir.clone(), &beneficiary, &credit_file).and_then(
+        |already_validated| {
+            if already_validated {
+                return Ok(());
+            }
+            save_object(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                &beneficiary,
+                &credit_file,
+                &credit,
+            )
+            .and_then(|_| {
+                record_state_transition(
+                    db_dir.clone(),
+                    tx_id,
+                    VALIDATOR,
+                    Some(CREDIT_STARTED_STATE),
+                    CREDIT_VALIDATED_STATE,
+                )
+            })
+        },
+    ) {
+        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+        return ValidationResult::error(&beneficiary, &ticker, tx_id);
+    }
+
+    ValidationResult {
+        user: beneficiary,
+        ticker,
+        amount: Some(credit.credit.enc_amount),
+        direction: Direction::Incoming,
+        tx_id,
+    }
+}
+
+pub fn validate_account(db_dir: ChainDirs, account_id: EncryptedAssetId) -> Result<(), Error> {
+    // The public account is only saved once it is validated, so the mapping cannot be
+    // cross-checked here.
+    let (user, ticker, tx_id) = get_user_ticker_from(account_id, db_dir.clone())?;
+    info!(
+        "Validating account{{tx_id: {}, account_id: {}, user: {}, ticker: {}}}",
         tx_id,
         PrintableAccountId(account_id.encode()),
         user,
         ticker
     );
+    let valid_asset_ids = get_asset_ids(db_dir.clone())?;
+    verify_and_save_account(db_dir, &user, &ticker, tx_id, &valid_asset_ids)
+}
+
+/// Runs the cryptographic verification of the account transaction.
+fn verify_account_tx(
+    ordered_account_tx: &OrderedPubAccountTx,
+    valid_asset_ids: &[Scalar],
+) -> Result<(), Error> {
+    AccountValidator {}
+        .verify(&ordered_account_tx.account_tx, valid_asset_ids)
+        .map_err(|error| Error::LibraryError { error })
+}
+
+fn verify_and_save_account(
+    db_dir: ChainDirs,
+    user: &String,
+    ticker: &String,
+    tx_id: u32,
+    valid_asset_ids: &[Scalar],
+) -> Result<(), Error> {
+    // Load the user's public account.
+    let load_objects_timer = Instant::now();
     let ordered_user_account_tx: OrderedPubAccountTx = load_object(
         db_dir.clone(),
         ON_CHAIN_DIR,
         COMMON_OBJECTS_DIR,
-        &account_create_transaction_file(tx_id, &user, &ticker),
+        &account_create_transaction_file(tx_id, user, ticker),
     )?;
-
-    let valid_asset_ids = get_asset_ids(db_dir.clone())?;
     timing!(
         "validator.account.load_objects",
         load_objects_timer,
@@ -372,19 +1264,31 @@ pub fn validate_account(db_dir: PathBuf, account_id: EncryptedAssetId) -> Result
         "tx_id" => tx_id.to_string()
     );
 
-    // Validate the account.
+    // Validate the account. The ticker is checked first, since it does not need any curve
+    // operations.
     let validate_account_timer = Instant::now();
-    let account_validator = AccountValidator {};
-    account_validator
-        .verify(&ordered_user_account_tx.account_tx, &valid_asset_ids)
-        .map_err(|error| Error::LibraryError { error })?;
+    let verified = asset_id_from_ticker(ticker)
+        .map_err(|error| Error::LibraryError { error })
+        .and_then(|asset_id| {
+            let asset_id: Scalar = asset_id.into();
+            if valid_asset_ids.contains(&asset_id) {
+                Ok(())
+            } else {
+                Err(Error::TickerNotAllowed {
+                    ticker: ticker.clone(),
+                })
+            }
+        })
+        .and_then(|_| verify_account_tx(&ordered_user_account_tx, valid_asset_ids));
 
     timing!(
         "validator.account",
         validate_account_timer,
         Instant::now(),
-        "tx_id" => tx_id.to_string()
+        "tx_id" => tx_id.to_string(),
+        "result" => outcome_label(&verified)
     );
+    verified?;
 
     // On success save the public account as validated.
     let save_objects_timer = Instant::now();
@@ -395,17 +1299,24 @@ pub fn validate_account(db_dir: PathBuf, account_id: EncryptedAssetId) -> Result
     save_object(
         db_dir.clone(),
         ON_CHAIN_DIR,
-        &user,
-        &user_public_account_file(&ticker),
+        user,
+        &user_public_account_file(ticker),
         &ordered_account,
     )?;
     save_object(
-        db_dir,
+        db_dir.clone(),
         ON_CHAIN_DIR,
-        &user,
-        &user_public_account_balance_file(&ticker),
+        user,
+        &user_public_account_balance_file(ticker),
         &ordered_user_account_tx.account_tx.initial_balance,
     )?;
+    save_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        user,
+        &registry_commitment_file(ticker),
+        &registry_commitment(valid_asset_ids),
+    )?;
 
     timing!(
         "validator.account.save_objects",
@@ -419,62 +1330,197 @@ pub fn validate_account(db_dir: PathBuf, account_id: EncryptedAssetId) -> Result
 
 fn process_transaction(
     instruction: TransferInstruction,
+    instruction_path: PathBuf,
     sender_pub_account: PubAccount,
     receiver_pub_account: PubAccount,
-    pending_balance: EncryptedAmount,
+    pending_balance: PendingBalance,
+) -> Result<(), Error> {
+    let tx = JustifiedTransferTx::decode(&mut &instruction.data[..]).map_err(|error| {
+        Error::ObjectLoadError {
+            error,
+            path: instruction_path,
+        }
+    })?;
+    verify_transfer_proofs(
+        &tx,
+        &sender_pub_account,
+        pending_balance.enc_amount(),
+        &receiver_pub_account,
+    )
+}
+
+fn verify_transfer_proofs(
+    tx: &JustifiedTransferTx,
+    sender_pub_account: &PubAccount,
+    pending_balance: &EncryptedAmount,
+    receiver_pub_account: &PubAccount,
 ) -> Result<(), Error> {
     let mut rng = OsRng::default();
-    let tx = JustifiedTransferTx::decode(&mut &instruction.data[..]).unwrap();
-    let validator = TransactionValidator;
-    validator
+    TransactionValidator
         .verify_transaction(
-            &tx,
-            &sender_pub_account,
-            &pending_balance,
-            &receiver_pub_account,
+            tx,
+            sender_pub_account,
+            pending_balance,
+            receiver_pub_account,
             &[],
             &mut rng,
         )
         .map_err(|error| Error::LibraryError { error })
 }
 
+/// An analysis tool, not a validation. Verifies the proofs of the transfer as if the sender's
+/// pending balance was `pending_balance_override`, e.g., to confirm that the range proof depends
+/// on the pending balance. The pending balance is not computed and the override is not checked
+/// against it. Nothing is saved, and the balances and the last validated tx_id are not changed.
+pub fn validate_transaction_with_pending(
+    db_dir: ChainDirs,
+    tx: &JustifiedTransferTx,
+    pending_balance_override: EncryptedAmount,
+) -> Result<(), Error> {
+    let memo = &tx.finalized_data.init_data.memo;
+    let (sender, sender_ticker, _) =
+        get_checked_user_ticker_from(memo.sender_account_id, db_dir.clone())?;
+    let (receiver, receiver_ticker, _) =
+        get_checked_user_ticker_from(memo.receiver_account_id, db_dir.clone())?;
+    if sender_ticker != receiver_ticker {
+        return Err(Error::TickerMismatch {
+            sender_ticker,
+            receiver_ticker,
+        });
+    }
+    let sender_ordered_pub_account: OrderedPubAccount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        &sender,
+        &user_public_account_file(&sender_ticker),
+    )?;
+    let receiver_ordered_pub_account: OrderedPubAccount = load_object(
+        db_dir,
+        ON_CHAIN_DIR,
+        &receiver,
+        &user_public_account_file(&receiver_ticker),
+    )?;
+    verify_transfer_proofs(
+        tx,
+        &sender_ordered_pub_account.pub_account,
+        &pending_balance_override,
+        &receiver_ordered_pub_account.pub_account,
+    )
+}
+
+/// Computes the sender's pending balance at the time of creating the transaction `tx_id`.
+pub fn sender_pending_balance(
+    db_dir: ChainDirs,
+    sender: &String,
+    ticker: &String,
+    tx_id: u32,
+) -> Result<PendingBalance, Error> {
+    let sender_ordered_pub_account: OrderedPubAccount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        sender,
+        &user_public_account_file(ticker),
+    )?;
+    let sender_account_balance: EncryptedAmount = load_object(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        sender,
+        &user_public_account_balance_file(ticker),
+    )?;
+    let ordering_state = last_ordering_state(
+        sender.clone(),
+        sender_ordered_pub_account.last_processed_tx_counter,
+        tx_id,
+        db_dir.clone(),
+    )?;
+    compute_enc_pending_balance(
+        sender,
+        ticker,
+        ordering_state,
+        sender_ordered_pub_account.last_processed_tx_counter,
+        ConfirmedBalance(sender_account_balance),
+        0,
+        db_dir,
+    )
+}
+
+/// Recomputes the sender's pending balance and checks that it equals the one that the
+/// transaction is about to be verified against.
+fn ensure_pending_balance_matches(
+    db_dir: ChainDirs,
+    sender: &String,
+    ticker: &String,
+    pending_balance: &PendingBalance,
+    tx_id: u32,
+) -> Result<(), Error> {
+    let recomputed = sender_pending_balance(db_dir, sender, ticker, tx_id)?;
+    if recomputed.enc_amount().encode() != pending_balance.enc_amount().encode() {
+        return Err(Error::PendingBalanceMismatch { tx_id });
+    }
+    Ok(())
+}
+
+/// The structural checks of a transfer. They do not allocate or use any curve operations, and
+/// therefore run before the pending balance is recomputed and before the proof verification.
+/// The pending balance that the caller passes to `validate_transaction` is computed earlier.
+fn check_transfer_structure(
+    tx: &JustifiedTransferTx,
+    instruction: &TransferInstruction,
+    (sender, sender_ticker, sender_account): (&str, &str, &PubAccount),
+    (receiver, receiver_ticker, receiver_account): (&str, &str, &PubAccount),
+    tx_id: u32,
+) -> Result<(), Error> {
+    if instruction.state != TransferTxState::Justification(TxSubstate::Started) {
+        return Err(Error::UnexpectedTransactionState {
+            tx_id,
+            state: instruction.state.to_string(),
+        });
+    }
+    if sender_ticker != receiver_ticker {
+        return Err(Error::TickerMismatch {
+            sender_ticker: sender_ticker.to_string(),
+            receiver_ticker: receiver_ticker.to_string(),
+        });
+    }
+    let memo = &tx.finalized_data.init_data.memo;
+    if memo.sender_account_id != sender_account.enc_asset_id {
+        return Err(Error::AccountIdMismatch {
+            tx_id,
+            user: sender.to_string(),
+        });
+    }
+    if memo.receiver_account_id != receiver_account.enc_asset_id {
+        return Err(Error::AccountIdMismatch {
+            tx_id,
+            user: receiver.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Validates a justified transfer against the sender's `pending_balance`. The outcome of the
+/// verification is looked up in and added to the `cache`. The proofs are not verified if
+/// `tx_id` is before `assume_valid_before`.
 pub fn validate_transaction(
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     tx: JustifiedTransferTx,
     mediator: String,
-    pending_balance: EncryptedAmount,
+    pending_balance: PendingBalance,
     tx_id: u32,
-) -> (ValidationResult, ValidationResult) {
+    cache: &mut VerificationCache,
+    assume_valid_before: Option<u32>,
+) -> Result<(ValidationResult, ValidationResult), Error> {
     let load_objects_timer = Instant::now();
     // Load the transaction, mediator's account, and issuer's public account.
 
-    let (sender, _, _) = match get_user_ticker_from(
+    let (sender, sender_ticker, _) = get_checked_user_ticker_from(
         tx.finalized_data.init_data.memo.sender_account_id,
         db_dir.clone(),
-    ) {
-        Err(error) => {
-            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-            return (
-                ValidationResult::error("n/a", "n/a"),
-                ValidationResult::error("n/a", "n/a"),
-            );
-        }
-        Ok(ok) => ok,
-    };
-
-    let (receiver, ticker, _) = match get_user_ticker_from(
+    )?;
+    let (receiver, ticker, _) = get_checked_user_ticker_from(
         tx.finalized_data.init_data.memo.receiver_account_id,
         db_dir.clone(),
-    ) {
-        Err(error) => {
-            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-            return (
-                ValidationResult::error("n/a", "n/a"),
-                ValidationResult::error("n/a", "n/a"),
-            );
-        }
-        Ok(ok) => ok,
-    };
+    )?;
 
     info!(
         "Validating asset transfer{{tx_id: {}, sender: {}, receiver: {}, ticker:{}, mediator: {}}}",
@@ -482,53 +1528,26 @@ pub fn validate_transaction(
     );
     let state = TransferTxState::Justification(TxSubstate::Started);
 
-    let mut instruction: TransferInstruction = match load_object(
+    let mut instruction: TransferInstruction = load_object(
         db_dir.clone(),
         ON_CHAIN_DIR,
         COMMON_OBJECTS_DIR,
-        &confidential_transaction_file(tx_id, &mediator, state),
-    ) {
-        Err(error) => {
-            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-            return (
-                ValidationResult::error(&sender, &ticker),
-                ValidationResult::error(&receiver, &ticker),
-            );
-        }
-        Ok(ok) => ok,
-    };
+        &confidential_transaction_file(tx_id, &mediator, &ticker, state),
+    )?;
 
-    let sender_ordered_pub_account: OrderedPubAccount = match load_object(
+    let sender_ordered_pub_account: OrderedPubAccount = load_object(
         db_dir.clone(),
         ON_CHAIN_DIR,
         &sender,
         &user_public_account_file(&ticker),
-    ) {
-        Err(error) => {
-            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-            return (
-                ValidationResult::error(&sender, &ticker),
-                ValidationResult::error(&receiver, &ticker),
-            );
-        }
-        Ok(ok) => ok,
-    };
+    )?;
 
-    let receiver_ordered_pub_account: OrderedPubAccount = match load_object(
+    let receiver_ordered_pub_account: OrderedPubAccount = load_object(
         db_dir.clone(),
         ON_CHAIN_DIR,
         &receiver,
         &user_public_account_file(&ticker),
-    ) {
-        Err(error) => {
-            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-            return (
-                ValidationResult::error(&sender, &ticker),
-                ValidationResult::error(&receiver, &ticker),
-            );
-        }
-        Ok(ok) => ok,
-    };
+    )?;
 
     timing!(
         "validator.issuance.load_objects",
@@ -537,45 +1556,118 @@ pub fn validate_transaction(
         "tx_id" => tx_id.to_string()
     );
 
-    let validate_transaction_timer = Instant::now();
-    let _result = match process_transaction(
-        instruction.clone(),
-        sender_ordered_pub_account.pub_account,
-        receiver_ordered_pub_account.pub_account,
-        pending_balance,
+    // Fail on the cheapest checks first.
+    if let Err(error) = ensure_not_closed(db_dir.clone(), &sender, &ticker)
+        .and_then(|_| ensure_not_closed(db_dir.clone(), &receiver, &ticker))
+    {
+        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+        return Ok((
+            ValidationResult::error(&sender, &ticker, tx_id),
+            ValidationResult::error(&receiver, &ticker, tx_id),
+        ));
+    }
+    if let Err(error) = check_transfer_structure(
+        &tx,
+        &instruction,
+        (
+            &sender,
+            &sender_ticker,
+            &sender_ordered_pub_account.pub_account,
+        ),
+        (
+            &receiver,
+            &ticker,
+            &receiver_ordered_pub_account.pub_account,
+        ),
+        tx_id,
     ) {
-        Err(error) => {
-            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-            return (
-                ValidationResult::error(&sender, &ticker),
-                ValidationResult::error(&receiver, &ticker),
-            );
-        }
-        Ok(ok) => ok,
-    };
+        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+        return Ok((
+            ValidationResult::error(&sender, &ticker, tx_id),
+            ValidationResult::error(&receiver, &ticker, tx_id),
+        ));
+    }
+
+    // TODO: CRYP-131: Remove this check once the pending balance computation is refactored.
+    if let Err(error) =
+        ensure_pending_balance_matches(db_dir.clone(), &sender, &ticker, &pending_balance, tx_id)
+    {
+        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+        return Ok((
+            ValidationResult::error(&sender, &ticker, tx_id),
+            ValidationResult::error(&receiver, &ticker, tx_id),
+        ));
+    }
+
+    let validate_transaction_timer = Instant::now();
+    let transfer_hash = content_hash(&[
+        &instruction.data,
+        &sender_ordered_pub_account.pub_account.encode(),
+        &receiver_ordered_pub_account.pub_account.encode(),
+        &pending_balance.enc_amount().encode(),
+    ]);
+    let instruction_path = construct_path(
+        db_dir.clone(),
+        ON_CHAIN_DIR,
+        COMMON_OBJECTS_DIR,
+        &confidential_transaction_file(tx_id, &mediator, &ticker, state),
+    )?;
+    let verified =
+        verify_unless_assumed_valid(cache, assume_valid_before, tx_id, transfer_hash, || {
+            process_transaction(
+                instruction.clone(),
+                instruction_path.clone(),
+                sender_ordered_pub_account.pub_account,
+                receiver_ordered_pub_account.pub_account,
+                pending_balance,
+            )
+        });
 
     timing!(
         "validator.transaction",
         validate_transaction_timer,
         Instant::now(),
-        "tx_id" =>  tx_id.to_string()
+        "tx_id" => tx_id.to_string(),
+        "result" => outcome_label(&verified)
     );
 
+    let _result = match verified {
+        // The instruction could not be decoded, this is not a verification outcome.
+        Err(error @ Error::ObjectLoadError { .. }) => return Err(error),
+        Err(error) => {
+            error!("Error in validation of tx-{}: {:#?}", tx_id, error);
+            return Ok((
+                ValidationResult::error(&sender, &ticker, tx_id),
+                ValidationResult::error(&receiver, &ticker, tx_id),
+            ));
+        }
+        Ok(ok) => ok,
+    };
+
     let save_objects_timer = Instant::now();
     // Save the transaction under the new state.
+    let old_state = instruction.state;
     instruction.state = TransferTxState::Justification(TxSubstate::Validated);
-    if let Err(error) = save_object(
+    if !is_already_validated(
         db_dir.clone(),
-        ON_CHAIN_DIR,
         COMMON_OBJECTS_DIR,
-        &confidential_transaction_file(tx_id, &sender, instruction.state),
-        &instruction,
-    ) {
-        error!("Error in validation of tx-{}: {:#?}", tx_id, error);
-        return (
-            ValidationResult::error(&sender, &ticker),
-            ValidationResult::error(&receiver, &ticker),
-        );
+        &confidential_transaction_file(tx_id, &sender, &ticker, instruction.state),
+    )? {
+        save_instruction(
+            db_dir.clone(),
+            COMMON_OBJECTS_DIR,
+            tx_id,
+            &ticker,
+            &sender,
+            &instruction,
+        )?;
+        record_state_transition(
+            db_dir.clone(),
+            tx_id,
+            VALIDATOR,
+            Some(old_state),
+            instruction.state,
+        )?;
     }
 
     timing!(
@@ -585,18 +1677,1659 @@ pub fn validate_transaction(
         "tx_id" => tx_id.to_string()
     );
 
-    (
+    Ok((
         ValidationResult {
             user: sender,
             ticker: ticker.clone(),
             direction: Direction::Outgoing,
             amount: Some(tx.finalized_data.init_data.memo.enc_amount_using_sender),
+            tx_id,
         },
         ValidationResult {
             user: receiver,
             ticker: ticker.clone(),
             direction: Direction::Incoming,
             amount: Some(tx.finalized_data.init_data.memo.enc_amount_using_receiver),
+            tx_id,
         },
-    )
+    ))
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account_create::{create_secret_account, process_create_account},
+        account_issue::process_issue_asset,
+        account_transfer::{process_create_tx, process_finalize_tx},
+        chain_dir,
+        chain_setup::process_asset_id_creation,
+        cheating, debug_decrypt, debug_decrypt_account_balance,
+        encoding::Encoding,
+        gen_seed, init_print_logger,
+        justify::justify_asset_transfer_transaction,
+        load_from_file,
+        profile::captured_counter,
+        readiness_reason,
+        test_utils::{assert_balance, copy_dir, justified_transfer, test_db_dir, unittest_dir},
+        tx_balance_deltas, update_account_map,
+        verification_cache::{VERIFICATION_CACHE_FILE, VERIFICATION_CACHE_HIT_COUNTER},
+        OrderedAssetInstruction, OrderingState, RejectionKind, OFF_CHAIN_DIR,
+    };
+    use cryptography::{
+        asset_id_from_ticker,
+        asset_proofs::CommitmentWitness,
+        mercat::{account::AccountCreator, AccountCreatorInitializer, PubAccountTx},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_account_cheats_are_rejected() {
+        let mut rng = StdRng::from_seed([17u8; 32]);
+        let valid_asset_ids: Vec<Scalar> = vec![asset_id_from_ticker("ACME").unwrap().into()];
+        let secret_account = create_secret_account(&mut rng, "ACME".to_string()).unwrap();
+        let account_tx = AccountCreator
+            .create(&secret_account, &valid_asset_ids, &mut rng)
+            .unwrap();
+        let ordered = |account_tx: PubAccountTx| OrderedPubAccountTx {
+            ordering_state: OrderingState::new(0),
+            account_tx,
+        };
+        verify_account_tx(&ordered(account_tx.clone()), &valid_asset_ids).unwrap();
+
+        // Both the asset id overwrite and the account id bump break the proofs.
+        let overwritten =
+            cheating::overwrite_account_asset_id(&mut rng, &secret_account, account_tx.clone())
+                .unwrap();
+        let bumped = cheating::bump_account_id(account_tx);
+        for cheated in vec![overwritten, bumped] {
+            match verify_account_tx(&ordered(cheated), &valid_asset_ids) {
+                Err(Error::LibraryError { .. }) => {}
+                other => panic!("Expected LibraryError, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_truncated_transfer_instruction() {
+        let db_dir = test_db_dir("truncated_transfer_instruction");
+
+        let (acme, alice, bob, mike) = (
+            String::from("ACME"),
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        // Truncate the payload of the instruction on disk.
+        let (tx, mut instruction, path) = justified_transfer(&db_dir);
+        let state = TransferTxState::Justification(TxSubstate::Started);
+        let file_name = confidential_transaction_file(4, &mike, &acme, state);
+        instruction.data.truncate(instruction.data.len() / 2);
+        save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &file_name,
+            &instruction,
+        )
+        .unwrap();
+
+        let sender_account: OrderedPubAccount = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_file(&acme),
+        )
+        .unwrap();
+        let receiver_account: OrderedPubAccount = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &bob,
+            &user_public_account_file(&acme),
+        )
+        .unwrap();
+        let pending_balance = sender_pending_balance(db_dir.clone(), &alice, &acme, 4).unwrap();
+        match process_transaction(
+            instruction,
+            path.clone(),
+            sender_account.pub_account,
+            receiver_account.pub_account,
+            pending_balance.clone(),
+        ) {
+            Err(Error::ObjectLoadError {
+                path: error_path, ..
+            }) => assert_eq!(error_path, path),
+            other => panic!("Expected ObjectLoadError, got {:?}", other),
+        }
+
+        // Only this transaction fails, and it does so without panicking.
+        match validate_transaction(
+            db_dir.clone(),
+            tx,
+            mike.clone(),
+            pending_balance,
+            4,
+            &mut VerificationCache::default(),
+            None,
+        ) {
+            Err(Error::ObjectLoadError {
+                path: error_path, ..
+            }) => assert_eq!(error_path, path),
+            other => panic!("Expected ObjectLoadError, got {:?}", other.map(|_| ())),
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(last_verified_tx_id(db_dir.clone()), 4);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_too_many_pending_transactions() {
+        let db_dir = test_db_dir("too_many_pending_transactions");
+
+        let limit = 3;
+        for tx_id in 0..=limit {
+            save_object(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                COMMON_OBJECTS_DIR,
+                &format!("tx_{}_ACME_alice_stray.json", tx_id),
+                &tx_id,
+            )
+            .unwrap();
+        }
+        match all_unverified_tx_files_with_limit(db_dir.clone(), limit as usize) {
+            Err(Error::TooManyPendingTransactions { count, limit }) => {
+                assert_eq!((count, limit), (4, 3))
+            }
+            other => panic!("Expected TooManyPendingTransactions, got {:?}", other),
+        }
+        assert_eq!(
+            all_unverified_tx_files_with_limit(db_dir.clone(), limit as usize + 1)
+                .unwrap()
+                .len(),
+            4
+        );
+        let options = ValidationOptions {
+            max_pending: limit as usize,
+            ..ValidationOptions::default()
+        };
+        match validate_all_pending_with_options(db_dir.clone(), None, &options) {
+            Err(Error::TooManyPendingTransactions { count, limit }) => {
+                assert_eq!((count, limit), (4, 3))
+            }
+            other => panic!("Expected TooManyPendingTransactions, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_stale_account_mapping() {
+        let db_dir = test_db_dir("stale_account_mapping");
+
+        let (tx, _, _) = justified_transfer(&db_dir);
+        let sender_account_id = tx.finalized_data.init_data.memo.sender_account_id;
+        assert_eq!(
+            get_checked_user_ticker_from(sender_account_id, db_dir.clone()).unwrap(),
+            (String::from("alice"), String::from("ACME"), 1)
+        );
+
+        // Point alice's account id to bob's account while the transfer is in flight.
+        update_account_map(
+            db_dir.clone(),
+            String::from("bob"),
+            String::from("ACME"),
+            sender_account_id,
+            1,
+        )
+        .unwrap();
+        let printable_id = PrintableAccountId(sender_account_id.encode()).to_string();
+        match get_checked_user_ticker_from(sender_account_id, db_dir.clone()) {
+            Err(Error::StaleAccountMapping { account_id }) => assert_eq!(account_id, printable_id),
+            other => panic!("Expected StaleAccountMapping, got {:?}", other),
+        }
+
+        // The lookup error follows the error strategy, like the other errors of the transfer.
+        let abort = ValidationOptions {
+            error_strategy: ErrorStrategy::Abort,
+            ..ValidationOptions::default()
+        };
+        match validate_all_pending_with_options(db_dir.clone(), None, &abort) {
+            Err(Error::StaleAccountMapping { account_id }) => assert_eq!(account_id, printable_id),
+            other => panic!("Expected StaleAccountMapping, got {:?}", other),
+        }
+        let summary = validate_all_pending_with_summary(db_dir.clone(), None).unwrap();
+        assert_eq!((summary.accepted, summary.rejected), (0, 1));
+        assert_eq!(last_verified_tx_id(db_dir.clone()), 4);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_transfer_structure_checks() {
+        let db_dir = test_db_dir("transfer_structure_checks");
+
+        let (tx, instruction, _) = justified_transfer(&db_dir);
+        let pub_account = |user: &str| {
+            let account: OrderedPubAccount = load_object(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                user,
+                &user_public_account_file(&String::from("ACME")),
+            )
+            .unwrap();
+            account.pub_account
+        };
+        let (alice, bob) = (pub_account("alice"), pub_account("bob"));
+        check_transfer_structure(
+            &tx,
+            &instruction,
+            ("alice", "ACME", &alice),
+            ("bob", "ACME", &bob),
+            4,
+        )
+        .unwrap();
+
+        let mut finalized = instruction.clone();
+        finalized.state = TransferTxState::Finalization(TxSubstate::Started);
+        match check_transfer_structure(
+            &tx,
+            &finalized,
+            ("alice", "ACME", &alice),
+            ("bob", "ACME", &bob),
+            4,
+        ) {
+            Err(Error::UnexpectedTransactionState { tx_id, .. }) => assert_eq!(tx_id, 4),
+            other => panic!("Expected UnexpectedTransactionState, got {:?}", other),
+        }
+        match check_transfer_structure(
+            &tx,
+            &instruction,
+            ("alice", "ACME", &alice),
+            ("bob", "BETA", &bob),
+            4,
+        ) {
+            Err(Error::TickerMismatch { .. }) => {}
+            other => panic!("Expected TickerMismatch, got {:?}", other),
+        }
+        match check_transfer_structure(
+            &tx,
+            &instruction,
+            ("alice", "ACME", &bob),
+            ("bob", "ACME", &alice),
+            4,
+        ) {
+            Err(Error::AccountIdMismatch { user, .. }) => assert_eq!(user, "alice"),
+            other => panic!("Expected AccountIdMismatch, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_accounts_are_updated_in_a_stable_order() {
+        let result = |user: &str, ticker: &str, tx_id| ValidationResult {
+            user: user.to_string(),
+            ticker: ticker.to_string(),
+            direction: Direction::Incoming,
+            amount: None,
+            tx_id,
+        };
+        let results = vec![
+            result("dave", "ACME", 4),
+            result("bob", "BETA", 5),
+            result("n/a", "n/a", 6),
+            result("alice", "BETA", 7),
+            result("carol", "ACME", 8),
+            result("bob", "ACME", 9),
+            result("alice", "ACME", 10),
+            result("dave", "ACME", 11),
+        ];
+        let order = |results: &[ValidationResult]| -> Vec<(String, String)> {
+            accounts_to_update(results).into_iter().collect()
+        };
+        let want: Vec<(String, String)> = vec![
+            ("alice", "ACME"),
+            ("alice", "BETA"),
+            ("bob", "ACME"),
+            ("bob", "BETA"),
+            ("carol", "ACME"),
+            ("dave", "ACME"),
+        ]
+        .into_iter()
+        .map(|(user, ticker)| (user.to_string(), ticker.to_string()))
+        .collect();
+
+        // Two runs over the same results, and a run over the results in another order, update
+        // the accounts in the same order.
+        assert_eq!(order(&results), want);
+        assert_eq!(order(&results), want);
+        let mut reversed = results.clone();
+        reversed.reverse();
+        assert_eq!(order(&reversed), want);
+    }
+
+    #[test]
+    fn test_tx_balance_deltas_match_validation() {
+        let db_dir = test_db_dir("tx_balance_deltas");
+
+        let acme = String::from("ACME");
+        let (tx, _, _) = justified_transfer(&db_dir);
+        let deltas = tx_balance_deltas(&CoreTransaction::TransferJustify {
+            tx,
+            mediator: String::from("mike"),
+            tx_id: 4,
+        });
+        let directions: Vec<Direction> = deltas
+            .iter()
+            .map(|(_, direction, _)| direction.clone())
+            .collect();
+        assert_eq!(directions, vec![Direction::Outgoing, Direction::Incoming]);
+
+        // Apply the deltas to the current balances, the way the validator will.
+        let mut projected = vec![];
+        for (account_id, direction, amount) in deltas {
+            let (user, ticker, _) = get_user_ticker_from(account_id, db_dir.clone()).unwrap();
+            assert_eq!(ticker, acme);
+            let mut balance: EncryptedAmount = load_object(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                &user,
+                &user_public_account_balance_file(&ticker),
+            )
+            .unwrap();
+            match direction {
+                Direction::Incoming => balance += amount,
+                Direction::Outgoing => balance -= amount,
+            }
+            projected.push((user, balance));
+        }
+
+        validate_all_pending(db_dir.clone()).unwrap();
+        for (user, balance) in projected {
+            let validated: EncryptedAmount = load_object(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                &user,
+                &user_public_account_balance_file(&acme),
+            )
+            .unwrap();
+            assert_eq!(validated.encode(), balance.encode(), "{}", user);
+        }
+        assert_balance(&db_dir, "alice", 6);
+        assert_balance(&db_dir, "bob", 4);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_parse_tx_name_errors() {
+        let (acme, alice) = (String::from("ACME"), String::from("alice"));
+        let init = TransferTxState::Initialization(TxSubstate::Started);
+        let parse = |file_name: &str| parse_tx_name(format!("/db/on-chain/common/{}", file_name));
+
+        let (tx_id, user, state, _) =
+            parse(&confidential_transaction_file(4, &alice, &acme, init)).unwrap();
+        assert_eq!((tx_id, user, state), (4, alice.clone(), init.to_string()));
+        let (tx_id, user, state, _) =
+            parse(&account_create_transaction_file(1, &alice, &acme)).unwrap();
+        assert_eq!(
+            (tx_id, user, state),
+            (1, alice, String::from("ticker#ACME"))
+        );
+
+        for file_name in &["tx_4.json", "tx_4_ACME_Alice_stray.json", "tx_4_alice.json"] {
+            match parse(file_name) {
+                Err(Error::MalformedTxFilename { file_name: name }) => {
+                    assert_eq!(&name, file_name)
+                }
+                other => panic!("Expected MalformedTxFilename, got {:?}", other),
+            }
+        }
+        for file_name in &[
+            format!("tx_four_ACME_alice_{}.json", init),
+            format!("tx_99999999999_ACME_alice_{}.json", init),
+        ] {
+            match parse(file_name) {
+                Err(Error::NonNumericTxId { file_name: name }) => assert_eq!(&name, file_name),
+                other => panic!("Expected NonNumericTxId, got {:?}", other),
+            }
+        }
+        for (file_name, want_state) in &[
+            ("tx_4_ACME_alice_stray.json", "stray"),
+            ("tx_1_alice_ticker#.json", "ticker#"),
+        ] {
+            match parse(file_name) {
+                Err(Error::UnknownState { state, .. }) => assert_eq!(&state, want_state),
+                other => panic!("Expected UnknownState, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_stray_tx_files_are_skipped_unless_strict() {
+        let db_dir = test_db_dir("stray_tx_files");
+
+        justified_transfer(&db_dir);
+        for file_name in &[
+            "tx_5_ACME_alice_stray.json",
+            "tx_five_ACME_alice_stray.json",
+        ] {
+            save_object(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                COMMON_OBJECTS_DIR,
+                file_name,
+                &0u32,
+            )
+            .unwrap();
+        }
+
+        match load_all_unverified_and_ready(db_dir.clone(), true, DEFAULT_MAX_PENDING) {
+            Err(error) => assert!(is_tx_name_error(&error), "{:?}", error),
+            Ok(_) => panic!("Expected the stray files to fail the strict loading"),
+        }
+        let ready =
+            load_all_unverified_and_ready(db_dir.clone(), false, DEFAULT_MAX_PENDING).unwrap();
+        assert_eq!(ready.len(), 1);
+        let strict = ValidationOptions {
+            strict_filenames: true,
+            ..ValidationOptions::default()
+        };
+        assert!(validate_all_pending_with_options(db_dir.clone(), None, &strict).is_err());
+
+        // The validation goes on without the stray files.
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(last_verified_tx_id(db_dir.clone()), 4);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_approver_denies_by_amount() {
+        let db_dir = test_db_dir("approver");
+
+        let (acme, alice, bob, mike) = (
+            String::from("ACME"),
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        justified_transfer(&db_dir);
+        let deny_above_three = |result: &ValidationResult| {
+            let account: OrderedPubAccount = load_object(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                result.user(),
+                &user_public_account_file(result.ticker()),
+            )
+            .unwrap();
+            let amount = debug_decrypt(
+                account.pub_account.enc_asset_id,
+                result.amount().unwrap().clone(),
+                db_dir.clone(),
+            )
+            .unwrap();
+            if amount > 3 {
+                Decision::Deny(RejectionReason {
+                    kind: RejectionKind::Compliance,
+                    message: String::from("above the limit"),
+                })
+            } else {
+                Decision::Approve
+            }
+        };
+        let balance = |user: &String| {
+            debug_decrypt_account_balance(user.clone(), acme.clone(), db_dir.clone()).unwrap()
+        };
+
+        // The transfer of 4 is verified, but denied.
+        validate_all_pending_with_approver(db_dir.clone(), Some(&deny_above_three)).unwrap();
+        assert_eq!(last_verified_tx_id(db_dir.clone()), 4);
+        assert_eq!(balance(&alice), 10);
+        assert_eq!(balance(&bob), 0);
+        let validated = confidential_transaction_file(
+            4,
+            &alice,
+            &acme,
+            TransferTxState::Justification(TxSubstate::Validated),
+        );
+        assert!(
+            !construct_path(db_dir.clone(), ON_CHAIN_DIR, COMMON_OBJECTS_DIR, &validated)
+                .unwrap()
+                .exists()
+        );
+        assert!(
+            construct_path(db_dir.clone(), ON_CHAIN_DIR, QUARANTINE_DIR, &validated)
+                .unwrap()
+                .exists()
+        );
+        let record: QuarantinedTransaction = load_from_file(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            QUARANTINE_DIR,
+            &quarantine_file(4),
+        )
+        .unwrap();
+        assert_eq!(record.reason.kind, RejectionKind::Compliance);
+
+        // A transfer of 2 is approved.
+        process_create_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            2,
+            false,
+            Encoding::default(),
+            5,
+            0,
+            false,
+        )
+        .unwrap();
+        process_finalize_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            acme.clone(),
+            2,
+            false,
+            Encoding::default(),
+            5,
+            false,
+        )
+        .unwrap();
+        justify_asset_transfer_transaction(
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            gen_seed(),
+            false,
+            Encoding::default(),
+            5,
+            None,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending_with_approver(db_dir.clone(), Some(&deny_above_three)).unwrap();
+        assert_eq!(balance(&alice), 8);
+        assert_eq!(balance(&bob), 2);
+        assert!(!construct_path(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            QUARANTINE_DIR,
+            &quarantine_file(5)
+        )
+        .unwrap()
+        .exists());
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_load_failure_vs_verification_failure() {
+        let db_dir = test_db_dir("load_vs_verification_failure");
+
+        let (acme, alice, mike) = (
+            String::from("ACME"),
+            String::from("alice"),
+            String::from("mike"),
+        );
+        let (tx, mut instruction, path) = justified_transfer(&db_dir);
+        let pending_balance = sender_pending_balance(db_dir.clone(), &alice, &acme, 4).unwrap();
+
+        // An instruction in the wrong state fails the verification.
+        instruction.state = TransferTxState::Finalization(TxSubstate::Started);
+        save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            path.file_name().unwrap().to_str().unwrap(),
+            &instruction,
+        )
+        .unwrap();
+        let (sender_result, receiver_result) = validate_transaction(
+            db_dir.clone(),
+            tx.clone(),
+            mike.clone(),
+            pending_balance.clone(),
+            4,
+            &mut VerificationCache::default(),
+            None,
+        )
+        .unwrap();
+        assert!(sender_result.amount.is_none());
+        assert!(receiver_result.amount.is_none());
+
+        // A missing instruction cannot even be loaded.
+        std::fs::remove_file(&path).unwrap();
+        assert!(validate_transaction(
+            db_dir.clone(),
+            tx,
+            mike,
+            pending_balance,
+            4,
+            &mut VerificationCache::default(),
+            None
+        )
+        .is_err());
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_pending_balance_mismatch() {
+        let db_dir = test_db_dir("pending_balance_mismatch");
+
+        let (acme, alice, mike) = (
+            String::from("ACME"),
+            String::from("alice"),
+            String::from("mike"),
+        );
+        let (tx, _, _) = justified_transfer(&db_dir);
+        let pending_balance = sender_pending_balance(db_dir.clone(), &alice, &acme, 4).unwrap();
+        ensure_pending_balance_matches(db_dir.clone(), &alice, &acme, &pending_balance, 4).unwrap();
+
+        // Another encryption of the same balance is not the recomputed pending balance.
+        let alice_account: OrderedPubAccount = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_file(&acme),
+        )
+        .unwrap();
+        let mut rng = StdRng::from_seed([5u8; 32]);
+        let diverged = PendingBalance(alice_account.pub_account.owner_enc_pub_key.encrypt(
+            &CommitmentWitness::new(Scalar::from(10u32), Scalar::random(&mut rng)),
+        ));
+        match ensure_pending_balance_matches(db_dir.clone(), &alice, &acme, &diverged, 4) {
+            Err(Error::PendingBalanceMismatch { tx_id }) => assert_eq!(tx_id, 4),
+            other => panic!("Expected PendingBalanceMismatch, got {:?}", other),
+        }
+        let (sender_result, receiver_result) = validate_transaction(
+            db_dir.clone(),
+            tx,
+            mike,
+            diverged,
+            4,
+            &mut VerificationCache::default(),
+            None,
+        )
+        .unwrap();
+        assert!(sender_result.amount.is_none());
+        assert!(receiver_result.amount.is_none());
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_validate_transaction_with_pending() {
+        let db_dir = test_db_dir("validate_with_pending");
+
+        let (acme, alice) = (String::from("ACME"), String::from("alice"));
+        let (tx, _, _) = justified_transfer(&db_dir);
+        let pending_balance = sender_pending_balance(db_dir.clone(), &alice, &acme, 4).unwrap();
+        validate_transaction_with_pending(
+            db_dir.clone(),
+            &tx,
+            pending_balance.enc_amount().clone(),
+        )
+        .unwrap();
+
+        // The transfer of 4 does not verify against a pending balance of 2.
+        let alice_account: OrderedPubAccount = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_file(&acme),
+        )
+        .unwrap();
+        let mut rng = StdRng::from_seed([7u8; 32]);
+        let too_small =
+            alice_account
+                .pub_account
+                .owner_enc_pub_key
+                .encrypt(&CommitmentWitness::new(
+                    Scalar::from(2u32),
+                    Scalar::random(&mut rng),
+                ));
+        assert!(validate_transaction_with_pending(db_dir.clone(), &tx, too_small).is_err());
+
+        // The analysis does not validate the transfer.
+        assert_eq!(last_verified_tx_id(db_dir.clone()), 3);
+        assert_balance(&db_dir, &alice, 10);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_assume_valid_before() {
+        let db_dir = test_db_dir("assume_valid_before");
+
+        let (acme, alice, bob, mike) = (
+            String::from("ACME"),
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        justified_transfer(&db_dir);
+        let verified = || -> HashMap<u32, serde_json::Value> {
+            load_from_file(
+                db_dir.clone(),
+                VALIDATOR_DIR,
+                COMMON_OBJECTS_DIR,
+                VERIFICATION_CACHE_FILE,
+            )
+            .unwrap()
+        };
+        let balance = |user: &String| {
+            debug_decrypt_account_balance(user.clone(), acme.clone(), db_dir.clone()).unwrap()
+        };
+
+        // The transfer before the checkpoint is applied without being verified.
+        let options = ValidationOptions {
+            assume_valid_before: Some(5),
+            ..ValidationOptions::default()
+        };
+        validate_all_pending_with_options(db_dir.clone(), None, &options).unwrap();
+        assert!(!verified().contains_key(&4));
+        assert_eq!(balance(&alice), 6);
+        assert_eq!(balance(&bob), 4);
+
+        // The verification resumes at the checkpoint.
+        process_create_tx(
+            gen_seed(),
+            db_dir.clone(),
+            bob.clone(),
+            alice.clone(),
+            mike.clone(),
+            acme.clone(),
+            1,
+            false,
+            Encoding::default(),
+            5,
+            0,
+            false,
+        )
+        .unwrap();
+        process_finalize_tx(
+            gen_seed(),
+            db_dir.clone(),
+            bob.clone(),
+            alice.clone(),
+            acme.clone(),
+            1,
+            false,
+            Encoding::default(),
+            5,
+            false,
+        )
+        .unwrap();
+        justify_asset_transfer_transaction(
+            db_dir.clone(),
+            bob.clone(),
+            alice.clone(),
+            mike.clone(),
+            acme.clone(),
+            gen_seed(),
+            false,
+            Encoding::default(),
+            5,
+            None,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending_with_options(db_dir.clone(), None, &options).unwrap();
+        assert!(verified().contains_key(&5));
+        assert_eq!(balance(&alice), 7);
+        assert_eq!(balance(&bob), 3);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_verification_cache_across_runs() {
+        let db_dir = test_db_dir("verification_cache_across_runs");
+        let snapshot = unittest_dir("verification_cache_across_runs_snapshot");
+        init_print_logger();
+
+        let (acme, alice, bob) = (
+            String::from("ACME"),
+            String::from("alice"),
+            String::from("bob"),
+        );
+        justified_transfer(&db_dir);
+        copy_dir(&db_dir.root, &snapshot);
+        let balances = || {
+            (
+                debug_decrypt_account_balance(alice.clone(), acme.clone(), db_dir.clone()).unwrap(),
+                debug_decrypt_account_balance(bob.clone(), acme.clone(), db_dir.clone()).unwrap(),
+            )
+        };
+        let hits = captured_counter(VERIFICATION_CACHE_HIT_COUNTER);
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(captured_counter(VERIFICATION_CACHE_HIT_COUNTER), hits);
+        assert_eq!(balances(), (6, 4));
+
+        // Validating the same transfer again, with the cache of the first run, skips its
+        // verification and updates the balances in the same way.
+        let cache_path = construct_path(
+            db_dir.clone(),
+            VALIDATOR_DIR,
+            COMMON_OBJECTS_DIR,
+            VERIFICATION_CACHE_FILE,
+        )
+        .unwrap();
+        let cache = std::fs::read(&cache_path).unwrap();
+        std::fs::remove_dir_all(&db_dir.root).unwrap();
+        copy_dir(&snapshot, &db_dir.root);
+        std::fs::write(&cache_path, cache).unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(captured_counter(VERIFICATION_CACHE_HIT_COUNTER), hits + 1);
+        assert_eq!(balances(), (6, 4));
+        assert_eq!(last_verified_tx_id(db_dir.clone()), 4);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+        let _ = std::fs::remove_dir_all(&snapshot);
+    }
+
+    #[test]
+    fn test_zero_issuance_is_rejected() {
+        let db_dir = test_db_dir("zero_issuance");
+
+        let (acme, alice) = (String::from("ACME"), String::from("alice"));
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_account(
+            Some(gen_seed()),
+            db_dir.clone(),
+            acme.clone(),
+            alice.clone(),
+            false,
+            Encoding::default(),
+            1,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        let issue = |amount: u32, tx_id: u32| {
+            process_issue_asset(
+                gen_seed(),
+                db_dir.clone(),
+                alice.clone(),
+                acme.clone(),
+                amount,
+                false,
+                Encoding::default(),
+                tx_id,
+                false,
+            )
+        };
+        let issuance_file = asset_transaction_file(
+            2,
+            &alice,
+            &acme,
+            AssetTxState::Initialization(TxSubstate::Started),
+        );
+
+        // The issuer refuses to issue zero, and nothing is written.
+        match issue(0, 2) {
+            Err(Error::ZeroIssuanceAmount { tx_id }) => assert_eq!(tx_id, 2),
+            other => panic!("Expected ZeroIssuanceAmount, got {:?}", other),
+        }
+        assert!(!construct_path(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &issuance_file
+        )
+        .unwrap()
+        .exists());
+
+        // A crafted issuance that claims zero is rejected before its proofs are verified.
+        issue(5, 2).unwrap();
+        let mut instruction: OrderedAssetInstruction = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &issuance_file,
+        )
+        .unwrap();
+        instruction.amount = 0;
+        save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &issuance_file,
+            &instruction,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        let verified: HashMap<u32, serde_json::Value> = load_from_file(
+            db_dir.clone(),
+            VALIDATOR_DIR,
+            COMMON_OBJECTS_DIR,
+            VERIFICATION_CACHE_FILE,
+        )
+        .unwrap_or_default();
+        assert!(!verified.contains_key(&2));
+        assert_balance(&db_dir, &alice, 0);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_outcome_counters() {
+        let db_dir = test_db_dir("outcome_counters");
+        init_print_logger();
+        let counters = || {
+            (
+                captured_counter(TX_ACCEPTED_COUNTER),
+                captured_counter(TX_REJECTED_COUNTER),
+            )
+        };
+        let (accepted, rejected) = counters();
+
+        let (acme, alice) = (String::from("ACME"), String::from("alice"));
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_account(
+            Some(gen_seed()),
+            db_dir.clone(),
+            acme.clone(),
+            alice.clone(),
+            false,
+            Encoding::default(),
+            1,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(counters(), (accepted + 1, rejected));
+
+        let issue = |amount: u32, tx_id: u32| {
+            process_issue_asset(
+                gen_seed(),
+                db_dir.clone(),
+                alice.clone(),
+                acme.clone(),
+                amount,
+                false,
+                Encoding::default(),
+                tx_id,
+                false,
+            )
+            .unwrap()
+        };
+
+        // An issuance whose amount was changed after its creation is rejected.
+        issue(5, 2);
+        let issuance_file = asset_transaction_file(
+            2,
+            &alice,
+            &acme,
+            AssetTxState::Initialization(TxSubstate::Started),
+        );
+        let mut instruction: OrderedAssetInstruction = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &issuance_file,
+        )
+        .unwrap();
+        instruction.amount = 6;
+        save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &issuance_file,
+            &instruction,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(counters(), (accepted + 1, rejected + 1));
+
+        issue(7, 3);
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(counters(), (accepted + 2, rejected + 1));
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_validation_summary() {
+        let db_dir = test_db_dir("validation_summary");
+
+        let acme = String::from("ACME");
+        let (alice, bob) = (String::from("alice"), String::from("bob"));
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        for (tx_id, user) in &[(1, &alice), (2, &bob)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                acme.clone(),
+                user.to_string(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            validate_all_pending_with_summary(db_dir.clone(), None).unwrap(),
+            ValidationSummary {
+                processed: 2,
+                accepted: 2,
+                rejected: 0,
+                updated_accounts: vec![],
+                last_validated_tx_id: Some(2),
+            }
+        );
+
+        for (tx_id, amount) in &[(3, 10), (4, 5)] {
+            process_issue_asset(
+                gen_seed(),
+                db_dir.clone(),
+                alice.clone(),
+                acme.clone(),
+                *amount,
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+            )
+            .unwrap();
+        }
+        // The amount of the second issuance is changed after its creation.
+        let issuance_file = asset_transaction_file(
+            4,
+            &alice,
+            &acme,
+            AssetTxState::Initialization(TxSubstate::Started),
+        );
+        let mut instruction: OrderedAssetInstruction = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &issuance_file,
+        )
+        .unwrap();
+        instruction.amount = 6;
+        save_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            COMMON_OBJECTS_DIR,
+            &issuance_file,
+            &instruction,
+        )
+        .unwrap();
+
+        let options = ValidationOptions {
+            debug_balances: Some(chain_dir(db_dir.clone(), OFF_CHAIN_DIR)),
+            ..ValidationOptions::default()
+        };
+        assert_eq!(
+            validate_all_pending_with_options(db_dir.clone(), None, &options).unwrap(),
+            ValidationSummary {
+                processed: 2,
+                accepted: 1,
+                rejected: 1,
+                updated_accounts: vec![AccountUpdate {
+                    user: alice,
+                    ticker: acme,
+                    balance_before: Some(0),
+                    balance_after: Some(10),
+                }],
+                last_validated_tx_id: Some(4),
+            }
+        );
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_validation_without_secret_keys() {
+        let db_dir = test_db_dir("validation_without_secret_keys");
+
+        let (acme, alice, bob) = (
+            String::from("ACME"),
+            String::from("alice"),
+            String::from("bob"),
+        );
+        justified_transfer(&db_dir);
+
+        // Move the secret keys away, as on a validator that never had them, and keep only the
+        // off-chain account map. The validation reads the map, but writes nothing off-chain.
+        let off_chain = chain_dir(db_dir.clone(), OFF_CHAIN_DIR);
+        let mut key_dir = db_dir.root.clone();
+        key_dir.push("keys");
+        std::fs::rename(&off_chain, &key_dir).unwrap();
+        copy_dir(
+            &key_dir.join(COMMON_OBJECTS_DIR),
+            &off_chain.join(COMMON_OBJECTS_DIR),
+        );
+        let off_chain_files = || -> Vec<(PathBuf, Vec<u8>)> {
+            let mut files: Vec<(PathBuf, Vec<u8>)> = std::fs::read_dir(&off_chain)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .flat_map(|dir| std::fs::read_dir(dir).unwrap())
+                .map(|entry| entry.unwrap().path())
+                .map(|path| (path.clone(), std::fs::read(path).unwrap()))
+                .collect();
+            files.sort();
+            files
+        };
+        let before = off_chain_files();
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(last_verified_tx_id(db_dir.clone()), 4);
+        assert_eq!(
+            std::fs::read_dir(&off_chain).unwrap().count(),
+            1,
+            "only the common directory is off-chain"
+        );
+        assert_eq!(off_chain_files(), before);
+
+        // The balances are only decrypted with an explicit key directory.
+        let alice_account: OrderedPubAccount = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_file(&acme),
+        )
+        .unwrap();
+        let alice_balance: EncryptedAmount = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_balance_file(&acme),
+        )
+        .unwrap();
+        let decrypt = |key_dir: Option<&PathBuf>| {
+            debug_decrypt_balance(
+                alice_account.pub_account.enc_asset_id,
+                alice_balance.clone(),
+                db_dir.clone(),
+                key_dir,
+            )
+            .unwrap()
+        };
+        assert_eq!(decrypt(None), None);
+        assert_eq!(decrypt(Some(&key_dir)), Some(6));
+
+        std::fs::remove_dir_all(&off_chain).unwrap();
+        std::fs::rename(&key_dir, &off_chain).unwrap();
+        assert_eq!(
+            debug_decrypt_account_balance(bob.clone(), acme.clone(), db_dir.clone()).unwrap(),
+            4
+        );
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_readiness_of_every_state() {
+        let substates = [
+            TxSubstate::Started,
+            TxSubstate::Validated,
+            TxSubstate::Rejected,
+        ];
+        let never = ReadinessStatus::NotReady {
+            missing_state: None,
+        };
+        let waiting_for = |state: TransferTxState| ReadinessStatus::NotReady {
+            missing_state: Some(state.to_string()),
+        };
+        for substate in substates.iter() {
+            let started = *substate == TxSubstate::Started;
+            let cases = vec![
+                (
+                    AssetTxState::Initialization(*substate).to_string(),
+                    if started {
+                        ReadinessStatus::Ready
+                    } else {
+                        never.clone()
+                    },
+                ),
+                (
+                    AssetTxState::Justification(*substate).to_string(),
+                    never.clone(),
+                ),
+                (
+                    TransferTxState::Initialization(*substate).to_string(),
+                    if started {
+                        waiting_for(TransferTxState::Finalization(TxSubstate::Started))
+                    } else {
+                        never.clone()
+                    },
+                ),
+                (
+                    TransferTxState::Finalization(*substate).to_string(),
+                    if started {
+                        waiting_for(TransferTxState::Justification(TxSubstate::Started))
+                    } else {
+                        never.clone()
+                    },
+                ),
+                (
+                    TransferTxState::Justification(*substate).to_string(),
+                    if started {
+                        ReadinessStatus::Ready
+                    } else {
+                        never.clone()
+                    },
+                ),
+            ];
+            for (state, expected) in cases {
+                assert_eq!(state_readiness(&state), expected, "{}", state);
+            }
+        }
+        assert_eq!(state_readiness("ticker#ACME"), ReadinessStatus::Ready);
+        assert_eq!(
+            state_readiness(CREDIT_STARTED_STATE),
+            ReadinessStatus::Ready
+        );
+        assert_eq!(state_readiness(CREDIT_VALIDATED_STATE), never);
+
+        let rejected = CoreTransaction::TransferRejected {
+            sender: String::from("alice"),
+            reason: None,
+            tx_id: 1,
+        };
+        assert_eq!(readiness_reason(&rejected), never);
+        assert_eq!(readiness_reason(&CoreTransaction::Invalid), never);
+
+        // A transaction is as far along as the furthest of its files.
+        let init =
+            state_readiness(&TransferTxState::Initialization(TxSubstate::Started).to_string());
+        let finalize =
+            state_readiness(&TransferTxState::Finalization(TxSubstate::Started).to_string());
+        assert_eq!(init.clone().merge(finalize.clone()), finalize);
+        assert_eq!(finalize.clone().merge(init), finalize);
+        assert_eq!(
+            finalize.merge(ReadinessStatus::Ready),
+            ReadinessStatus::Ready
+        );
+    }
+
+    #[test]
+    fn test_status_shows_the_missing_step() {
+        let db_dir = test_db_dir("status_missing_step");
+
+        let (acme, alice, bob, mike) = (
+            String::from("ACME"),
+            String::from("alice"),
+            String::from("bob"),
+            String::from("mike"),
+        );
+        justified_transfer(&db_dir);
+        validate_all_pending(db_dir.clone()).unwrap();
+        let not_ready = || process_validation_status(db_dir.clone()).unwrap().not_ready;
+        assert!(not_ready().is_empty());
+
+        process_create_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            mike.clone(),
+            acme.clone(),
+            1,
+            false,
+            Encoding::default(),
+            5,
+            0,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            not_ready(),
+            vec![NotReadyTransaction {
+                tx_id: 5,
+                missing_state: Some(TransferTxState::Finalization(TxSubstate::Started).to_string()),
+            }]
+        );
+
+        process_finalize_tx(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            bob.clone(),
+            acme.clone(),
+            1,
+            false,
+            Encoding::default(),
+            5,
+            false,
+        )
+        .unwrap();
+        let status = process_validation_status(db_dir.clone()).unwrap();
+        assert_eq!(status.pending_not_ready_count, 1);
+        assert_eq!(
+            status.not_ready[0].missing_state,
+            Some(TransferTxState::Justification(TxSubstate::Started).to_string())
+        );
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_tickers_are_updated_in_isolation() {
+        let db_dir = test_db_dir("ticker_isolation");
+
+        let (acme, beta) = (String::from("ACME"), String::from("BETA"));
+        let alice = String::from("alice");
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone(), beta.clone()]).unwrap();
+        for (tx_id, ticker) in &[(1, &acme), (2, &beta)] {
+            process_create_account(
+                Some(gen_seed()),
+                db_dir.clone(),
+                ticker.to_string(),
+                alice.clone(),
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+                false,
+                false,
+            )
+            .unwrap();
+        }
+        validate_all_pending(db_dir.clone()).unwrap();
+        for (tx_id, ticker, amount) in &[(3, &acme, 10), (4, &beta, 20), (5, &acme, 30)] {
+            process_issue_asset(
+                gen_seed(),
+                db_dir.clone(),
+                alice.clone(),
+                ticker.to_string(),
+                *amount,
+                false,
+                Encoding::default(),
+                *tx_id,
+                false,
+            )
+            .unwrap();
+        }
+
+        // The balance of alice's ACME account is lost, so its update fails.
+        let acme_balance_file = construct_path(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_balance_file(&acme),
+        )
+        .unwrap();
+        let acme_balance = std::fs::read(&acme_balance_file).unwrap();
+        std::fs::remove_file(&acme_balance_file).unwrap();
+        assert!(validate_all_pending(db_dir.clone()).is_err());
+
+        // BETA is updated regardless, and only its marker advances. The last validated tx_id
+        // is held back before the first ACME transaction of the run.
+        assert_eq!(
+            debug_decrypt_account_balance(alice.clone(), beta.clone(), db_dir.clone()).unwrap(),
+            20
+        );
+        assert_eq!(
+            last_validated_tx_id_of_ticker(db_dir.clone(), &beta),
+            Some(4)
+        );
+        assert_eq!(
+            last_validated_tx_id_of_ticker(db_dir.clone(), &acme),
+            Some(1)
+        );
+        assert_eq!(last_verified_tx_id(db_dir.clone()), 2);
+
+        // Once the balance is back, the ACME transactions are retried, and BETA is not updated
+        // twice.
+        std::fs::write(&acme_balance_file, acme_balance).unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        assert_eq!(
+            debug_decrypt_account_balance(alice.clone(), acme.clone(), db_dir.clone()).unwrap(),
+            40
+        );
+        assert_eq!(
+            debug_decrypt_account_balance(alice.clone(), beta.clone(), db_dir.clone()).unwrap(),
+            20
+        );
+        assert_eq!(
+            last_validated_tx_id_of_ticker(db_dir.clone(), &acme),
+            Some(5)
+        );
+        assert_eq!(last_verified_tx_id(db_dir.clone()), 5);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+    #[test]
+    fn test_failed_staged_write_changes_no_account() {
+        let db_dir = test_db_dir("failed_staged_write");
+
+        let (acme, alice, carol) = (
+            String::from("ACME"),
+            String::from("alice"),
+            String::from("carol"),
+        );
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_account(
+            Some(gen_seed()),
+            db_dir.clone(),
+            acme.clone(),
+            alice.clone(),
+            false,
+            Encoding::default(),
+            1,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        let account_file = construct_path(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_file(&acme),
+        )
+        .unwrap();
+        let account_before = std::fs::read(&account_file).unwrap();
+
+        // carol's directory is taken by a file, so her staged files cannot be written.
+        let mut carol_dir = chain_dir(db_dir.clone(), ON_CHAIN_DIR);
+        carol_dir.push(&carol);
+        std::fs::write(&carol_dir, b"").unwrap();
+        let alice_staged =
+            stage_balance_update(db_dir.clone(), &alice, &acme, &[], Some(5), false, None).unwrap();
+        let carol_staged = StagedBalance {
+            account: alice_staged.account.clone(),
+            balance: alice_staged.balance.clone(),
+            update: AccountUpdate {
+                user: carol.clone(),
+                ..alice_staged.update.clone()
+            },
+        };
+        assert!(save_staged_balances(db_dir.clone(), vec![alice_staged, carol_staged]).is_err());
+
+        // alice's account is not changed, and her staged files are removed.
+        assert_eq!(std::fs::read(&account_file).unwrap(), account_before);
+        assert!(!construct_path(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &staged_file(&user_public_account_file(&acme))
+        )
+        .unwrap()
+        .exists());
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_counter_regression_is_refused() {
+        let db_dir = test_db_dir("counter_regression");
+
+        let (acme, alice) = (String::from("ACME"), String::from("alice"));
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_account(
+            Some(gen_seed()),
+            db_dir.clone(),
+            acme.clone(),
+            alice.clone(),
+            false,
+            Encoding::default(),
+            1,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            2,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        let counter = || {
+            load_object::<OrderedPubAccount>(
+                db_dir.clone(),
+                ON_CHAIN_DIR,
+                &alice,
+                &user_public_account_file(&acme),
+            )
+            .unwrap()
+            .last_processed_tx_counter
+        };
+        assert_eq!(counter(), Some(2));
+
+        // A validation that ends before the last processed transaction of the account.
+        match stage_balance_update(db_dir.clone(), &alice, &acme, &[], Some(1), false, None) {
+            Err(Error::CounterRegression { old, new, .. }) => assert_eq!((old, new), (2, 1)),
+            other => panic!("Expected CounterRegression, got {:?}", other),
+        }
+        assert_eq!(counter(), Some(2));
+        assert_balance(&db_dir, &alice, 10);
+
+        stage_balance_update(db_dir.clone(), &alice, &acme, &[], Some(1), true, None)
+            .and_then(|staged| save_staged_balances(db_dir.clone(), vec![staged]))
+            .unwrap();
+        assert_eq!(counter(), Some(1));
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+
+    #[test]
+    fn test_overdraw_during_reduce() {
+        let db_dir = test_db_dir("overdraw_during_reduce");
+
+        let (acme, alice) = (String::from("ACME"), String::from("alice"));
+        process_asset_id_creation(db_dir.clone(), vec![acme.clone()]).unwrap();
+        process_create_account(
+            Some(gen_seed()),
+            db_dir.clone(),
+            acme.clone(),
+            alice.clone(),
+            false,
+            Encoding::default(),
+            1,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+        process_issue_asset(
+            gen_seed(),
+            db_dir.clone(),
+            alice.clone(),
+            acme.clone(),
+            10,
+            false,
+            Encoding::default(),
+            2,
+            false,
+        )
+        .unwrap();
+        validate_all_pending(db_dir.clone()).unwrap();
+
+        let account: OrderedPubAccount = load_object(
+            db_dir.clone(),
+            ON_CHAIN_DIR,
+            &alice,
+            &user_public_account_file(&acme),
+        )
+        .unwrap();
+        let mut rng = StdRng::from_seed([9u8; 32]);
+        let mut result = |direction: Direction, amount: u32, tx_id: u32| {
+            let enc_amount =
+                account
+                    .pub_account
+                    .owner_enc_pub_key
+                    .encrypt(&CommitmentWitness::new(
+                        Scalar::from(amount),
+                        Scalar::random(&mut rng),
+                    ));
+            ValidationResult::new(&alice, &acme, direction, Some(enc_amount), tx_id)
+        };
+        let credit_listed_after_spend = vec![
+            result(Direction::Outgoing, 12, 4),
+            result(Direction::Incoming, 5, 3),
+        ];
+        let overdraw = vec![result(Direction::Outgoing, 4, 5)];
+
+        // The overdraw check needs the decrypted amounts.
+        let key_dir = chain_dir(db_dir.clone(), OFF_CHAIN_DIR);
+        // The credit is applied first, whatever the order of the results.
+        stage_balance_update(
+            db_dir.clone(),
+            &alice,
+            &acme,
+            &credit_listed_after_spend,
+            Some(4),
+            false,
+            Some(&key_dir),
+        )
+        .and_then(|staged| save_staged_balances(db_dir.clone(), vec![staged]))
+        .unwrap();
+        assert_balance(&db_dir, &alice, 3);
+        match stage_balance_update(
+            db_dir.clone(),
+            &alice,
+            &acme,
+            &overdraw,
+            Some(5),
+            false,
+            Some(&key_dir),
+        ) {
+            Err(Error::NegativeBalanceDuringReduce { user, tx_id, .. }) => {
+                assert_eq!((user, tx_id), (alice.clone(), 5))
+            }
+            other => panic!("Expected NegativeBalanceDuringReduce, got {:?}", other),
+        }
+        assert_balance(&db_dir, &alice, 3);
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
 }
@@ -0,0 +1,169 @@
+//! An on-disk cache of the outcome of the cryptographic verification of the instructions.
+//! The entries are keyed by the transaction id and carry the hash of everything the
+//! verification depends on, i.e., the instruction bytes and the account states. An entry is
+//! only used if the content hash matches, so changing any input invalidates it. The cache is
+//! loaded once at the start of a validation run and saved once at its end. Since a cached outcome
+//! is trusted, the cache is kept in the validator's own `VALIDATOR_DIR`, and not on-chain.
+
+use crate::{
+    errors::Error, load_from_file, save_to_file, ChainDirs, COMMON_OBJECTS_DIR, VALIDATOR_DIR,
+};
+use log::debug;
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::collections::HashMap;
+
+pub const VERIFICATION_CACHE_FILE: &str = "verification_cache.json";
+
+/// Counts the verifications that were skipped, since their outcome was cached.
+pub const VERIFICATION_CACHE_HIT_COUNTER: &str = "validator.verification_cache.hit";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct CacheEntry {
+    content_hash: String,
+    valid: bool,
+}
+
+/// Hashes the inputs of a verification. Each part is length-prefixed, so that moving bytes
+/// between the parts changes the hash.
+pub fn content_hash(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha3_256::new();
+    for part in parts {
+        hasher.input(&(part.len() as u64).to_le_bytes());
+        hasher.input(part);
+    }
+    hex::encode(hasher.result())
+}
+
+/// The verification outcomes of a database directory, keyed by the transaction id. An empty
+/// cache, e.g., `VerificationCache::default()`, verifies everything.
+#[derive(Clone, Debug, Default)]
+pub struct VerificationCache {
+    entries: HashMap<u32, CacheEntry>,
+    changed: bool,
+}
+
+impl VerificationCache {
+    /// Loads the outcomes of the earlier runs. A missing or unreadable cache file is empty.
+    pub fn load(db_dir: ChainDirs) -> Self {
+        let entries = load_from_file(
+            db_dir,
+            VALIDATOR_DIR,
+            COMMON_OBJECTS_DIR,
+            VERIFICATION_CACHE_FILE,
+        )
+        .unwrap_or_default();
+        Self {
+            entries,
+            changed: false,
+        }
+    }
+
+    /// Saves the cache if a new outcome was added since it was loaded.
+    pub fn save(&self, db_dir: ChainDirs) -> Result<(), Error> {
+        if !self.changed {
+            return Ok(());
+        }
+        save_to_file(
+            db_dir,
+            VALIDATOR_DIR,
+            COMMON_OBJECTS_DIR,
+            VERIFICATION_CACHE_FILE,
+            &self.entries,
+        )
+    }
+
+    /// Runs `verify` unless the cache already holds the outcome of verifying the same content
+    /// for `tx_id`. A cached failure is reported as `Error::CachedVerificationFailure`.
+    pub fn verify<F>(&mut self, tx_id: u32, content_hash: String, verify: F) -> Result<(), Error>
+    where
+        F: FnOnce() -> Result<(), Error>,
+    {
+        if let Some(entry) = self.entries.get(&tx_id) {
+            if entry.content_hash == content_hash {
+                debug!("tx-{}: Using the cached verification outcome.", tx_id);
+                counter!(VERIFICATION_CACHE_HIT_COUNTER, 1);
+                return if entry.valid {
+                    Ok(())
+                } else {
+                    Err(Error::CachedVerificationFailure { tx_id })
+                };
+            }
+        }
+
+        let result = verify();
+        self.entries.insert(
+            tx_id,
+            CacheEntry {
+                content_hash,
+                valid: result.is_ok(),
+            },
+        );
+        self.changed = true;
+        result
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_db_dir;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_cached_verify() {
+        let db_dir = test_db_dir("verification_cache");
+
+        let calls = Cell::new(0);
+        let verify_ok = || {
+            calls.set(calls.get() + 1);
+            Ok(())
+        };
+        let hash = content_hash(&["tx".as_bytes(), "balance".as_bytes()]);
+
+        // The second verification with the same content is skipped with the same outcome.
+        let mut cache = VerificationCache::load(db_dir.clone());
+        cache.verify(1, hash.clone(), verify_ok).unwrap();
+        cache.verify(1, hash.clone(), verify_ok).unwrap();
+        assert_eq!(calls.get(), 1);
+
+        // Changing the content invalidates the entry.
+        let new_hash = content_hash(&["tx".as_bytes(), "new balance".as_bytes()]);
+        cache.verify(1, new_hash.clone(), verify_ok).unwrap();
+        assert_eq!(calls.get(), 2);
+
+        // Failures are cached too.
+        let verify_err = || {
+            calls.set(calls.get() + 1);
+            Err(Error::DecodeError)
+        };
+        assert!(cache.verify(2, hash.clone(), verify_err).is_err());
+        match cache.verify(2, hash.clone(), verify_err) {
+            Err(Error::CachedVerificationFailure { tx_id }) => assert_eq!(tx_id, 2),
+            other => panic!("Expected CachedVerificationFailure, got {:?}", other),
+        }
+        assert_eq!(calls.get(), 3);
+
+        // Nothing is written until the cache is saved, and the saved outcomes are used by the
+        // next run.
+        assert!(VerificationCache::load(db_dir.clone()).entries.is_empty());
+        cache.save(db_dir.clone()).unwrap();
+        let mut cache = VerificationCache::load(db_dir.clone());
+        cache.verify(1, new_hash, verify_ok).unwrap();
+        assert!(cache.verify(2, hash, verify_err).is_err());
+        assert_eq!(calls.get(), 3);
+
+        // Moving bytes between the parts changes the hash.
+        assert_ne!(
+            content_hash(&["ab".as_bytes(), "c".as_bytes()]),
+            content_hash(&["a".as_bytes(), "bc".as_bytes()])
+        );
+
+        let _ = std::fs::remove_dir_all(&db_dir.root);
+    }
+}
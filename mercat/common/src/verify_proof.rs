@@ -0,0 +1,410 @@
+//! Verification of a single correctness proof from its encoded components. This serves as a
+//! black-box oracle when debugging the proofs of other implementations. The proofs of known
+//! values that `prove_correctness` creates serve as its test inputs.
+
+use crate::{create_rng_from_seed, encoding::Encoding, errors::Error};
+use bulletproofs::PedersenGens;
+use codec::{Decode, Encode};
+use cryptography::asset_proofs::{
+    correctness_proof::{
+        CorrectnessFinalResponse, CorrectnessInitialMessage, CorrectnessProverAwaitingChallenge,
+        CorrectnessVerifier,
+    },
+    encryption_proofs::{
+        single_property_prover, single_property_verifier, AssetProofVerifier, ZKPChallenge,
+        ENCRYPTION_PROOFS_CHALLENGE_LABEL, ENCRYPTION_PROOFS_LABEL,
+    },
+    transcript::{TranscriptProtocol, UpdateTranscript},
+    CipherText, CommitmentWitness, ElgamalPublicKey, ElgamalSecretKey,
+};
+use curve25519_dalek::{ristretto::CompressedRistretto, scalar::Scalar};
+use merlin::Transcript;
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt,
+};
+
+/// The encoded components of a correctness proof, as given on the command line.
+pub struct RawCorrectnessProof<'a> {
+    pub value: &'a str,
+    pub pub_key: &'a str,
+    pub cipher: &'a str,
+    pub initial_message: &'a str,
+    pub challenge: &'a str,
+    pub response: &'a str,
+    pub encoding: Encoding,
+}
+
+/// A non-interactive correctness proof, together with the statement that it proves.
+#[derive(Clone, Debug)]
+pub struct CorrectnessProof {
+    pub value: Scalar,
+    pub cipher: CipherText,
+    pub initial_message: CorrectnessInitialMessage,
+    pub final_response: CorrectnessFinalResponse,
+}
+
+fn malformed(field: &str, reason: String) -> Error {
+    Error::MalformedProofInput {
+        field: field.to_string(),
+        reason,
+    }
+}
+
+/// Decodes `value` and checks that it is `points` canonical Ristretto points.
+fn decode_points(
+    field: &str,
+    value: &str,
+    points: usize,
+    encoding: Encoding,
+) -> Result<Vec<u8>, Error> {
+    let bytes = encoding
+        .decode(value)
+        .map_err(|error| malformed(field, error.to_string()))?;
+    if bytes.len() != 32 * points {
+        return Err(malformed(
+            field,
+            format!("expected {} bytes, got {}", 32 * points, bytes.len()),
+        ));
+    }
+    for point in bytes.chunks(32) {
+        if CompressedRistretto::from_slice(point)
+            .decompress()
+            .is_none()
+        {
+            return Err(malformed(
+                field,
+                String::from("not a valid Ristretto point"),
+            ));
+        }
+    }
+    Ok(bytes)
+}
+
+/// Decodes `value` and checks that it is a canonical scalar.
+fn decode_scalar(field: &str, value: &str, encoding: Encoding) -> Result<Scalar, Error> {
+    let bytes = encoding
+        .decode(value)
+        .map_err(|error| malformed(field, error.to_string()))?;
+    let bytes: [u8; 32] = bytes
+        .as_slice()
+        .try_into()
+        .map_err(|_| malformed(field, format!("expected 32 bytes, got {}", bytes.len())))?;
+    Scalar::from_canonical_bytes(bytes)
+        .ok_or_else(|| malformed(field, String::from("not a canonical scalar")))
+}
+
+fn decode_object<T: Decode>(field: &str, bytes: &[u8]) -> Result<T, Error> {
+    T::decode(&mut &bytes[..]).map_err(|error| malformed(field, error.to_string()))
+}
+
+/// Returns whether the proof is valid. Fails if any of the components is malformed.
+pub fn verify_raw_correctness_proof(raw: &RawCorrectnessProof) -> Result<bool, Error> {
+    let encoding = raw.encoding;
+    let value = decode_scalar("value", raw.value, encoding)?;
+    let pub_key: ElgamalPublicKey = decode_object(
+        "pubkey",
+        &decode_points("pubkey", raw.pub_key, 1, encoding)?,
+    )?;
+    let cipher: CipherText =
+        decode_object("cipher", &decode_points("cipher", raw.cipher, 2, encoding)?)?;
+    let initial_message: CorrectnessInitialMessage = decode_object(
+        "initial",
+        &decode_points("initial", raw.initial_message, 2, encoding)?,
+    )?;
+    let challenge = ZKPChallenge::try_from(decode_scalar("challenge", raw.challenge, encoding)?)
+        .map_err(|error| malformed("challenge", error.to_string()))?;
+    let response: CorrectnessFinalResponse = decode_object(
+        "response",
+        &decode_scalar("response", raw.response, encoding)?.to_bytes(),
+    )?;
+
+    let gens = PedersenGens::default();
+    let verifier = CorrectnessVerifier {
+        value,
+        pub_key,
+        cipher,
+        pc_gens: &gens,
+    };
+    Ok(verifier
+        .verify(&challenge, &initial_message, &response)
+        .is_ok())
+}
+
+/// A correctness proof of a freshly encrypted value, with its components encoded the way
+/// `verify_raw_correctness_proof` reads them.
+#[derive(Clone, Debug)]
+pub struct EncodedCorrectnessProof {
+    pub secret_key: String,
+    pub value: String,
+    pub pub_key: String,
+    pub cipher: String,
+    pub initial_message: String,
+    pub challenge: String,
+    pub response: String,
+}
+
+/// Formats the proof as the arguments of the `verify-proof` command.
+impl fmt::Display for EncodedCorrectnessProof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "--value {} --pubkey {} --cipher {} --initial {} --challenge {} --response {}",
+            self.value,
+            self.pub_key,
+            self.cipher,
+            self.initial_message,
+            self.challenge,
+            self.response
+        )
+    }
+}
+
+/// Recomputes the challenge of a non-interactive proof, the same way as
+/// `single_property_prover` and `single_property_verifier` do.
+fn fiat_shamir_challenge(
+    initial_message: &CorrectnessInitialMessage,
+) -> Result<ZKPChallenge, Error> {
+    let mut transcript = Transcript::new(ENCRYPTION_PROOFS_LABEL);
+    initial_message
+        .update_transcript(&mut transcript)
+        .map_err(|error| Error::LibraryError { error })?;
+    transcript
+        .scalar_challenge(ENCRYPTION_PROOFS_CHALLENGE_LABEL)
+        .map_err(|error| Error::LibraryError { error })
+}
+
+/// Encrypts `value` under the given encoded secret key, or a new one if there is none, and
+/// proves the correctness of the encryption.
+pub fn prove_correctness(
+    value: u32,
+    secret_key: Option<&str>,
+    seed: String,
+    encoding: Encoding,
+) -> Result<EncodedCorrectnessProof, Error> {
+    let mut rng = create_rng_from_seed(Some(seed))?;
+    let secret_key = match secret_key {
+        Some(secret_key) => decode_scalar("secret-key", secret_key, encoding)?,
+        None => Scalar::random(&mut rng),
+    };
+    let pub_key = ElgamalSecretKey::new(secret_key).get_public_key();
+    let witness = CommitmentWitness::new(Scalar::from(value), Scalar::random(&mut rng));
+    let cipher = pub_key.encrypt(&witness);
+
+    let gens = PedersenGens::default();
+    let (initial_message, final_response) = single_property_prover(
+        CorrectnessProverAwaitingChallenge::new(pub_key, witness, &gens),
+        &mut rng,
+    )
+    .map_err(|error| Error::LibraryError { error })?;
+    let challenge = fiat_shamir_challenge(&initial_message)?;
+
+    Ok(EncodedCorrectnessProof {
+        secret_key: encoding.encode(secret_key.as_bytes()),
+        value: encoding.encode(Scalar::from(value).as_bytes()),
+        pub_key: encoding.encode(pub_key.encode()),
+        cipher: encoding.encode(cipher.encode()),
+        initial_message: encoding.encode(initial_message.encode()),
+        challenge: encoding.encode(challenge.x().as_bytes()),
+        response: encoding.encode(final_response.encode()),
+    })
+}
+
+/// Accepts if every proof proves the `(value, cipher)` of the verifier, and verifies. A proof of a
+/// different statement is reported as a disagreement, even if it is valid on its own.
+pub fn verify_any(
+    verifier: &CorrectnessVerifier,
+    proofs: &[CorrectnessProof],
+) -> Result<(), Error> {
+    for (index, proof) in proofs.iter().enumerate() {
+        if proof.value != verifier.value || proof.cipher != verifier.cipher {
+            return Err(Error::ProofDisagreement { index });
+        }
+        single_property_verifier(verifier, proof.initial_message, proof.final_response)
+            .map_err(|_| Error::InvalidCorrectnessProof { index })?;
+    }
+    Ok(())
+}
+
+// ------------------------------------------------------------------------------------------------
+// -                                            Tests                                             -
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptography::asset_proofs::{
+        correctness_proof::CorrectnessProverAwaitingChallenge,
+        encryption_proofs::single_property_prover, CommitmentWitness, ElgamalSecretKey,
+    };
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn point_hex(multiple: u64) -> String {
+        hex::encode(
+            (Scalar::from(multiple) * RISTRETTO_BASEPOINT_POINT)
+                .compress()
+                .as_bytes(),
+        )
+    }
+
+    fn scalar_hex(value: u64) -> String {
+        hex::encode(Scalar::from(value).as_bytes())
+    }
+
+    fn assert_malformed(result: Result<bool, Error>, want_field: &str) {
+        match result {
+            Err(Error::MalformedProofInput { field, .. }) => assert_eq!(field, want_field),
+            other => panic!("Expected MalformedProofInput, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_verify_raw_correctness_proof() {
+        let (value, pub_key, challenge, response) =
+            (scalar_hex(7), point_hex(2), scalar_hex(3), scalar_hex(4));
+        let pair = format!("{}{}", point_hex(5), point_hex(6));
+        let raw = RawCorrectnessProof {
+            value: &value,
+            pub_key: &pub_key,
+            cipher: &pair,
+            initial_message: &pair,
+            challenge: &challenge,
+            response: &response,
+            encoding: Encoding::Hex,
+        };
+        // Well-formed, but not a proof.
+        assert_eq!(verify_raw_correctness_proof(&raw).unwrap(), false);
+
+        let short = point_hex(2)[2..].to_string();
+        assert_malformed(
+            verify_raw_correctness_proof(&RawCorrectnessProof {
+                pub_key: &short,
+                ..raw
+            }),
+            "pubkey",
+        );
+        assert_malformed(
+            verify_raw_correctness_proof(&RawCorrectnessProof {
+                cipher: &point_hex(5),
+                ..raw
+            }),
+            "cipher",
+        );
+        // The group order is close to 2^252, so all ones is not a canonical scalar.
+        let non_canonical = "ff".repeat(32);
+        assert_malformed(
+            verify_raw_correctness_proof(&RawCorrectnessProof {
+                response: &non_canonical,
+                ..raw
+            }),
+            "response",
+        );
+        // Not every 32 bytes are the encoding of a point.
+        let non_point = format!("{}{}", non_canonical, point_hex(6));
+        assert_malformed(
+            verify_raw_correctness_proof(&RawCorrectnessProof {
+                initial_message: &non_point,
+                ..raw
+            }),
+            "initial",
+        );
+        assert_malformed(
+            verify_raw_correctness_proof(&RawCorrectnessProof {
+                value: "not hex",
+                ..raw
+            }),
+            "value",
+        );
+    }
+
+    fn prove(
+        rng: &mut StdRng,
+        gens: &PedersenGens,
+        pub_key: ElgamalPublicKey,
+        value: u32,
+        blinding: Scalar,
+    ) -> CorrectnessProof {
+        let witness = CommitmentWitness::new(Scalar::from(value), blinding);
+        let cipher = pub_key.encrypt(&witness);
+        let (initial_message, final_response) = single_property_prover(
+            CorrectnessProverAwaitingChallenge::new(pub_key, witness, gens),
+            rng,
+        )
+        .unwrap();
+        CorrectnessProof {
+            value: Scalar::from(value),
+            cipher,
+            initial_message,
+            final_response,
+        }
+    }
+
+    #[test]
+    fn test_verify_any() {
+        let mut rng = StdRng::from_seed([17u8; 32]);
+        let gens = PedersenGens::default();
+        let pub_key = ElgamalSecretKey::new(Scalar::random(&mut rng)).get_public_key();
+        let blinding = Scalar::random(&mut rng);
+
+        // Two independent proofs of the same encryption of 7.
+        let first = prove(&mut rng, &gens, pub_key, 7, blinding);
+        let second = prove(&mut rng, &gens, pub_key, 7, blinding);
+        let verifier = CorrectnessVerifier {
+            value: first.value,
+            pub_key,
+            cipher: first.cipher,
+            pc_gens: &gens,
+        };
+        assert!(verify_any(&verifier, &[first.clone(), second.clone()]).is_ok());
+
+        // A valid proof, but of another value.
+        let other = prove(&mut rng, &gens, pub_key, 8, blinding);
+        match verify_any(&verifier, &[first, other, second]) {
+            Err(Error::ProofDisagreement { index }) => assert_eq!(index, 1),
+            result => panic!("Expected ProofDisagreement, got {:?}", result),
+        }
+    }
+
+    /// Reads the arguments of the `verify-proof` command back from the printed proof.
+    fn verify_printed(printed: &str, encoding: Encoding) -> Result<bool, Error> {
+        let args: Vec<&str> = printed.split_whitespace().collect();
+        let arg = |name: &str| {
+            let position = args.iter().position(|arg| *arg == name).unwrap();
+            args[position + 1]
+        };
+        verify_raw_correctness_proof(&RawCorrectnessProof {
+            value: arg("--value"),
+            pub_key: arg("--pubkey"),
+            cipher: arg("--cipher"),
+            initial_message: arg("--initial"),
+            challenge: arg("--challenge"),
+            response: arg("--response"),
+            encoding,
+        })
+    }
+
+    #[test]
+    fn test_prove_then_verify() {
+        let seed = base64::encode([42u8; 32]);
+        for encoding in [Encoding::Hex, Encoding::Base64].iter() {
+            let proof = prove_correctness(7, None, seed.clone(), *encoding).unwrap();
+            assert!(verify_printed(&proof.to_string(), *encoding).unwrap());
+
+            // A supplied secret key is used instead of a new one.
+            let again =
+                prove_correctness(7, Some(&proof.secret_key), seed.clone(), *encoding).unwrap();
+            assert_eq!(again.pub_key, proof.pub_key);
+            assert!(verify_printed(&again.to_string(), *encoding).unwrap());
+        }
+
+        // A proof does not verify for another value.
+        let proof = prove_correctness(7, None, seed.clone(), Encoding::Hex).unwrap();
+        let other = prove_correctness(8, None, seed, Encoding::Hex).unwrap();
+        let mixed = EncodedCorrectnessProof {
+            value: other.value,
+            ..proof
+        };
+        assert!(!verify_printed(&mixed.to_string(), Encoding::Hex).unwrap());
+    }
+}
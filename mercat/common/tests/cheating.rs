@@ -0,0 +1,37 @@
+//! Integration tests link against the library as it is built for the CLIs, i.e. without
+//! `cfg(test)`. Without the `cheating` feature, no cheat code path must be reachable.
+
+#![cfg(not(feature = "cheating"))]
+
+use mercat_common::{
+    account_create::process_create_account, encoding::Encoding, ensure_cheating_supported,
+    errors::Error, ChainDirs,
+};
+use std::path::PathBuf;
+
+#[test]
+fn cheating_is_not_compiled_in() {
+    assert!(ensure_cheating_supported(false).is_ok());
+    match ensure_cheating_supported(true) {
+        Err(Error::CheatingNotSupported) => {}
+        other => panic!("Expected CheatingNotSupported, got {:?}", other),
+    }
+
+    // The CLI entry points reject the cheat flag before touching the database.
+    let result = process_create_account(
+        None,
+        ChainDirs::new(PathBuf::from("/nonexistent")),
+        "ACME".to_string(),
+        "alice".to_string(),
+        false,
+        Encoding::default(),
+        0,
+        true,
+        false,
+        false,
+    );
+    match result {
+        Err(Error::CheatingNotSupported) => {}
+        other => panic!("Expected CheatingNotSupported, got {:?}", other),
+    }
+}
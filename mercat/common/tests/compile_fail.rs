@@ -0,0 +1,11 @@
+//! The misuses of the API that must be rejected by the compiler.
+
+#[test]
+fn compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/mixed_balances.rs");
+    // With the `cheating` feature, the cheat code is meant to be reachable.
+    if cfg!(not(feature = "cheating")) {
+        t.compile_fail("tests/ui/cheating_without_feature.rs");
+    }
+}
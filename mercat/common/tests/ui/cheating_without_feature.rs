@@ -0,0 +1,9 @@
+//! The cheat code paths are only compiled in with the `cheating` feature, so a binary built
+//! without it cannot reach them. `cheat_on_amount` is gated by the feature alone, and not by
+//! `cfg(test)`.
+
+use mercat_common::cheating::cheat_on_amount;
+
+fn main() {
+    let _ = cheat_on_amount(0, 1);
+}
@@ -0,0 +1,22 @@
+//! A pending balance already has the pending transfers subtracted, so it cannot be used as the
+//! confirmed balance that the pending balance is computed from.
+
+use mercat_common::{
+    balances::{compute_enc_pending_balance, PendingBalance},
+    ChainDirs, OrderingState,
+};
+use std::path::PathBuf;
+
+fn recompute_from_pending(pending_balance: PendingBalance, ordering_state: OrderingState) {
+    let _ = compute_enc_pending_balance(
+        &String::from("alice"),
+        &String::from("ACME"),
+        ordering_state,
+        None,
+        pending_balance,
+        0,
+        ChainDirs::new(PathBuf::new()),
+    );
+}
+
+fn main() {}
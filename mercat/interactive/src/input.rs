@@ -1,5 +1,5 @@
 use log::info;
-use mercat_common::gen_seed;
+use mercat_common::{encoding::Encoding, gen_seed};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -43,6 +43,14 @@ pub struct CreateUserAccountInfo {
     /// Space separated list of ticker names.
     #[structopt(short, long, help = "Space separated list of a ticker names.")]
     pub valid_ticker_names: Vec<String>,
+
+    /// The text encoding of the binary data that is read from or printed to the command line.
+    #[structopt(
+        long,
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
+    )]
+    pub encoding: Encoding,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
@@ -77,6 +85,14 @@ pub struct CreateMediatorAccountInfo {
         help = "Base64 encoding of an initial seed for the RNG. If not provided, the seed will be chosen at random."
     )]
     pub seed: Option<String>,
+
+    /// The text encoding of the binary data that is read from or printed to the command line.
+    #[structopt(
+        long,
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
+    )]
+    pub encoding: Encoding,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
@@ -115,6 +131,14 @@ pub struct IssueAssetInfo {
     /// The issuer's name. An account must have already been created for this user.
     #[structopt(short, long, help = "The name of the issuer.")]
     pub issuer: String,
+
+    /// The text encoding of the binary data that is read from or printed to the command line.
+    #[structopt(
+        long,
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
+    )]
+    pub encoding: Encoding,
 }
 #[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
 pub struct CreateTransactionInfo {
@@ -153,21 +177,29 @@ pub struct CreateTransactionInfo {
     #[structopt(long, help = "The sender's name.")]
     pub sender: String,
 
-    /// The receiver's base64 public account.
+    /// The receiver's encoded public account.
     #[structopt(
         short,
         long,
-        help = "The receiver's base64 public account (encrypted asset id, followed by encryption public key)."
+        help = "The receiver's encoded public account (encrypted asset id, followed by encryption public key)."
     )]
     pub receiver: Vec<String>,
 
-    /// The mediator's base64 public encryption key.
-    #[structopt(short, long, help = "The mediator's base64 public encryption key.")]
+    /// The mediator's encoded public encryption key.
+    #[structopt(short, long, help = "The mediator's encoded public encryption key.")]
     pub mediator: String,
 
-    /// The sender's base64 pending balance.
-    #[structopt(short, long, help = "The sender's base64 pending balance.")]
+    /// The sender's encoded pending balance.
+    #[structopt(short, long, help = "The sender's encoded pending balance.")]
     pub pending_balance: String,
+
+    /// The text encoding of the binary data that is read from or printed to the command line.
+    #[structopt(
+        long,
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
+    )]
+    pub encoding: Encoding,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
@@ -207,13 +239,17 @@ pub struct FinalizeTransactionInfo {
     #[structopt(short, long, help = "The sender's name.")]
     pub receiver: String,
 
-    /// The initial transaction as base64 encoded string.
+    /// The encoded initial transaction.
+    #[structopt(short, long, help = "The encoded initial transaction.")]
+    pub init_tx: String,
+
+    /// The text encoding of the binary data that is read from or printed to the command line.
     #[structopt(
-        short,
         long,
-        help = "The initial transaction as base64 encoded string."
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
     )]
-    pub init_tx: String,
+    pub encoding: Encoding,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
@@ -239,21 +275,21 @@ pub struct JustifyTransferInfo {
     )]
     pub ticker: String,
 
-    /// The sender's base64 public account (encrypted asset id, followed by encryption public key).
+    /// The sender's encoded public account (encrypted asset id, followed by encryption public key).
     #[structopt(
         long,
-        help = "The sender's base64 public account (encrypted asset id, followed by encryption public key)."
+        help = "The sender's encoded public account (encrypted asset id, followed by encryption public key)."
     )]
     pub sender: Vec<String>,
 
-    /// The sender's base64 balance.
-    #[structopt(long, help = "The sender's base64 balance.")]
+    /// The sender's encoded balance.
+    #[structopt(long, help = "The sender's encoded balance.")]
     pub sender_balance: String,
 
-    /// The receiver's base64 public account (encrypted asset id, followed by encryption public key).
+    /// The receiver's encoded public account (encrypted asset id, followed by encryption public key).
     #[structopt(
         long,
-        help = "The receiver's base64 public account (encrypted asset id, followed by encryption public key)."
+        help = "The receiver's encoded public account (encrypted asset id, followed by encryption public key)."
     )]
     pub receiver: Vec<String>,
 
@@ -270,9 +306,17 @@ pub struct JustifyTransferInfo {
     )]
     pub seed: Option<String>,
 
-    /// Finalized tx as base64.
-    #[structopt(short, long, help = "Finalized tx as base64.")]
+    /// The encoded finalized tx.
+    #[structopt(short, long, help = "The encoded finalized tx.")]
     pub finalized_tx: String,
+
+    /// The text encoding of the binary data that is read from or printed to the command line.
+    #[structopt(
+        long,
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
+    )]
+    pub encoding: Encoding,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
@@ -302,20 +346,44 @@ pub struct DecryptAccountInfo {
     )]
     pub ticker: String,
 
-    /// An encrypted value as base64.
-    #[structopt(short, long, help = "An encrypted value as base64.")]
+    /// An encoded encrypted value.
+    #[structopt(short, long, help = "An encoded encrypted value.")]
     pub encrypted_value: String,
+
+    /// The text encoding of the binary data that is read from or printed to the command line.
+    #[structopt(
+        long,
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
+    )]
+    pub encoding: Encoding,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
 pub struct OpsInfo {
     /// The first encrypted value.
-    #[structopt(short, long, help = "The first encrypted value (base64 encoded)")]
+    #[structopt(
+        short,
+        long,
+        help = "The first encrypted value (hex or base64 encoded)"
+    )]
     pub first: String,
 
     /// The second encrypted value.
-    #[structopt(short, long, help = "The second encrypted value (base64 encoded)")]
+    #[structopt(
+        short,
+        long,
+        help = "The second encrypted value (hex or base64 encoded)"
+    )]
     pub second: String,
+
+    /// The text encoding of the binary data that is read from or printed to the command line.
+    #[structopt(
+        long,
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
+    )]
+    pub encoding: Encoding,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
@@ -346,6 +414,7 @@ pub fn parse_input() -> CLI {
                 db_dir: cfg.db_dir,
                 ticker: cfg.ticker,
                 valid_ticker_names: cfg.valid_ticker_names,
+                encoding: cfg.encoding,
             };
 
             info!(
@@ -367,6 +436,7 @@ pub fn parse_input() -> CLI {
                 seed,
                 db_dir,
                 user: cfg.user.clone(),
+                encoding: cfg.encoding,
             };
 
             info!(
@@ -389,6 +459,7 @@ pub fn parse_input() -> CLI {
                 amount: cfg.amount,
                 db_dir,
                 issuer: cfg.issuer,
+                encoding: cfg.encoding,
             };
 
             info!(
@@ -413,6 +484,7 @@ pub fn parse_input() -> CLI {
                 receiver: cfg.receiver,
                 mediator: cfg.mediator,
                 pending_balance: cfg.pending_balance,
+                encoding: cfg.encoding,
             };
 
             info!(
@@ -435,6 +507,7 @@ pub fn parse_input() -> CLI {
                 db_dir,
                 receiver: cfg.receiver,
                 init_tx: cfg.init_tx,
+                encoding: cfg.encoding,
             };
 
             info!(
@@ -459,6 +532,7 @@ pub fn parse_input() -> CLI {
                 mediator: cfg.mediator,
                 seed,
                 finalized_tx: cfg.finalized_tx,
+                encoding: cfg.encoding,
             };
 
             info!(
@@ -476,6 +550,7 @@ pub fn parse_input() -> CLI {
                 db_dir,
                 user: cfg.user.clone(),
                 encrypted_value: cfg.encrypted_value,
+                encoding: cfg.encoding,
             };
 
             info!(
@@ -3,7 +3,6 @@
 
 mod input;
 
-use base64;
 use codec::{Decode, Encode};
 use cryptography::{
     asset_proofs::{CommitmentWitness, ElgamalSecretKey},
@@ -22,13 +21,13 @@ use env_logger;
 use input::{parse_input, CLI};
 use log::info;
 use mercat_common::{
-    account_issue::process_issue_asset, create_rng_from_seed, debug_decrypt_base64_account_balance,
-    errors::Error, init_print_logger, justify::process_create_mediator, load_object, save_object,
-    user_public_account_file, user_secret_account_file, OrderedPubAccount, OFF_CHAIN_DIR,
-    ON_CHAIN_DIR, SECRET_ACCOUNT_FILE,
+    account_issue::process_issue_asset, create_rng_from_seed,
+    debug_decrypt_encoded_account_balance, encoding::Encoding, errors::Error, init_print_logger,
+    install_panic_hook, justify::process_create_mediator, load_object, save_object,
+    secret_store::load_secret_object, user_public_account_file, user_secret_account_file,
+    ChainDirs, OrderedPubAccount, OFF_CHAIN_DIR, ON_CHAIN_DIR, SECRET_ACCOUNT_FILE,
 };
 use rand::{CryptoRng, RngCore};
-use std::path::PathBuf;
 
 const TX_ID: u32 = 1;
 
@@ -41,83 +40,95 @@ fn main() {
     env_logger::init();
     info!("Starting the program.");
     init_print_logger();
+    install_panic_hook();
 
     let args = parse_input();
 
     match args {
         CLI::CreateUserAccount(cfg) => {
-            let db_dir = cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap();
+            let db_dir = ChainDirs::new(cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap());
             process_create_account(
                 cfg.seed,
                 db_dir,
                 cfg.user,
                 cfg.ticker,
                 cfg.valid_ticker_names,
+                cfg.encoding,
             )
             .unwrap()
         }
         CLI::CreateMediatorAccount(cfg) => process_create_mediator(
             cfg.seed.ok_or(Error::EmptySeed).unwrap(),
-            cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap(),
+            ChainDirs::new(cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap()),
             cfg.user,
+            cfg.encoding,
+            false,
         )
         .unwrap(),
         CLI::Mint(cfg) => process_issue_asset(
             cfg.seed.ok_or(Error::EmptySeed).unwrap(),
-            cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap(),
+            ChainDirs::new(cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap()),
             cfg.issuer,
             cfg.account_id_from_ticker,
             cfg.amount,
             true,
+            cfg.encoding,
             TX_ID,
             false,
         )
         .unwrap(),
         CLI::CreateTransaction(cfg) => process_create_tx(
             cfg.seed.ok_or(Error::EmptySeed).unwrap(),
-            cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap(),
+            ChainDirs::new(cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap()),
             cfg.sender,
             cfg.receiver,
             cfg.mediator,
             cfg.account_id_from_ticker,
             cfg.amount,
             cfg.pending_balance,
+            cfg.encoding,
         )
         .unwrap(),
         CLI::FinalizeTransaction(cfg) => process_finalize_tx(
             cfg.seed.ok_or(Error::EmptySeed).unwrap(),
-            cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap(),
+            ChainDirs::new(cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap()),
             cfg.receiver,
             cfg.account_id_from_ticker,
             cfg.amount,
             cfg.init_tx,
+            cfg.encoding,
         )
         .unwrap(),
         CLI::JustifyTransaction(cfg) => justify_asset_transfer_transaction(
             cfg.seed.ok_or(Error::EmptySeed).unwrap(),
-            cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap(),
+            ChainDirs::new(cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap()),
             cfg.sender,
             cfg.sender_balance,
             cfg.receiver,
             cfg.mediator,
             cfg.ticker,
             cfg.finalized_tx,
+            cfg.encoding,
         )
         .unwrap(),
         CLI::Decrypt(cfg) => info!(
             "Account balance: {}",
-            debug_decrypt_base64_account_balance(
+            debug_decrypt_encoded_account_balance(
                 cfg.user,
                 cfg.encrypted_value,
+                cfg.encoding,
                 cfg.ticker,
-                cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap()
+                ChainDirs::new(cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap())
             )
             .unwrap()
         ),
-        CLI::Add(cfg) => info!("Result: {}", add_subtract(Op::Add, cfg.first, cfg.second,)),
+        CLI::Add(cfg) => info!(
+            "Result: {}",
+            add_subtract(Op::Add, cfg.first, cfg.second, cfg.encoding)
+        ),
         CLI::Subtract(cfg) => info!(
             "Result: {}",
-            add_subtract(Op::Subtract, cfg.first, cfg.second,)
+            add_subtract(Op::Subtract, cfg.first, cfg.second, cfg.encoding)
         ),
     };
     info!("The program finished successfully.");
@@ -125,10 +136,11 @@ fn main() {
 
 fn process_create_account(
     seed: Option<String>,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     user: String,
     ticker: String,
     ticker_names: Vec<String>,
+    encoding: Encoding,
 ) -> Result<(), Error> {
     let mut rng = create_rng_from_seed(seed)?;
 
@@ -162,10 +174,12 @@ fn process_create_account(
     let account_id = account_tx.pub_account.enc_asset_id.clone();
 
     info!(
-        "CLI log: tx-{}:\n\nAccount ID as base64:\n{}\n\nAccount Transaction as base64:\n{}\n",
+        "CLI log: tx-{}:\n\nAccount ID as {}:\n{}\n\nAccount Transaction as {}:\n{}\n",
         TX_ID,
-        base64::encode(account_id.encode()),
-        base64::encode(account_tx.encode())
+        encoding,
+        encoding.encode(account_id.encode()),
+        encoding,
+        encoding.encode(account_tx.encode())
     );
 
     let ordered_account = OrderedPubAccount {
@@ -209,13 +223,14 @@ fn create_secret_account<R: RngCore + CryptoRng>(
 
 pub fn process_create_tx(
     seed: String,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     sender: String,
     receiver: Vec<String>,
     mediator: String,
     ticker: String,
     amount: u32,
     pending_balance: String,
+    encoding: Encoding,
 ) -> Result<(), Error> {
     let mut rng = create_rng_from_seed(Some(seed))?;
 
@@ -226,27 +241,22 @@ pub fn process_create_tx(
         &user_public_account_file(&ticker),
     )?;
     let sender_account = Account {
-        secret: load_object(
-            db_dir.clone(),
-            OFF_CHAIN_DIR,
-            &sender,
-            &user_secret_account_file(&ticker),
-        )?,
+        secret: load_secret_object(db_dir.clone(), &sender, &user_secret_account_file(&ticker))?,
         public: sender_ordered_pub_account.pub_account,
     };
 
     // Calculate the pending
-    let mut data: &[u8] = &base64::decode(pending_balance).unwrap();
+    let mut data: &[u8] = &encoding.decode(pending_balance).unwrap();
     let pending_balance = EncryptedAmount::decode(&mut data).unwrap(); // For now the same as initial balance
 
-    let mut data0: &[u8] = &base64::decode(&receiver[0]).unwrap();
-    let mut data1: &[u8] = &base64::decode(&receiver[1]).unwrap();
+    let mut data0: &[u8] = &encoding.decode(&receiver[0]).unwrap();
+    let mut data1: &[u8] = &encoding.decode(&receiver[1]).unwrap();
     let receiver_pub_account = PubAccount {
         enc_asset_id: EncryptedAssetId::decode(&mut data0).unwrap(),
         owner_enc_pub_key: EncryptionPubKey::decode(&mut data1).unwrap(),
     };
 
-    let mut data: &[u8] = &base64::decode(mediator).unwrap();
+    let mut data: &[u8] = &encoding.decode(mediator).unwrap();
     let mediator_account = EncryptionPubKey::decode(&mut data).unwrap();
 
     // Initialize the transaction.
@@ -271,8 +281,9 @@ pub fn process_create_tx(
         .map_err(|error| Error::LibraryError { error })?;
 
     info!(
-        "CLI log: Initialized Transaction as base64:\n{}\n",
-        base64::encode(asset_tx.encode())
+        "CLI log: Initialized Transaction as {}:\n{}\n",
+        encoding,
+        encoding.encode(asset_tx.encode())
     );
 
     Ok(())
@@ -280,11 +291,12 @@ pub fn process_create_tx(
 
 pub fn process_finalize_tx(
     seed: String,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     receiver: String,
     ticker: String,
     amount: u32,
     init_tx: String,
+    encoding: Encoding,
 ) -> Result<(), Error> {
     let mut rng = create_rng_from_seed(Some(seed))?;
 
@@ -296,16 +308,15 @@ pub fn process_finalize_tx(
     )?;
 
     let receiver_account = Account {
-        secret: load_object(
+        secret: load_secret_object(
             db_dir.clone(),
-            OFF_CHAIN_DIR,
             &receiver,
             &user_secret_account_file(&ticker),
         )?,
         public: receiver_ordered_pub_account.pub_account,
     };
 
-    let mut data: &[u8] = &base64::decode(&init_tx).unwrap();
+    let mut data: &[u8] = &encoding.decode(&init_tx).unwrap();
     let tx = InitializedTransferTx::decode(&mut data).unwrap();
 
     // Finalize the transaction.
@@ -316,8 +327,9 @@ pub fn process_finalize_tx(
 
     // Save the artifacts to file.
     info!(
-        "CLI log: Finalized Transaction as base64:\n{}\n",
-        base64::encode(asset_tx.encode())
+        "CLI log: Finalized Transaction as {}:\n{}\n",
+        encoding,
+        encoding.encode(asset_tx.encode())
     );
 
     Ok(())
@@ -325,39 +337,36 @@ pub fn process_finalize_tx(
 
 pub fn justify_asset_transfer_transaction(
     seed: String,
-    db_dir: PathBuf,
+    db_dir: ChainDirs,
     sender: Vec<String>,
     sender_balance: String,
     receiver: Vec<String>,
     mediator: String,
     ticker: String,
     finalized_tx: String,
+    encoding: Encoding,
 ) -> Result<(), Error> {
     // Load the transaction, mediator's credentials, and issuer's public account.
     let mut rng = create_rng_from_seed(Some(seed))?;
 
-    let mut data: &[u8] = &base64::decode(&finalized_tx).unwrap();
+    let mut data: &[u8] = &encoding.decode(&finalized_tx).unwrap();
     let asset_tx = FinalizedTransferTx::decode(&mut data).unwrap();
 
-    let mediator_account: MediatorAccount = load_object(
-        db_dir.clone(),
-        OFF_CHAIN_DIR,
-        &mediator,
-        SECRET_ACCOUNT_FILE,
-    )?;
+    let mediator_account: MediatorAccount =
+        load_secret_object(db_dir.clone(), &mediator, SECRET_ACCOUNT_FILE)?;
 
-    let mut data0: &[u8] = &base64::decode(&sender[0]).unwrap();
-    let mut data1: &[u8] = &base64::decode(&sender[1]).unwrap();
+    let mut data0: &[u8] = &encoding.decode(&sender[0]).unwrap();
+    let mut data1: &[u8] = &encoding.decode(&sender[1]).unwrap();
     let sender_pub_account = PubAccount {
         enc_asset_id: EncryptedAssetId::decode(&mut data0).unwrap(),
         owner_enc_pub_key: EncryptionPubKey::decode(&mut data1).unwrap(),
     };
 
-    let mut data: &[u8] = &base64::decode(&sender_balance).unwrap();
+    let mut data: &[u8] = &encoding.decode(&sender_balance).unwrap();
     let sender_balance = EncryptedAmount::decode(&mut data).unwrap();
 
-    let mut data0: &[u8] = &base64::decode(&receiver[0]).unwrap();
-    let mut data1: &[u8] = &base64::decode(&receiver[1]).unwrap();
+    let mut data0: &[u8] = &encoding.decode(&receiver[0]).unwrap();
+    let mut data1: &[u8] = &encoding.decode(&receiver[1]).unwrap();
     let receiver_pub_account = PubAccount {
         enc_asset_id: EncryptedAssetId::decode(&mut data0).unwrap(),
         owner_enc_pub_key: EncryptionPubKey::decode(&mut data1).unwrap(),
@@ -384,21 +393,22 @@ pub fn justify_asset_transfer_transaction(
         .map_err(|error| Error::LibraryError { error })?;
 
     info!(
-        "CLI log: Justified Transaction as base64:\n{}\n",
-        base64::encode(justified_tx.encode())
+        "CLI log: Justified Transaction as {}:\n{}\n",
+        encoding,
+        encoding.encode(justified_tx.encode())
     );
 
     Ok(())
 }
 
-fn add_subtract(op: Op, first: String, second: String) -> String {
-    let mut data: &[u8] = &base64::decode(first).unwrap();
+fn add_subtract(op: Op, first: String, second: String, encoding: Encoding) -> String {
+    let mut data: &[u8] = &encoding.decode(first).unwrap();
     let first = EncryptedAmount::decode(&mut data).unwrap();
-    let mut data: &[u8] = &base64::decode(second).unwrap();
+    let mut data: &[u8] = &encoding.decode(second).unwrap();
     let second = EncryptedAmount::decode(&mut data).unwrap();
 
     match op {
-        Op::Add => base64::encode((first + second).encode()),
-        Op::Subtract => base64::encode((first - second).encode()),
+        Op::Add => encoding.encode((first + second).encode()),
+        Op::Subtract => encoding.encode((first - second).encode()),
     }
 }
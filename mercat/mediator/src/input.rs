@@ -1,6 +1,9 @@
 use confy;
 use log::info;
-use mercat_common::{gen_seed, save_config};
+use mercat_common::{
+    encoding::Encoding, gen_seed, save_config, tx_ids::TxIdArg, ChainDirs, RejectionKind,
+    RejectionReason,
+};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -29,6 +32,24 @@ pub struct CreateMediatorAccountInfo {
     )]
     pub db_dir: Option<PathBuf>,
 
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
     /// An optional seed, to feed to the RNG, that can be passed to reproduce a previous run of this CLI.
     /// The seed can be found inside the logs.
     #[structopt(
@@ -45,6 +66,23 @@ pub struct CreateMediatorAccountInfo {
         help = "Path to save the input command line arguments as a config file."
     )]
     pub save_config: Option<PathBuf>,
+
+    /// Encrypts the secret mediator file with a passphrase. The passphrase is read from the
+    /// `MERCAT_PASSPHRASE` environment variable, or from the terminal if it is not set. The
+    /// other commands ask for the passphrase when they load an encrypted file.
+    #[structopt(
+        long,
+        help = "Encrypt the secret mediator file with a passphrase from MERCAT_PASSPHRASE or the terminal."
+    )]
+    pub encrypt_secrets: bool,
+
+    /// The text encoding of the binary data that is read from or printed to the command line.
+    #[structopt(
+        long,
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
+    )]
+    pub encoding: Encoding,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
@@ -68,6 +106,24 @@ pub struct JustifyIssuanceInfo {
     )]
     pub db_dir: Option<PathBuf>,
 
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
     /// The transaction ID for the asset issuance transaction.
     /// This ID must be the same as the one used to initialize the asset issuance,
     /// using the `mercat-account` CLI.
@@ -92,13 +148,21 @@ pub struct JustifyIssuanceInfo {
     )]
     pub seed: Option<String>,
 
-    /// Whether to reject an issuance transaction.
+    /// The category of the reason for rejecting an issuance transaction, if rejecting.
     #[structopt(
         short,
         long,
-        help = "If present the mediator will reject the transaction."
+        help = "If present the mediator will reject the transaction. One of compliance, wrong-amount, unknown-counterparty, or other."
     )]
-    pub reject: bool,
+    pub reject: Option<RejectionKind>,
+
+    /// A free-form explanation of the rejection.
+    #[structopt(
+        long,
+        requires = "reject",
+        help = "A message explaining the rejection to the issuer."
+    )]
+    pub reject_message: Option<String>,
 
     /// An optional path to save the config used for this experiment.
     #[structopt(
@@ -109,7 +173,10 @@ pub struct JustifyIssuanceInfo {
     pub save_config: Option<PathBuf>,
 
     /// Instructs the CLI to act as a cheater.
-    #[structopt(long, help = "Instructs the CLI to act as a cheater.")]
+    #[structopt(
+        long,
+        help = "Instructs the CLI to act as a cheater. Requires the `cheating` feature."
+    )]
     pub cheat: bool,
 }
 
@@ -127,6 +194,24 @@ pub struct JustifyTransferInfo {
     )]
     pub db_dir: Option<PathBuf>,
 
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
     /// The transaction ID for the asset transaction.
     /// This ID must be the same as the one used to create the transaction,
     /// using the `mercat-account` CLI.
@@ -166,13 +251,28 @@ pub struct JustifyTransferInfo {
     )]
     pub seed: Option<String>,
 
-    /// Whether to reject a transaction.
+    /// The category of the reason for rejecting a transaction, if rejecting.
     #[structopt(
         short,
         long,
-        help = "If present the mediator will reject the transaction."
+        help = "If present the mediator will reject the transaction. One of compliance, wrong-amount, unknown-counterparty, or other."
+    )]
+    pub reject: Option<RejectionKind>,
+
+    /// A free-form explanation of the rejection.
+    #[structopt(
+        long,
+        requires = "reject",
+        help = "A message explaining the rejection to the sender."
+    )]
+    pub reject_message: Option<String>,
+
+    /// Overwrites an earlier justification or rejection of the transaction.
+    #[structopt(
+        long,
+        help = "Justify the transaction even if it is already justified or rejected."
     )]
-    pub reject: bool,
+    pub re_justify: bool,
 
     /// An optional path to save the config used for this experiment.
     #[structopt(
@@ -183,7 +283,10 @@ pub struct JustifyTransferInfo {
     pub save_config: Option<PathBuf>,
 
     /// Instructs the CLI to act as a cheater.
-    #[structopt(long, help = "Instructs the CLI to act as a cheater.")]
+    #[structopt(
+        long,
+        help = "Instructs the CLI to act as a cheater. Requires the `cheating` feature."
+    )]
     pub cheat: bool,
 
     /// Instructs the CLI to print the transaction data in stdout.
@@ -192,6 +295,182 @@ pub struct JustifyTransferInfo {
         help = "Instructs the CLI to print the transaction data in stdout."
     )]
     pub stdout: bool,
+
+    /// The text encoding of the binary data that is read from or printed to the command line.
+    #[structopt(
+        long,
+        default_value = "hex",
+        help = "The encoding of the binary inputs and outputs. Either hex or base64."
+    )]
+    pub encoding: Encoding,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
+pub struct CreditInfo {
+    /// The directory that will serve as the database of the on/off-chain data and will be used
+    /// to save and load the data that in a real execution would be written to the on/off the
+    /// blockchain. Defaults to the current directory. This directory will have two main
+    /// sub-directories: `on-chain` and `off-chain`.
+    #[structopt(
+        parse(from_os_str),
+        help = "The directory to load and save the input and output files. Defaults to current directory.",
+        short,
+        long
+    )]
+    pub db_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
+    /// The transaction ID of the credit. `auto` allocates the next free id. An explicit id must
+    /// not be used by any instruction yet.
+    #[structopt(
+        long,
+        help = "The transaction ID, or auto to allocate the next free one."
+    )]
+    pub tx_id: TxIdArg,
+
+    /// An asset ticker name which is a string of at most 12 characters.
+    #[structopt(
+        short,
+        long,
+        help = "The asset ticker name. String of at most 12 characters."
+    )]
+    pub ticker: String,
+
+    /// The name of the user whose account is credited.
+    /// An account must have already been created for this user, using `mercat-account`
+    /// CLI.
+    #[structopt(long, help = "The name of the user whose account is credited.")]
+    pub beneficiary: String,
+
+    /// The deposited amount.
+    #[structopt(short, long, help = "The deposited amount.")]
+    pub amount: u32,
+
+    /// The name of the mediator. The mediator must be authorized for the ticker, using
+    /// `mercat-chain-setup`.
+    #[structopt(short, long, help = "The name of the mediator.")]
+    pub mediator: String,
+
+    /// An optional seed, to feed to the RNG, that can be passed to reproduce a previous run of this CLI.
+    /// The seed can be found inside the logs.
+    #[structopt(
+        short,
+        long,
+        help = "Base64 encoding of an initial seed for the RNG. If not provided, the seed will be chosen at random."
+    )]
+    pub seed: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
+pub struct MigrateInfo {
+    /// The directory that will serve as the database of the on/off-chain data and will be used
+    /// to save and load the data that in a real execution would be written to the on/off the
+    /// blockchain. Defaults to the current directory. This directory will have two main
+    /// sub-directories: `on-chain` and `off-chain`.
+    #[structopt(
+        parse(from_os_str),
+        help = "The directory to load and save the input and output files. Defaults to current directory.",
+        short,
+        long
+    )]
+    pub db_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
+    /// The name of the mediator that is replaced.
+    #[structopt(long, help = "The name of the mediator that is replaced.")]
+    pub old_mediator: String,
+
+    /// The name of the new mediator. Its account must have already been created.
+    #[structopt(long, help = "The name of the new mediator.")]
+    pub new_mediator: String,
+}
+
+impl JustifyTransferInfo {
+    /// Combines the rejection flags into the reason that is persisted in the instruction.
+    pub fn rejection_reason(&self) -> Option<RejectionReason> {
+        self.reject.map(|kind| RejectionReason {
+            kind,
+            message: self.reject_message.clone().unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
+pub struct BatchInfo {
+    /// A JSON array of operations, each with an `op` of `justify`.
+    #[structopt(parse(from_os_str), long, help = "The JSON file with the operations.")]
+    pub batch: PathBuf,
+
+    /// The directory that will serve as the database of the on/off-chain data and will be used
+    /// to save and load the data that in a real execution would be written to the on/off the
+    /// blockchain. Defaults to the current directory. This directory will have two main
+    /// sub-directories: `on-chain` and `off-chain`.
+    #[structopt(
+        parse(from_os_str),
+        help = "The directory to load and save the input and output files. Defaults to current directory.",
+        short,
+        long
+    )]
+    pub db_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
+    /// Runs the remaining operations after a failure, instead of stopping at the first one.
+    #[structopt(
+        long,
+        help = "Run the remaining operations after a failure, and report all failures at the end."
+    )]
+    pub continue_on_error: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, StructOpt)]
@@ -201,6 +480,34 @@ pub enum CLI {
 
     /// Justify a MERCAT transfer transaction.
     JustifyTransferTransaction(JustifyTransferInfo),
+
+    /// Credit an account with a deposit from outside of MERCAT.
+    Credit(CreditInfo),
+
+    /// Move the transfers that are not justified yet to a new mediator.
+    Migrate(MigrateInfo),
+
+    /// Justify the transfers of a batch file.
+    Batch(BatchInfo),
+}
+
+impl CLI {
+    /// Returns the chain directories of the command, with its `--on-chain-dir` and
+    /// `--off-chain-dir` overrides applied. `None` if the command has no database directory.
+    pub fn chain_dirs(&self) -> Option<ChainDirs> {
+        let (db_dir, on_chain_dir, off_chain_dir) = match self {
+            CLI::Create(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::JustifyTransferTransaction(cfg) => {
+                (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir)
+            }
+            CLI::Credit(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::Migrate(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+            CLI::Batch(cfg) => (&cfg.db_dir, &cfg.on_chain_dir, &cfg.off_chain_dir),
+        };
+        db_dir.clone().map(|db_dir| {
+            ChainDirs::with_overrides(db_dir, on_chain_dir.clone(), off_chain_dir.clone())
+        })
+    }
 }
 
 pub fn parse_input() -> Result<CLI, confy::ConfyError> {
@@ -219,7 +526,11 @@ pub fn parse_input() -> Result<CLI, confy::ConfyError> {
                 save_config: cfg.save_config.clone(),
                 seed,
                 db_dir,
+                on_chain_dir: cfg.on_chain_dir,
+                off_chain_dir: cfg.off_chain_dir,
                 user: cfg.user.clone(),
+                encrypt_secrets: cfg.encrypt_secrets,
+                encoding: cfg.encoding,
             };
 
             info!(
@@ -241,6 +552,8 @@ pub fn parse_input() -> Result<CLI, confy::ConfyError> {
             info!("Seed: {:?}", seed.clone().unwrap());
             let cfg = JustifyTransferInfo {
                 db_dir,
+                on_chain_dir: cfg.on_chain_dir,
+                off_chain_dir: cfg.off_chain_dir,
                 tx_id: cfg.tx_id,
                 ticker: cfg.ticker,
                 sender: cfg.sender,
@@ -248,9 +561,12 @@ pub fn parse_input() -> Result<CLI, confy::ConfyError> {
                 mediator: cfg.mediator,
                 seed,
                 reject: cfg.reject,
+                reject_message: cfg.reject_message,
+                re_justify: cfg.re_justify,
                 save_config: cfg.save_config.clone(),
                 cheat: cfg.cheat,
                 stdout: cfg.stdout,
+                encoding: cfg.encoding,
             };
 
             info!(
@@ -263,5 +579,50 @@ pub fn parse_input() -> Result<CLI, confy::ConfyError> {
 
             return Ok(CLI::JustifyTransferTransaction(cfg));
         }
+
+        CLI::Credit(cfg) => {
+            // Set the default seed and db_dir if needed.
+            let db_dir = cfg.db_dir.clone().or_else(|| std::env::current_dir().ok());
+
+            let seed: Option<String> = cfg.seed.clone().or_else(|| Some(gen_seed()));
+            info!("Seed: {:?}", seed.clone().unwrap());
+            let cfg = CreditInfo {
+                db_dir,
+                seed,
+                ..cfg
+            };
+
+            info!(
+                "Parsed the following config from the command line:\n{:#?}",
+                cfg.clone()
+            );
+
+            return Ok(CLI::Credit(cfg));
+        }
+
+        CLI::Migrate(cfg) => {
+            // Set the default db_dir if needed.
+            let db_dir = cfg.db_dir.clone().or_else(|| std::env::current_dir().ok());
+            let cfg = MigrateInfo { db_dir, ..cfg };
+
+            info!(
+                "Parsed the following config from the command line:\n{:#?}",
+                cfg.clone()
+            );
+
+            return Ok(CLI::Migrate(cfg));
+        }
+
+        CLI::Batch(cfg) => {
+            let db_dir = cfg.db_dir.clone().or_else(|| std::env::current_dir().ok());
+            let cfg = BatchInfo { db_dir, ..cfg };
+
+            info!(
+                "Parsed the following config from the command line:\n{:#?}",
+                cfg.clone()
+            );
+
+            return Ok(CLI::Batch(cfg));
+        }
     }
 }
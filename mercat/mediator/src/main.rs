@@ -5,9 +5,15 @@
 mod input;
 
 use mercat_common::{
+    batch::{load_batch, process_batch},
     errors::Error,
-    init_print_logger,
+    init_profiling_logger, install_panic_hook,
     justify::{justify_asset_transfer_transaction, process_create_mediator},
+    mediator_credit::{
+        ensure_distinct_signing_contexts, process_mediator_credit, signing_contexts,
+    },
+    migration::process_migrate_mediator,
+    tx_ids::resolve_tx_id,
 };
 
 use env_logger;
@@ -19,33 +25,81 @@ use std::time::Instant;
 fn main() {
     env_logger::init();
     info!("Starting the program.");
-    init_print_logger();
+    let profile = init_profiling_logger();
+    install_panic_hook();
+    ensure_distinct_signing_contexts(&signing_contexts()).unwrap();
 
     let parse_arg_timer = Instant::now();
     let args = parse_input().unwrap();
+    let chain_dirs = args.chain_dirs();
     timing!("mediator.argument_parse", parse_arg_timer, Instant::now());
 
     match args {
         CLI::Create(cfg) => process_create_mediator(
             cfg.seed.ok_or(Error::EmptySeed).unwrap(),
-            cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap(),
+            chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
             cfg.user,
+            cfg.encoding,
+            cfg.encrypt_secrets,
         )
         .unwrap(),
-        CLI::JustifyTransferTransaction(cfg) => justify_asset_transfer_transaction(
-            cfg.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap(),
-            cfg.sender,
-            cfg.receiver,
-            cfg.mediator,
-            cfg.ticker,
-            cfg.seed.ok_or(Error::EmptySeed).unwrap(),
-            cfg.stdout,
-            cfg.tx_id,
-            cfg.reject,
-            cfg.cheat,
-        )
-        .unwrap(),
+        CLI::JustifyTransferTransaction(cfg) => {
+            let rejection_reason = cfg.rejection_reason();
+            justify_asset_transfer_transaction(
+                chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
+                cfg.sender,
+                cfg.receiver,
+                cfg.mediator,
+                cfg.ticker,
+                cfg.seed.ok_or(Error::EmptySeed).unwrap(),
+                cfg.stdout,
+                cfg.encoding,
+                cfg.tx_id,
+                rejection_reason,
+                cfg.re_justify,
+                cfg.cheat,
+            )
+            .unwrap();
+        }
+        CLI::Credit(cfg) => {
+            let db_dir = chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap();
+            let tx_id = resolve_tx_id(db_dir.clone(), cfg.tx_id).unwrap();
+            process_mediator_credit(
+                cfg.seed.ok_or(Error::EmptySeed).unwrap(),
+                db_dir,
+                cfg.mediator,
+                cfg.beneficiary,
+                cfg.ticker,
+                cfg.amount,
+                tx_id,
+            )
+            .unwrap()
+        }
+        CLI::Migrate(cfg) => {
+            let migrated = process_migrate_mediator(
+                chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
+                cfg.old_mediator,
+                cfg.new_mediator,
+            )
+            .unwrap();
+            info!("Moved {} transfers to the new mediator.", migrated.len());
+        }
+        CLI::Batch(cfg) => {
+            let ops = load_batch(&cfg.batch).unwrap();
+            let report = process_batch(
+                chain_dirs.ok_or(Error::EmptyDatabaseDir).unwrap(),
+                &ops,
+                cfg.continue_on_error,
+            );
+            println!("{}", report);
+            if !report.is_success() {
+                std::process::exit(1);
+            }
+        }
     };
 
+    if let Some(report) = profile.report_if_enabled() {
+        println!("{}", report);
+    }
     info!("The program finished successfully.");
 }
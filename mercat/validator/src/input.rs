@@ -1,5 +1,6 @@
 use confy;
 use log::info;
+use mercat_common::{encoding::Encoding, ErrorStrategy};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -17,6 +18,268 @@ pub struct CLI {
         long
     )]
     pub db_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the on-chain data, e.g., to keep it on a shared storage.
+    /// Defaults to the `on-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the on-chain data. Defaults to the on-chain sub-directory of the db-dir."
+    )]
+    pub on_chain_dir: Option<PathBuf>,
+
+    /// Overrides the directory of the off-chain data, e.g., to keep the secrets on a local
+    /// encrypted disk. Defaults to the `off-chain` sub-directory of the db_dir.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "The directory of the off-chain data. Defaults to the off-chain sub-directory of the db-dir."
+    )]
+    pub off_chain_dir: Option<PathBuf>,
+
+    /// Refuses to load the pending transactions if there are more transaction files than this,
+    /// e.g., because the database directory is littered with stray files.
+    #[structopt(
+        long,
+        default_value = "100000",
+        help = "The maximum number of pending transaction files to load."
+    )]
+    pub max_pending: usize,
+
+    /// Fails the validation on the first transaction file with a malformed name, instead of
+    /// logging and skipping it.
+    #[structopt(
+        long,
+        help = "Fail on the transaction files with malformed names instead of skipping them."
+    )]
+    pub strict_filenames: bool,
+
+    /// What to do with a transaction that cannot be loaded or decoded: `skip` logs the error and
+    /// continues with the rest of the transactions, `abort` stops the validation.
+    #[structopt(
+        long,
+        default_value = "skip",
+        help = "What to do with a transaction that cannot be loaded: skip or abort."
+    )]
+    pub error_strategy: ErrorStrategy,
+
+    /// Skips the cryptographic verification of the issuances and transfers before this
+    /// transaction id, while still applying their balance changes. Only use it to bootstrap from
+    /// a trusted checkpoint, as any invalid transaction before it is accepted.
+    #[structopt(
+        long,
+        help = "Skip the verification of the transactions before this id. Only safe with a trusted checkpoint."
+    )]
+    pub assume_valid_before: Option<u32>,
+
+    /// Allows the validation to move the last processed counter of an account back, e.g., when
+    /// an earlier slice of the transactions is validated again. Refused by default.
+    #[structopt(
+        long,
+        help = "Allow the last processed counters of the accounts to move back."
+    )]
+    pub allow_regress: bool,
+
+    /// Decrypts the balances and the amounts with the secret keys in this directory, which has
+    /// the layout of the off-chain directory, for the debug logs and for tracking the balances
+    /// in plain text. Without it, the validation does not load any secret key.
+    #[structopt(
+        parse(from_os_str),
+        long,
+        help = "Decrypt the balances for debugging with the secret keys in this directory."
+    )]
+    pub debug_balances: Option<PathBuf>,
+
+    /// Collects the duration of every phase, e.g., the loading of the objects and the
+    /// verification, and prints a breakdown sorted by the total time at exit.
+    #[structopt(
+        long,
+        help = "Print a breakdown of the time spent in each phase at exit."
+    )]
+    pub profile: bool,
+
+    /// Prints a digest of the validation at the end: the number of the processed, accepted and
+    /// rejected transactions, the updated accounts, and the new last validated tx_id.
+    #[structopt(long, help = "Print a summary of the validation at the end.")]
+    pub summary: bool,
+
+    /// Prints the summary as JSON.
+    #[structopt(long, requires = "summary", help = "Print the summary as JSON.")]
+    pub json: bool,
+
+    /// An optional subcommand. Validates all the pending transactions if omitted.
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+}
+
+#[derive(StructOpt, Debug, Serialize, Deserialize, Clone)]
+pub enum Command {
+    /// Walk the hash chain of the audit log and report the first broken link.
+    VerifyAudit,
+
+    /// Report the last validated transaction and the number of pending transactions.
+    Status {
+        /// Print the status as JSON to stdout.
+        #[structopt(long, help = "Print the status as JSON.")]
+        json: bool,
+    },
+
+    /// Audit the consistency of the balances, the pending transfers and the last validated
+    /// tx_id. Exits with 1 if an inconsistency is found.
+    Doctor,
+
+    /// Rename the transaction files written without the ticker in their names.
+    MigrateFileNames,
+
+    /// Move the account map and the bookkeeping of the validator from where the earlier
+    /// versions kept them. Run once, before the first validation with this version.
+    MigrateValidationState,
+
+    /// Verify a correctness proof given by its encoded components. Prints "valid" or
+    /// "invalid", and exits with 1 if the proof is invalid.
+    VerifyProof {
+        /// The hex encoded value that the cipher text encrypts.
+        #[structopt(long, help = "The encrypted value as a hex encoded scalar.")]
+        value: String,
+
+        /// The hex encoded public key of the encryption.
+        #[structopt(long, help = "The public key as a hex encoded compressed point.")]
+        pubkey: String,
+
+        /// The hex encoded cipher text.
+        #[structopt(long, help = "The cipher text as two hex encoded compressed points.")]
+        cipher: String,
+
+        /// The hex encoded initial message of the proof.
+        #[structopt(
+            long,
+            help = "The initial message as two hex encoded compressed points."
+        )]
+        initial: String,
+
+        /// The hex encoded challenge of the proof.
+        #[structopt(long, help = "The challenge as a hex encoded scalar.")]
+        challenge: String,
+
+        /// The hex encoded final response of the proof.
+        #[structopt(long, help = "The final response as a hex encoded scalar.")]
+        response: String,
+
+        /// The text encoding of the components.
+        #[structopt(
+            long,
+            default_value = "hex",
+            help = "The encoding of the components. Either hex or base64."
+        )]
+        encoding: Encoding,
+    },
+
+    /// Encrypt a value and prove the correctness of the encryption. Prints the proof as the
+    /// arguments of `verify-proof`.
+    Prove {
+        /// The value to encrypt.
+        #[structopt(long, help = "The value to encrypt.")]
+        value: u32,
+
+        /// The encoded ElGamal secret key. A new key is generated if it is omitted.
+        #[structopt(
+            long,
+            help = "The secret key as an encoded scalar. If not provided, a new key is generated."
+        )]
+        secret_key: Option<String>,
+
+        /// Base64 encoding of an initial seed for the RNG.
+        #[structopt(
+            long,
+            help = "Base64 encoding of an initial seed for the RNG. If not provided, the seed will be chosen at random."
+        )]
+        seed: Option<String>,
+
+        /// The text encoding of the printed components.
+        #[structopt(
+            long,
+            default_value = "hex",
+            help = "The encoding of the printed components. Either hex or base64."
+        )]
+        encoding: Encoding,
+    },
+
+    /// Remove the initialization and finalization files of the validated or rejected transfers.
+    /// Only lists the files unless `--apply` is given.
+    Compact {
+        /// Remove the listed files.
+        #[structopt(long, help = "Remove the files instead of only listing them.")]
+        apply: bool,
+    },
+
+    /// Create the governance key that signs the issuance policies.
+    CreateGovernanceKey {
+        /// Base64 encoding of an initial seed for the RNG.
+        #[structopt(
+            long,
+            help = "Base64 encoding of an initial seed for the RNG. If not provided, the seed will be chosen at random."
+        )]
+        seed: Option<String>,
+    },
+
+    /// Sign and publish the issuance policy of a ticker with the governance key. The validator
+    /// rejects the issuances that violate it.
+    SetIssuancePolicy {
+        /// The ticker that the policy applies to.
+        #[structopt(short, long, help = "The asset ticker name.")]
+        ticker: String,
+
+        /// The largest amount of a single issuance.
+        #[structopt(long, help = "The maximum amount of a single issuance.")]
+        max_amount: u32,
+
+        /// Space separated list of the users that may receive issuances. Any issuer is allowed
+        /// if it is empty.
+        #[structopt(
+            long,
+            help = "Space separated list of the users that may receive issuances. Defaults to any."
+        )]
+        beneficiaries: Vec<String>,
+
+        /// Base64 encoding of an initial seed for the RNG.
+        #[structopt(
+            long,
+            help = "Base64 encoding of an initial seed for the RNG. If not provided, the seed will be chosen at random."
+        )]
+        seed: Option<String>,
+    },
+
+    /// Print the total amount issued in a ticker. It decrypts the sums of the issuances with the
+    /// secret accounts of their issuers.
+    TotalSupply {
+        /// The ticker whose supply is printed.
+        #[structopt(short, long, help = "The asset ticker name.")]
+        ticker: String,
+    },
+
+    /// Check that an account was validated against an asset registry. Exits with 1 if the
+    /// commitment that was stored with the account does not match.
+    VerifyRegistryCommitment {
+        /// The owner of the account.
+        #[structopt(short, long, help = "The name of the user.")]
+        user: String,
+
+        /// The ticker of the account.
+        #[structopt(short, long, help = "The asset ticker name.")]
+        ticker: String,
+
+        /// Space separated list of the tickers of the registry snapshot. Defaults to the current
+        /// registry.
+        #[structopt(
+            long,
+            help = "Space separated list of the tickers of the registry. Defaults to the current registry."
+        )]
+        registry: Vec<String>,
+    },
+
+    /// Rewrite a lost or corrupted last validated tx_id from the validated instructions and
+    /// accounts. Fails if the accounts did not process all of their validated instructions.
+    ReconstructMarker,
 }
 
 pub fn parse_input() -> Result<CLI, confy::ConfyError> {
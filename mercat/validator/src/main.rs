@@ -3,20 +3,225 @@
 
 mod input;
 use env_logger;
-use input::parse_input;
+use input::{parse_input, Command};
 use log::info;
-use mercat_common::{errors::Error, init_print_logger, validate::validate_all_pending};
+use mercat_common::{
+    audit::verify_audit_log,
+    chain_setup::verify_registry_commitment,
+    compaction::compact_tx_files,
+    doctor::{process_doctor, process_reconstruct_marker},
+    errors::Error,
+    gen_seed, init_profiling_logger, install_panic_hook,
+    issuance_policy::{process_create_governance_key, process_set_issuance_policy, IssuancePolicy},
+    migrate_validation_state,
+    migration::migrate_tx_file_names,
+    supply::process_total_supply,
+    validate::{process_validation_status, validate_all_pending_with_options, ValidationOptions},
+    verify_proof::{prove_correctness, verify_raw_correctness_proof, RawCorrectnessProof},
+    ChainDirs,
+};
 use metrics::timing;
 use std::time::Instant;
 
 fn main() {
     env_logger::init();
     info!("Starting the program.");
-    init_print_logger();
+    let profile = init_profiling_logger();
+    install_panic_hook();
 
     let parse_arg_timer = Instant::now();
     let args = parse_input().unwrap();
     timing!("validator.argument_parse", parse_arg_timer, Instant::now());
-    validate_all_pending(args.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap()).unwrap();
+    let db_dir = ChainDirs::with_overrides(
+        args.db_dir.ok_or(Error::EmptyDatabaseDir).unwrap(),
+        args.on_chain_dir,
+        args.off_chain_dir,
+    );
+    if args.profile {
+        profile.set_enabled(true);
+    }
+    let options = ValidationOptions {
+        strict_filenames: args.strict_filenames,
+        error_strategy: args.error_strategy,
+        assume_valid_before: args.assume_valid_before,
+        allow_regress: args.allow_regress,
+        debug_balances: args.debug_balances,
+        max_pending: args.max_pending,
+    };
+    match args.cmd {
+        None if args.summary => {
+            let summary = validate_all_pending_with_options(db_dir, None, &options).unwrap();
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&summary).unwrap());
+            } else {
+                println!("{}", summary);
+            }
+        }
+        None => {
+            validate_all_pending_with_options(db_dir, None, &options).unwrap();
+        }
+        Some(Command::VerifyAudit) => {
+            let records = verify_audit_log(db_dir).unwrap();
+            info!("The audit log is intact. Verified {} records.", records);
+        }
+        Some(Command::Doctor) => {
+            let report = process_doctor(db_dir).unwrap();
+            println!("{}", report);
+            if !report.is_healthy() {
+                std::process::exit(1);
+            }
+        }
+        Some(Command::MigrateFileNames) => {
+            let renamed = migrate_tx_file_names(db_dir).unwrap();
+            info!("Renamed {} transaction files.", renamed);
+        }
+        Some(Command::MigrateValidationState) => {
+            let moved = migrate_validation_state(db_dir).unwrap();
+            info!("Moved {} files of the validation state.", moved);
+        }
+        Some(Command::VerifyProof {
+            value,
+            pubkey,
+            cipher,
+            initial,
+            challenge,
+            response,
+            encoding,
+        }) => {
+            // The panic hook hides the error message, so the malformed input is reported here.
+            match verify_raw_correctness_proof(&RawCorrectnessProof {
+                value: &value,
+                pub_key: &pubkey,
+                cipher: &cipher,
+                initial_message: &initial,
+                challenge: &challenge,
+                response: &response,
+                encoding,
+            }) {
+                Ok(true) => println!("valid"),
+                Ok(false) => {
+                    println!("invalid");
+                    std::process::exit(1);
+                }
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(2);
+                }
+            }
+        }
+        Some(Command::Prove {
+            value,
+            secret_key,
+            seed,
+            encoding,
+        }) => {
+            let seed = seed.unwrap_or_else(gen_seed);
+            info!("Seed: {:?}", seed);
+            match prove_correctness(value, secret_key.as_deref(), seed, encoding) {
+                Ok(proof) => {
+                    info!("Secret key: {}", proof.secret_key);
+                    println!("{} --encoding {}", proof, encoding);
+                }
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(2);
+                }
+            }
+        }
+        Some(Command::Compact { apply }) => {
+            let files = compact_tx_files(db_dir, apply).unwrap();
+            if apply {
+                info!("Removed {} superseded transaction files.", files.len());
+            } else {
+                info!(
+                    "Found {} superseded transaction files. Pass --apply to remove them.",
+                    files.len()
+                );
+            }
+        }
+        Some(Command::CreateGovernanceKey { seed }) => {
+            process_create_governance_key(seed.unwrap_or_else(gen_seed), db_dir).unwrap()
+        }
+        Some(Command::SetIssuancePolicy {
+            ticker,
+            max_amount,
+            beneficiaries,
+            seed,
+        }) => process_set_issuance_policy(
+            seed.unwrap_or_else(gen_seed),
+            db_dir,
+            IssuancePolicy {
+                ticker,
+                max_amount,
+                beneficiaries,
+            },
+        )
+        .unwrap(),
+        Some(Command::VerifyRegistryCommitment {
+            user,
+            ticker,
+            registry,
+        }) => {
+            let registry = if registry.is_empty() {
+                None
+            } else {
+                Some(registry)
+            };
+            match verify_registry_commitment(db_dir, &user, &ticker, registry) {
+                Ok(()) => println!("valid"),
+                Err(error) => {
+                    println!("invalid: {}", error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Command::ReconstructMarker) => {
+            let last = process_reconstruct_marker(db_dir).unwrap();
+            info!("Reconstructed the last validated tx_id: {:?}", last);
+        }
+        Some(Command::TotalSupply { ticker }) => {
+            let total = process_total_supply(db_dir, ticker.clone()).unwrap();
+            println!("{}: total supply {}", ticker, total);
+        }
+        Some(Command::Status { json }) => {
+            let status = process_validation_status(db_dir).unwrap();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&status).unwrap());
+            } else {
+                info!("Last validated tx_id: {:?}", status.last_validated_tx_id);
+                info!(
+                    "Pending, ready for validation: {}",
+                    status.pending_ready_count
+                );
+                info!("Pending, not ready: {}", status.pending_not_ready_count);
+                for not_ready in status.not_ready {
+                    match not_ready.missing_state {
+                        Some(state) => {
+                            info!("Not ready tx-{}: waiting for {}", not_ready.tx_id, state)
+                        }
+                        None => info!(
+                            "Not ready tx-{}: no further step makes it ready",
+                            not_ready.tx_id
+                        ),
+                    }
+                }
+                for rejected in status.rejected {
+                    match rejected.reason {
+                        Some(reason) => info!(
+                            "Rejected tx-{} from {}: {}",
+                            rejected.tx_id, rejected.sender, reason
+                        ),
+                        None => info!(
+                            "Rejected tx-{} from {}: no reason given",
+                            rejected.tx_id, rejected.sender
+                        ),
+                    }
+                }
+            }
+        }
+    }
+    if let Some(report) = profile.report_if_enabled() {
+        println!("{}", report);
+    }
     info!("The program finished successfully.");
 }